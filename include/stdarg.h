@@ -0,0 +1,64 @@
+#ifndef __STDARG_H
+#define __STDARG_H
+
+// va_list of the System V ABI. __va_area__ of a variadic function begins with
+// it, followed by the register save area: rdi - r9 and xmm0 - xmm7
+typedef struct {
+  unsigned int gp_offset;
+  unsigned int fp_offset;
+  void *overflow_arg_area;
+  void *reg_save_area;
+} __va_elem;
+
+typedef __va_elem va_list[1];
+
+static void __va_copy(__va_elem *dst, __va_elem *src) {
+  dst->gp_offset = src->gp_offset;
+  dst->fp_offset = src->fp_offset;
+  dst->overflow_arg_area = src->overflow_arg_area;
+  dst->reg_save_area = src->reg_save_area;
+}
+
+// an argument passed on the stack
+static void *__va_arg_mem(__va_elem *ap, int size) {
+  char *p = ap->overflow_arg_area;
+  ap->overflow_arg_area = p + (size + 7) / 8 * 8;
+  return p;
+}
+
+// an argument passed in a general purpose register, or on the stack if they have run out
+static void *__va_arg_gp(__va_elem *ap, int size) {
+  if (ap->gp_offset >= 48)
+    return __va_arg_mem(ap, size);
+
+  char *p = ap->reg_save_area;
+  p = p + ap->gp_offset;
+  ap->gp_offset += 8;
+  return p;
+}
+
+// an argument passed in an xmm register, or on the stack if they have run out
+static void *__va_arg_fp(__va_elem *ap, int size) {
+  if (ap->fp_offset >= 176)
+    return __va_arg_mem(ap, size);
+
+  char *p = ap->reg_save_area;
+  p = p + ap->fp_offset;
+  ap->fp_offset += 16;
+  return p;
+}
+
+#define va_start(ap, last) __va_copy(ap, (__va_elem *)__va_area__)
+#define va_end(ap) ((void)0)
+#define va_copy(dest, src) __va_copy(dest, src)
+
+#define va_arg(ap, ty)                                                  \
+  (*(ty *)(__builtin_reg_class(ty) == 0 ? __va_arg_mem(ap, sizeof(ty)) : \
+           __builtin_reg_class(ty) == 1 ? __va_arg_gp(ap, sizeof(ty)) :  \
+           __va_arg_fp(ap, sizeof(ty))))
+
+// for the headers of libc, such as vprintf of stdio.h
+#define __GNUC_VA_LIST 1
+typedef va_list __gnuc_va_list;
+
+#endif
@@ -1,10 +1,15 @@
 use crate::program::Offset;
 
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
 #[derive(PartialEq, Debug, Clone)]
 pub struct Member {
     pub ty: Box<Type>,
     pub name: String,
-    pub offset: Offset
+    pub offset: Offset,
+    pub bit_field: Option<BitField>
 }
 
 impl Member {
@@ -12,11 +17,58 @@ impl Member {
         Self {
             ty,
             name: name.into(),
-            offset: Offset::Unset
+            offset: Offset::Unset,
+            bit_field: None
         }
     }
 }
 
+// a bit-field occupies `width` bits from the `offset`-th bit of the storage
+// unit of its type, which is at the offset of the member
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct BitField {
+    pub offset: usize,
+    pub width: usize
+}
+
+// a struct tag declared before its members, e.g. `struct node;` or the tag
+// within its own body. all the uses of the tag share it, so they see the
+// members once the struct is completed
+#[derive(Clone, Default)]
+pub struct StructTag(Rc<RefCell<Option<Type>>>, Rc<String>);
+
+impl StructTag {
+    pub fn new(name: Rc<String>) -> Self {
+        StructTag(Rc::new(RefCell::new(None)), name)
+    }
+
+    pub fn name(&self) -> &Rc<String> {
+        &self.1
+    }
+
+    pub fn complete(&self, ty: Type) {
+        *self.0.borrow_mut() = Some(ty);
+    }
+
+    pub fn get(&self) -> Option<Type> {
+        self.0.borrow().clone()
+    }
+}
+
+// a self-referential struct contains itself, so the tag is compared and
+// printed by its identity, not by the members
+impl PartialEq for StructTag {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl fmt::Debug for StructTag {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "StructTag({})", if self.0.borrow().is_some() { "complete" } else { "incomplete" })
+    }
+}
+
 // TODO: Strを追加
 // codegenとかでchar * に変換する?
 #[derive(PartialEq, Debug, Clone)]
@@ -24,6 +76,12 @@ pub enum Type {
     Int,
     Short,
     Long,
+    UChar,
+    UShort,
+    UInt,
+    ULong,
+    Float,
+    Double,
     Ptr {
         base: Box<Type>
     },
@@ -36,9 +94,19 @@ pub enum Type {
     Struct {
         members: Vec<Member>,
         align: usize, // alignment sizeはこの値の倍数になる
-        size: usize
+        size: usize,
+        is_union: bool, // all the members are at offset 0
+        // the tag, e.g. `point` of `struct point { ... }`. None if the struct is anonymous
+        name: Option<Rc<String>>
+    },
+    IncompleteStruct(StructTag),
+    // a function declared without parameters, such as `int f()`, has no
+    // parameter types and takes any arguments
+    Func {
+        ret: Box<Type>,
+        params: Vec<Type>,
+        is_variadic: bool
     },
-    Func(Box<Type>),
     Void,
     Bool,
     Enum,
@@ -50,14 +118,35 @@ impl Type {
         match *counter {
             1 => Ok(Type::Void), // void
             4 => Ok(Type::Bool), // bool
-            16 => Ok(Type::Char), // char
+            16 // char
+            | 8208 => Ok(Type::Char), // signed + char
+            16400 => Ok(Type::UChar), // unsigned + char
             64 //short
-            | 320 => Ok(Type::Short), // short + long
-            256 => Ok(Type::Int), // int
+            | 320 // short + int
+            | 8256 // signed + short
+            | 8512 => Ok(Type::Short), // signed + short + int
+            16448 // unsigned + short
+            | 16704 => Ok(Type::UShort), // unsigned + short + int
+            256 // int
+            | 8192 // signed
+            | 8448 => Ok(Type::Int), // signed + int
+            16384 // unsigned
+            | 16640 => Ok(Type::UInt), // unsigned + int
             1024 // long
             | 1280 // long + int
             | 2048 // long + long
-            | 2304 => Ok(Type::Long), // long + long + int
+            | 2304 // long + long + int
+            | 9216 // signed + long
+            | 9472 // signed + long + int
+            | 10240 // signed + long + long
+            | 10496 => Ok(Type::Long), // signed + long + long + int
+            17408 // unsigned + long
+            | 17664 // unsigned + long + int
+            | 18432 // unsigned + long + long
+            | 18688 => Ok(Type::ULong), // unsigned + long + long + int
+            32768 => Ok(Type::Float), // float
+            65536 // double
+            | 66560 => Ok(Type::Double), // long + double
             _ => {
                 let msg = format!("counter is {}, invalid type", counter);
                 return Err(msg)
@@ -67,16 +156,19 @@ impl Type {
 
     pub fn size(&self) -> usize {
         match self {
-            Type::Int => 4,
-            Type::Short => 2,
-            Type::Long => 8,
+            Type::Int | Type::UInt => 4,
+            Type::Short | Type::UShort => 2,
+            Type::Long | Type::ULong => 8,
+            Type::Float => 4,
+            Type::Double => 8,
             Type::Ptr { .. } => 8,
             Type::Array { base, len, .. } => base.size() * len,
-            Type::Char => 1,
+            Type::Char | Type::UChar => 1,
             Type::Void => 1,
             Type::Bool => 1,
             Type::Struct { size, .. } => *size,
-            Type::Func(_) => 1,
+            Type::IncompleteStruct(tag) => tag.get().map_or(0, |ty| ty.size()),
+            Type::Func { .. } => 1,
             Type::Enum => 4,
             Type::Dummy => 0
         }
@@ -96,11 +188,28 @@ impl Type {
             | Type::Short
             | Type::Long
             | Type::Char
-            | Type::Bool => true,
+            | Type::UInt
+            | Type::UShort
+            | Type::ULong
+            | Type::UChar
+            | Type::Bool
+            | Type::Enum => true,
             _ => false
         }
     }
 
+    pub fn is_flonum(&self) -> bool {
+        matches!(self, Type::Float | Type::Double)
+    }
+
+    pub fn is_arithmetic(&self) -> bool {
+        self.is_integer() || self.is_flonum()
+    }
+
+    pub fn is_unsigned(&self) -> bool {
+        matches!(self, Type::UInt | Type::UShort | Type::ULong | Type::UChar | Type::Bool)
+    }
+
     pub fn has_base(&self) -> bool {
         match self {
             Type::Ptr { .. } | Type::Array { .. } => true,
@@ -110,16 +219,19 @@ impl Type {
 
     pub fn align(&self) -> usize {
         match self {
-            Type::Int => 4,
-            Type::Short => 2,
-            Type::Long => 8,
+            Type::Int | Type::UInt => 4,
+            Type::Short | Type::UShort => 2,
+            Type::Long | Type::ULong => 8,
+            Type::Float => 4,
+            Type::Double => 8,
             Type::Ptr { .. } => 8,
             Type::Array { base, .. } => base.align(),
-            Type::Char => 1,
+            Type::Char | Type::UChar => 1,
             Type::Void => 1,
             Type::Bool => 1,
             Type::Struct { align, .. } => *align,
-            Type::Func(_) => 1,
+            Type::IncompleteStruct(tag) => tag.get().map_or(1, |ty| ty.align()),
+            Type::Func { .. } => 1,
             Type::Enum => 4,
             Type::Dummy => 0
         }
@@ -127,9 +239,10 @@ impl Type {
 
     pub fn replace_ptr_to(&mut self, dist: Type) {
         match self {
-            Type::Ptr { base, .. } => {
+            Type::Ptr { base, .. } | Type::Array { base, .. } => {
                     base.replace_ptr_to(dist);
             },
+            Type::Func { ret, .. } => ret.replace_ptr_to(dist),
             _ => *self = dist
         }
     }
@@ -137,9 +250,105 @@ impl Type {
     pub fn is_incomplete(&self) -> bool {
         match self {
             Type::Array { is_incomplete, .. } => *is_incomplete,
+            Type::IncompleteStruct(tag) => tag.get().is_none(),
             _ => false
         }
     }
+
+    // the struct which an incomplete struct tag has been completed with
+    pub fn resolve(&self) -> Box<Type> {
+        match self {
+            Type::IncompleteStruct(tag) => Box::new(tag.get().unwrap_or_else(|| self.clone())),
+            _ => Box::new(self.clone())
+        }
+    }
+
+    // the C declaration of `name` with this type, e.g. `int (*name)[3]`, or the type
+    // name if `name` is empty. `struct_name` spells a struct or union type
+    pub fn declare(&self, name: &str, struct_name: &mut dyn FnMut(&Type) -> String) -> String {
+        match self {
+            Type::Ptr { base } => {
+                let name = match base.as_ref() {
+                    Type::Array { .. } | Type::Func { .. } => format!("(*{})", name),
+                    _ => format!("*{}", name)
+                };
+                base.declare(&name, struct_name)
+            },
+            Type::Array { base, is_incomplete: true, .. } => base.declare(&format!("{}[]", name), struct_name),
+            Type::Array { base, len, .. } => base.declare(&format!("{}[{}]", name, len), struct_name),
+            Type::Func { ret, params, is_variadic } => {
+                let mut params: Vec<String> = params.iter().map(|param| param.declare("", struct_name)).collect();
+                if *is_variadic {
+                    params.push("...".to_string());
+                }
+                ret.declare(&format!("{}({})", name, params.join(", ")), struct_name)
+            },
+            _ => {
+                let base = match self {
+                    Type::Int | Type::Enum | Type::Dummy => "int".to_string(),
+                    Type::Short => "short".to_string(),
+                    Type::Long => "long".to_string(),
+                    Type::UChar => "unsigned char".to_string(),
+                    Type::UShort => "unsigned short".to_string(),
+                    Type::UInt => "unsigned int".to_string(),
+                    Type::ULong => "unsigned long".to_string(),
+                    Type::Float => "float".to_string(),
+                    Type::Double => "double".to_string(),
+                    Type::Char => "char".to_string(),
+                    Type::Void => "void".to_string(),
+                    Type::Bool => "_Bool".to_string(),
+                    _ => struct_name(self)
+                };
+                if name.is_empty() { base } else { format!("{} {}", base, name) }
+            }
+        }
+    }
+}
+
+// a struct is shown with its size, since it has no name
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let decl = self.declare("", &mut |ty| match ty.resolve().as_ref() {
+            Type::Struct { size, is_union, .. } => format!("{} ({} bytes)", if *is_union { "union" } else { "struct" }, size),
+            _ => "struct (incomplete)".to_string()
+        });
+
+        write!(f, "{}", decl)
+    }
+}
+
+// the usual arithmetic conversions for two arithmetic operands.
+// if one of them is a floating-point type, the wider floating-point type wins.
+// otherwise types smaller than int are promoted to int first, then the larger type wins.
+// if both have the same size, the unsigned one wins
+pub fn common_type(ty1: &Type, ty2: &Type) -> Type {
+    if let (Type::Double, _) | (_, Type::Double) = (ty1, ty2) {
+        return Type::Double
+    }
+    if let (Type::Float, _) | (_, Type::Float) = (ty1, ty2) {
+        return Type::Float
+    }
+
+    let ty1 = promote(ty1);
+    let ty2 = promote(ty2);
+
+    if ty1.size() != ty2.size() {
+        return if ty1.size() > ty2.size() { ty1 } else { ty2 }
+    }
+
+    if ty2.is_unsigned() { ty2 } else { ty1 }
+}
+
+// integer promotion. every value of a type smaller than int fits in int
+pub fn promote(ty: &Type) -> Type {
+    if ty.size() < 4 {
+        return Type::Int
+    }
+
+    match ty {
+        Type::Enum => Type::Int,
+        _ => ty.clone()
+    }
 }
 
 pub enum TypeCounter {
@@ -150,6 +359,10 @@ pub enum TypeCounter {
     Int,
     Long,
     Other,
+    Signed,
+    Unsigned,
+    Float,
+    Double,
     Zero
 }
 
@@ -162,6 +375,10 @@ impl TypeCounter {
             "short" => TypeCounter::Short,
             "int" => TypeCounter::Int,
             "long" => TypeCounter::Long,
+            "signed" => TypeCounter::Signed,
+            "unsigned" => TypeCounter::Unsigned,
+            "float" => TypeCounter::Float,
+            "double" => TypeCounter::Double,
             _  => TypeCounter::Zero
         }
     }
@@ -175,6 +392,10 @@ impl TypeCounter {
             TypeCounter::Int => 1 << 8,
             TypeCounter::Long => 1 << 10,
             TypeCounter::Other => 1 << 12,
+            TypeCounter::Signed => 1 << 13,
+            TypeCounter::Unsigned => 1 << 14,
+            TypeCounter::Float => 1 << 15,
+            TypeCounter::Double => 1 << 16,
             TypeCounter::Zero => 0,
         }
     }
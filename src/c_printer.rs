@@ -0,0 +1,667 @@
+use crate::node::{ Expr, ExprWrapper, Stmt, StmtKind };
+use crate::program::{ Function, Initializer, Program, Var };
+use crate::_type::{ Member, Type };
+
+use std::cell::RefCell;
+use std::collections::{ HashMap, HashSet };
+use std::rc::Rc;
+
+// print the program as C source, which compiles to the same program.
+// typedefs, enum constants and struct tags are gone from the AST, so the
+// struct types get new tags. local variables are declared at the top of
+// the function with unique names, since the initializers are assignments
+pub fn print(prog: &Program) -> String {
+    CPrinter::new(prog).print()
+}
+
+struct CPrinter<'a> {
+    prog: &'a Program,
+    // struct and union types in the order of their tags
+    structs: Vec<Type>,
+    // names of the variables
+    names: HashMap<*const RefCell<Var>, String>,
+    // names which a variable may not take in the current scope
+    used: HashSet<String>,
+    // declarations of the functions called or defined
+    funcs: Vec<(String, Type)>
+}
+
+impl<'a> CPrinter<'a> {
+    fn new(prog: &'a Program) -> Self {
+        Self { prog, structs: Vec::new(), names: HashMap::new(), used: HashSet::new(), funcs: Vec::new() }
+    }
+
+    fn print(mut self) -> String {
+        for func in &self.prog.fns {
+            self.used.insert(func.name.to_string());
+            self.declare_func(&func.name, &func.ty);
+        }
+        for var in self.prog.globals.iter().filter(|var| var.borrow().contents.is_none()) {
+            self.new_name(var);
+        }
+
+        let mut globals = Vec::new();
+        for var in self.prog.globals.iter().filter(|var| var.borrow().contents.is_none()) {
+            globals.push(self.global(var));
+        }
+        let globals_used = self.used.clone();
+        let mut fns = Vec::new();
+        for func in &self.prog.fns {
+            fns.push(self.function(func));
+            self.used = globals_used.clone();
+        }
+
+        let mut protos = Vec::new();
+        for (name, ty) in self.funcs.clone() {
+            let is_static = self.prog.fns.iter().any(|func| *func.name == name && func.is_static);
+            protos.push(format!("{}{};", if is_static { "static " } else { "" }, self.decl(&ty, &name)));
+        }
+
+        let mut out = String::new();
+        for section in [self.struct_defs(), protos, globals].iter() {
+            for line in section {
+                out.push_str(line);
+                out.push('\n');
+            }
+            if !section.is_empty() {
+                out.push('\n');
+            }
+        }
+        for func in fns {
+            out.push_str(&func);
+            out.push('\n');
+        }
+
+        out
+    }
+
+    // forward declarations of all the tags, then the definitions, each of which
+    // follows those of the structs it contains
+    fn struct_defs(&mut self) -> Vec<String> {
+        let mut defs = Vec::new();
+        let mut deps = Vec::new();
+        let mut i = 0;
+        // a definition may give tags to more structs
+        while i < self.structs.len() {
+            let members = match &self.structs[i] {
+                Type::Struct { members, .. } => members.clone(),
+                _ => {
+                    defs.push(None);
+                    deps.push(Vec::new());
+                    i += 1;
+                    continue
+                }
+            };
+
+            let mut lines = vec![format!("{} {{", self.decl(&self.structs[i].clone(), ""))];
+            for member in &members {
+                let bits = member.bit_field.map_or(String::new(), |bf| format!(" : {}", bf.width));
+                lines.push(format!("  {}{};", self.decl(&member.ty, &member.name), bits));
+            }
+            lines.push("};".to_string());
+            defs.push(Some(lines));
+            deps.push(members.iter().filter_map(|member| self.contained_struct(&member.ty)).collect());
+            i += 1;
+        }
+
+        let mut out: Vec<String> = (0..self.structs.len())
+            .map(|i| format!("{};", self.decl(&self.structs[i].clone(), "")))
+            .collect();
+        let mut done = vec![false; defs.len()];
+        for i in 0..defs.len() {
+            emit_def(i, &mut defs, &deps, &mut done, &mut out);
+        }
+
+        out
+    }
+
+    // the index of the struct which a member of this type contains by value
+    fn contained_struct(&mut self, ty: &Type) -> Option<usize> {
+        match ty {
+            Type::Array { base, .. } => self.contained_struct(base),
+            Type::Struct { .. } | Type::IncompleteStruct(_) => {
+                let resolved = ty.resolve();
+                self.structs.iter().position(|s| *s == *resolved)
+            },
+            _ => None
+        }
+    }
+
+    fn decl(&mut self, ty: &Type, name: &str) -> String {
+        let mut structs = std::mem::take(&mut self.structs);
+        let decl = ty.declare(name, &mut |ty| struct_name(&mut structs, ty));
+        self.structs = structs;
+
+        decl
+    }
+
+    fn declare_func(&mut self, name: &str, ty: &Type) {
+        if !self.funcs.iter().any(|(n, _)| n == name) {
+            self.funcs.push((name.to_string(), ty.clone()));
+        }
+    }
+
+    // a unique name for a variable. names which are not identifiers, and
+    // those of shadowed variables, are changed
+    fn new_name(&mut self, var: &Rc<RefCell<Var>>) -> String {
+        let base: String = var.borrow().name.chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '_' { c } else { '_' })
+            .collect();
+        let base = if base.is_empty() || base.starts_with(|c: char| c.is_ascii_digit()) { format!("v{}", base) } else { base };
+
+        let mut name = base.clone();
+        let mut n = 1;
+        while self.used.contains(&name) {
+            name = format!("{}_{}", base, n);
+            n += 1;
+        }
+        self.used.insert(name.clone());
+        self.names.insert(Rc::as_ptr(var), name.clone());
+
+        name
+    }
+
+    fn var_name(&self, var: &Rc<RefCell<Var>>) -> String {
+        match self.names.get(&Rc::as_ptr(var)) {
+            Some(name) => name.clone(),
+            None => var.borrow().name.clone()
+        }
+    }
+
+    fn global(&mut self, var: &Rc<RefCell<Var>>) -> String {
+        let name = self.var_name(var);
+        let var = var.borrow();
+        let decl = self.decl(&var.ty, &name);
+
+        let init = match &var.init {
+            Some(init) => init,
+            None => return format!("{};", decl)
+        };
+        let mut bytes = vec![0u8; var.ty.size()];
+        let mut relocs = HashMap::new();
+        let mut pos = 0;
+        for item in init {
+            match item {
+                Initializer::Val { sz, val } => {
+                    for (i, byte) in val.to_le_bytes().iter().take(*sz).enumerate() {
+                        if pos + i < bytes.len() {
+                            bytes[pos + i] = *byte;
+                        }
+                    }
+                    pos += sz;
+                },
+                Initializer::Label { name, addend } => {
+                    relocs.insert(pos, (name.clone(), *addend));
+                    pos += 8;
+                },
+                Initializer::Zero(sz) => pos += sz
+            }
+        }
+        // a variable is zero-initialized without an initializer
+        if relocs.is_empty() && bytes.iter().all(|byte| *byte == 0) {
+            return format!("{};", decl)
+        }
+
+        format!("{} = {};", decl, self.init(&var.ty, &bytes, &relocs, 0))
+    }
+
+    // the initializer of the object of the type at `offset` of the data
+    fn init(&mut self, ty: &Type, bytes: &[u8], relocs: &HashMap<usize, (String, isize)>, offset: usize) -> String {
+        match ty {
+            Type::Array { base, len, .. } => {
+                let elems: Vec<String> = (0..*len).map(|i| self.init(base, bytes, relocs, offset + i * base.size())).collect();
+                format!("{{{}}}", elems.join(", "))
+            },
+            Type::Struct { members, is_union: false, .. } => {
+                let members: Vec<String> = members.iter().map(|member| self.member_init(member, bytes, relocs, offset)).collect();
+                format!("{{{}}}", members.join(", "))
+            },
+            // the largest member is initialized, which covers the others
+            Type::Struct { members, is_union: true, .. } => {
+                let (i, member) = members.iter().enumerate()
+                    .fold(None, |max: Option<(usize, &Member)>, (i, member)| match max {
+                        Some((_, m)) if m.ty.size() >= member.ty.size() => max,
+                        _ => Some((i, member))
+                    })
+                    .expect("union has no members");
+                let init = self.member_init(member, bytes, relocs, offset);
+                if i == 0 { format!("{{{}}}", init) } else { format!("{{.{} = {}}}", member.name, init) }
+            },
+            Type::IncompleteStruct(_) => self.init(&ty.resolve(), bytes, relocs, offset),
+            _ => {
+                if let Some((name, addend)) = relocs.get(&offset) {
+                    let addr = self.label(name);
+                    let cast = self.decl(ty, "");
+                    return match addend {
+                        0 => format!("({}){}", cast, addr),
+                        _ => format!("({})((char *){} + {})", cast, addr, addend)
+                    }
+                }
+
+                let val = read(bytes, offset, ty.size());
+                match ty {
+                    Type::Float => float_literal(f32::from_bits(val as u32) as f64, true),
+                    Type::Double => float_literal(f64::from_bits(val), false),
+                    _ => {
+                        let val = if ty.is_unsigned() || ty.has_base() { val as isize } else { sign_extend(val, ty.size() * 8) };
+                        self.num(val, ty)
+                    }
+                }
+            }
+        }
+    }
+
+    fn member_init(&mut self, member: &Member, bytes: &[u8], relocs: &HashMap<usize, (String, isize)>, offset: usize) -> String {
+        let offset = offset + member.offset.value();
+        let bf = match member.bit_field {
+            Some(bf) => bf,
+            None => return self.init(&member.ty, bytes, relocs, offset)
+        };
+
+        let unit = read(bytes, offset, member.ty.size()) >> bf.offset;
+        let val = unit & (u64::MAX >> (64 - bf.width));
+        let val = if member.ty.is_unsigned() { val as isize } else { sign_extend(val, bf.width) };
+
+        val.to_string()
+    }
+
+    // the address of a global variable, a function or a string literal
+    fn label(&self, name: &str) -> String {
+        let var = self.prog.globals.iter().find(|var| var.borrow().name == name);
+        match var {
+            Some(var) => match &var.borrow().contents {
+                Some(bytes) => string_literal(bytes),
+                None => format!("&{}", self.var_name(var))
+            },
+            None => format!("&{}", name)
+        }
+    }
+
+    fn function(&mut self, func: &Function) -> String {
+        let (ret, is_variadic) = match func.ty.as_ref() {
+            Type::Func { ret, is_variadic, .. } => (ret.clone(), *is_variadic),
+            _ => (func.ty.clone(), false)
+        };
+
+        let mut params = Vec::new();
+        for param in &func.params {
+            let name = self.new_name(param);
+            params.push(self.decl(&param.borrow().ty, &name));
+        }
+        if is_variadic {
+            params.push("...".to_string());
+        }
+        let head = self.decl(&ret, &format!("{}({})", func.name, params.join(", ")));
+
+        let mut lines = vec![format!("{}{} {{", if func.is_static { "static " } else { "" }, head)];
+        // __va_area__ and the unnamed buffers for the struct return values are
+        // made by the compiler again
+        self.used.insert("__va_area__".to_string());
+        // the locals are in the reverse order of the declarations
+        for var in func.locals.iter().rev() {
+            let is_va_area = func.va_area.as_ref().is_some_and(|va_area| Rc::ptr_eq(va_area, var));
+            if is_va_area || var.borrow().name.is_empty() || func.params.iter().any(|param| Rc::ptr_eq(param, var)) {
+                continue
+            }
+            let name = self.new_name(var);
+            lines.push(format!("  {};", self.decl(&var.borrow().ty, &name)));
+        }
+        for stmt in &func.nodes {
+            if !is_null(stmt) {
+                lines.extend(self.stmt(stmt).into_iter().map(|line| format!("  {}", line)));
+            }
+        }
+        lines.push("}".to_string());
+
+        lines.join("\n") + "\n"
+    }
+
+    // the lines of a statement, which are indented by the caller
+    fn stmt(&mut self, stmt: &Stmt) -> Vec<String> {
+        match &stmt.kind {
+            StmtKind::Return { val } => vec![format!("return {};", self.top_expr(val))],
+            StmtKind::ExprStmt { val } | StmtKind::PureExpr(val) => match val.expr.as_ref() {
+                Expr::Null => vec![";".to_string()],
+                _ => vec![format!("{};", self.top_expr(val))]
+            },
+            StmtKind::If { cond, then, els } => {
+                let cond = self.top_expr(cond);
+                let mut lines = self.block(&format!("if ({}) ", cond), then);
+                if let Some(els) = els.as_deref() {
+                    let els = match els.kind {
+                        StmtKind::If { .. } => self.stmt(els),
+                        _ => self.block("", els)
+                    };
+                    lines.pop();
+                    lines.push(format!("}} else {}", els[0]));
+                    lines.extend(els.into_iter().skip(1));
+                }
+                lines
+            },
+            StmtKind::While { cond, then } => {
+                let cond = self.top_expr(cond);
+                self.block(&format!("while ({}) ", cond), then)
+            },
+            StmtKind::DoWhile { then, cond } => {
+                let mut lines = self.block("do ", then);
+                let cond = self.top_expr(cond);
+                lines.pop();
+                lines.push(format!("}} while ({});", cond));
+                lines
+            },
+            StmtKind::For { init, cond, inc, then } => {
+                // a declaration is done before the loop
+                let (init, decl) = match init.as_ref().as_ref().map(|init| (init, &init.kind)) {
+                    Some((_, StmtKind::ExprStmt { val })) if *val.expr != Expr::Null => (self.top_expr(val), None),
+                    Some((stmt, _)) if !is_null(stmt) => (String::new(), Some(self.stmt(stmt))),
+                    _ => (String::new(), None)
+                };
+                let cond = cond.as_ref().map_or(String::new(), |cond| self.top_expr(cond));
+                let inc = match inc.as_ref().as_ref().map(|inc| &inc.kind) {
+                    Some(StmtKind::ExprStmt { val }) => self.top_expr(val),
+                    _ => String::new()
+                };
+                let head = format!("for ({};{};{}) ", init, space_before(cond), space_before(inc));
+                let lines = self.block(&head, then);
+                match decl {
+                    Some(decl) => {
+                        let mut block = vec!["{".to_string()];
+                        block.extend(decl.into_iter().chain(lines).map(|line| format!("  {}", line)));
+                        block.push("}".to_string());
+                        block
+                    },
+                    None => lines
+                }
+            },
+            StmtKind::Block { .. } => self.block("", stmt),
+            StmtKind::Break => vec!["break;".to_string()],
+            StmtKind::Continue => vec!["continue;".to_string()],
+            StmtKind::Goto(label) => vec![format!("goto {};", label)],
+            StmtKind::Label(stmt, label) => {
+                let mut lines = vec![format!("{}:", label)];
+                lines.extend(self.stmt(stmt));
+                lines
+            },
+            StmtKind::Switch { cond, then, .. } => {
+                let cond = self.top_expr(cond);
+                self.block(&format!("switch ({}) ", cond), then)
+            },
+            StmtKind::Case { val, stmt, .. } => {
+                let mut lines = vec![format!("case {}:", val)];
+                lines.extend(self.stmt(stmt));
+                lines
+            },
+            StmtKind::Default(stmt) => {
+                let mut lines = vec!["default:".to_string()];
+                lines.extend(self.stmt(stmt));
+                lines
+            }
+        }
+    }
+
+    // `head` followed by the statement in braces
+    fn block(&mut self, head: &str, stmt: &Stmt) -> Vec<String> {
+        let stmts = match &stmt.kind {
+            StmtKind::Block { stmts } => stmts.iter().collect(),
+            _ => vec![stmt]
+        };
+
+        let mut lines = vec![format!("{}{{", head)];
+        for stmt in stmts.into_iter().filter(|stmt| !is_null(stmt)) {
+            lines.extend(self.stmt(stmt).into_iter().map(|line| format!("  {}", line)));
+        }
+        lines.push("}".to_string());
+
+        lines
+    }
+
+    // an expression without the outermost parentheses
+    fn top_expr(&mut self, ew: &ExprWrapper) -> String {
+        let expr = self.expr(ew);
+        match ew.expr.as_ref() {
+            Expr::Comma { .. } | Expr::StmtExpr(_) | Expr::MemZero(_) => expr,
+            _ => unparen(expr)
+        }
+    }
+
+    fn expr(&mut self, ew: &ExprWrapper) -> String {
+        match ew.expr.as_ref() {
+            Expr::Eq { lhs, rhs } => self.binary(lhs, "==", rhs),
+            Expr::Neq { lhs, rhs } => self.binary(lhs, "!=", rhs),
+            Expr::Gt { lhs, rhs } => self.binary(lhs, ">", rhs),
+            Expr::Ge { lhs, rhs } => self.binary(lhs, ">=", rhs),
+            Expr::Lt { lhs, rhs } => self.binary(lhs, "<", rhs),
+            Expr::Le { lhs, rhs } => self.binary(lhs, "<=", rhs),
+            Expr::Add { lhs, rhs } | Expr::PtrAdd { lhs, rhs } => self.binary(lhs, "+", rhs),
+            Expr::Sub { lhs, rhs } | Expr::PtrSub { lhs, rhs } | Expr::PtrDiff { lhs, rhs } => self.binary(lhs, "-", rhs),
+            Expr::Mul { lhs, rhs } => self.binary(lhs, "*", rhs),
+            Expr::Div { lhs, rhs } => self.binary(lhs, "/", rhs),
+            Expr::Mod { lhs, rhs } => self.binary(lhs, "%", rhs),
+            Expr::BitAnd { lhs, rhs } => self.binary(lhs, "&", rhs),
+            Expr::BitOr { lhs, rhs } => self.binary(lhs, "|", rhs),
+            Expr::BitXor { lhs, rhs } => self.binary(lhs, "^", rhs),
+            Expr::Shl { lhs, rhs } => self.binary(lhs, "<<", rhs),
+            Expr::Shr { lhs, rhs } => self.binary(lhs, ">>", rhs),
+            Expr::LogAnd { lhs, rhs } => self.binary(lhs, "&&", rhs),
+            Expr::LogOr { lhs, rhs } => self.binary(lhs, "||", rhs),
+            Expr::Assign { var, val } => self.binary(var, "=", val),
+            Expr::AddEq { var, val } | Expr::PtrAddEq { var, val } => self.binary(var, "+=", val),
+            Expr::SubEq { var, val } | Expr::PtrSubEq { var, val } => self.binary(var, "-=", val),
+            Expr::MulEq { var, val } => self.binary(var, "*=", val),
+            Expr::DivEq { var, val } => self.binary(var, "/=", val),
+            Expr::ModEq { var, val } => self.binary(var, "%=", val),
+            Expr::BitAndEq { var, val } => self.binary(var, "&=", val),
+            Expr::BitOrEq { var, val } => self.binary(var, "|=", val),
+            Expr::BitXorEq { var, val } => self.binary(var, "^=", val),
+            Expr::ShlEq { var, val } => self.binary(var, "<<=", val),
+            Expr::ShrEq { var, val } => self.binary(var, ">>=", val),
+            Expr::Num { val, ty } => self.num(*val, ty),
+            Expr::FNum { val } => float_literal(*val, *ew.ty == Type::Float),
+            Expr::Cast(ty, operand) => {
+                let ty = self.decl(ty, "");
+                format!("(({}){})", ty, self.expr(operand))
+            },
+            Expr::Var(var) => {
+                let v = var.borrow();
+                match (&v.contents, v.ty.as_ref()) {
+                    (Some(bytes), _) => string_literal(bytes),
+                    (None, Type::Func { .. }) if !v.is_local => {
+                        self.declare_func(&v.name, &v.ty);
+                        v.name.clone()
+                    },
+                    _ => self.var_name(var)
+                }
+            },
+            Expr::PreInc(var) => format!("(++{})", self.expr(var)),
+            Expr::PreDec(var) => format!("(--{})", self.expr(var)),
+            Expr::PostInc(var) => format!("({}++)", self.expr(var)),
+            Expr::PostDec(var) => format!("({}--)", self.expr(var)),
+            Expr::Comma { lhs, rhs } => {
+                let lhs = match &lhs.kind {
+                    StmtKind::ExprStmt { val } => self.top_expr(val),
+                    _ => format!("({{ {} }})", self.stmt(lhs).join(" "))
+                };
+                format!("({}, {})", lhs, self.top_expr(rhs))
+            },
+            Expr::Cond { cond, then, els } => {
+                format!("({} ? {} : {})", self.expr(cond), self.expr(then), self.expr(els))
+            },
+            Expr::FnCall { callee, args, .. } => {
+                let callee = self.expr(callee);
+                let args: Vec<String> = args.iter().map(|arg| self.top_expr(arg)).collect();
+                format!("{}({})", callee, args.join(", "))
+            },
+            Expr::Addr { operand } => format!("(&{})", self.expr(operand)),
+            Expr::Deref { operand } => format!("(*{})", self.expr(operand)),
+            Expr::Not(operand) => format!("(!{})", self.expr(operand)),
+            Expr::BitNot(operand) => format!("(~{})", self.expr(operand)),
+            Expr::Null => "0".to_string(),
+            Expr::StmtExpr(stmts) => {
+                let lines: Vec<String> = stmts.iter().filter(|stmt| !is_null(stmt)).flat_map(|stmt| self.stmt(stmt)).collect();
+                format!("({{ {} }})", lines.iter().map(|line| line.trim()).collect::<Vec<_>>().join(" "))
+            },
+            Expr::Member(base, member) => format!("{}.{}", self.expr(base), member.name),
+            // a loop, since there is no memset
+            Expr::MemZero(var) => {
+                let name = self.var_name(var);
+                format!(
+                    "({{ char *__p = (char *)&{0}; unsigned long __i; for (__i = 0; __i < sizeof({0}); __i++) __p[__i] = 0; 0; }})",
+                    name
+                )
+            }
+        }
+    }
+
+    fn binary(&mut self, lhs: &ExprWrapper, op: &str, rhs: &ExprWrapper) -> String {
+        format!("({} {} {})", self.expr(lhs), op, self.expr(rhs))
+    }
+
+    // an integer constant of the type
+    fn num(&mut self, val: isize, ty: &Type) -> String {
+        let (suffix, min) = match ty {
+            Type::Int => ("", i32::MIN as isize),
+            Type::Long => ("L", isize::MIN),
+            Type::UInt => return format!("{}U", val as u32),
+            Type::ULong => return format!("{}UL", val as u64),
+            _ => {
+                let ty = self.decl(ty, "");
+                let val = if val as i32 as isize == val { self.num(val, &Type::Int) } else { self.num(val, &Type::Long) };
+                return format!("(({}){})", ty, val)
+            }
+        };
+
+        if val == min {
+            format!("(-{}{} - 1)", -(val + 1), suffix)
+        } else if val < 0 {
+            format!("(-{}{})", -val, suffix)
+        } else {
+            format!("{}{}", val, suffix)
+        }
+    }
+}
+
+// the tag of a struct or union type, which is given on first use
+fn struct_name(structs: &mut Vec<Type>, ty: &Type) -> String {
+    let ty = ty.resolve();
+    let i = match structs.iter().position(|s| *s == *ty) {
+        Some(i) => i,
+        None => {
+            structs.push(*ty.clone());
+            structs.len() - 1
+        }
+    };
+    let kind = match *ty {
+        Type::Struct { is_union: true, .. } => "union",
+        _ => "struct"
+    };
+
+    format!("{} s{}", kind, i)
+}
+
+fn emit_def(i: usize, defs: &mut Vec<Option<Vec<String>>>, deps: &[Vec<usize>], done: &mut Vec<bool>, out: &mut Vec<String>) {
+    if done[i] {
+        return
+    }
+    done[i] = true;
+    for dep in &deps[i] {
+        emit_def(*dep, defs, deps, done, out);
+    }
+    if let Some(lines) = defs[i].take() {
+        out.extend(lines);
+    }
+}
+
+fn is_null(stmt: &Stmt) -> bool {
+    match &stmt.kind {
+        StmtKind::ExprStmt { val } => *val.expr == Expr::Null,
+        _ => false
+    }
+}
+
+// remove the parentheses around the whole expression
+fn unparen(expr: String) -> String {
+    if !expr.starts_with('(') || !expr.ends_with(')') {
+        return expr
+    }
+
+    let mut depth = 0;
+    let mut in_str = false;
+    let mut escaped = false;
+    for (i, c) in expr.char_indices() {
+        if in_str {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_str = false,
+                _ => {}
+            }
+            continue
+        }
+        match c {
+            '"' => in_str = true,
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                // the first parenthesis is closed before the end
+                if depth == 0 && i != expr.len() - 1 {
+                    return expr
+                }
+            },
+            _ => {}
+        }
+    }
+
+    expr[1..expr.len() - 1].to_string()
+}
+
+// a string literal of the bytes, without the terminating nul. the escape
+// sequences which the tokenizer reads are preferred to octal ones
+fn string_literal(bytes: &[u8]) -> String {
+    let bytes = bytes.strip_suffix(&[0]).unwrap_or(bytes);
+    let mut lit = String::from("\"");
+    for (i, byte) in bytes.iter().enumerate() {
+        match byte {
+            b'"' => lit.push_str("\\\""),
+            b'\\' => lit.push_str("\\\\"),
+            7 => lit.push_str("\\a"),
+            8 => lit.push_str("\\b"),
+            9 => lit.push_str("\\t"),
+            10 => lit.push_str("\\n"),
+            11 => lit.push_str("\\v"),
+            12 => lit.push_str("\\f"),
+            13 => lit.push_str("\\r"),
+            27 => lit.push_str("\\e"),
+            0 if !bytes.get(i + 1).is_some_and(|next| next.is_ascii_digit()) => lit.push_str("\\0"),
+            0x20 ..= 0x7e => lit.push(*byte as char),
+            _ => lit.push_str(&format!("\\{:03o}", byte))
+        }
+    }
+    lit.push('"');
+
+    lit
+}
+
+fn float_literal(val: f64, is_float: bool) -> String {
+    let lit = match val.abs() {
+        abs if abs.is_nan() => "(0.0 / 0.0)".to_string(),
+        abs if abs.is_infinite() => "(1.0 / 0.0)".to_string(),
+        abs => format!("{:?}", abs)
+    };
+    let lit = if is_float { format!("{}f", lit) } else { lit };
+
+    if val.is_sign_negative() { format!("(-{})", lit) } else { lit }
+}
+
+fn space_before(s: String) -> String {
+    if s.is_empty() { s } else { format!(" {}", s) }
+}
+
+// the little-endian unsigned integer of `size` bytes at `offset`
+fn read(bytes: &[u8], offset: usize, size: usize) -> u64 {
+    bytes[offset..offset + size].iter().rev().fold(0, |val, byte| val << 8 | *byte as u64)
+}
+
+fn sign_extend(val: u64, bits: usize) -> isize {
+    let shift = 64 - bits;
+    ((val << shift) as i64 >> shift) as isize
+}
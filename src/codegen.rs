@@ -1,668 +1,1275 @@
-use crate::node::{ Stmt, Expr, ExprWrapper };
-use crate::program::{ Program, Var };
-use crate::_type::Type;
+mod regalloc;
+mod peephole;
+mod dwarf;
+
+use crate::ir::{ self, Function, BlockId, Reg, Operand, Ty, ArgTy, StructRegs, BinOp, Cond, Inst, Callee, Terminator };
+use crate::program::{ Initializer, align_to };
+use crate::tokenizer::loc::Loc;
+use crate::diagnostic::{ Diagnostic, Severity, Span };
+use regalloc::Location;
 
-use std::cell::RefCell;
 use std::convert::TryFrom;
+use std::fmt;
+use std::io::{ self, BufWriter, Write };
+use std::rc::Rc;
 
-const ARG_REG1: [&str; 6] = ["dil", "sil", "dl", "cl", "r8b", "r9b"];
-const ARG_REG2: [&str; 6] = ["di", "si", "dx", "cx", "r8w", "r9w"];
-const ARG_REG4: [&str; 6] = ["edi", "esi", "edx", "ecx", "r8d", "r9d"];
 const ARG_REG8: [&str; 6] = ["rdi", "rsi", "rdx", "rcx", "r8", "r9"];
+const FP_ARG_REGS: usize = 8;
+
+#[derive(Debug)]
+pub enum CodegenError {
+    Io(io::Error),
+    // the program cannot be compiled, e.g. too many arguments
+    Invalid(Diagnostic)
+}
+
+impl fmt::Display for CodegenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CodegenError::Io(e) => write!(f, "cannot write the output: {}", e),
+            CodegenError::Invalid(diag) => write!(f, "{}", diag)
+        }
+    }
+}
+
+impl From<io::Error> for CodegenError {
+    fn from(e: io::Error) -> Self {
+        CodegenError::Io(e)
+    }
+}
 
-pub struct CodeGenerator<'a> {
-    prog: &'a Program,
-    funcname: RefCell<String>,
+impl From<String> for CodegenError {
+    fn from(msg: String) -> Self {
+        CodegenError::Invalid(Diagnostic::error(msg))
+    }
+}
+
+// the number of instructions before and after the peephole optimization
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PeepholeStats {
+    pub before: usize,
+    pub after: usize
+}
+
+// generates the assembly from the IR. the virtual registers are assigned to
+// machine registers or stack slots by `regalloc`, and rax, rcx, rdx, rdi and
+// xmm0 - xmm1 are used as scratch registers
+pub struct CodeGenerator<'a, W: Write> {
+    prog: &'a ir::Program,
+    // the assembly of the current function, which is written to `sink` when it is done
+    out: Vec<u8>,
+    sink: BufWriter<W>,
     labelseq: usize,
-    brkseq: usize,
-    contseq: usize
+    funcname: String,
+    // locations of the virtual registers of the current function
+    locs: Vec<Option<Location>>,
+    // Some if the peephole optimization is enabled
+    peephole: Option<PeepholeStats>,
+    // source files numbered by `.file`, from 1
+    files: Vec<Rc<String>>,
+    // Some if the debug information is written (-g)
+    debug_info: Option<dwarf::Unit>,
+    // the location of the statement being generated, for an error in it
+    loc: Option<Loc>
 }
 
-impl<'a> CodeGenerator<'a> {
-    pub fn new(prog: &'a Program) -> Self {
-        //println!("{:#?}", prog);
+impl<'a, W: Write> CodeGenerator<'a, W> {
+    // the assembly is written to `out` through a buffer
+    pub fn new(prog: &'a ir::Program, out: W) -> Self {
         Self {
             prog,
-            funcname: RefCell::new(String::new()),
+            out: Vec::new(),
+            sink: BufWriter::new(out),
             labelseq: 0,
-            brkseq: 0,
-            contseq: 0
-        }
-    }
-
-    pub fn codegen(&mut self) {
-        println!(".intel_syntax noprefix");
-        self.emit_data();
-        self.emit_text();
-    }
-
-    fn gen_expr(&mut self, expr_wrapper: &ExprWrapper) -> Result<(), String> {
-        match expr_wrapper.expr.as_ref() {
-            Expr::AddEq { var, val }
-            | Expr::PtrAddEq { var, val }
-            | Expr::SubEq { var, val }
-            | Expr::PtrSubEq { var, val }
-            | Expr::MulEq { var, val }
-            | Expr::DivEq { var, val } => {
-                self.gen_lval(var);
-                println!("  push [rsp]");
-                load(var.ty.as_ref());
-                self.gen_expr(val)?;
-                self.gen_binary(expr_wrapper);
-                store(expr_wrapper.ty.as_ref());
-            }
-            Expr::Add { lhs, rhs }
-            | Expr::PtrAdd { lhs, rhs }
-            | Expr::Sub { lhs, rhs }
-            | Expr::PtrSub { lhs, rhs }
-            | Expr::PtrDiff { lhs, rhs }
-            | Expr::Mul { lhs, rhs }
-            | Expr::Div { lhs, rhs }
-            | Expr::Eq { lhs, rhs }
-            | Expr::Neq { lhs, rhs }
-            | Expr::Lt { lhs, rhs }
-            | Expr::Le { lhs, rhs }
-            | Expr::BitAnd { lhs, rhs }
-            | Expr::BitOr { lhs, rhs }
-            | Expr::BitXor { lhs, rhs } => {
-                self.gen_expr(lhs)?;
-                self.gen_expr(rhs)?;
-
-                self.gen_binary(expr_wrapper)
-            }
-            | Expr::Gt { lhs, rhs }
-            | Expr::Ge { lhs, rhs } => {
-                self.gen_expr(rhs)?;
-                self.gen_expr(lhs)?;
-
-                self.gen_binary(expr_wrapper)
-            }
-            Expr::Num { val } => {
-                // `push` instraction cannot push a 64-bit integer. In order to push it,
-                // we have to first load a large integer to aregister using movabs and then
-                // push it to the stack
-                //
-                // if 64bit int is able to convert 32bit int, can be treated as 32bit
-                if i32::try_from(*val).is_ok() {
-                    println!("  push {}", val);
-                } else {
-                    println!("  movabs rax, {}", val);
-                    println!("  push rax");
-                }
-            },
-            Expr::Cast(ty, expr_wrapper) => {
-                self.gen_expr(expr_wrapper)?;
-                trancate(ty);
-            }
-            Expr::Var(_) => {
-                self.gen_addr(expr_wrapper);
-                match *expr_wrapper.ty {
-                    Type::Array { .. } => {},
-                    _ => { load(&expr_wrapper.ty); }
-                }
-            }
-            Expr::Assign { var, val, .. } => {
-                match *expr_wrapper.ty {
-                    Type::Array { .. } => { panic!("not an lvalue") },
-                    _ => { self.gen_addr(var); }
-                }
+            funcname: String::new(),
+            locs: Vec::new(),
+            peephole: None,
+            files: Vec::new(),
+            debug_info: None,
+            loc: None
+        }
+    }
 
-                self.gen_expr(val)?;
-                store(&expr_wrapper.ty);
-            }
-            Expr::PreInc(ew) => {
-                self.gen_lval(ew);
-                println!("  push [rsp]");
-                load(ew.ty.as_ref());
-                inc(ew.ty.as_ref());
-                store(ew.ty.as_ref());
-            }
-            Expr::PreDec(ew) => {
-                self.gen_lval(ew);
-                println!("  push [rsp]");
-                load(ew.ty.as_ref());
-                dec(ew.ty.as_ref());
-                store(ew.ty.as_ref());
-            }
-            Expr::PostInc(ew) => {
-                self.gen_lval(ew);
-                println!("  push [rsp]");
-                load(ew.ty.as_ref());
-                inc(ew.ty.as_ref());
-                store(ew.ty.as_ref());
-                dec(ew.ty.as_ref());
-            }
-            Expr::PostDec(ew) => {
-                self.gen_lval(ew);
-                println!("  push [rsp]");
-                load(ew.ty.as_ref());
-                dec(ew.ty.as_ref());
-                store(ew.ty.as_ref());
-                inc(ew.ty.as_ref());
-            }
-            Expr::Comma { lhs, rhs } => {
-                //println!("{:#?}", expr_wrapper);
-                self.gen_stmt(lhs)?;
-                self.gen_expr(rhs)?;
-            }
-            Expr::FnCall { fn_name, args, .. } => {
-                let arg_size = args.len();
-                for arg in args {
-                    self.gen_expr(arg)?;
-                }
+    pub fn enable_peephole(&mut self) {
+        self.peephole.get_or_insert_with(PeepholeStats::default);
+    }
 
-                // 引数をスタックにpushして各レジスタにpopすることで引数を渡す
-                // sub(2, 4)のとき
-                // push 2
-                // push 4
-                // ここで関数定義がsub(a, b)ならば，a -> rdi, b -> rsiになるようにすれば良い
-                // このように引数の順序を保持するには,使うレジスタの逆からpopする必要がある
-                // pop rsi -> 4
-                // pop rdi -> 2
-                // clang, gccでassemblyダンプしてみると何かわかるかもしれない
-                // cc -S -mllvm --x86-asm-syntax=intel assign.c -O0
-                for idx in (0..arg_size).rev() {
-                    println!("  pop {}", ARG_REG8[idx])
-                }
+    pub fn peephole_stats(&self) -> Option<&PeepholeStats> {
+        self.peephole.as_ref()
+    }
 
-                // ABIの仕様で関数呼び出しの前にRSPを(16の倍数)にする必要がある
-                // push, popは8バイト単位で変更を行うのでcall命令を行うときにスタックが(16の倍数)byteになっているとは限らない
-                // やりたいことは, RSP(スタックの先頭のポインタ)が16の倍数でなければ8を追加する(スタックの方向的にsub rsp, 8をする)
-                // RAX は variadic function のために0にセットする
-                // 「x86 関数呼び出し アライメント」でぐぐるといろいろ出てくる
-                self.labelseq += 1;
-
-                // and rax, 15
-                // 15 -> 00001111
-                // andの結果が5ビット目より下位ビットが立っている場合16で割り切れない事になる
-                // 5ビット目以上はandでは常に0になる（15のビットより）
-                // つまり16で割り切れる場合，andによってZF = 1になる(アライメントを調整しなくて良い)
-                // この場合, raxを0にセットしてcall命令を呼ぶだけ
-                // 16で割り切れない場合，ZF = 0より
-                // sub rsp, 8 mov rax, 0 をして関数を呼ぶ
-                println!("  mov rax, rsp");
-                println!("  and rax, 15"); // and: オペランドの論理積を計算し，第一引数に格納
-
-                // jnz: フラグレジスタのZFが0の時(比較の結果，等しくない)，adr[,x]のアドレスへ分岐(実行が移動)する
-                println!("  jnz .L.call.{}", self.labelseq);
-                println!("  mov rax, 0");
-                println!("  call {}", fn_name);
-                println!("  jmp .L.end.{}", self.labelseq);
-                println!(".L.call.{}:", self.labelseq);
-                println!("  sub rsp, 8");
-                println!("  mov rax, 0");
-
-                println!("  call {}", fn_name);
-
-                println!("  add rsp, 8");
-                println!(".L.end.{}:", self.labelseq);
-                println!("  push rax");
-            }
-            Expr::Addr { operand } => {
-                self.gen_addr(operand);
-            }
-            Expr::Deref { operand } => {
-                self.gen_expr(operand)?;
-                match *expr_wrapper.ty {
-                    Type::Array { .. } => {},
-                    _ => { load(&expr_wrapper.ty); }
-                }
+    // describe the program in DWARF for a debugger. `filename` is the source
+    // file, compiled in the directory `comp_dir`
+    pub fn enable_debug_info(&mut self, filename: &str, comp_dir: &str) {
+        self.debug_info = Some(dwarf::Unit { name: filename.to_string(), comp_dir: comp_dir.to_string() });
+    }
+
+    pub fn codegen(&mut self) -> Result<(), CodegenError> {
+        writeln!(self.out, ".intel_syntax noprefix")?;
+        self.emit_data()?;
+        self.flush(false)?;
+        self.emit_text()?;
+        if let Some(unit) = &self.debug_info {
+            write!(self.sink, "{}", dwarf::emit(self.prog, unit, &self.files))?;
+        }
+        // the stack is not executable
+        writeln!(self.sink, ".section .note.GNU-stack,\"\",@progbits")?;
+        self.sink.flush()?;
+
+        Ok(())
+    }
+
+    // write the buffered assembly to the sink, optimizing it if `optimize` is set
+    // and the peephole optimization is enabled
+    fn flush(&mut self, optimize: bool) -> Result<(), CodegenError> {
+        let stats = match &mut self.peephole {
+            Some(stats) if optimize => stats,
+            _ => {
+                self.sink.write_all(&self.out)?;
+                self.out.clear();
+                return Ok(())
             }
-            Expr::Not(target) => {
-                self.gen_expr(target)?;
-                println!("  pop rax");
-                println!("  cmp rax, 0");
-                println!("  sete al");
-                println!("  movzb rax, al");
-                println!("  push rax");
-            }
-            Expr::BitNot(target) => {
-                self.gen_expr(target)?;
-                println!("  pop rax");
-                println!("  not rax");
-                println!("  push rax");
-            }
-            Expr::LogAnd { lhs, rhs } => {
-                self.labelseq += 1;
-                let seq = self.labelseq;
-                self.gen_expr(lhs)?;
-                println!("  pop rax");
-                println!("  cmp rax, 0");
-                println!("  je .L.false.{}", seq);
-                self.gen_expr(rhs)?;
-                println!("  pop rax");
-                println!("  cmp rax, 0");
-                println!("  je .L.false.{}", seq);
-                println!("  push 1");
-                println!("  jmp .L.end.{}", seq);
-                println!(".L.false.{}:", seq);
-                println!("  push 0");
-                println!(".L.end.{}:", seq);
-            }
-            Expr::LogOr { lhs, rhs } => {
-                self.labelseq += 1;
-                let seq = self.labelseq;
-                self.gen_expr(lhs)?;
-                println!("  pop rax");
-                println!("  cmp rax, 0");
-                println!("  jne .L.true.{}", seq);
-                self.gen_expr(rhs)?;
-                println!("  pop rax");
-                println!("  cmp rax, 0");
-                println!("  jne .L.true.{}", seq);
-                println!("  push 0");
-                println!("  jmp .L.end.{}", seq);
-                println!(".L.true.{}:", seq);
-                println!("  push 1");
-                println!(".L.end.{}:", seq);
-            }
-            Expr::Null => {},
-            Expr::StmtExpr(stmts) => {
-                for stmt in stmts {
-                    self.gen_stmt(stmt)?;
+        };
+
+        let asm = String::from_utf8_lossy(&self.out);
+        let mut lines = peephole::parse(&asm);
+        stats.before += peephole::count(&lines);
+        peephole::optimize(&mut lines);
+        stats.after += peephole::count(&lines);
+
+        for line in &lines {
+            writeln!(self.sink, "{}", line)?;
+        }
+        self.out.clear();
+
+        Ok(())
+    }
+
+    fn emit_data(&mut self) -> Result<(), CodegenError> {
+        writeln!(self.out, ".data")?;
+        for v in &self.prog.globals {
+            let var = v.borrow();
+            writeln!(self.out, ".align {}", var.ty.align())?;
+            writeln!(self.out, "{}:", var.name)?;
+            if let Some(contents) = &var.contents {
+                for ch in contents {
+                    writeln!(self.out, "  .byte {}", ch)?;
                 }
-            },
-            Expr::Member(_, __) => {
-                self.gen_addr(expr_wrapper);
-                match *expr_wrapper.ty {
-                    Type::Array { .. } => {},
-                    _ => { load(&expr_wrapper.ty); }
+            } else if let Some(init) = &var.init {
+                for data in init {
+                    match data {
+                        Initializer::Val { sz: 1, val } => writeln!(self.out, "  .byte {}", val)?,
+                        Initializer::Val { sz: 2, val } => writeln!(self.out, "  .short {}", val)?,
+                        Initializer::Val { sz: 4, val } => writeln!(self.out, "  .long {}", val)?,
+                        Initializer::Val { val, .. } => writeln!(self.out, "  .quad {}", val)?,
+                        Initializer::Label { name, addend } => writeln!(self.out, "  .quad {}{:+}", name, addend)?,
+                        Initializer::Zero(sz) => writeln!(self.out, "  .zero {}", sz)?
+                    }
                 }
+            } else {
+                writeln!(self.out, "  .zero {}", var.ty.size())?;
             }
         }
+
         Ok(())
     }
 
-    fn gen_stmt(&mut self, stmt: &Stmt) -> Result<(), String> {
-        match stmt {
-            Stmt::Return { val } => {
-                self.gen_expr(val)?;
+    fn emit_text(&mut self) -> Result<(), CodegenError> {
+        writeln!(self.out, ".text")?;
+        if self.debug_info.is_some() {
+            writeln!(self.out, ".L.text.begin:")?;
+        }
+        for func in &self.prog.fns {
+            self.gen_function(func)?;
+        }
+        if self.debug_info.is_some() {
+            writeln!(self.out, ".L.text.end:")?;
+            self.flush(false)?;
+        }
 
-                println!("  pop rax");
-                println!("  jmp .L.return.{}", self.funcname.borrow().as_str());
+        Ok(())
+    }
+
+    fn gen_function(&mut self, func: &Function) -> Result<(), CodegenError> {
+        let alloc = regalloc::allocate(func);
+        self.funcname = func.name.to_string();
+        self.locs = alloc.locs;
+
+        // the frame has the local variables, the spill slots and the saved registers
+        // from the top. rsp is kept aligned to 16 bytes, as required at a call
+        let save_area = func.stack_size + alloc.spill_size;
+        let frame_size = align_to(save_area + alloc.saved.len() * 8, 16);
+
+        // the files are numbered before the function, so that the peephole
+        // optimization does not see `.file` between the instructions
+        let locs = func.loc.iter().chain(func.blocks.iter().flat_map(|block| block.insts.iter()).filter_map(|inst| match inst {
+            Inst::Loc(loc) => Some(loc),
+            _ => None
+        }));
+        for loc in locs {
+            if !self.files.contains(&loc.file) {
+                self.files.push(Rc::clone(&loc.file));
+                writeln!(self.out, ".file {} {}", self.files.len(), dwarf::quote(&loc.file))?;
             }
-            Stmt::ExprStmt { val } => {
-                self.gen_expr(val)?;
-                if let Expr::Null = *val.expr {
-                } else {
-                    println!("  add rsp, 8");
+        }
+
+        if !func.is_static {
+            writeln!(self.out, ".global {}", func.name)?;
+        }
+        writeln!(self.out, "{}:", func.name)?;
+        let cfi = self.debug_info.is_some();
+        if cfi {
+            writeln!(self.out, "  .cfi_startproc")?;
+        }
+        // the prologue is at the line of the function
+        if let Some(loc) = &func.loc {
+            self.gen_loc(loc)?;
+        }
+
+        // Prologue
+        writeln!(self.out, "  push rbp")?;
+        if cfi {
+            writeln!(self.out, "  .cfi_def_cfa_offset 16")?;
+            writeln!(self.out, "  .cfi_offset 6, -16")?;
+        }
+        writeln!(self.out, "  mov rbp, rsp")?;
+        if cfi {
+            writeln!(self.out, "  .cfi_def_cfa_register 6")?;
+        }
+        writeln!(self.out, "  sub rsp, {}", frame_size)?;
+        for (i, reg) in alloc.saved.iter().enumerate() {
+            writeln!(self.out, "  mov [rbp-{}], {}", save_area + (i + 1) * 8, reg)?;
+        }
+
+        for (id, block) in func.blocks.iter().enumerate() {
+            writeln!(self.out, "{}:", self.label(BlockId(id)))?;
+            for inst in &block.insts {
+                self.gen_inst(inst, &func.params)?;
+            }
+            let next = Some(BlockId(id + 1)).filter(|next| next.0 < func.blocks.len());
+            self.gen_term(&block.term, next, func.ret_regs.as_ref())?;
+        }
+
+        // Epilogue
+        writeln!(self.out, ".L.return.{}:", func.name)?;
+        for (i, reg) in alloc.saved.iter().enumerate() {
+            writeln!(self.out, "  mov {}, [rbp-{}]", reg, save_area + (i + 1) * 8)?;
+        }
+        writeln!(self.out, "  mov rsp, rbp")?;
+        writeln!(self.out, "  pop rbp")?;
+        if cfi {
+            writeln!(self.out, "  .cfi_def_cfa 7, 8")?;
+        }
+        writeln!(self.out, "  ret")?;
+        if cfi {
+            writeln!(self.out, "  .cfi_endproc")?;
+            writeln!(self.out, ".L.end.{}:", func.name)?;
+        }
+        self.flush(true)?;
+
+        Ok(())
+    }
+
+    fn gen_inst(&mut self, inst: &Inst, params: &[ArgTy]) -> Result<(), CodegenError> {
+        match inst {
+            Inst::Loc(loc) => self.gen_loc(loc)?,
+            Inst::Mov { dst, src, .. } => {
+                match self.loc(*dst) {
+                    Location::Reg(reg) => self.load_to(reg, *src)?,
+                    Location::Stack(offset) => {
+                        let src = match src {
+                            Operand::Imm(val) if i32::try_from(*val).is_ok() => val.to_string(),
+                            _ => self.reg_of(*src, "rax")?.to_string()
+                        };
+                        writeln!(self.out, "  mov qword ptr [rbp-{}], {}", offset, src)?;
+                    }
                 }
             }
-            Stmt::If { cond, then, els } => {
-                // if (A) B else Cのアセンブリ疑似コード
-                //   Aをコンパイルしたコード(この式の結果はstackにpushされているはず)
-                //   pop rax
-                //   cmp rax, 0
-                //   je .L.else.XXX (rax == 0 出なければjumpしない(Bが実行される))
-                //   Bをコンパイルしたコード
-                //   jmp .L.end.XXX (elseブロックに行かないようにjumpする)
-                // .L.else.XXX
-                //   Cをコンパイルしたコード
-                // .L.end.XXX
-
-                self.labelseq += 1;
-                let seq = self.labelseq;
-
-                self.gen_expr(cond)?;
-                // stackのトップにcondの結果が格納されているはず
-                println!("  pop rax");
-                // conditionの結果を0と比較する. (条件式が偽であることかをみている)
-                // 等しい場合, je .L.else.XXX でelse blockの処理に飛ぶ(jump equal)
-                println!("  cmp rax, 0");
-                // else block exist
-                if let Some(els_block) = els {
-                    println!("  je .L.else.{}", seq);
-                    self.gen_stmt(then)?;
-                    println!("  jmp .L.end.{}", seq);
-                    println!(".L.else.{}:", seq);
-                    self.gen_stmt(els_block)?;
-                    println!(".L.end.{}:", seq);
-                // not exist
-                } else {
-                    println!("  je .L.end.{}", seq);
-                    self.gen_stmt(then)?;
-                    println!(".L.end.{}:", seq);
+            Inst::Bin { op, ty, dst, lhs, rhs } => {
+                if ty.is_flonum() {
+                    return self.gen_flonum_binary(*op, *ty, *dst, *lhs, *rhs)
+                }
+
+                match op {
+                    BinOp::Div | BinOp::Rem => {
+                        // idiv命令は符号あり除算を行う命令
+                        // rdxとraxをとってそれを合わせたものを128bit整数とみなす
+                        // それを引数のレジスタの64bit整数で割り，商をrax, 余をrdxにセットする
+                        // cqo命令を使うと、RAXに入っている64ビットの値を128ビットに伸ばして
+                        // rdxとraxにセットすることができる
+                        // 符号なしの場合はrdxを0にしてdiv命令を使う
+                        let divisor = match rhs {
+                            Operand::Imm(_) => {
+                                self.load_to("rdi", *rhs)?;
+                                "rdi".to_string()
+                            },
+                            Operand::Reg(reg) => self.loc_str(*reg)
+                        };
+                        self.load_to("rax", *lhs)?;
+                        if ty.is_unsigned() {
+                            writeln!(self.out, "  mov rdx, 0")?;
+                            writeln!(self.out, "  div {}", divisor)?;
+                        } else {
+                            writeln!(self.out, "  cqo")?;
+                            writeln!(self.out, "  idiv {}", divisor)?;
+                        }
+                        let res = if *op == BinOp::Div { "rax" } else { "rdx" };
+                        self.truncate(res, *ty)?;
+                        self.set(*dst, res)?;
+                    }
+                    BinOp::Shl | BinOp::Shr => {
+                        let count = match rhs {
+                            Operand::Imm(val) => (val & 63).to_string(),
+                            Operand::Reg(_) => {
+                                self.load_to("rcx", *rhs)?;
+                                "cl".to_string()
+                            }
+                        };
+                        let work = self.work_reg(*dst, *rhs);
+                        self.load_to(work, *lhs)?;
+                        let mnemonic = match op {
+                            BinOp::Shl => "shl",
+                            _ if ty.is_unsigned() => "shr",
+                            _ => "sar"
+                        };
+                        writeln!(self.out, "  {} {}, {}", mnemonic, work, count)?;
+                        self.truncate(work, *ty)?;
+                        self.set(*dst, work)?;
+                    }
+                    _ => {
+                        let src = self.src_in(*rhs, "rdi")?;
+                        let work = self.work_reg(*dst, *rhs);
+                        self.load_to(work, *lhs)?;
+                        let mnemonic = match op {
+                            BinOp::Add => "add",
+                            BinOp::Sub => "sub",
+                            BinOp::Mul => "imul",
+                            BinOp::And => "and",
+                            BinOp::Or => "or",
+                            _ => "xor"
+                        };
+                        writeln!(self.out, "  {} {}, {}", mnemonic, work, src)?;
+                        // the result of arithmetic smaller than 64 bits wraps around
+                        self.truncate(work, *ty)?;
+                        self.set(*dst, work)?;
+                    }
                 }
             }
-            Stmt::While { cond, then } => {
-                self.labelseq += 1;
-                let seq = self.labelseq;
-                let brk = self.brkseq;
-                self.brkseq = seq;
-                let cont = self.contseq;
-                self.contseq = seq;
-
-                println!(".L.continue.{}:", seq);
-                let _ = self.gen_expr(cond);
-                println!("  pop rax");
-                println!("  cmp rax, 0");
-                println!("  je .L.break.{}", seq);
-
-                self.gen_stmt(then)?;
-                println!("  jmp .L.continue.{}", seq);
-                println!(".L.break.{}:", seq);
-
-                self.brkseq = brk;
-                self.contseq = cont;
-            }
-            Stmt::For { init, cond, inc, then } => {
-                self.labelseq += 1;
-                let seq = self.labelseq;
-                let brk = self.brkseq;
-                self.brkseq = seq;
-                let cont = self.contseq;
-                self.contseq = seq;
-
-                init.as_ref()
-                    .as_ref()
-                    .map(|stmt| self.gen_stmt(stmt));
-                println!(".L.begin.{}:", seq);
-
-                cond.as_ref().map(|x| {
-                    let _ = self.gen_expr(x);
-                    println!("  pop rax");
-                    println!("  cmp rax, 0");
-                    println!("  je .L.break.{}", seq);
-                });
-
-                self.gen_stmt(then)?;
-                println!(".L.continue.{}:", seq);
-
-                inc.as_ref()
-                    .as_ref()
-                    .map(|x| self.gen_stmt(x));
-                println!("  jmp .L.begin.{}", seq);
-                println!(".L.break.{}:", seq);
-
-                self.brkseq = brk;
-                self.contseq = cont;
-            }
-            Stmt::Block { stmts } => {
-                for stmt in stmts {
-                    self.gen_stmt(stmt)?;
+            Inst::Cmp { cond, ty, dst, lhs, rhs } => {
+                if ty.is_flonum() {
+                    return self.gen_flonum_cmp(*cond, *ty, *dst, *lhs, *rhs)
                 }
-            },
-            Stmt::PureExpr(expr_wrapper) => self.gen_expr(expr_wrapper)?,
-            Stmt::Break => {
-                if self.brkseq == 0 {
-                    return Err("stray break".to_string())
+
+                // cmp命令: 二つの引数のレジスタを比較して, フラグレジスタに結果を格納
+                // setcc命令: フラグレジスタの値を8bitのレジスタに格納する
+                // movzx命令: 上位bitを0埋めして書き込む
+                let src = self.src_in(*rhs, "rdi")?;
+                let lhs = self.reg_of(*lhs, "rax")?;
+                writeln!(self.out, "  cmp {}, {}", lhs, src)?;
+                let cc = match (cond, ty.is_unsigned()) {
+                    (Cond::Eq, _) => "e",
+                    (Cond::Ne, _) => "ne",
+                    (Cond::Lt, false) => "l",
+                    (Cond::Le, false) => "le",
+                    (Cond::Gt, false) => "g",
+                    (Cond::Ge, false) => "ge",
+                    (Cond::Lt, true) => "b",
+                    (Cond::Le, true) => "be",
+                    (Cond::Gt, true) => "a",
+                    (Cond::Ge, true) => "ae"
+                };
+                let res = self.dst_reg(*dst);
+                writeln!(self.out, "  set{} {}", cc, sized_reg(res, 1))?;
+                writeln!(self.out, "  movzx {}, {}", res, sized_reg(res, 1))?;
+                self.set(*dst, res)?;
+            }
+            Inst::Not { ty, dst, src } => {
+                let work = self.dst_reg(*dst);
+                self.load_to(work, *src)?;
+                writeln!(self.out, "  not {}", work)?;
+                self.truncate(work, *ty)?;
+                self.set(*dst, work)?;
+            }
+            Inst::Conv { from, to, dst, src } => self.gen_conv(*from, *to, *dst, *src)?,
+            Inst::Load { ty, dst, addr } => {
+                let addr = self.reg_of(Operand::Reg(*addr), "rax")?;
+                let work = self.dst_reg(*dst);
+                // unsigned values are zero-extended. writing to a 32-bit register clears the upper 32 bits
+                match ty {
+                    Ty::I8 => writeln!(self.out, "  movsx {}, byte ptr [{}]", work, addr)?,
+                    Ty::U8 => writeln!(self.out, "  movzx {}, byte ptr [{}]", work, addr)?,
+                    Ty::I16 => writeln!(self.out, "  movsx {}, word ptr [{}]", work, addr)?,
+                    Ty::U16 => writeln!(self.out, "  movzx {}, word ptr [{}]", work, addr)?,
+                    Ty::I32 => writeln!(self.out, "  movsxd {}, dword ptr [{}]", work, addr)?,
+                    Ty::U32 | Ty::F32 => writeln!(self.out, "  mov {}, dword ptr [{}]", sized_reg(work, 4), addr)?,
+                    Ty::I64 | Ty::U64 | Ty::F64 => writeln!(self.out, "  mov {}, qword ptr [{}]", work, addr)?
                 }
-                println!("  jmp .L.break.{}", self.brkseq);
+                self.set(*dst, work)?;
             }
-            Stmt::Continue => {
-                if self.contseq == 0 {
-                    return Err("stray continue".to_string())
+            Inst::Store { ty, addr, val } => {
+                let addr = self.reg_of(Operand::Reg(*addr), "rax")?;
+                let ptr = match ty.size() {
+                    1 => "byte",
+                    2 => "word",
+                    4 => "dword",
+                    _ => "qword"
+                };
+                match val {
+                    Operand::Imm(val) if i32::try_from(*val).is_ok() => {
+                        writeln!(self.out, "  mov {} ptr [{}], {}", ptr, addr, val)?;
+                    },
+                    _ => {
+                        let val = self.reg_of(*val, "rdi")?;
+                        writeln!(self.out, "  mov {} ptr [{}], {}", ptr, addr, sized_reg(val, ty.size()))?;
+                    }
                 }
-                println!("  jmp .L.continue.{}", self.contseq);
             }
-            Stmt::Goto(label_name) => {
-                println!("  jmp .L.label.{}.{}", self.funcname.borrow(), *label_name);
+            Inst::LocalAddr { dst, offset, .. } => {
+                // lea: アドレスのロード
+                let work = self.dst_reg(*dst);
+                writeln!(self.out, "  lea {}, [rbp-{}]", work, offset)?;
+                self.set(*dst, work)?;
             }
-            Stmt::Label(stmt, label_name) => {
-                println!(".L.label.{}.{}:", self.funcname.borrow(), *label_name);
-                self.gen_stmt(stmt)?;
+            Inst::GlobalAddr { dst, name } => {
+                let work = self.dst_reg(*dst);
+                writeln!(self.out, "  lea {}, [rip + {}]", work, name)?;
+                self.set(*dst, work)?;
             }
-        };
+            Inst::Param { ty, dst, idx } => {
+                let work = self.dst_reg(*dst);
+                match (&classify_args(params).locs[*idx], ty) {
+                    (ArgLoc::Fp(fp), Ty::F32) => writeln!(self.out, "  movd {}, xmm{}", sized_reg(work, 4), fp)?,
+                    (ArgLoc::Fp(fp), _) => writeln!(self.out, "  movq {}, xmm{}", work, fp)?,
+                    (ArgLoc::Gp(gp), _) => writeln!(self.out, "  mov {}, {}", work, ARG_REG8[*gp])?,
+                    // above the return address and the saved rbp
+                    (ArgLoc::Stack(slot), Ty::F32) => writeln!(self.out, "  mov {}, [rbp+{}]", sized_reg(work, 4), 16 + slot * 8)?,
+                    (ArgLoc::Stack(slot), _) => writeln!(self.out, "  mov {}, [rbp+{}]", work, 16 + slot * 8)?,
+                    (ArgLoc::Regs(_), _) => unreachable!()
+                }
+                // the upper bits of a register holding a small value are undefined
+                if !ty.is_flonum() {
+                    self.truncate(work, *ty)?;
+                }
+                self.set(*dst, work)?;
+            }
+            Inst::StructParam { idx, offset } => {
+                let dst = -(*offset as isize);
+                match (&classify_args(params).locs[*idx], &params[*idx]) {
+                    (ArgLoc::Regs(locs), ArgTy::Regs(regs)) => {
+                        // rax is used not to clobber the argument registers, which va_start may read
+                        for (i, loc) in locs.iter().enumerate() {
+                            let src = match loc {
+                                ArgLoc::Gp(gp) => {
+                                    writeln!(self.out, "  mov rax, {}", ARG_REG8[*gp])?;
+                                    "rax".to_string()
+                                },
+                                ArgLoc::Fp(fp) => format!("xmm{}", fp),
+                                _ => unreachable!()
+                            };
+                            self.store_bytes(&src, "rbp", dst + i as isize * 8, eightbyte_size(regs.size, i))?;
+                        }
+                    },
+                    (ArgLoc::Stack(slot), ArgTy::Regs(StructRegs { size, .. }))
+                    | (ArgLoc::Stack(slot), ArgTy::Memory(size)) => {
+                        self.copy_bytes(("rbp", 16 + *slot as isize * 8), ("rbp", dst), *size, "rax")?;
+                    },
+                    _ => unreachable!()
+                }
+            }
+            Inst::Call { ret, dst, callee, args, ret_buf } => {
+                let tys: Vec<ArgTy> = args.iter().map(|(ty, _)| ty.clone()).collect();
+                let ArgLocs { locs, fp, slots, .. } = classify_args(&tys);
 
-        Ok(())
-    }
+                // the arguments which do not fit in the registers are passed on the stack,
+                // the first one at the top. rsp stays aligned to 16 bytes.
+                // they are written first, while the argument registers are free
+                let stack_size = align_to(slots * 8, 16);
+                if stack_size > 0 {
+                    writeln!(self.out, "  sub rsp, {}", stack_size)?;
+                }
+                for ((ty, arg), loc) in args.iter().zip(&locs) {
+                    let slot = match loc {
+                        ArgLoc::Stack(slot) => *slot as isize,
+                        _ => continue
+                    };
+                    let src = self.reg_of(*arg, "rax")?;
+                    match ty {
+                        ArgTy::Scalar(_) => writeln!(self.out, "  mov [rsp+{}], {}", slot * 8, src)?,
+                        ArgTy::Regs(StructRegs { size, .. }) | ArgTy::Memory(size) => {
+                            self.copy_bytes((src, 0), ("rsp", slot * 8), *size, "rdx")?;
+                        }
+                    }
+                }
+                for ((ty, arg), loc) in args.iter().zip(&locs) {
+                    match (loc, ty) {
+                        (ArgLoc::Gp(gp), _) => self.load_to(ARG_REG8[*gp], *arg)?,
+                        (ArgLoc::Fp(i), _) => self.load_xmm(&format!("xmm{}", i), *arg)?,
+                        (ArgLoc::Regs(locs), ArgTy::Regs(regs)) => {
+                            let src = self.reg_of(*arg, "rax")?;
+                            for (i, loc) in locs.iter().enumerate() {
+                                let dst = match loc {
+                                    ArgLoc::Gp(gp) => ARG_REG8[*gp].to_string(),
+                                    ArgLoc::Fp(fp) => format!("xmm{}", fp),
+                                    _ => unreachable!()
+                                };
+                                self.load_bytes(&dst, src, i as isize * 8, eightbyte_size(regs.size, i))?;
+                            }
+                        },
+                        _ => {}
+                    }
+                }
 
-    fn gen_binary(&self, ew: &ExprWrapper) {
-        println!("  pop rdi");
-        println!("  pop rax");
+                // al holds the number of xmm registers used for a variadic function
+                writeln!(self.out, "  mov rax, {}", fp)?;
+                match callee {
+                    Callee::Direct(name) => writeln!(self.out, "  call {}", name)?,
+                    // the argument registers are taken, r11 is free at this point
+                    Callee::Indirect(op) => {
+                        let reg = self.reg_of(*op, "r11")?;
+                        writeln!(self.out, "  call {}", reg)?;
+                    }
+                }
+                if stack_size > 0 {
+                    writeln!(self.out, "  add rsp, {}", stack_size)?;
+                }
 
-        match ew.expr.as_ref() {
-            Expr::Add { .. } | Expr::AddEq { .. } => {
-                println!("  add rax, rdi");
-            }
-            Expr::PtrAdd { .. } | Expr::PtrAddEq { .. } => {
-                println!("  imul rdi, {}", ew.ty.base_size());
-                println!("  add rax, rdi");
-            }
-            Expr::Sub { .. } | Expr::SubEq { .. } => {
-                println!(" sub rax, rdi");
+                // the eightbytes of a struct are returned in rax and rdx, or xmm0 and xmm1
+                if let Some((regs, offset)) = ret_buf {
+                    for (i, src) in struct_ret_regs(regs).iter().enumerate() {
+                        let dst = -(*offset as isize) + i as isize * 8;
+                        self.store_bytes(src, "rbp", dst, eightbyte_size(regs.size, i))?;
+                    }
+                }
+
+                if let Some(dst) = dst {
+                    // a floating-point value is returned in xmm0
+                    match ret {
+                        Ty::F32 => writeln!(self.out, "  movd eax, xmm0")?,
+                        Ty::F64 => writeln!(self.out, "  movq rax, xmm0")?,
+                        _ => self.truncate("rax", *ret)?
+                    }
+                    self.set(*dst, "rax")?;
+                }
             }
-            Expr::PtrSub { .. } | Expr::PtrSubEq { .. } => {
-                println!("  imul rdi, {}", ew.ty.base_size());
-                println!("  sub rax, rdi");
+            Inst::MemZero { addr, size } => {
+                // rep stosb: fill rcx bytes from [rdi] with al
+                self.load_to("rdi", Operand::Reg(*addr))?;
+                writeln!(self.out, "  mov rcx, {}", size)?;
+                writeln!(self.out, "  mov al, 0")?;
+                writeln!(self.out, "  rep stosb")?;
             }
-            Expr::PtrDiff { lhs, .. } => {
-                println!("  sub rax, rdi");
-                println!("  cqo");
-                println!("  mov rdi, {}", lhs.ty.base_size());
-                println!("  idiv rdi");
+            Inst::MemCopy { dst, src, size } => {
+                // rep movsb: copy rcx bytes from [rsi] to [rdi]
+                self.load_to("rdi", Operand::Reg(*dst))?;
+                self.load_to("rsi", Operand::Reg(*src))?;
+                writeln!(self.out, "  mov rcx, {}", size)?;
+                writeln!(self.out, "  rep movsb")?;
             }
-            Expr::Mul { .. } | Expr::MulEq { .. } => {
-                println!("  imul rax, rdi");
+            Inst::VaArea { offset } => {
+                // the va_list starts after the named parameters
+                let ArgLocs { gp, fp, slots, .. } = classify_args(params);
+
+                // gp_offset, fp_offset, overflow_arg_area and reg_save_area
+                writeln!(self.out, "  mov dword ptr [rbp-{}], {}", offset, gp * 8)?;
+                writeln!(self.out, "  mov dword ptr [rbp-{}], {}", offset - 4, ARG_REG8.len() * 8 + fp * 16)?;
+                writeln!(self.out, "  lea rax, [rbp+{}]", 16 + slots * 8)?;
+                writeln!(self.out, "  mov [rbp-{}], rax", offset - 8)?;
+                writeln!(self.out, "  lea rax, [rbp-{}]", offset - 24)?;
+                writeln!(self.out, "  mov [rbp-{}], rax", offset - 16)?;
+
+                // the register save area
+                for (i, reg) in ARG_REG8.iter().enumerate() {
+                    writeln!(self.out, "  mov [rbp-{}], {}", offset - 24 - i * 8, reg)?;
+                }
+                for i in 0..FP_ARG_REGS {
+                    writeln!(self.out, "  movsd [rbp-{}], xmm{}", offset - 72 - i * 16, i)?;
+                }
             }
-            Expr::Div { .. } | Expr::DivEq { .. } => {
-                // idiv命令は符号あり除算を行う命令
-                // rdxとraxをとってそれを合わせたものを128bit整数とみなす
-                // それを引数のレジスタの64bit整数で割り，商をrax, 余をrdxにセットする
-                // cqo命令を使うと、RAXに入っている64ビットの値を128ビットに伸ばして
-                // rdxとraxにセットすることができる
-                println!("  cqo");
-                println!("  idiv rdi");
+        }
+
+        Ok(())
+    }
+
+    // `next` is the block placed right after the current one, to which no jump is needed
+    // `ret_regs` is the struct returned in registers by the function
+    fn gen_term(&mut self, term: &Terminator, next: Option<BlockId>, ret_regs: Option<&StructRegs>) -> Result<(), CodegenError> {
+        match term {
+            Terminator::Jmp(dst) => self.jump(*dst, next)?,
+            Terminator::Br { ty, cond: Operand::Imm(val), then, els } => {
+                let taken = match ty {
+                    Ty::F32 => f32::from_bits(*val as u32) != 0.0,
+                    Ty::F64 => f64::from_bits(*val as u64) != 0.0,
+                    _ => *val != 0
+                };
+                self.jump(if taken { *then } else { *els }, next)?;
             }
-            Expr::Eq { .. } => {
-                // cmp命令: 二つの引数のレジスタを比較して, フラグレジスタに結果を格納
-                // sete命令: 指定のレジスタにフラグレジスタの値を格納. seteであれば==の時1になる
-                //           8bitしか書き込めないのでalを指定している
-                //           setneは!=のとき1になる
-                // movzb命令: movzb dist, srcでsrcをdistに書き込む．またsrcで指定されたbitより上の桁は0埋めする
-                // al: raxの下位8bitのエイリアス. alを変更するとraxも変更される
-                println!("  cmp rax, rdi");
-                println!("  sete al");
-                println!("  movzb rax, al");
+            Terminator::Br { ty, cond, then, els } => {
+                if ty.is_flonum() {
+                    // NaN is true. ucomis sets PF if either operand is NaN
+                    let sfx = if *ty == Ty::F32 { "ss" } else { "sd" };
+                    self.load_xmm("xmm0", *cond)?;
+                    writeln!(self.out, "  xorpd xmm1, xmm1")?;
+                    writeln!(self.out, "  ucomi{} xmm0, xmm1", sfx)?;
+                    writeln!(self.out, "  jne {}", self.label(*then))?;
+                    writeln!(self.out, "  jp {}", self.label(*then))?;
+                    return self.jump(*els, next)
+                }
+
+                let cond = match cond {
+                    Operand::Reg(reg) => self.loc_str(*reg),
+                    _ => unreachable!()
+                };
+                writeln!(self.out, "  cmp {}, 0", cond)?;
+                if next == Some(*els) {
+                    writeln!(self.out, "  jne {}", self.label(*then))?;
+                } else {
+                    writeln!(self.out, "  je {}", self.label(*els))?;
+                    self.jump(*then, next)?;
+                }
             }
-            Expr::Neq { .. } => {
-                println!("  cmp rax, rdi");
-                println!("  setne al");
-                println!("  movzb rax, al");
+            Terminator::Switch { val, cases, default } => {
+                self.load_to("rax", *val)?;
+
+                let vals: Vec<i64> = cases.iter().map(|(val, _)| *val).collect();
+                if is_dense(&vals) {
+                    return self.gen_jump_table(cases, *default)
+                }
+                for (val, dst) in cases {
+                    if i32::try_from(*val).is_ok() {
+                        writeln!(self.out, "  cmp rax, {}", val)?;
+                    } else {
+                        writeln!(self.out, "  movabs rdi, {}", val)?;
+                        writeln!(self.out, "  cmp rax, rdi")?;
+                    }
+                    writeln!(self.out, "  je {}", self.label(*dst))?;
+                }
+                self.jump(*default, next)?;
             }
-            Expr::Gt { .. } => {
-                println!("  cmp rax, rdi");
-                println!("  setl al");
-                println!("  movzb rax, al");
+            Terminator::Ret(val) => {
+                // a floating-point value is returned in xmm0
+                match val {
+                    Some((_, val)) if ret_regs.is_some() => {
+                        let regs = ret_regs.unwrap();
+                        let src = self.reg_of(*val, "rcx")?;
+                        for (i, dst) in struct_ret_regs(regs).iter().enumerate() {
+                            self.load_bytes(dst, src, i as isize * 8, eightbyte_size(regs.size, i))?;
+                        }
+                    },
+                    Some((ty, val)) if ty.is_flonum() => self.load_xmm("xmm0", *val)?,
+                    Some((_, val)) => self.load_to("rax", *val)?,
+                    None => {}
+                }
+                // the epilogue follows the last block
+                if next.is_some() {
+                    writeln!(self.out, "  jmp .L.return.{}", self.funcname)?;
+                }
             }
-            Expr::Ge { .. } => {
-                println!("  cmp rax, rdi");
-                println!("  setle al");
-                println!("  movzb rax, al");
+        }
+
+        Ok(())
+    }
+
+    // rax holds the switch condition.
+    // the table has an entry for each value in min..=max, which is the offset
+    // from the table to the case block (or to the default block)
+    fn gen_jump_table(&mut self, cases: &[(i64, BlockId)], default: BlockId) -> Result<(), CodegenError> {
+        self.labelseq += 1;
+        let seq = self.labelseq;
+        let min = cases.iter().map(|(val, _)| *val).min().unwrap();
+        let max = cases.iter().map(|(val, _)| *val).max().unwrap();
+
+        if i32::try_from(min).is_ok() {
+            writeln!(self.out, "  sub rax, {}", min)?;
+        } else {
+            writeln!(self.out, "  movabs rdi, {}", min)?;
+            writeln!(self.out, "  sub rax, rdi")?;
+        }
+        // out of range values (including negative ones) are above max - min as unsigned
+        writeln!(self.out, "  cmp rax, {}", max - min)?;
+        writeln!(self.out, "  ja {}", self.label(default))?;
+        writeln!(self.out, "  lea rdi, [rip + .L.jtable.{}]", seq)?;
+        writeln!(self.out, "  movsxd rax, dword ptr [rdi + rax * 4]")?;
+        writeln!(self.out, "  add rax, rdi")?;
+        writeln!(self.out, "  jmp rax")?;
+
+        writeln!(self.out, ".section .rodata")?;
+        writeln!(self.out, ".align 4")?;
+        writeln!(self.out, ".L.jtable.{}:", seq)?;
+        for val in min ..= max {
+            let dst = cases.iter().find(|(v, _)| *v == val).map_or(default, |(_, dst)| *dst);
+            writeln!(self.out, "  .long {} - .L.jtable.{}", self.label(dst), seq)?;
+        }
+        writeln!(self.out, ".text")?;
+
+        Ok(())
+    }
+
+    fn gen_flonum_binary(&mut self, op: BinOp, ty: Ty, dst: Reg, lhs: Operand, rhs: Operand) -> Result<(), CodegenError> {
+        // sd: scalar double, ss: scalar single
+        let sfx = if ty == Ty::F32 { "ss" } else { "sd" };
+        let mnemonic = match op {
+            BinOp::Add => "add",
+            BinOp::Sub => "sub",
+            BinOp::Mul => "mul",
+            BinOp::Div => "div",
+            _ => return Err(self.error(format!("invalid operands to {}", op)))
+        };
+        self.load_xmm("xmm0", lhs)?;
+        self.load_xmm("xmm1", rhs)?;
+        writeln!(self.out, "  {}{} xmm0, xmm1", mnemonic, sfx)?;
+        self.xmm0_to_rax(ty)?;
+
+        self.set(dst, "rax")
+    }
+
+    fn gen_flonum_cmp(&mut self, cond: Cond, ty: Ty, dst: Reg, lhs: Operand, rhs: Operand) -> Result<(), CodegenError> {
+        let sfx = if ty == Ty::F32 { "ss" } else { "sd" };
+        self.load_xmm("xmm0", lhs)?;
+        self.load_xmm("xmm1", rhs)?;
+
+        // ucomis sets ZF, PF and CF if either operand is NaN (unordered).
+        // a < b is b > a, which is false for NaN
+        match cond {
+            Cond::Eq => {
+                writeln!(self.out, "  ucomi{} xmm0, xmm1", sfx)?;
+                writeln!(self.out, "  sete al")?;
+                writeln!(self.out, "  setnp dl")?;
+                writeln!(self.out, "  and al, dl")?;
+            },
+            Cond::Ne => {
+                writeln!(self.out, "  ucomi{} xmm0, xmm1", sfx)?;
+                writeln!(self.out, "  setne al")?;
+                writeln!(self.out, "  setp dl")?;
+                writeln!(self.out, "  or al, dl")?;
+            },
+            Cond::Lt | Cond::Le => {
+                writeln!(self.out, "  ucomi{} xmm1, xmm0", sfx)?;
+                writeln!(self.out, "  set{} al", if cond == Cond::Lt { "a" } else { "ae" })?;
+            },
+            Cond::Gt | Cond::Ge => {
+                writeln!(self.out, "  ucomi{} xmm0, xmm1", sfx)?;
+                writeln!(self.out, "  set{} al", if cond == Cond::Gt { "a" } else { "ae" })?;
             }
-            Expr::Lt { .. } => {
-                println!("  cmp rax, rdi");
-                println!("  setl al");
-                println!("  movzb rax, al");
+        }
+        writeln!(self.out, "  movzx rax, al")?;
+
+        self.set(dst, "rax")
+    }
+
+    fn gen_conv(&mut self, from: Ty, to: Ty, dst: Reg, src: Operand) -> Result<(), CodegenError> {
+        match (from.is_flonum(), to.is_flonum()) {
+            (false, false) => {
+                let work = self.dst_reg(dst);
+                self.load_to(work, src)?;
+                self.truncate(work, to)?;
+                return self.set(dst, work)
+            },
+            (true, true) => {
+                self.load_xmm("xmm0", src)?;
+                match (from, to) {
+                    (Ty::F64, Ty::F32) => writeln!(self.out, "  cvtsd2ss xmm0, xmm0")?,
+                    (Ty::F32, Ty::F64) => writeln!(self.out, "  cvtss2sd xmm0, xmm0")?,
+                    _ => {}
+                }
+                self.xmm0_to_rax(to)?;
+            },
+            // int -> floating-point
+            (false, true) => {
+                let sfx = if to == Ty::F32 { "ss" } else { "sd" };
+                self.load_to("rax", src)?;
+                writeln!(self.out, "  cvtsi2{} xmm0, rax", sfx)?;
+                if from == Ty::U64 {
+                    // a value above i64::MAX is halved (keeping the lowest bit for rounding),
+                    // converted and doubled
+                    writeln!(self.out, "  test rax, rax")?;
+                    writeln!(self.out, "  jns 1f")?;
+                    writeln!(self.out, "  mov rdi, rax")?;
+                    writeln!(self.out, "  shr rdi, 1")?;
+                    writeln!(self.out, "  and eax, 1")?;
+                    writeln!(self.out, "  or rdi, rax")?;
+                    writeln!(self.out, "  cvtsi2{} xmm0, rdi", sfx)?;
+                    writeln!(self.out, "  add{} xmm0, xmm0", sfx)?;
+                    writeln!(self.out, "1:")?;
+                }
+                self.xmm0_to_rax(to)?;
+            },
+            // floating-point -> int. cvtt truncates toward zero
+            (true, false) => {
+                let sfx = if from == Ty::F32 { "ss" } else { "sd" };
+                self.load_xmm("xmm0", src)?;
+                writeln!(self.out, "  cvtt{}2si rax, xmm0", sfx)?;
+                if to == Ty::U64 {
+                    // a value not below 2^63 is reduced by 2^63, converted, and
+                    // the highest bit is set back
+                    let two_63 = if from == Ty::F32 { 0x5f00_0000u64 } else { 0x43e0_0000_0000_0000 };
+                    writeln!(self.out, "  mov rdi, {}", two_63)?;
+                    writeln!(self.out, "  movq xmm1, rdi")?;
+                    writeln!(self.out, "  comi{} xmm0, xmm1", sfx)?;
+                    writeln!(self.out, "  jb 1f")?;
+                    writeln!(self.out, "  sub{} xmm0, xmm1", sfx)?;
+                    writeln!(self.out, "  cvtt{}2si rax, xmm0", sfx)?;
+                    writeln!(self.out, "  btc rax, 63")?;
+                    writeln!(self.out, "1:")?;
+                }
+                self.truncate("rax", to)?;
             }
-            Expr::Le { .. } => {
-                println!("  cmp rax, rdi");
-                println!("  setle al");
-                println!("  movzb rax, al");
+        }
+
+        self.set(dst, "rax")
+    }
+
+    // move a floating-point value of type `ty` from xmm0 to rax
+    fn xmm0_to_rax(&mut self, ty: Ty) -> Result<(), CodegenError> {
+        match ty {
+            Ty::F32 => writeln!(self.out, "  movd eax, xmm0")?,
+            _ => writeln!(self.out, "  movq rax, xmm0")?
+        }
+
+        Ok(())
+    }
+}
+
+// a switch statement is lowered to a jump table if its case values
+// are close enough to each other. otherwise a chain of compares is used
+fn is_dense(cases: &[i64]) -> bool {
+    if cases.len() < 4 {
+        return false
+    }
+
+    let min = *cases.iter().min().unwrap() as i128;
+    let max = *cases.iter().max().unwrap() as i128;
+
+    max - min < (cases.len() * 3) as i128
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ArgLoc {
+    Gp(usize),
+    Fp(usize),
+    // the eightbytes of a struct, each in Gp or Fp
+    Regs(Vec<ArgLoc>),
+    // the index of the first 8-byte slot on the stack
+    Stack(usize)
+}
+
+// where the arguments are passed, and the numbers of the general purpose
+// registers, the xmm registers and the stack slots used
+struct ArgLocs {
+    locs: Vec<ArgLoc>,
+    gp: usize,
+    fp: usize,
+    slots: usize
+}
+
+// floating-point arguments are passed in xmm0 - xmm7 and the others in general
+// purpose registers, in order of each kind. a struct is passed in registers only
+// if all of its eightbytes fit. the rest are passed on the stack in order
+fn classify_args(tys: &[ArgTy]) -> ArgLocs {
+    let (mut gp, mut fp, mut slots) = (0, 0, 0);
+
+    let locs = tys.iter().map(|ty| {
+        let eightbytes = match ty {
+            ArgTy::Scalar(ty) => vec![*ty],
+            ArgTy::Regs(regs) => regs.eightbytes.clone(),
+            ArgTy::Memory(size) => {
+                slots += size.div_ceil(8);
+                return ArgLoc::Stack(slots - size.div_ceil(8))
             }
-            Expr::BitAnd { .. } => {
-                println!("  and rax, rdi");
+        };
+
+        let nfp = eightbytes.iter().filter(|ty| ty.is_flonum()).count();
+        if gp + eightbytes.len() - nfp > ARG_REG8.len() || fp + nfp > FP_ARG_REGS {
+            slots += eightbytes.len();
+            return ArgLoc::Stack(slots - eightbytes.len())
+        }
+        let mut locs: Vec<ArgLoc> = eightbytes.iter().map(|ty| {
+            if ty.is_flonum() {
+                fp += 1;
+                ArgLoc::Fp(fp - 1)
+            } else {
+                gp += 1;
+                ArgLoc::Gp(gp - 1)
             }
-            Expr::BitOr { .. } => {
-                println!("  or rax, rdi");
+        }).collect();
+
+        match ty {
+            ArgTy::Scalar(_) => locs.remove(0),
+            _ => ArgLoc::Regs(locs)
+        }
+    }).collect();
+
+    ArgLocs { locs, gp, fp, slots }
+}
+
+// the registers in which the eightbytes of a struct are returned
+fn struct_ret_regs(regs: &StructRegs) -> Vec<&'static str> {
+    let (mut gp, mut fp) = (["rax", "rdx"].iter(), ["xmm0", "xmm1"].iter());
+    regs.eightbytes.iter()
+        .map(|ty| if ty.is_flonum() { fp.next() } else { gp.next() })
+        .map(|reg| *reg.unwrap())
+        .collect()
+}
+
+// the size of the i-th eightbyte of a struct, which may be short at the end
+fn eightbyte_size(size: usize, i: usize) -> usize {
+    (size - i * 8).min(8)
+}
+
+// a memory operand such as [rbp-16]
+fn mem(base: &str, offset: isize) -> String {
+    match offset {
+        0 => format!("[{}]", base),
+        _ => format!("[{}{:+}]", base, offset)
+    }
+}
+
+// the name of the lower `size` bytes of a 64-bit register
+fn sized_reg(reg: &str, size: usize) -> String {
+    match (reg, size) {
+        (_, 8) => reg.to_string(),
+        // r8 - r15
+        (_, 1) if reg[1..].parse::<usize>().is_ok() => format!("{}b", reg),
+        (_, 2) if reg[1..].parse::<usize>().is_ok() => format!("{}w", reg),
+        (_, _) if reg[1..].parse::<usize>().is_ok() => format!("{}d", reg),
+        // rax, rbx, rcx, rdx
+        (_, _) if reg.ends_with('x') => {
+            let c = &reg[1..2];
+            match size {
+                1 => format!("{}l", c),
+                2 => format!("{}x", c),
+                _ => format!("e{}x", c)
             }
-            Expr::BitXor { .. } => {
-                println!("  xor rax, rdi");
+        },
+        // rdi, rsi
+        (_, _) => {
+            let c = &reg[1..];
+            match size {
+                1 => format!("{}l", c),
+                2 => c.to_string(),
+                _ => format!("e{}", c)
             }
-            _ => unreachable!()
         }
+    }
+}
+
+impl<'a, W: Write> CodeGenerator<'a, W> {
+    // the following instructions are at `loc` in the source. the file is numbered by gen_function
+    fn gen_loc(&mut self, loc: &Loc) -> Result<(), CodegenError> {
+        let file = self.files.iter().position(|file| *file == loc.file).unwrap() + 1;
+        writeln!(self.out, "  .loc {} {} {}", file, loc.row, loc.col)?;
+        self.loc = Some(loc.clone());
 
-        println!("  push rax");
+        Ok(())
+    }
+
+    // an error in the statement at the last `.loc`
+    fn error(&self, msg: String) -> CodegenError {
+        CodegenError::Invalid(Diagnostic::new(Severity::Error, msg, self.loc.clone().map(|loc| Span::new(loc, 1))))
+    }
+
+    fn label(&self, block: BlockId) -> String {
+        format!(".L.{}.{}", self.funcname, block)
     }
 
-    fn gen_lval(&mut self, ew: &ExprWrapper) {
-        if let Type::Array { .. } = *ew.ty {
-            panic!("not an lvalue");
+    fn jump(&mut self, dst: BlockId, next: Option<BlockId>) -> Result<(), CodegenError> {
+        if next != Some(dst) {
+            writeln!(self.out, "  jmp {}", self.label(dst))?;
         }
-        self.gen_addr(ew)
+
+        Ok(())
+    }
+
+    fn loc(&self, reg: Reg) -> Location {
+        self.locs[reg.0].unwrap_or_else(|| panic!("{} is not allocated", reg))
     }
 
-    // pushes the given node's address to the stack
-    fn gen_addr(&mut self, expr_wrapper: &ExprWrapper) {
-        match expr_wrapper.expr.as_ref() {
-            Expr::Deref { operand } => {
-                let _ = self.gen_expr(operand);
+    // a register or a memory operand
+    fn loc_str(&self, reg: Reg) -> String {
+        match self.loc(reg) {
+            Location::Reg(reg) => reg.to_string(),
+            Location::Stack(offset) => format!("qword ptr [rbp-{}]", offset)
+        }
+    }
+
+    // the register to compute the value of `dst` in. it is the register of `dst`
+    // if it has one, otherwise rax
+    fn dst_reg(&self, dst: Reg) -> &'static str {
+        match self.loc(dst) {
+            Location::Reg(reg) => reg,
+            Location::Stack(_) => "rax"
+        }
+    }
+
+    // same as dst_reg, but the register must not hold `src`, which is read after
+    // the register is written
+    fn work_reg(&self, dst: Reg, src: Operand) -> &'static str {
+        match (self.loc(dst), src) {
+            (Location::Reg(reg), Operand::Reg(src)) if self.loc(src) == Location::Reg(reg) => "rax",
+            (Location::Reg(reg), _) => reg,
+            (Location::Stack(_), _) => "rax"
+        }
+    }
+
+    // an operand which can be the source of an arithmetic instruction, that is, a register,
+    // a memory operand or a 32-bit immediate. otherwise the value is loaded to `scratch`
+    fn src_in(&mut self, op: Operand, scratch: &'static str) -> Result<String, CodegenError> {
+        match op {
+            Operand::Imm(val) if i32::try_from(val).is_ok() => Ok(val.to_string()),
+            Operand::Imm(_) => {
+                self.load_to(scratch, op)?;
+                Ok(scratch.to_string())
+            },
+            Operand::Reg(reg) => Ok(self.loc_str(reg))
+        }
+    }
+
+    // a register which holds the value. a value not in a register is loaded to `scratch`
+    fn reg_of(&mut self, op: Operand, scratch: &'static str) -> Result<&'static str, CodegenError> {
+        if let Operand::Reg(reg) = op {
+            if let Location::Reg(reg) = self.loc(reg) {
+                return Ok(reg)
             }
-            Expr::Var(var) => {
-                if var.borrow().is_local {
-                    // lea: アドレスのロード
-                    println!("  lea rax, [rbp-{}]", var.borrow().offset.value());
-                    println!("  push rax");
-                } else {
-                    println!("  push offset {}", var.borrow().name);
+        }
+        self.load_to(scratch, op)?;
+
+        Ok(scratch)
+    }
+
+    fn load_to(&mut self, dst: &str, op: Operand) -> Result<(), CodegenError> {
+        match op {
+            Operand::Imm(val) if i32::try_from(val).is_ok() => writeln!(self.out, "  mov {}, {}", dst, val)?,
+            // mov cannot take a 64-bit immediate
+            Operand::Imm(val) => writeln!(self.out, "  movabs {}, {}", dst, val)?,
+            Operand::Reg(reg) => {
+                let src = self.loc_str(reg);
+                if src != dst {
+                    writeln!(self.out, "  mov {}, {}", dst, src)?;
                 }
-            },
-            Expr::Member(ew, member) => {
-                self.gen_addr(ew);
-                println!("  pop rax");
-                println!("  add rax, {}", member.offset.value());
-                println!("  push rax");
-            },
+            }
+        }
+
+        Ok(())
+    }
+
+    // load `size` bytes at [base+offset] to a register, zero-extended.
+    // a piece of a struct is loaded byte by byte, not to read past its end
+    fn load_bytes(&mut self, dst: &str, base: &str, offset: isize, size: usize) -> Result<(), CodegenError> {
+        match size {
+            _ if dst.starts_with("xmm") && size <= 4 => writeln!(self.out, "  movss {}, dword ptr {}", dst, mem(base, offset))?,
+            _ if dst.starts_with("xmm") => writeln!(self.out, "  movsd {}, qword ptr {}", dst, mem(base, offset))?,
+            8 => writeln!(self.out, "  mov {}, qword ptr {}", dst, mem(base, offset))?,
+            4 => writeln!(self.out, "  mov {}, dword ptr {}", sized_reg(dst, 4), mem(base, offset))?,
+            2 => writeln!(self.out, "  movzx {}, word ptr {}", dst, mem(base, offset))?,
             _ => {
-                panic!("unexpected operand {:?}", expr_wrapper);
+                let last = offset + size as isize - 1;
+                writeln!(self.out, "  movzx {}, byte ptr {}", dst, mem(base, last))?;
+                for i in (offset..last).rev() {
+                    writeln!(self.out, "  shl {}, 8", dst)?;
+                    writeln!(self.out, "  mov {}, byte ptr {}", sized_reg(dst, 1), mem(base, i))?;
+                }
             }
         }
+
+        Ok(())
     }
 
-    fn emit_data(&self) {
-        println!(".data");
-        self.prog.globals.iter().for_each(|v| {
-            let var = v.borrow();
-            println!("{}:", var.name);
-            if let Some(contents) = &var.contents {
-                contents.iter().for_each(|ch| {
-                    println!("  .byte {}", ch);
-                });
-            } else {
-                println!("  .zero {}", var.ty.size());
+    // store the lower `size` bytes of a register to [base+offset].
+    // a general purpose register is clobbered if it is stored in pieces
+    fn store_bytes(&mut self, src: &str, base: &str, offset: isize, size: usize) -> Result<(), CodegenError> {
+        if src.starts_with("xmm") {
+            let op = if size <= 4 { "movss dword ptr" } else { "movsd qword ptr" };
+            writeln!(self.out, "  {} {}, {}", op, mem(base, offset), src)?;
+            return Ok(())
+        }
+
+        let mut done = 0;
+        while done < size {
+            let n = [8, 4, 2, 1].iter().copied().find(|n| *n <= size - done).unwrap();
+            writeln!(self.out, "  mov {}, {}", mem(base, offset + done as isize), sized_reg(src, n))?;
+            done += n;
+            if done < size {
+                writeln!(self.out, "  shr {}, {}", src, n * 8)?;
             }
-        });
+        }
+
+        Ok(())
+    }
+
+    // copy `size` bytes through the register `tmp`
+    fn copy_bytes(&mut self, src: (&str, isize), dst: (&str, isize), size: usize, tmp: &str) -> Result<(), CodegenError> {
+        let mut done = 0;
+        while done < size {
+            let n = [8, 4, 2, 1].iter().copied().find(|n| *n <= size - done).unwrap();
+            let reg = sized_reg(tmp, n);
+            writeln!(self.out, "  mov {}, {}", reg, mem(src.0, src.1 + done as isize))?;
+            writeln!(self.out, "  mov {}, {}", mem(dst.0, dst.1 + done as isize), reg)?;
+            done += n;
+        }
+
+        Ok(())
     }
 
-    fn emit_text(&mut self) {
-        println!(".text");
-        self.prog.fns.iter().for_each(|func| {
-            let mut node_iter = func.nodes.iter();
-            *self.funcname.borrow_mut() = func.name.to_string();
-            let funcname = self.funcname.borrow().to_string();
-            if !func.is_static {
-                println!(".global {}", funcname);
+    fn load_xmm(&mut self, xmm: &str, op: Operand) -> Result<(), CodegenError> {
+        let src = match op {
+            Operand::Reg(reg) => self.loc_str(reg),
+            Operand::Imm(_) => {
+                self.load_to("rax", op)?;
+                "rax".to_string()
             }
-            println!("{}:", funcname);
+        };
+        writeln!(self.out, "  movq {}, {}", xmm, src)?;
 
-            // Prologue
-            println!("  push rbp");
-            println!("  mov rbp, rsp");
-            println!("  sub rsp, {}", func.stack_size);
+        Ok(())
+    }
 
-            func.params.iter().enumerate().for_each(|(i, var)| {
-                load_arg(&*var.borrow(), i);
-            });
+    // write `src` to the location of `dst`
+    fn set(&mut self, dst: Reg, src: &str) -> Result<(), CodegenError> {
+        match self.loc(dst) {
+            Location::Reg(reg) if reg == src => {},
+            Location::Reg(reg) => writeln!(self.out, "  mov {}, {}", reg, src)?,
+            Location::Stack(offset) => writeln!(self.out, "  mov qword ptr [rbp-{}], {}", offset, src)?
+        }
 
-            while let Some(node) = node_iter.next() {
-                let _ = self.gen_stmt(node);
-            };
+        Ok(())
+    }
 
-            // Epilogue
-            // 最後の式の結果がRAXに残っているのでそれが返り値になる
-            println!(".L.return.{}:", funcname);
-            println!("  mov rsp, rbp");
-            println!("  pop rbp");
-            println!("  ret");
-        });
+    // sign- or zero-extend the lower bits of `reg` as a value of `ty`
+    fn truncate(&mut self, reg: &str, ty: Ty) -> Result<(), CodegenError> {
+        match ty {
+            Ty::I8 => writeln!(self.out, "  movsx {}, {}", reg, sized_reg(reg, 1))?,
+            Ty::U8 => writeln!(self.out, "  movzx {}, {}", reg, sized_reg(reg, 1))?,
+            Ty::I16 => writeln!(self.out, "  movsx {}, {}", reg, sized_reg(reg, 2))?,
+            Ty::U16 => writeln!(self.out, "  movzx {}, {}", reg, sized_reg(reg, 2))?,
+            Ty::I32 => writeln!(self.out, "  movsxd {}, {}", reg, sized_reg(reg, 4))?,
+            Ty::U32 => writeln!(self.out, "  mov {}, {}", sized_reg(reg, 4), sized_reg(reg, 4))?,
+            _ => {}
+        }
+
+        Ok(())
     }
 }
 
-fn load_arg(var: &Var, idx: usize) {
-    match var.ty.size() {
-        1 => println!("  mov [rbp-{}], {}", var.offset.value(), ARG_REG1[idx]),
-        2 => println!("  mov [rbp-{}], {}", var.offset.value(), ARG_REG2[idx]),
-        4 => println!("  mov [rbp-{}], {}", var.offset.value(), ARG_REG4[idx]),
-        8 => println!("  mov [rbp-{}], {}", var.offset.value(), ARG_REG8[idx]),
-        x => unreachable!(format!("{} is unexpected byte size of type", x))
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::tokenizer::Tokenizer;
+
+    // the assembly of the program without optimizations
+    fn compile(input: &str) -> String {
+        let tokens = Tokenizer::new(input.to_string(), "test.c").tokenize().expect("cannot tokenize");
+        let ast = Parser::new(&tokens).parse().unwrap_or_else(|errors| panic!("cannot parse: {}", errors[0].message));
+        let prog = ir::lower(&ast).expect("cannot lower");
+
+        let mut asm = Vec::new();
+        CodeGenerator::new(&prog, &mut asm).codegen().expect("cannot generate the assembly");
+
+        String::from_utf8(asm).unwrap()
     }
-}
 
-fn load(ty: &Type) {
-    println!("  pop rax");
-    match ty.size() {
-        1 => println!("  movsx rax, byte ptr [rax]"),
-        2 => println!("  movsx rax, word ptr [rax]"),
-        4 => println!("  movsxd rax, dword ptr [rax]"),
-        8 => println!("  mov rax, [rax]"),
-        x => unreachable!(format!("{} is unexpected byte size of type", x))
-    };
-
-    println!("  push rax");
-}
+    // a sink which fails on every write
+    struct Broken;
 
-fn store(ty: &Type) {
-    println!("  pop rdi");
-    println!("  pop rax");
-
-    if let Type::Bool = ty {
-        // bool
-        // => 0         = 0
-        //    otherwise = 1
-        // 0と比較して一致しないときゼロフラグがセットされるのでこれをdilに格納
-        // またゼロ拡張したいのでmovzb rdi, dilする
-        println!("  cmp rdi, 0");
-        println!("  setne dil"); // dilはrdiの下位8bit, raxのalみたいなもん
-        println!("  movzb rdi, dil");
-    }
-
-    match ty.size() {
-        1 => println!("  mov [rax], dil"),
-        2 => println!("  mov [rax], di"),
-        4 => println!("  mov [rax], edi"),
-        8 => println!("  mov [rax], rdi"),
-        x => unreachable!(format!("{} is unexpected byte size of type", x))
-    };
-
-    println!("  push rdi");
-}
+    impl Write for Broken {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(io::Error::other("broken"))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn has_inst(asm: &str, inst: &str) -> bool {
+        asm.lines().any(|line| line.trim_start().starts_with(inst))
+    }
 
-fn trancate(ty: &Type) {
-    println!("  pop rax");
+    // the whole assembly is in the sink when codegen returns: the data, then
+    // each function in the order of the source
+    #[test]
+    fn writes_to_sink() {
+        let asm = compile("int g = 3; int f(int x) { return x+g; } int main() { return f(2); }");
+        assert!(asm.starts_with(".intel_syntax noprefix\n.data\n"), "{}", asm);
+        assert!(asm.ends_with(".section .note.GNU-stack,\"\",@progbits\n"), "{}", asm);
 
-    if let Type::Bool = ty {
-        println!("  cmp rax, 0");
-        println!("  setne al");
+        let labels: Vec<&str> = asm.lines().filter(|line| !line.starts_with('.') && line.ends_with(':')).collect();
+        assert_eq!(labels, ["g:", "f:", "main:"], "{}", asm);
+        assert!(has_inst(&asm, ".long 3"), "{}", asm);
     }
 
-    match ty.size() {
-        1 => println!("  movsx rax, al"),
-        2 => println!("  movsx rax, ax"),
-        4 => println!("  movsxd rax, eax"),
-        _ => {}
+    #[test]
+    fn write_error_is_reported() {
+        let tokens = Tokenizer::new("int main() { return 0; }".to_string(), "test.c").tokenize().unwrap();
+        let prog = ir::lower(&Parser::new(&tokens).parse().unwrap()).unwrap();
+
+        let result = CodeGenerator::new(&prog, Broken).codegen();
+        assert!(matches!(result, Err(CodegenError::Io(_))));
     }
 
-    println!("  push rax");
-}
+    #[test]
+    fn signed_and_unsigned_comparison() {
+        let asm = compile("int f(int x, int y) { return x>y; }");
+        assert!(has_inst(&asm, "setg "), "{}", asm);
+        let asm = compile("int f(int x, int y) { return x>=y; }");
+        assert!(has_inst(&asm, "setge "), "{}", asm);
 
-fn inc(ty: &Type) {
-    println!("  pop rax");
-    let sz = if ty.has_base() {
-        ty.base_size()
-    } else {
-        1
-    };
-    println!("  add rax, {}", sz);
-    println!("  push rax");
-}
+        let asm = compile("int f(unsigned x, unsigned y) { return x>y; }");
+        assert!(has_inst(&asm, "seta "), "{}", asm);
+        let asm = compile("int f(unsigned x, unsigned y) { return x>=y; }");
+        assert!(has_inst(&asm, "setae "), "{}", asm);
+    }
+
+    #[test]
+    fn signed_and_unsigned_shift() {
+        let asm = compile("int f(int x, int y) { return x>>y; }");
+        assert!(has_inst(&asm, "sar "), "{}", asm);
+        let asm = compile("unsigned f(unsigned x, int y) { return x>>y; }");
+        assert!(has_inst(&asm, "shr "), "{}", asm);
+        let asm = compile("int f(int x, int y) { return x<<y; }");
+        assert!(has_inst(&asm, "shl "), "{}", asm);
+    }
 
-fn dec(ty: &Type) {
-    println!("  pop rax");
-    let sz = if ty.has_base() {
-        ty.base_size()
-    } else {
-        1
-    };
-    println!("  sub rax, {}", sz);
-    println!("  push rax");
+    #[test]
+    fn modulo() {
+        let asm = compile("int f(int x, int y) { return x%y; }");
+        assert!(has_inst(&asm, "idiv "), "{}", asm);
+        let asm = compile("unsigned f(unsigned x, unsigned y) { return x%y; }");
+        assert!(has_inst(&asm, "div "), "{}", asm);
+    }
+
+    // the temporaries of nested binary expressions are kept in registers, so the
+    // only push and pop are those of rbp in the prologue and the epilogue
+    #[test]
+    fn temporaries_in_registers() {
+        let asm = compile("int f(int a, int b, int c) { return (a+b)*(a-c)/(b+c*a) - (a<<b|c); }");
+        let stack: Vec<&str> = asm.lines()
+            .map(str::trim_start)
+            .filter(|line| line.starts_with("push ") || line.starts_with("pop "))
+            .collect();
+        assert_eq!(stack, ["push rbp", "pop rbp"], "{}", asm);
+    }
+
+    #[test]
+    fn calls() {
+        let asm = compile("int g(); int f() { return g(); }");
+        assert!(has_inst(&asm, "call g"), "{}", asm);
+
+        // a call through a pointer is indirect
+        let asm = compile("int f(int (*fp)(int)) { return fp(1); }");
+        let indirect = asm.lines()
+            .filter_map(|line| line.trim_start().strip_prefix("call "))
+            .any(|callee| callee.starts_with('r'));
+        assert!(indirect, "{}", asm);
+    }
+
+    #[test]
+    fn source_locations() {
+        let asm = compile("int f(int x) {\n  int y = x;\n  return y;\n}");
+        assert!(has_inst(&asm, ".file 1 \"test.c\""), "{}", asm);
+        // the prologue is at the function name, and each statement at its first token
+        let locs: Vec<&str> = asm.lines().map(str::trim_start).filter(|line| line.starts_with(".loc ")).collect();
+        assert_eq!(locs, [".loc 1 1 5", ".loc 1 2 3", ".loc 1 3 3"], "{}", asm);
+        // the debug information is written only when it is enabled
+        assert!(!has_inst(&asm, ".cfi_startproc") && !asm.contains(".debug_info"), "{}", asm);
+    }
 }
@@ -0,0 +1,474 @@
+use crate::ir::{ Function, Program };
+use crate::program::{ Var, Offset };
+use crate::_type::{ Type, Member };
+
+use std::fmt::Write;
+use std::rc::Rc;
+
+// DWARF 4 debug information for -g, written as assembler directives.
+//
+// .debug_info has a single compile unit with a subprogram for each function,
+// whose parameters and local variables are located by their offsets from rbp.
+// the line table in .debug_line is made by the assembler from the `.file` and
+// `.loc` directives, so only a label marking its start is written here. the
+// abbreviations are made as the entries need them
+
+const DW_TAG_ARRAY_TYPE: u64 = 0x01;
+const DW_TAG_FORMAL_PARAMETER: u64 = 0x05;
+const DW_TAG_MEMBER: u64 = 0x0d;
+const DW_TAG_POINTER_TYPE: u64 = 0x0f;
+const DW_TAG_COMPILE_UNIT: u64 = 0x11;
+const DW_TAG_STRUCTURE_TYPE: u64 = 0x13;
+const DW_TAG_SUBROUTINE_TYPE: u64 = 0x15;
+const DW_TAG_UNION_TYPE: u64 = 0x17;
+const DW_TAG_UNSPECIFIED_PARAMETERS: u64 = 0x18;
+const DW_TAG_SUBRANGE_TYPE: u64 = 0x21;
+const DW_TAG_BASE_TYPE: u64 = 0x24;
+const DW_TAG_SUBPROGRAM: u64 = 0x2e;
+const DW_TAG_VARIABLE: u64 = 0x34;
+
+const DW_AT_LOCATION: u64 = 0x02;
+const DW_AT_NAME: u64 = 0x03;
+const DW_AT_BYTE_SIZE: u64 = 0x0b;
+const DW_AT_STMT_LIST: u64 = 0x10;
+const DW_AT_LOW_PC: u64 = 0x11;
+const DW_AT_HIGH_PC: u64 = 0x12;
+const DW_AT_LANGUAGE: u64 = 0x13;
+const DW_AT_COMP_DIR: u64 = 0x1b;
+const DW_AT_PRODUCER: u64 = 0x25;
+const DW_AT_PROTOTYPED: u64 = 0x27;
+const DW_AT_COUNT: u64 = 0x37;
+const DW_AT_DATA_MEMBER_LOCATION: u64 = 0x38;
+const DW_AT_DECL_FILE: u64 = 0x3a;
+const DW_AT_DECL_LINE: u64 = 0x3b;
+const DW_AT_DECLARATION: u64 = 0x3c;
+const DW_AT_ENCODING: u64 = 0x3e;
+const DW_AT_EXTERNAL: u64 = 0x3f;
+const DW_AT_FRAME_BASE: u64 = 0x40;
+const DW_AT_TYPE: u64 = 0x49;
+const DW_AT_BIT_SIZE: u64 = 0x0d;
+const DW_AT_DATA_BIT_OFFSET: u64 = 0x6b;
+
+const DW_ATE_BOOLEAN: u8 = 0x02;
+const DW_ATE_FLOAT: u8 = 0x04;
+const DW_ATE_SIGNED: u8 = 0x05;
+const DW_ATE_SIGNED_CHAR: u8 = 0x06;
+const DW_ATE_UNSIGNED: u8 = 0x07;
+const DW_ATE_UNSIGNED_CHAR: u8 = 0x08;
+
+const DW_LANG_C99: u8 = 0x0c;
+
+const DW_OP_ADDR: u8 = 0x03;
+const DW_OP_BREG6: u8 = 0x76;
+const DW_OP_FBREG: u8 = 0x91;
+
+// the source file which is compiled, and the directory it is compiled in
+#[derive(Debug, Clone)]
+pub(in super) struct Unit {
+    pub name: String,
+    pub comp_dir: String
+}
+
+// the value of an attribute, which also decides its form
+enum Value {
+    Str(String),
+    Data1(u8),
+    Udata(u64),
+    // a reference to another entry by its label
+    Ref(String),
+    Addr(String),
+    // an expression of 8 bytes, such as the difference of two labels
+    Data8(String),
+    SecOffset(String),
+    Flag,
+    Expr(Vec<u8>),
+    // DW_OP_addr with the address of a symbol
+    AddrExpr(String)
+}
+
+impl Value {
+    fn form(&self) -> u64 {
+        match self {
+            Value::Addr(_) => 0x01,
+            Value::Data8(_) => 0x07,
+            Value::Str(_) => 0x08,
+            Value::Data1(_) => 0x0b,
+            Value::Udata(_) => 0x0f,
+            Value::Ref(_) => 0x13,
+            Value::SecOffset(_) => 0x17,
+            Value::Expr(_) | Value::AddrExpr(_) => 0x18,
+            Value::Flag => 0x19
+        }
+    }
+}
+
+// an abbreviation is a tag, whether the entry has children, and the attributes with their forms
+type Abbrev = (u64, bool, Vec<(u64, u64)>);
+
+struct DebugInfo<'a> {
+    files: &'a [Rc<String>],
+    abbrevs: Vec<Abbrev>,
+    // the entries of the subprograms and the global variables
+    info: String,
+    // the entries of the types, which are children of the compile unit
+    types: String,
+    // the labels of the type entries
+    type_labels: Vec<(Type, String)>
+}
+
+// the debug sections of the program. `files` are the source files numbered by `.file`, from 1
+pub(in super) fn emit(prog: &Program, unit: &Unit, files: &[Rc<String>]) -> String {
+    let mut debug = DebugInfo {
+        files,
+        abbrevs: Vec::new(),
+        info: String::new(),
+        types: String::new(),
+        type_labels: Vec::new()
+    };
+
+    let cu = debug.entry(DW_TAG_COMPILE_UNIT, true, vec![
+        (DW_AT_PRODUCER, Value::Str("rust_chibicc".to_string())),
+        (DW_AT_LANGUAGE, Value::Data1(DW_LANG_C99)),
+        (DW_AT_NAME, Value::Str(unit.name.clone())),
+        (DW_AT_COMP_DIR, Value::Str(unit.comp_dir.clone())),
+        (DW_AT_LOW_PC, Value::Addr(".L.text.begin".to_string())),
+        (DW_AT_HIGH_PC, Value::Data8(".L.text.end-.L.text.begin".to_string())),
+        (DW_AT_STMT_LIST, Value::SecOffset(".L.debug_line".to_string()))
+    ]);
+    debug.info.push_str(&cu);
+    for var in &prog.globals {
+        debug.global(&var.borrow());
+    }
+    for func in &prog.fns {
+        debug.subprogram(func);
+    }
+    let types = std::mem::take(&mut debug.types);
+    debug.info.push_str(&types);
+    debug.info.push_str("  .byte 0\n");
+
+    let mut out = String::new();
+    out.push_str(".section .debug_abbrev,\"\",@progbits\n");
+    out.push_str(".L.debug_abbrev:\n");
+    for (i, (tag, children, attrs)) in debug.abbrevs.iter().enumerate() {
+        let _ = writeln!(out, "  .uleb128 {}", i + 1);
+        let _ = writeln!(out, "  .uleb128 {}", tag);
+        let _ = writeln!(out, "  .byte {}", *children as u8);
+        for (at, form) in attrs {
+            let _ = writeln!(out, "  .uleb128 {}", at);
+            let _ = writeln!(out, "  .uleb128 {}", form);
+        }
+        out.push_str("  .byte 0\n  .byte 0\n");
+    }
+    out.push_str("  .byte 0\n");
+
+    // a reference is the offset from the start of the unit, which is .L.debug_info
+    out.push_str(".section .debug_info,\"\",@progbits\n");
+    out.push_str(".L.debug_info:\n");
+    out.push_str("  .long .L.debug_info.end-.L.debug_info.start\n");
+    out.push_str(".L.debug_info.start:\n");
+    out.push_str("  .short 4\n");
+    out.push_str("  .long .L.debug_abbrev\n");
+    out.push_str("  .byte 8\n");
+    out.push_str(&debug.info);
+    out.push_str(".L.debug_info.end:\n");
+
+    out.push_str(".section .debug_line,\"\",@progbits\n");
+    out.push_str(".L.debug_line:\n");
+
+    out
+}
+
+impl<'a> DebugInfo<'a> {
+    fn abbrev(&mut self, tag: u64, children: bool, attrs: &[(u64, Value)]) -> usize {
+        let abbrev = (tag, children, attrs.iter().map(|(at, val)| (*at, val.form())).collect());
+        match self.abbrevs.iter().position(|a| *a == abbrev) {
+            Some(i) => i + 1,
+            None => {
+                self.abbrevs.push(abbrev);
+                self.abbrevs.len()
+            }
+        }
+    }
+
+    // the directives of an entry. the children of an entry which has them follow
+    // it, and end with `.byte 0`
+    fn entry(&mut self, tag: u64, children: bool, attrs: Vec<(u64, Value)>) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "  .uleb128 {}", self.abbrev(tag, children, &attrs));
+        for (_, val) in attrs {
+            let _ = match val {
+                Value::Str(s) => writeln!(out, "  .string {}", quote(&s)),
+                Value::Data1(val) => writeln!(out, "  .byte {}", val),
+                Value::Udata(val) => writeln!(out, "  .uleb128 {}", val),
+                Value::Ref(label) => writeln!(out, "  .long {}-.L.debug_info", label),
+                Value::Addr(label) | Value::Data8(label) => writeln!(out, "  .quad {}", label),
+                Value::SecOffset(label) => writeln!(out, "  .long {}", label),
+                Value::Flag => Ok(()),
+                Value::Expr(bytes) => {
+                    let _ = writeln!(out, "  .uleb128 {}", bytes.len());
+                    bytes.iter().try_for_each(|b| writeln!(out, "  .byte {}", b))
+                },
+                Value::AddrExpr(name) => writeln!(out, "  .uleb128 9\n  .byte {}\n  .quad {}", DW_OP_ADDR, name)
+            };
+        }
+
+        out
+    }
+
+    fn global(&mut self, var: &Var) {
+        // string literals have no name in the source
+        if var.name.starts_with(".L.") {
+            return
+        }
+
+        let mut attrs = vec![(DW_AT_NAME, Value::Str(var.name.clone()))];
+        attrs.extend(self.decl(var));
+        attrs.extend(self.type_ref(&var.ty).map(|label| (DW_AT_TYPE, Value::Ref(label))));
+        attrs.push((DW_AT_LOCATION, Value::AddrExpr(var.name.clone())));
+        let entry = self.entry(DW_TAG_VARIABLE, false, attrs);
+        self.info.push_str(&entry);
+    }
+
+    fn subprogram(&mut self, func: &Function) {
+        let mut attrs = Vec::new();
+        if !func.is_static {
+            attrs.push((DW_AT_EXTERNAL, Value::Flag));
+        }
+        attrs.push((DW_AT_NAME, Value::Str(func.name.to_string())));
+        if let Some(loc) = &func.loc {
+            attrs.push((DW_AT_DECL_FILE, Value::Udata(self.file(&loc.file))));
+            attrs.push((DW_AT_DECL_LINE, Value::Udata(loc.row as u64)));
+        }
+        if let Type::Func { ret, .. } = func.ty.as_ref() {
+            attrs.extend(self.type_ref(ret).map(|label| (DW_AT_TYPE, Value::Ref(label))));
+        }
+        attrs.push((DW_AT_LOW_PC, Value::Addr(func.name.to_string())));
+        attrs.push((DW_AT_HIGH_PC, Value::Data8(format!(".L.end.{}-{}", func.name, func.name))));
+        attrs.push((DW_AT_FRAME_BASE, Value::Expr(vec![DW_OP_BREG6, 0])));
+
+        let vars: Vec<String> = func.param_vars.iter().map(|var| (DW_TAG_FORMAL_PARAMETER, var))
+            .chain(func.local_vars.iter().map(|var| (DW_TAG_VARIABLE, var)))
+            .filter_map(|(tag, var)| self.local(tag, &var.borrow(), &func.promoted))
+            .collect();
+        let entry = self.entry(DW_TAG_SUBPROGRAM, !vars.is_empty(), attrs);
+        self.info.push_str(&entry);
+        if !vars.is_empty() {
+            vars.iter().for_each(|var| self.info.push_str(var));
+            self.info.push_str("  .byte 0\n");
+        }
+    }
+
+    // a variable is at its offset below the frame base, which is rbp. the
+    // variables made by the compiler are left out. a variable promoted to a
+    // register has no location, which a debugger shows as optimized out
+    fn local(&mut self, tag: u64, var: &Var, promoted: &[usize]) -> Option<String> {
+        let offset = match (&var.loc, &var.offset) {
+            (Some(_), Offset::Value(offset)) if !var.name.is_empty() => *offset as i64,
+            _ => return None
+        };
+
+        let mut attrs = vec![(DW_AT_NAME, Value::Str(var.name.clone()))];
+        attrs.extend(self.decl(var));
+        attrs.extend(self.type_ref(&var.ty).map(|label| (DW_AT_TYPE, Value::Ref(label))));
+        if !promoted.contains(&(offset as usize)) {
+            let mut expr = vec![DW_OP_FBREG];
+            expr.extend(sleb128(-offset));
+            attrs.push((DW_AT_LOCATION, Value::Expr(expr)));
+        }
+
+        Some(self.entry(tag, false, attrs))
+    }
+
+    fn decl(&mut self, var: &Var) -> Vec<(u64, Value)> {
+        match &var.loc {
+            Some(loc) => vec![
+                (DW_AT_DECL_FILE, Value::Udata(self.file(&loc.file))),
+                (DW_AT_DECL_LINE, Value::Udata(loc.row as u64))
+            ],
+            None => vec![]
+        }
+    }
+
+    // the number of the file in the line table. 0 is no file
+    fn file(&self, file: &Rc<String>) -> u64 {
+        self.files.iter().position(|f| f == file).map_or(0, |i| i as u64 + 1)
+    }
+
+    // the label of the entry of a type, which is written when the type is seen
+    // first. void has no entry
+    fn type_ref(&mut self, ty: &Type) -> Option<String> {
+        // a struct through its tag is the same as the struct
+        let resolved;
+        let ty = match ty {
+            Type::Void => return None,
+            Type::IncompleteStruct(tag) => match tag.get() {
+                Some(ty) => {
+                    resolved = ty;
+                    &resolved
+                },
+                None => ty
+            },
+            _ => ty
+        };
+        if let Some((_, label)) = self.type_labels.iter().find(|(t, _)| t == ty) {
+            return Some(label.clone())
+        }
+
+        // the label is known before the entry is written, so that a struct can
+        // refer to itself through a pointer
+        let label = format!(".L.debug_type.{}", self.type_labels.len());
+        self.type_labels.push((ty.clone(), label.clone()));
+        let entry = self.type_entry(ty);
+        let _ = write!(self.types, "{}:\n{}", label, entry);
+
+        Some(label)
+    }
+
+    fn type_entry(&mut self, ty: &Type) -> String {
+        let (name, encoding) = match ty {
+            Type::Char => ("char", DW_ATE_SIGNED_CHAR),
+            Type::UChar => ("unsigned char", DW_ATE_UNSIGNED_CHAR),
+            Type::Short => ("short", DW_ATE_SIGNED),
+            Type::UShort => ("unsigned short", DW_ATE_UNSIGNED),
+            Type::UInt => ("unsigned int", DW_ATE_UNSIGNED),
+            Type::Long => ("long", DW_ATE_SIGNED),
+            Type::ULong => ("unsigned long", DW_ATE_UNSIGNED),
+            Type::Float => ("float", DW_ATE_FLOAT),
+            Type::Double => ("double", DW_ATE_FLOAT),
+            Type::Bool => ("_Bool", DW_ATE_BOOLEAN),
+            Type::Ptr { base } => {
+                let mut attrs = vec![(DW_AT_BYTE_SIZE, Value::Udata(8))];
+                attrs.extend(self.type_ref(base).map(|label| (DW_AT_TYPE, Value::Ref(label))));
+                return self.entry(DW_TAG_POINTER_TYPE, false, attrs)
+            },
+            Type::Array { base, is_incomplete, len } => {
+                let base = self.type_ref(base);
+                let index = self.type_ref(&Type::ULong);
+                let mut attrs = vec![];
+                attrs.extend(index.map(|label| (DW_AT_TYPE, Value::Ref(label))));
+                if !*is_incomplete {
+                    attrs.push((DW_AT_COUNT, Value::Udata(*len as u64)));
+                }
+                let subrange = self.entry(DW_TAG_SUBRANGE_TYPE, false, attrs);
+                let array = self.entry(DW_TAG_ARRAY_TYPE, true, base.map(|label| (DW_AT_TYPE, Value::Ref(label))).into_iter().collect());
+                return format!("{}{}  .byte 0\n", array, subrange)
+            },
+            Type::Struct { members, size, is_union, name, .. } => {
+                let tag = if *is_union { DW_TAG_UNION_TYPE } else { DW_TAG_STRUCTURE_TYPE };
+                let members: Vec<String> = members.iter().map(|member| self.member(member)).collect();
+                let mut attrs: Vec<_> = name.iter().map(|name| (DW_AT_NAME, Value::Str(name.to_string()))).collect();
+                attrs.push((DW_AT_BYTE_SIZE, Value::Udata(*size as u64)));
+                let mut out = self.entry(tag, !members.is_empty(), attrs);
+                if !members.is_empty() {
+                    members.iter().for_each(|member| out.push_str(member));
+                    out.push_str("  .byte 0\n");
+                }
+                return out
+            },
+            // a struct declared but not defined has no members
+            Type::IncompleteStruct(tag) => return self.entry(DW_TAG_STRUCTURE_TYPE, false, vec![
+                (DW_AT_NAME, Value::Str(tag.name().to_string())),
+                (DW_AT_DECLARATION, Value::Flag)
+            ]),
+            Type::Func { ret, params, is_variadic } => {
+                let mut attrs = vec![(DW_AT_PROTOTYPED, Value::Flag)];
+                attrs.extend(self.type_ref(ret).map(|label| (DW_AT_TYPE, Value::Ref(label))));
+                let mut children = String::new();
+                for param in params {
+                    let attrs = self.type_ref(param).map(|label| (DW_AT_TYPE, Value::Ref(label))).into_iter().collect();
+                    children.push_str(&self.entry(DW_TAG_FORMAL_PARAMETER, false, attrs));
+                }
+                if *is_variadic {
+                    children.push_str(&self.entry(DW_TAG_UNSPECIFIED_PARAMETERS, false, vec![]));
+                }
+                let mut out = self.entry(DW_TAG_SUBROUTINE_TYPE, !children.is_empty(), attrs);
+                if !children.is_empty() {
+                    out.push_str(&children);
+                    out.push_str("  .byte 0\n");
+                }
+                return out
+            },
+            // an enum is an int
+            Type::Int | Type::Enum | Type::Dummy | Type::Void => ("int", DW_ATE_SIGNED)
+        };
+
+        self.entry(DW_TAG_BASE_TYPE, false, vec![
+            (DW_AT_NAME, Value::Str(name.to_string())),
+            (DW_AT_ENCODING, Value::Data1(encoding)),
+            (DW_AT_BYTE_SIZE, Value::Udata(ty.size() as u64))
+        ])
+    }
+
+    // a bit-field is located by bits from the start of the struct
+    fn member(&mut self, member: &Member) -> String {
+        let mut attrs = Vec::new();
+        if !member.name.is_empty() {
+            attrs.push((DW_AT_NAME, Value::Str(member.name.clone())));
+        }
+        attrs.extend(self.type_ref(&member.ty).map(|label| (DW_AT_TYPE, Value::Ref(label))));
+        let offset = match member.offset {
+            Offset::Value(offset) => offset as u64,
+            Offset::Unset => 0
+        };
+        match member.bit_field {
+            Some(bf) => {
+                attrs.push((DW_AT_BIT_SIZE, Value::Udata(bf.width as u64)));
+                attrs.push((DW_AT_DATA_BIT_OFFSET, Value::Udata(offset * 8 + bf.offset as u64)));
+            },
+            None => attrs.push((DW_AT_DATA_MEMBER_LOCATION, Value::Udata(offset)))
+        }
+
+        self.entry(DW_TAG_MEMBER, false, attrs)
+    }
+}
+
+// a string literal for the assembler
+pub(in super) fn quote(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' | '\\' => {
+                out.push('\\');
+                out.push(c);
+            },
+            '\n' => out.push_str("\\n"),
+            _ => out.push(c)
+        }
+    }
+    out.push('"');
+
+    out
+}
+
+fn sleb128(mut val: i64) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    loop {
+        let byte = (val & 0x7f) as u8;
+        val >>= 7;
+        if (val == 0 && byte & 0x40 == 0) || (val == -1 && byte & 0x40 != 0) {
+            bytes.push(byte);
+            return bytes
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signed_leb128() {
+        assert_eq!(sleb128(0), vec![0x00]);
+        assert_eq!(sleb128(2), vec![0x02]);
+        assert_eq!(sleb128(-2), vec![0x7e]);
+        assert_eq!(sleb128(127), vec![0xff, 0x00]);
+        assert_eq!(sleb128(-128), vec![0x80, 0x7f]);
+    }
+
+    #[test]
+    fn quoted_strings() {
+        assert_eq!(quote("a.c"), "\"a.c\"");
+        assert_eq!(quote("a\"b\\c"), "\"a\\\"b\\\\c\"");
+    }
+}
@@ -0,0 +1,523 @@
+use std::convert::TryFrom;
+use std::fmt;
+
+// peephole optimization over the assembly of a function.
+//
+// the assembly is parsed to a list of lines, and a short sequence of
+// instructions is rewritten to a shorter one:
+//   - `mov r, r` is removed
+//   - a write to a register which is never read is removed
+//   - `lea r, [m]` followed by an access to `[r]` accesses `[m]` directly
+//   - `mov a, b; mov c, a` becomes `mov c, b`
+//   - `push a; pop b` becomes `mov b, a`
+//   - instructions after `jmp`, up to the next label, are removed
+//   - a jump to the label right after it is removed
+// a register is dead if it is overwritten before being read in the straight
+// code which follows. any register is considered live at a label, a jump or
+// a directive. `.loc` is not a boundary: it is kept before the instructions
+// which replace the ones around it
+
+// general purpose registers in 64, 32, 16 and 8 bits
+const GP_REGS: [[&str; 4]; 16] = [
+    ["rax", "eax", "ax", "al"],
+    ["rbx", "ebx", "bx", "bl"],
+    ["rcx", "ecx", "cx", "cl"],
+    ["rdx", "edx", "dx", "dl"],
+    ["rsi", "esi", "si", "sil"],
+    ["rdi", "edi", "di", "dil"],
+    ["rbp", "ebp", "bp", "bpl"],
+    ["rsp", "esp", "sp", "spl"],
+    ["r8", "r8d", "r8w", "r8b"],
+    ["r9", "r9d", "r9w", "r9b"],
+    ["r10", "r10d", "r10w", "r10b"],
+    ["r11", "r11d", "r11w", "r11b"],
+    ["r12", "r12d", "r12w", "r12b"],
+    ["r13", "r13d", "r13w", "r13b"],
+    ["r14", "r14d", "r14w", "r14b"],
+    ["r15", "r15d", "r15w", "r15b"]
+];
+const XMM_REGS: [&str; 16] = [
+    "xmm0", "xmm1", "xmm2", "xmm3", "xmm4", "xmm5", "xmm6", "xmm7",
+    "xmm8", "xmm9", "xmm10", "xmm11", "xmm12", "xmm13", "xmm14", "xmm15"
+];
+
+// register families. xmm registers are 16 - 31
+const RAX: usize = 0;
+const RCX: usize = 2;
+const RDX: usize = 3;
+const RSI: usize = 4;
+const RDI: usize = 5;
+const RBP: usize = 6;
+const RSP: usize = 7;
+const R8: usize = 8;
+const R9: usize = 9;
+const XMM0: usize = 16;
+
+// a call reads the arguments and al, the number of vector registers used
+const CALL_READS: [usize; 15] = [RAX, RDI, RSI, RDX, RCX, R8, R9, XMM0, XMM0 + 1, XMM0 + 2, XMM0 + 3, XMM0 + 4, XMM0 + 5, XMM0 + 6, XMM0 + 7];
+
+// instructions which write their destination without reading it
+const WRITE_ONLY: [&str; 10] = ["mov", "movabs", "movsx", "movsxd", "movzx", "lea", "movd", "movq", "cvttsd2si", "cvttss2si"];
+
+// a machine register. registers sharing the same storage, such as rax, eax,
+// ax and al, are in the same family
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(in super) struct Register {
+    pub name: &'static str,
+    pub family: usize,
+    // bytes
+    pub size: usize
+}
+
+impl Register {
+    fn from_name(name: &str) -> Option<Self> {
+        for (family, names) in GP_REGS.iter().enumerate() {
+            if let Some(i) = names.iter().position(|n| *n == name) {
+                return Some(Register { name: names[i], family, size: 8 >> i })
+            }
+        }
+
+        XMM_REGS.iter().position(|n| *n == name)
+            .map(|i| Register { name: XMM_REGS[i], family: XMM0 + i, size: 16 })
+    }
+}
+
+// caller-saved registers are clobbered by a call
+fn is_caller_saved(family: usize) -> bool {
+    match family {
+        RAX | RCX | RDX | RSI | RDI | R8 | R9 | 10 | 11 => true,
+        _ => family >= XMM0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(in super) enum Arg {
+    Reg(Register),
+    Imm(i64),
+    // a memory operand such as `dword ptr [rbp-8]`. `addr` is the part in the brackets
+    Mem { ptr: Option<String>, addr: String },
+    // anything else, such as a label or `offset sym`
+    Sym(String)
+}
+
+impl Arg {
+    fn parse(s: &str) -> Self {
+        if let Some(reg) = Register::from_name(s) {
+            return Arg::Reg(reg)
+        }
+        if let Ok(val) = s.parse() {
+            return Arg::Imm(val)
+        }
+        if let (Some(open), true) = (s.find('['), s.ends_with(']')) {
+            let ptr = s[..open].trim();
+            return Arg::Mem {
+                ptr: Some(ptr.to_string()).filter(|ptr| !ptr.is_empty()),
+                addr: s[open + 1..s.len() - 1].to_string()
+            }
+        }
+
+        Arg::Sym(s.to_string())
+    }
+
+    // the register families read to evaluate the operand
+    fn regs(&self) -> Vec<usize> {
+        match self {
+            Arg::Reg(reg) => vec![reg.family],
+            Arg::Mem { addr, .. } => {
+                addr.split(|c: char| !c.is_ascii_alphanumeric())
+                    .filter_map(Register::from_name)
+                    .map(|reg| reg.family)
+                    .collect()
+            },
+            _ => vec![]
+        }
+    }
+
+    fn is_reg64(&self) -> bool {
+        match self {
+            Arg::Reg(reg) => reg.size == 8,
+            _ => false
+        }
+    }
+
+    // [reg]
+    fn is_at(&self, reg: &Register) -> bool {
+        match self {
+            Arg::Mem { addr, .. } => addr == reg.name,
+            _ => false
+        }
+    }
+}
+
+impl fmt::Display for Arg {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Arg::Reg(reg) => write!(f, "{}", reg.name),
+            Arg::Imm(val) => write!(f, "{}", val),
+            Arg::Mem { ptr: Some(ptr), addr } => write!(f, "{} [{}]", ptr, addr),
+            Arg::Mem { ptr: None, addr } => write!(f, "[{}]", addr),
+            Arg::Sym(sym) => write!(f, "{}", sym)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(in super) struct Insn {
+    pub op: String,
+    pub args: Vec<Arg>
+}
+
+impl Insn {
+    fn new(op: &str, args: Vec<Arg>) -> Self {
+        Insn { op: op.to_string(), args }
+    }
+
+    fn is_jump(&self) -> bool {
+        self.op.starts_with('j') || self.op == "ret"
+    }
+
+    // the register whose whole value is written without being read. writing
+    // a 32-bit register clears its upper half, but writing a smaller one does not
+    fn overwritten(&self) -> Option<Register> {
+        match (self.op.as_str(), self.args.first()) {
+            (op, Some(Arg::Reg(reg))) if WRITE_ONLY.contains(&op) && reg.size >= 4 => Some(*reg),
+            ("pop", Some(Arg::Reg(reg))) => Some(*reg),
+            _ => None
+        }
+    }
+
+    // the operands which are read. the destination is read unless it is overwritten
+    fn sources(&self) -> &[Arg] {
+        match self.overwritten() {
+            Some(_) => &self.args[1..],
+            None => &self.args
+        }
+    }
+
+    // registers read without being named in the operands
+    fn implicit_reads(&self) -> &'static [usize] {
+        match self.op.as_str() {
+            "call" => &CALL_READS,
+            "cqo" => &[RAX],
+            "div" | "idiv" => &[RAX, RDX],
+            "rep" => &[RAX, RCX, RSI, RDI],
+            _ => &[]
+        }
+    }
+
+    fn reads(&self, family: usize) -> bool {
+        self.implicit_reads().contains(&family)
+            || self.sources().iter().any(|arg| arg.regs().contains(&family))
+    }
+
+    fn kills(&self, family: usize) -> bool {
+        match self.op.as_str() {
+            "call" => is_caller_saved(family),
+            "cqo" => family == RDX,
+            _ => self.overwritten().is_some_and(|reg| reg.family == family)
+        }
+    }
+}
+
+impl fmt::Display for Insn {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "  {}", self.op)?;
+        for (i, arg) in self.args.iter().enumerate() {
+            write!(f, "{}{}", if i == 0 { " " } else { ", " }, arg)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(in super) enum Line {
+    Label(String),
+    Insn(Insn),
+    // a source location (`.loc`), which the rewriting skips
+    Loc(String),
+    // a directive, which is kept as it is
+    Other(String)
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Line::Label(label) => write!(f, "{}:", label),
+            Line::Insn(insn) => write!(f, "{}", insn),
+            Line::Loc(line) | Line::Other(line) => write!(f, "{}", line)
+        }
+    }
+}
+
+pub(in super) fn parse(asm: &str) -> Vec<Line> {
+    asm.lines().map(|line| {
+        let trimmed = line.trim();
+        if trimmed.ends_with(':') && !trimmed.contains(' ') {
+            return Line::Label(trimmed[..trimmed.len() - 1].to_string())
+        }
+        if trimmed.starts_with(".loc ") {
+            return Line::Loc(line.to_string())
+        }
+        if trimmed.starts_with('.') || trimmed.is_empty() {
+            return Line::Other(line.to_string())
+        }
+
+        match trimmed.find(' ') {
+            Some(i) => Line::Insn(Insn::new(&trimmed[..i], trimmed[i + 1..].split(", ").map(Arg::parse).collect())),
+            None => Line::Insn(Insn::new(trimmed, Vec::new()))
+        }
+    }).collect()
+}
+
+// the number of instructions
+pub(in super) fn count(lines: &[Line]) -> usize {
+    lines.iter().filter(|line| matches!(line, Line::Insn(_))).count()
+}
+
+pub(in super) fn optimize(lines: &mut Vec<Line>) {
+    while rewrite(lines) {}
+}
+
+fn rewrite(lines: &mut Vec<Line>) -> bool {
+    let mut changed = false;
+    let mut i = 0;
+
+    while i < lines.len() {
+        let j = skip_locs(lines, i + 1);
+        let (cur, next) = match (&lines[i], lines.get(j)) {
+            (Line::Insn(cur), Some(Line::Insn(next))) => (cur, Some((j, next))),
+            (Line::Insn(cur), _) => (cur, None),
+            _ => {
+                i += 1;
+                continue
+            }
+        };
+
+        if let Some((len, insns)) = rewrite_insn(lines, i, cur, next) {
+            let end = if len == 2 { j + 1 } else { i + 1 };
+            let locs: Vec<Line> = lines[i + 1..end].iter().filter(|line| matches!(line, Line::Loc(_))).cloned().collect();
+            lines.splice(i..end, locs.into_iter().chain(insns.into_iter().map(Line::Insn)));
+            changed = true;
+            continue
+        }
+
+        if cur.op == "jmp" {
+            match &lines.get(j) {
+                // unreachable, with the locations before it
+                Some(Line::Insn(_)) => {
+                    lines.drain(i + 1..=j);
+                    changed = true;
+                    continue
+                },
+                Some(Line::Label(label)) if cur.args == [Arg::Sym(label.clone())] => {
+                    lines.remove(i);
+                    changed = true;
+                    continue
+                },
+                _ => {}
+            }
+        }
+
+        i += 1;
+    }
+
+    changed
+}
+
+// the index of the first line from `start` which is not `.loc`
+fn skip_locs(lines: &[Line], start: usize) -> usize {
+    (start..lines.len()).find(|&i| !matches!(lines[i], Line::Loc(_))).unwrap_or(lines.len())
+}
+
+// the instructions replacing `len` instructions from lines[i]. `next` is the
+// instruction which follows with its index
+fn rewrite_insn(lines: &[Line], i: usize, cur: &Insn, next: Option<(usize, &Insn)>) -> Option<(usize, Vec<Insn>)> {
+    let op = cur.op.as_str();
+
+    // mov r, r. `mov eax, eax` clears the upper half of rax
+    if op == "mov" && cur.args.len() == 2 && cur.args[0] == cur.args[1] && cur.args[0].is_reg64() {
+        return Some((1, vec![]))
+    }
+
+    // a value which is never read
+    if let Some(reg) = cur.overwritten() {
+        if op != "pop" && reg.family != RBP && reg.family != RSP && is_dead(lines, i + 1, reg.family) {
+            return Some((1, vec![]))
+        }
+    }
+
+    let (j, next) = next?;
+
+    // lea r, [m]; op ..., [r] => op ..., [m]
+    if let (true, [Arg::Reg(reg), Arg::Mem { addr, .. }]) = (op == "lea", cur.args.as_slice()) {
+        let reads = next.sources().iter().filter(|arg| arg.regs().contains(&reg.family)).count();
+        if reg.size == 8 && reads == 1 && next.sources().iter().any(|arg| arg.is_at(reg))
+            && !next.implicit_reads().contains(&reg.family)
+            && (next.kills(reg.family) || is_dead(lines, j + 1, reg.family)) {
+            let mut insn = next.clone();
+            for arg in &mut insn.args {
+                if arg.is_at(reg) {
+                    *arg = match arg {
+                        Arg::Mem { ptr, .. } => Arg::Mem { ptr: ptr.clone(), addr: addr.clone() },
+                        _ => unreachable!()
+                    };
+                }
+            }
+            return Some((2, vec![insn]))
+        }
+    }
+
+    // mov a, b; mov c, a => mov c, b
+    if let ([a, b], [c, a2]) = (cur.args.as_slice(), next.args.as_slice()) {
+        let fits = match (c, b) {
+            (Arg::Reg(c), _) => c.size == 8,
+            // a memory operand takes a register, or a 32-bit immediate if its size is given
+            (Arg::Mem { .. }, Arg::Reg(_)) => op == "mov",
+            (Arg::Mem { ptr: Some(_), .. }, Arg::Imm(val)) => op == "mov" && i32::try_from(*val).is_ok(),
+            _ => false
+        };
+        if let Arg::Reg(reg) = a {
+            if (op == "mov" || op == "movabs") && next.op == "mov" && a == a2 && a.is_reg64() && fits
+                && !c.regs().contains(&reg.family) && is_dead(lines, j + 1, reg.family) {
+                return Some((2, vec![Insn::new(op, vec![c.clone(), b.clone()])]))
+            }
+        }
+    }
+
+    // push a; pop b => mov b, a
+    if let ("push", "pop", [a], [b]) = (op, next.op.as_str(), cur.args.as_slice(), next.args.as_slice()) {
+        if a == b {
+            return Some((2, vec![]))
+        }
+        if let (Arg::Reg(_), Arg::Reg(_)) = (a, b) {
+            return Some((2, vec![Insn::new("mov", vec![b.clone(), a.clone()])]))
+        }
+    }
+
+    None
+}
+
+// whether the value of a register is never read from lines[start..]
+fn is_dead(lines: &[Line], start: usize, family: usize) -> bool {
+    for line in &lines[start..] {
+        let insn = match line {
+            Line::Insn(insn) => insn,
+            Line::Loc(_) => continue,
+            _ => return false
+        };
+        if insn.reads(family) || insn.is_jump() {
+            return false
+        }
+        if insn.kills(family) {
+            return true
+        }
+    }
+
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ parse, count, optimize };
+
+    fn peephole(asm: &str) -> String {
+        let mut lines = parse(asm);
+        optimize(&mut lines);
+        lines.iter().map(|line| format!("{}\n", line)).collect()
+    }
+
+    // the assembly which is not changed
+    fn assert_kept(asm: &str) {
+        assert_eq!(peephole(asm), asm);
+    }
+
+    #[test]
+    fn move_to_itself() {
+        assert_eq!(peephole("  mov rax, rax\n  ret\n"), "  ret\n");
+        // the upper half of rax is cleared
+        assert_kept("  mov eax, eax\n  ret\n");
+    }
+
+    #[test]
+    fn dead_write() {
+        assert_eq!(peephole("  mov rax, 1\n  mov rax, 2\n  ret\n"), "  mov rax, 2\n  ret\n");
+        // r10 is not preserved across a call
+        assert_eq!(peephole("  mov r10, 1\n  call f\n  ret\n"), "  call f\n  ret\n");
+    }
+
+    #[test]
+    fn live_write_is_kept() {
+        assert_kept("  mov rax, 1\n  add rdi, rax\n  mov rax, 2\n  ret\n");
+        // an argument of the call
+        assert_kept("  mov rdi, 1\n  call f\n  ret\n");
+        // anything is live at a label or a jump
+        assert_kept("  mov rax, 1\n.L.1:\n  mov rax, 2\n  ret\n");
+        assert_kept("  mov rax, 1\n  je .L.1\n  mov rax, 2\n  ret\n");
+        // a smaller register does not clear the rest of it
+        assert_kept("  mov rax, 1\n  mov al, 2\n  ret\n");
+    }
+
+    #[test]
+    fn address_is_folded() {
+        assert_eq!(
+            peephole("  lea rax, [rbp-8]\n  mov dword ptr [rax], 3\n  mov rax, 0\n  ret\n"),
+            "  mov dword ptr [rbp-8], 3\n  mov rax, 0\n  ret\n"
+        );
+        assert_eq!(
+            peephole("  lea rax, [rbp-8]\n  mov eax, dword ptr [rax]\n  ret\n"),
+            "  mov eax, dword ptr [rbp-8]\n  ret\n"
+        );
+    }
+
+    #[test]
+    fn live_address_is_kept() {
+        assert_kept("  lea rax, [rbp-8]\n  mov dword ptr [rax], 3\n  mov rdi, rax\n  ret\n");
+        // rax is read twice
+        assert_kept("  lea rax, [rbp-8]\n  mov qword ptr [rax], rax\n  mov rax, 0\n  ret\n");
+    }
+
+    #[test]
+    fn moves_are_combined() {
+        assert_eq!(peephole("  mov rax, 5\n  mov rdi, rax\n  mov rax, 1\n  ret\n"), "  mov rdi, 5\n  mov rax, 1\n  ret\n");
+        assert_eq!(
+            peephole("  mov rax, 5\n  mov qword ptr [rbp-8], rax\n  mov rax, 1\n  ret\n"),
+            "  mov qword ptr [rbp-8], 5\n  mov rax, 1\n  ret\n"
+        );
+    }
+
+    #[test]
+    fn live_move_is_kept() {
+        assert_kept("  mov rax, 5\n  mov rdi, rax\n  add rax, rdi\n  ret\n");
+        // an immediate does not fit in a memory operand without its size
+        assert_kept("  movabs rax, 4294967296\n  mov qword ptr [rbp-8], rax\n  mov rax, 1\n  ret\n");
+    }
+
+    #[test]
+    fn push_pop() {
+        assert_eq!(peephole("  push rax\n  pop rdi\n  ret\n"), "  mov rdi, rax\n  ret\n");
+        assert_eq!(peephole("  push rax\n  pop rax\n  ret\n"), "  ret\n");
+    }
+
+    #[test]
+    fn jump() {
+        // the code after jmp, and then the jump to the next label, are removed
+        assert_eq!(peephole("  jmp .L.end.1\n  mov rax, 1\n.L.end.1:\n  ret\n"), ".L.end.1:\n  ret\n");
+        assert_kept("  jmp .L.end.1\n.L.else.1:\n  mov rax, 1\n.L.end.1:\n  ret\n");
+    }
+
+    // the source location of a removed instruction stays before the following one
+    #[test]
+    fn location_is_kept() {
+        assert_eq!(
+            peephole("  mov rax, 1\n  .loc 1 3 5\n  mov rax, 2\n  ret\n"),
+            "  .loc 1 3 5\n  mov rax, 2\n  ret\n"
+        );
+    }
+
+    #[test]
+    fn instructions_are_counted() {
+        let lines = parse("  .text\nf:\n  push rbp\n  .loc 1 1 1\n  mov rbp, rsp\n  ret\n");
+        assert_eq!(count(&lines), 3);
+    }
+}
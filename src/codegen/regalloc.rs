@@ -0,0 +1,158 @@
+use crate::ir::{ Function, Inst };
+
+// linear scan register allocation.
+//
+// the live range of a virtual register is approximated by a single interval
+// over the instructions numbered in the layout order, from the first position
+// where it is live to the last one. a register which does not fit in the
+// machine registers is spilled to the stack for its whole live range
+
+// callee-saved registers survive function calls
+const CALLEE_SAVED: [&str; 5] = ["rbx", "r12", "r13", "r14", "r15"];
+// caller-saved registers which the instruction selection never uses as a scratch.
+// they hold only values which are not live across a call
+const CALLER_SAVED: [&str; 2] = ["r10", "r11"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(in super) enum Location {
+    Reg(&'static str),
+    // a spill slot `offset` bytes below the frame pointer
+    Stack(usize)
+}
+
+pub(in super) struct Allocation {
+    // None for a register which is never used
+    pub locs: Vec<Option<Location>>,
+    // callee-saved registers used in the function, which must be restored on return
+    pub saved: Vec<&'static str>,
+    // bytes of the spill slots, which are placed below the local variables
+    pub spill_size: usize
+}
+
+struct Interval {
+    reg: usize,
+    start: usize,
+    end: usize,
+    across_call: bool
+}
+
+pub(in super) fn allocate(func: &Function) -> Allocation {
+    let intervals = intervals(func);
+    let mut locs = vec![None; func.nregs];
+    let mut saved = Vec::new();
+    let mut spill_size = 0;
+    let spill = |spill_size: &mut usize| {
+        *spill_size += 8;
+        Location::Stack(func.stack_size + *spill_size)
+    };
+
+    // intervals which hold a machine register, and the free machine registers
+    let mut active: Vec<(usize, &'static str)> = Vec::new();
+    let mut free: Vec<&'static str> = CALLER_SAVED.iter().chain(CALLEE_SAVED.iter()).copied().collect();
+
+    for (idx, iv) in intervals.iter().enumerate() {
+        // an interval which ends where this one starts is read before this one is written
+        active.retain(|(i, reg)| {
+            if intervals[*i].end <= iv.start {
+                free.push(reg);
+                return false
+            }
+            true
+        });
+
+        let fits = |reg: &str| !iv.across_call || CALLEE_SAVED.contains(&reg);
+        // prefer caller-saved registers, which need not be saved in the prologue
+        let candidate = CALLER_SAVED.iter().chain(CALLEE_SAVED.iter())
+            .find(|reg| fits(reg) && free.contains(reg))
+            .copied();
+
+        let reg = match candidate {
+            Some(reg) => {
+                free.retain(|r| *r != reg);
+                reg
+            },
+            None => {
+                // spill the interval which ends last, this one or an active one
+                let victim = active.iter().enumerate()
+                    .filter(|(_, (_, reg))| fits(reg))
+                    .max_by_key(|(_, (i, _))| intervals[*i].end)
+                    .map(|(pos, (i, reg))| (pos, *i, *reg));
+                match victim {
+                    Some((pos, i, reg)) if intervals[i].end > iv.end => {
+                        locs[intervals[i].reg] = Some(spill(&mut spill_size));
+                        active.remove(pos);
+                        reg
+                    },
+                    _ => {
+                        locs[iv.reg] = Some(spill(&mut spill_size));
+                        continue
+                    }
+                }
+            }
+        };
+
+        locs[iv.reg] = Some(Location::Reg(reg));
+        active.push((idx, reg));
+        if CALLEE_SAVED.contains(&reg) && !saved.contains(&reg) {
+            saved.push(reg);
+        }
+    }
+
+    // keep the order of the registers in the prologue stable
+    saved.sort_by_key(|reg| CALLEE_SAVED.iter().position(|r| r == reg));
+
+    Allocation { locs, saved, spill_size }
+}
+
+// live intervals sorted by their start
+fn intervals(func: &Function) -> Vec<Interval> {
+    let (live_in, live_out) = func.liveness();
+    let mut ranges: Vec<Option<(usize, usize)>> = vec![None; func.nregs];
+    let mut extend = |reg: usize, pos: usize| {
+        ranges[reg] = match ranges[reg] {
+            Some((start, end)) => Some((start.min(pos), end.max(pos))),
+            None => Some((pos, pos))
+        };
+    };
+    let mut calls = Vec::new();
+
+    let mut pos = 0;
+    for (id, block) in func.blocks.iter().enumerate() {
+        // a register live at the beginning of the block starts before its first
+        // instruction, so that it never shares a machine register with a value
+        // which dies there
+        for reg in &live_in[id] {
+            extend(reg.0, pos);
+        }
+        pos += 1;
+        for inst in &block.insts {
+            if let Inst::Call { .. } = inst {
+                calls.push(pos);
+            }
+            for reg in inst.uses().into_iter().chain(inst.def()) {
+                extend(reg.0, pos);
+            }
+            pos += 1;
+        }
+        for reg in block.term.uses().into_iter().chain(live_out[id].iter().copied()) {
+            extend(reg.0, pos);
+        }
+        pos += 1;
+    }
+
+    let mut intervals: Vec<Interval> = ranges.into_iter().enumerate()
+        .filter_map(|(reg, range)| range.map(|(start, end)| Interval {
+            reg,
+            start,
+            end,
+            // the arguments are read before the call, and the result is written after it
+            across_call: {
+                let next = calls.partition_point(|call| *call <= start);
+                calls.get(next).is_some_and(|call| *call < end)
+            }
+        }))
+        .collect();
+    intervals.sort_by_key(|iv| iv.start);
+
+    intervals
+}
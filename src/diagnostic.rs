@@ -0,0 +1,183 @@
+use crate::token::Token;
+use crate::tokenizer::loc::Loc;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::rc::Rc;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Note => write!(f, "note")
+        }
+    }
+}
+
+// a range of the source code. `len` is the number of columns to underline
+#[derive(Debug, Clone, PartialEq)]
+pub struct Span {
+    pub loc: Loc,
+    pub len: usize
+}
+
+impl Span {
+    pub fn new(loc: Loc, len: usize) -> Self {
+        Self { loc, len }
+    }
+
+    // the span of the spelling of `tok`
+    pub fn from_token(tok: &Token) -> Self {
+        let len = if tok.token_type.at_eof() { 1 } else { tok.token_type.tk_str().len() };
+        Self::new(tok.loc.clone(), len)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    // None if the diagnostic is not related to a specific location (e.g. a missing file)
+    pub span: Option<Span>,
+    pub notes: Vec<Diagnostic>,
+    // name of the -W option which controls this diagnostic, e.g. "unused-variable"
+    pub flag: Option<&'static str>
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, message: impl Into<String>, span: Option<Span>) -> Self {
+        Self { severity, message: message.into(), span, notes: Vec::new(), flag: None }
+    }
+
+    pub fn warning(flag: &'static str, message: impl Into<String>, span: Option<Span>) -> Self {
+        Self { flag: Some(flag), ..Self::new(Severity::Warning, message, span) }
+    }
+
+    pub fn error(message: impl Into<String>) -> Self {
+        Self::new(Severity::Error, message, None)
+    }
+
+    pub fn error_at(tok: &Token, message: impl Into<String>) -> Self {
+        Self::new(Severity::Error, message, Some(Span::from_token(tok)))
+    }
+
+    pub fn with_note(mut self, note: Diagnostic) -> Self {
+        self.notes.push(Diagnostic { severity: Severity::Note, ..note });
+        self
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    // render like gcc and clang:
+    //
+    //   foo.c:3:5: error: expected ';'
+    //       3 |   x = 1
+    //         |     ^~
+    pub fn render(&self, sources: &SourceMap) -> String {
+        let mut out = format!("{}\n", self);
+
+        if let Some(span) = &self.span {
+            if let Some(line) = sources.line(&span.loc.file, span.loc.row) {
+                out.push_str(&render_snippet(&line, span));
+            }
+        }
+
+        for note in &self.notes {
+            out.push_str(&note.render(sources));
+        }
+
+        out
+    }
+}
+
+// errors which have no location. the location is attached by the caller if needed
+impl From<String> for Diagnostic {
+    fn from(message: String) -> Self {
+        Self::error(message)
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(span) = &self.span {
+            write!(f, "{}: ", span.loc)?;
+        }
+        write!(f, "{}: {}", self.severity, self.message)?;
+
+        // a warning turned into an error by -Werror still shows its option
+        match (self.flag, self.severity) {
+            (Some(flag), Severity::Error) => write!(f, " [-Werror={}]", flag),
+            (Some(flag), _) => write!(f, " [-W{}]", flag),
+            (None, _) => Ok(())
+        }
+    }
+}
+
+// sort diagnostics in the order of the source, whichever pass found them. the
+// files keep the order in which they first appear, and a diagnostic without a
+// location comes last
+pub fn sort_by_location(diags: &mut [Diagnostic]) {
+    let mut files: Vec<Rc<String>> = Vec::new();
+    for span in diags.iter().filter_map(|diag| diag.span.as_ref()) {
+        if !files.contains(&span.loc.file) {
+            files.push(span.loc.file.clone());
+        }
+    }
+
+    diags.sort_by_key(|diag| match &diag.span {
+        Some(span) => (files.iter().position(|file| *file == span.loc.file).unwrap(), span.loc.row, span.loc.col),
+        None => (files.len(), 0, 0)
+    });
+}
+
+fn render_snippet(line: &str, span: &Span) -> String {
+    let row = span.loc.row.to_string();
+    let gutter = " ".repeat(row.len());
+
+    // keep tabs so that the caret is aligned with the source line
+    let indent: String = line.chars()
+        .take(span.loc.col.saturating_sub(1))
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    let underline = format!("^{}", "~".repeat(span.len.saturating_sub(1)));
+
+    format!(" {} | {}\n {} | {}{}\n", row, line, gutter, indent, underline)
+}
+
+// contents of source files, used to show the source line of a diagnostic.
+// a file which is not registered is read from the disk
+#[derive(Debug, Default)]
+pub struct SourceMap {
+    files: HashMap<String, String>
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, name: impl Into<String>, content: impl Into<String>) {
+        self.files.insert(name.into(), content.into());
+    }
+
+    // `row` starts from 1
+    pub fn line(&self, name: &str, row: usize) -> Option<String> {
+        let line = |content: &str| content.lines().nth(row.checked_sub(1)?).map(|l| l.to_string());
+
+        match self.files.get(name) {
+            Some(content) => line(content),
+            None => line(&fs::read_to_string(name).ok()?)
+        }
+    }
+}
@@ -0,0 +1,256 @@
+use crate::token::{ Token, TokenType };
+use crate::node::{ Expr, ExprWrapper, Stmt, StmtKind };
+use crate::program::{ Function, Offset, Program, Var };
+
+use std::fmt::Write;
+
+// one token per line with its location, e.g. `a.c:1:5 Ident main`
+pub fn tokens(tokens: &[Token]) -> String {
+    let mut out = String::new();
+    for tok in tokens {
+        let _ = match &tok.token_type {
+            TokenType::Reserved(reserved) => writeln!(out, "{} Reserved {}", tok.loc, reserved.op),
+            TokenType::Num(num) => writeln!(out, "{} Num {} ({})", tok.loc, num.tk_str, num.ty),
+            TokenType::PpNum(num) => writeln!(out, "{} PpNum {}", tok.loc, num.tk_str),
+            TokenType::Ident(ident) => writeln!(out, "{} Ident {}", tok.loc, ident.name),
+            TokenType::Symbol(symbol) => writeln!(out, "{} Symbol {}", tok.loc, symbol.sym),
+            TokenType::Str(s) => writeln!(out, "{} Str {}", tok.loc, s.tk_str),
+            TokenType::Eof => writeln!(out, "{} Eof", tok.loc)
+        };
+    }
+
+    out
+}
+
+// the tree of the program. each node is indented under its parent, and an
+// expression is shown with its type
+pub fn ast(prog: &Program) -> String {
+    let mut dump = AstDump { out: String::new(), depth: 0 };
+    for var in &prog.globals {
+        dump.line(&format!("global {}", var_desc(&var.borrow())));
+    }
+    for func in &prog.fns {
+        dump.function(func);
+    }
+
+    dump.out
+}
+
+struct AstDump {
+    out: String,
+    depth: usize
+}
+
+impl AstDump {
+    fn line(&mut self, text: &str) {
+        let _ = writeln!(self.out, "{:indent$}{}", "", text, indent = self.depth * 2);
+    }
+
+    // the lines written by `f` are indented under the current line
+    fn nested(&mut self, f: impl FnOnce(&mut Self)) {
+        self.depth += 1;
+        f(self);
+        self.depth -= 1;
+    }
+
+    fn function(&mut self, func: &Function) {
+        let is_static = if func.is_static { " static" } else { "" };
+        self.line(&format!("function {}: {}{} (stack {})", func.name, func.ty, is_static, func.stack_size));
+        self.nested(|dump| {
+            for param in &func.params {
+                dump.line(&format!("param {}", var_desc(&param.borrow())));
+            }
+            for var in func.locals.iter().rev().filter(|var| !func.params.iter().any(|param| std::rc::Rc::ptr_eq(param, var))) {
+                dump.line(&format!("local {}", var_desc(&var.borrow())));
+            }
+            for stmt in &func.nodes {
+                dump.stmt(stmt);
+            }
+        });
+    }
+
+    // a child with a label, e.g. `cond:`
+    fn labeled_stmt(&mut self, label: &str, stmt: &Stmt) {
+        self.line(label);
+        self.nested(|dump| dump.stmt(stmt));
+    }
+
+    fn labeled_expr(&mut self, label: &str, ew: &ExprWrapper) {
+        self.line(label);
+        self.nested(|dump| dump.expr(ew));
+    }
+
+    // a statement from the source is shown with its location, e.g. `Return at 4:3`
+    fn stmt_line(&mut self, stmt: &Stmt, text: &str) {
+        match &stmt.loc {
+            Some(loc) => self.line(&format!("{} at {}:{}", text, loc.row, loc.col)),
+            None => self.line(text)
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::Return { val } | StmtKind::ExprStmt { val } | StmtKind::PureExpr(val) => {
+                self.stmt_line(stmt, &stmt.kind.to_string());
+                self.nested(|dump| dump.expr(val));
+            },
+            StmtKind::If { cond, then, els } => {
+                self.stmt_line(stmt, "If");
+                self.nested(|dump| {
+                    dump.labeled_expr("cond:", cond);
+                    dump.labeled_stmt("then:", then);
+                    if let Some(els) = els {
+                        dump.labeled_stmt("else:", els);
+                    }
+                });
+            },
+            StmtKind::While { cond, then } | StmtKind::DoWhile { then, cond } => {
+                self.stmt_line(stmt, &stmt.kind.to_string());
+                self.nested(|dump| {
+                    dump.labeled_expr("cond:", cond);
+                    dump.labeled_stmt("then:", then);
+                });
+            },
+            StmtKind::For { init, cond, inc, then } => {
+                self.stmt_line(stmt, "For");
+                self.nested(|dump| {
+                    if let Some(init) = init.as_ref() {
+                        dump.labeled_stmt("init:", init);
+                    }
+                    if let Some(cond) = cond {
+                        dump.labeled_expr("cond:", cond);
+                    }
+                    if let Some(inc) = inc.as_ref() {
+                        dump.labeled_stmt("inc:", inc);
+                    }
+                    dump.labeled_stmt("then:", then);
+                });
+            },
+            StmtKind::Block { stmts } => {
+                self.stmt_line(stmt, "Block");
+                self.nested(|dump| stmts.iter().for_each(|stmt| dump.stmt(stmt)));
+            },
+            StmtKind::Break | StmtKind::Continue => self.stmt_line(stmt, &stmt.kind.to_string()),
+            StmtKind::Goto(label) => self.stmt_line(stmt, &format!("Goto {}", label)),
+            StmtKind::Label(stmt, label) => {
+                self.stmt_line(stmt, &format!("Label {}", label));
+                self.nested(|dump| dump.stmt(stmt));
+            },
+            StmtKind::Switch { cond, then, cases, has_default } => {
+                let cases: Vec<String> = cases.iter().map(|val| val.to_string()).collect();
+                let default = if *has_default { " default" } else { "" };
+                self.stmt_line(stmt, &format!("Switch (cases {}{})", cases.join(", "), default));
+                self.nested(|dump| {
+                    dump.labeled_expr("cond:", cond);
+                    dump.labeled_stmt("then:", then);
+                });
+            },
+            StmtKind::Case { val, stmt, .. } => {
+                self.stmt_line(stmt, &format!("Case {}", val));
+                self.nested(|dump| dump.stmt(stmt));
+            },
+            StmtKind::Default(stmt) => {
+                self.stmt_line(stmt, "Default");
+                self.nested(|dump| dump.stmt(stmt));
+            }
+        }
+    }
+
+    fn expr(&mut self, ew: &ExprWrapper) {
+        let detail = match ew.expr.as_ref() {
+            Expr::Num { val, .. } => format!(" {}", val),
+            Expr::FNum { val } => format!(" {:?}", val),
+            Expr::Var(var) | Expr::MemZero(var) => {
+                let var = var.borrow();
+                return self.line(&format!("{} {}", ew.expr, var_desc(&var)))
+            },
+            Expr::Member(_, member) => format!(" .{} (offset {})", member.name, offset(&member.offset)),
+            _ => String::new()
+        };
+        self.line(&format!("{}{}: {}", ew.expr, detail, ew.ty));
+
+        self.nested(|dump| match ew.expr.as_ref() {
+            Expr::Eq { lhs, rhs }
+            | Expr::Neq { lhs, rhs }
+            | Expr::Gt { lhs, rhs }
+            | Expr::Ge { lhs, rhs }
+            | Expr::Lt { lhs, rhs }
+            | Expr::Le { lhs, rhs }
+            | Expr::Add { lhs, rhs }
+            | Expr::Sub { lhs, rhs }
+            | Expr::Mul { lhs, rhs }
+            | Expr::Div { lhs, rhs }
+            | Expr::Mod { lhs, rhs }
+            | Expr::BitAnd { lhs, rhs }
+            | Expr::BitOr { lhs, rhs }
+            | Expr::BitXor { lhs, rhs }
+            | Expr::Shl { lhs, rhs }
+            | Expr::Shr { lhs, rhs }
+            | Expr::LogAnd { lhs, rhs }
+            | Expr::LogOr { lhs, rhs }
+            | Expr::PtrAdd { lhs, rhs }
+            | Expr::PtrSub { lhs, rhs }
+            | Expr::PtrDiff { lhs, rhs } => {
+                dump.expr(lhs);
+                dump.expr(rhs);
+            },
+            Expr::Assign { var, val }
+            | Expr::AddEq { var, val }
+            | Expr::PtrAddEq { var, val }
+            | Expr::SubEq { var, val }
+            | Expr::PtrSubEq { var, val }
+            | Expr::MulEq { var, val }
+            | Expr::DivEq { var, val }
+            | Expr::ModEq { var, val }
+            | Expr::BitAndEq { var, val }
+            | Expr::BitOrEq { var, val }
+            | Expr::BitXorEq { var, val }
+            | Expr::ShlEq { var, val }
+            | Expr::ShrEq { var, val } => {
+                dump.expr(var);
+                dump.expr(val);
+            },
+            Expr::Cast(_, operand)
+            | Expr::PreInc(operand)
+            | Expr::PreDec(operand)
+            | Expr::PostInc(operand)
+            | Expr::PostDec(operand)
+            | Expr::Addr { operand }
+            | Expr::Deref { operand }
+            | Expr::Not(operand)
+            | Expr::BitNot(operand)
+            | Expr::Member(operand, _) => dump.expr(operand),
+            Expr::Comma { lhs, rhs } => {
+                dump.stmt(lhs);
+                dump.expr(rhs);
+            },
+            Expr::Cond { cond, then, els } => {
+                dump.labeled_expr("cond:", cond);
+                dump.labeled_expr("then:", then);
+                dump.labeled_expr("else:", els);
+            },
+            Expr::FnCall { callee, args, .. } => {
+                dump.expr(callee);
+                args.iter().for_each(|arg| dump.expr(arg));
+            },
+            Expr::StmtExpr(stmts) => stmts.iter().for_each(|stmt| dump.stmt(stmt)),
+            Expr::Num { .. } | Expr::FNum { .. } | Expr::Var(_) | Expr::MemZero(_) | Expr::Null => {}
+        });
+    }
+}
+
+// e.g. `x: int (local, offset 4)`
+fn var_desc(var: &Var) -> String {
+    match (&var.contents, var.is_local) {
+        (_, true) => format!("{}: {} (local, offset {})", var.name, var.ty, offset(&var.offset)),
+        (Some(bytes), false) => format!("{}: {} {:?}", var.name, var.ty, String::from_utf8_lossy(bytes)),
+        (None, false) => format!("{}: {} (global)", var.name, var.ty)
+    }
+}
+
+fn offset(offset: &Offset) -> String {
+    match offset {
+        Offset::Value(val) => val.to_string(),
+        Offset::Unset => "unset".to_string()
+    }
+}
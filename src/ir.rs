@@ -0,0 +1,678 @@
+// three-address intermediate representation between the AST and the assembly.
+//
+// a function is a list of basic blocks. each block is a straight sequence of
+// instructions followed by a single terminator, which is the only place the
+// control flow leaves the block. values are held in an unlimited number of
+// virtual registers (%0, %1, ...), which are assigned to machine registers or
+// stack slots by the code generator.
+//
+// a virtual register always holds a 64-bit value. an integer is kept sign- or
+// zero-extended according to its type, and a floating-point value is kept as
+// its bit pattern
+mod lower;
+pub mod opt;
+
+pub use lower::lower;
+
+use crate::program::Var;
+use crate::_type::Type;
+use crate::tokenizer::loc::Loc;
+
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::fmt;
+use std::rc::Rc;
+
+#[derive(Debug)]
+pub struct Program {
+    pub fns: Vec<Function>,
+    // global variables are emitted as they are in the AST
+    pub globals: Vec<Rc<RefCell<Var>>>
+}
+
+#[derive(Debug)]
+pub struct Function {
+    pub name: Rc<String>,
+    pub is_static: bool,
+    // types of the parameters, in order. a struct returned in memory is
+    // written to the address given by the hidden first parameter
+    pub params: Vec<ArgTy>,
+    // a struct returned in registers, whose address is given to Ret
+    pub ret_regs: Option<StructRegs>,
+    // blocks[0] is the entry. a block is identified by its index
+    pub blocks: Vec<Block>,
+    // the number of virtual registers
+    pub nregs: usize,
+    // bytes of the local variables
+    pub stack_size: usize,
+    // the function in the source, which the debug information describes.
+    // the variables are in the order of their declarations
+    pub ty: Box<Type>,
+    pub loc: Option<Loc>,
+    pub param_vars: Vec<Rc<RefCell<Var>>>,
+    pub local_vars: Vec<Rc<RefCell<Var>>>,
+    // the offsets of the variables which mem2reg holds in registers. their
+    // stack slots are never written
+    pub promoted: Vec<usize>
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+    pub insts: Vec<Inst>,
+    pub term: Terminator
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Reg(pub usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BlockId(pub usize);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operand {
+    Reg(Reg),
+    Imm(i64)
+}
+
+// the type of a value. pointers are u64, and _Bool is u8
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ty {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    I64,
+    U64,
+    F32,
+    F64
+}
+
+// how a value is passed to a function (System V ABI). a struct is held by its address
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ArgTy {
+    Scalar(Ty),
+    // a struct of up to 16 bytes. it is passed on the stack if there are not
+    // enough registers for all of its eightbytes
+    Regs(StructRegs),
+    // a larger struct, copied to the stack. the value is its size
+    Memory(usize)
+}
+
+// a struct passed in registers. each eightbyte is passed in a general purpose
+// register (I64) if it has an integer, or in an xmm register (F64) otherwise
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StructRegs {
+    pub size: usize,
+    pub eightbytes: Vec<Ty>
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    // Div, Rem and Shr are signed or unsigned according to the type
+    Div,
+    Rem,
+    And,
+    Or,
+    Xor,
+    Shl,
+    Shr
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cond {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Inst {
+    // dst = src
+    Mov { ty: Ty, dst: Reg, src: Operand },
+    // dst = lhs op rhs
+    Bin { op: BinOp, ty: Ty, dst: Reg, lhs: Operand, rhs: Operand },
+    // dst = lhs cond rhs ? 1 : 0. `ty` is the type of the operands
+    Cmp { cond: Cond, ty: Ty, dst: Reg, lhs: Operand, rhs: Operand },
+    // dst = ~src
+    Not { ty: Ty, dst: Reg, src: Operand },
+    // dst = (to)src
+    Conv { from: Ty, to: Ty, dst: Reg, src: Operand },
+    // dst = *addr
+    Load { ty: Ty, dst: Reg, addr: Reg },
+    // *addr = val
+    Store { ty: Ty, addr: Reg, val: Operand },
+    // dst = the address of a local variable, `offset` bytes below the frame pointer
+    LocalAddr { dst: Reg, name: String, offset: usize },
+    // dst = the address of a global symbol
+    GlobalAddr { dst: Reg, name: String },
+    // dst = the idx-th parameter of the function
+    Param { ty: Ty, dst: Reg, idx: usize },
+    // copy the idx-th parameter, a struct, to the local variable `offset` bytes below the frame pointer
+    StructParam { idx: usize, offset: usize },
+    // dst = callee(args...). dst is None for a void function. a struct returned
+    // in registers is written to the local variable at `ret_buf` (its offset)
+    Call { ret: Ty, dst: Option<Reg>, callee: Callee, args: Vec<(ArgTy, Operand)>, ret_buf: Option<(StructRegs, usize)> },
+    // fill `size` bytes from addr with 0
+    MemZero { addr: Reg, size: usize },
+    // copy `size` bytes from src to dst
+    MemCopy { dst: Reg, src: Reg, size: usize },
+    // save the argument registers to __va_area__ of a variadic function, `offset`
+    // bytes below the frame pointer, and initialize its va_list. this follows the
+    // Param instructions, before the argument registers are clobbered
+    VaArea { offset: usize },
+    // the following instructions are generated from the source at the location.
+    // it does nothing
+    Loc(Loc)
+}
+
+// a function called by its name, or through a pointer
+#[derive(Debug, Clone, PartialEq)]
+pub enum Callee {
+    Direct(String),
+    Indirect(Operand)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Terminator {
+    Jmp(BlockId),
+    // jump to `then` if cond is not 0, otherwise to `els`. `ty` is the type of cond
+    Br { ty: Ty, cond: Operand, then: BlockId, els: BlockId },
+    // jump to the block of the case whose value equals val, or to `default`
+    Switch { val: Operand, cases: Vec<(i64, BlockId)>, default: BlockId },
+    Ret(Option<(Ty, Operand)>)
+}
+
+impl Ty {
+    // arrays, structs and functions are represented by their address
+    pub fn from_type(ty: &Type) -> Self {
+        match ty {
+            Type::Char => Ty::I8,
+            Type::UChar | Type::Bool => Ty::U8,
+            Type::Short => Ty::I16,
+            Type::UShort => Ty::U16,
+            Type::Int | Type::Enum => Ty::I32,
+            Type::UInt => Ty::U32,
+            Type::Long => Ty::I64,
+            Type::Float => Ty::F32,
+            Type::Double => Ty::F64,
+            _ => Ty::U64
+        }
+    }
+
+    pub fn size(self) -> usize {
+        match self {
+            Ty::I8 | Ty::U8 => 1,
+            Ty::I16 | Ty::U16 => 2,
+            Ty::I32 | Ty::U32 | Ty::F32 => 4,
+            Ty::I64 | Ty::U64 | Ty::F64 => 8
+        }
+    }
+
+    pub fn is_flonum(self) -> bool {
+        matches!(self, Ty::F32 | Ty::F64)
+    }
+
+    pub fn is_unsigned(self) -> bool {
+        matches!(self, Ty::U8 | Ty::U16 | Ty::U32 | Ty::U64)
+    }
+}
+
+impl Inst {
+    // the register written by the instruction
+    pub fn def(&self) -> Option<Reg> {
+        match self {
+            Inst::Mov { dst, .. }
+            | Inst::Bin { dst, .. }
+            | Inst::Cmp { dst, .. }
+            | Inst::Not { dst, .. }
+            | Inst::Conv { dst, .. }
+            | Inst::Load { dst, .. }
+            | Inst::LocalAddr { dst, .. }
+            | Inst::GlobalAddr { dst, .. }
+            | Inst::Param { dst, .. } => Some(*dst),
+            Inst::Call { dst, .. } => *dst,
+            Inst::Store { .. } | Inst::StructParam { .. } | Inst::MemZero { .. } | Inst::MemCopy { .. } | Inst::VaArea { .. } | Inst::Loc(_) => None
+        }
+    }
+
+    // the registers read by the instruction
+    pub fn uses(&self) -> Vec<Reg> {
+        let ops = match self {
+            Inst::Mov { src, .. } | Inst::Not { src, .. } | Inst::Conv { src, .. } => vec![*src],
+            Inst::Bin { lhs, rhs, .. } | Inst::Cmp { lhs, rhs, .. } => vec![*lhs, *rhs],
+            Inst::Load { addr, .. } | Inst::MemZero { addr, .. } => vec![Operand::Reg(*addr)],
+            Inst::Store { addr, val, .. } => vec![Operand::Reg(*addr), *val],
+            Inst::MemCopy { dst, src, .. } => vec![Operand::Reg(*dst), Operand::Reg(*src)],
+            Inst::Call { callee, args, .. } => {
+                let callee = match callee {
+                    Callee::Indirect(op) => Some(*op),
+                    Callee::Direct(_) => None
+                };
+                args.iter().map(|(_, arg)| *arg).chain(callee).collect()
+            },
+            Inst::LocalAddr { .. }
+            | Inst::GlobalAddr { .. }
+            | Inst::Param { .. }
+            | Inst::StructParam { .. }
+            | Inst::VaArea { .. }
+            | Inst::Loc(_) => vec![]
+        };
+
+        ops.into_iter().filter_map(Operand::reg).collect()
+    }
+
+    pub fn def_mut(&mut self) -> Option<&mut Reg> {
+        match self {
+            Inst::Mov { dst, .. }
+            | Inst::Bin { dst, .. }
+            | Inst::Cmp { dst, .. }
+            | Inst::Not { dst, .. }
+            | Inst::Conv { dst, .. }
+            | Inst::Load { dst, .. }
+            | Inst::LocalAddr { dst, .. }
+            | Inst::GlobalAddr { dst, .. }
+            | Inst::Param { dst, .. } => Some(dst),
+            Inst::Call { dst, .. } => dst.as_mut(),
+            Inst::Store { .. } | Inst::StructParam { .. } | Inst::MemZero { .. } | Inst::MemCopy { .. } | Inst::VaArea { .. } | Inst::Loc(_) => None
+        }
+    }
+
+    // the operands read by the instruction. an address is always a register,
+    // so it is not included
+    pub fn operands_mut(&mut self) -> Vec<&mut Operand> {
+        match self {
+            Inst::Mov { src, .. } | Inst::Not { src, .. } | Inst::Conv { src, .. } => vec![src],
+            Inst::Bin { lhs, rhs, .. } | Inst::Cmp { lhs, rhs, .. } => vec![lhs, rhs],
+            Inst::Store { val, .. } => vec![val],
+            Inst::Call { callee, args, .. } => args.iter_mut().map(|(_, arg)| arg).chain(callee.operand_mut()).collect(),
+            _ => vec![]
+        }
+    }
+
+    // the registers read by the instruction, including addresses
+    pub fn uses_mut(&mut self) -> Vec<&mut Reg> {
+        let (ops, addrs): (Vec<&mut Operand>, Vec<&mut Reg>) = match self {
+            Inst::Mov { src, .. } | Inst::Not { src, .. } | Inst::Conv { src, .. } => (vec![src], vec![]),
+            Inst::Bin { lhs, rhs, .. } | Inst::Cmp { lhs, rhs, .. } => (vec![lhs, rhs], vec![]),
+            Inst::Load { addr, .. } | Inst::MemZero { addr, .. } => (vec![], vec![addr]),
+            Inst::Store { addr, val, .. } => (vec![val], vec![addr]),
+            Inst::MemCopy { dst, src, .. } => (vec![], vec![dst, src]),
+            Inst::Call { callee, args, .. } => (args.iter_mut().map(|(_, arg)| arg).chain(callee.operand_mut()).collect(), vec![]),
+            Inst::LocalAddr { .. }
+            | Inst::GlobalAddr { .. }
+            | Inst::Param { .. }
+            | Inst::StructParam { .. }
+            | Inst::VaArea { .. }
+            | Inst::Loc(_) => (vec![], vec![])
+        };
+
+        ops.into_iter()
+            .filter_map(|op| match op {
+                Operand::Reg(reg) => Some(reg),
+                Operand::Imm(_) => None
+            })
+            .chain(addrs)
+            .collect()
+    }
+
+    // the local variable (its offset) written by the instruction without its address
+    pub fn local_written(&self) -> Option<usize> {
+        match self {
+            Inst::StructParam { offset, .. } | Inst::VaArea { offset } => Some(*offset),
+            Inst::Call { ret_buf, .. } => ret_buf.as_ref().map(|(_, offset)| *offset),
+            _ => None
+        }
+    }
+
+    // an instruction without a side effect can be removed if its result is not used
+    pub fn is_pure(&self) -> bool {
+        !matches!(
+            self,
+            Inst::Store { .. }
+                | Inst::StructParam { .. }
+                | Inst::Call { .. }
+                | Inst::MemZero { .. }
+                | Inst::MemCopy { .. }
+                | Inst::VaArea { .. }
+        )
+    }
+}
+
+impl Terminator {
+    pub fn succs(&self) -> Vec<BlockId> {
+        match self {
+            Terminator::Jmp(dst) => vec![*dst],
+            Terminator::Br { then, els, .. } => vec![*then, *els],
+            Terminator::Switch { cases, default, .. } => {
+                cases.iter().map(|(_, dst)| *dst).chain(Some(*default)).collect()
+            },
+            Terminator::Ret(_) => vec![]
+        }
+    }
+
+    pub fn succs_mut(&mut self) -> Vec<&mut BlockId> {
+        match self {
+            Terminator::Jmp(dst) => vec![dst],
+            Terminator::Br { then, els, .. } => vec![then, els],
+            Terminator::Switch { cases, default, .. } => {
+                cases.iter_mut().map(|(_, dst)| dst).chain(Some(default)).collect()
+            },
+            Terminator::Ret(_) => vec![]
+        }
+    }
+
+    pub fn uses(&self) -> Vec<Reg> {
+        match self {
+            Terminator::Br { cond: val, .. }
+            | Terminator::Switch { val, .. }
+            | Terminator::Ret(Some((_, val))) => val.reg().into_iter().collect(),
+            _ => vec![]
+        }
+    }
+
+    pub fn operand_mut(&mut self) -> Option<&mut Operand> {
+        match self {
+            Terminator::Br { cond: val, .. }
+            | Terminator::Switch { val, .. }
+            | Terminator::Ret(Some((_, val))) => Some(val),
+            _ => None
+        }
+    }
+}
+
+impl Operand {
+    pub fn reg(self) -> Option<Reg> {
+        match self {
+            Operand::Reg(reg) => Some(reg),
+            Operand::Imm(_) => None
+        }
+    }
+}
+
+impl Function {
+    // predecessors of each block
+    pub fn preds(&self) -> Vec<Vec<BlockId>> {
+        let mut preds = vec![Vec::new(); self.blocks.len()];
+        for (id, block) in self.blocks.iter().enumerate() {
+            for succ in block.term.succs() {
+                if !preds[succ.0].contains(&BlockId(id)) {
+                    preds[succ.0].push(BlockId(id));
+                }
+            }
+        }
+
+        preds
+    }
+
+    // the registers live at the beginning and at the end of each block
+    pub fn liveness(&self) -> (Vec<HashSet<Reg>>, Vec<HashSet<Reg>>) {
+        // registers read before written in the block, and registers written in the block
+        let mut gen = vec![HashSet::new(); self.blocks.len()];
+        let mut kill = vec![HashSet::new(); self.blocks.len()];
+        for (id, block) in self.blocks.iter().enumerate() {
+            for inst in &block.insts {
+                for reg in inst.uses() {
+                    if !kill[id].contains(&reg) {
+                        gen[id].insert(reg);
+                    }
+                }
+                kill[id].extend(inst.def());
+            }
+            for reg in block.term.uses() {
+                if !kill[id].contains(&reg) {
+                    gen[id].insert(reg);
+                }
+            }
+        }
+
+        let mut live_in = gen.clone();
+        let mut live_out = vec![HashSet::new(); self.blocks.len()];
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for id in (0..self.blocks.len()).rev() {
+                let out: HashSet<Reg> = self.blocks[id].term.succs().iter()
+                    .flat_map(|succ| live_in[succ.0].iter().copied())
+                    .collect();
+                for reg in &out {
+                    if !kill[id].contains(reg) && live_in[id].insert(*reg) {
+                        changed = true;
+                    }
+                }
+                live_out[id] = out;
+            }
+        }
+
+        (live_in, live_out)
+    }
+}
+
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for var in &self.globals {
+            let var = var.borrow();
+            writeln!(f, "global {} ({} bytes)", var.name, var.ty.size())?;
+        }
+        for func in &self.fns {
+            writeln!(f)?;
+            write!(f, "{}", func)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let params: Vec<String> = self.params.iter().map(|ty| ty.to_string()).collect();
+        write!(f, "function {}({})", self.name, params.join(", "))?;
+        if let Some(regs) = &self.ret_regs {
+            write!(f, " -> {}", regs)?;
+        }
+        if self.is_static {
+            write!(f, " static")?;
+        }
+        writeln!(f, " stack {}", self.stack_size)?;
+
+        let preds = self.preds();
+        for (id, block) in self.blocks.iter().enumerate() {
+            write!(f, "{}:", BlockId(id))?;
+            if !preds[id].is_empty() {
+                let preds: Vec<String> = preds[id].iter().map(|pred| pred.to_string()).collect();
+                write!(f, "  ; preds {}", preds.join(", "))?;
+            }
+            writeln!(f)?;
+            for inst in &block.insts {
+                writeln!(f, "  {}", inst)?;
+            }
+            writeln!(f, "  {}", block.term)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Inst {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Inst::Mov { ty, dst, src } => write!(f, "{} = mov {} {}", dst, ty, Typed(*ty, *src)),
+            Inst::Bin { op, ty, dst, lhs, rhs } => {
+                write!(f, "{} = {} {} {}, {}", dst, op, ty, Typed(*ty, *lhs), Typed(*ty, *rhs))
+            },
+            Inst::Cmp { cond, ty, dst, lhs, rhs } => {
+                write!(f, "{} = cmp {} {} {}, {}", dst, cond, ty, Typed(*ty, *lhs), Typed(*ty, *rhs))
+            },
+            Inst::Not { ty, dst, src } => write!(f, "{} = not {} {}", dst, ty, src),
+            Inst::Conv { from, to, dst, src } => write!(f, "{} = conv {} {} to {}", dst, from, Typed(*from, *src), to),
+            Inst::Load { ty, dst, addr } => write!(f, "{} = load {} [{}]", dst, ty, addr),
+            Inst::Store { ty, addr, val } => write!(f, "store {} [{}], {}", ty, addr, Typed(*ty, *val)),
+            Inst::LocalAddr { dst, name, offset } => write!(f, "{} = local {} (rbp-{})", dst, name, offset),
+            Inst::GlobalAddr { dst, name } => write!(f, "{} = global {}", dst, name),
+            Inst::Param { ty, dst, idx } => write!(f, "{} = param {} {}", dst, ty, idx),
+            Inst::StructParam { idx, offset } => write!(f, "param struct {} to (rbp-{})", idx, offset),
+            Inst::Call { ret, dst, callee, args, ret_buf } => {
+                let args: Vec<String> = args.iter()
+                    .map(|(ty, arg)| match ty {
+                        ArgTy::Scalar(ty) => format!("{} {}", ty, Typed(*ty, *arg)),
+                        ty => format!("{} [{}]", ty, arg)
+                    })
+                    .collect();
+                match (dst, ret_buf) {
+                    (Some(dst), _) => write!(f, "{} = call {} {}({})", dst, ret, callee, args.join(", ")),
+                    (None, Some((regs, offset))) => write!(f, "call {} {}({}) to (rbp-{})", regs, callee, args.join(", "), offset),
+                    (None, None) => write!(f, "call void {}({})", callee, args.join(", "))
+                }
+            },
+            Inst::MemZero { addr, size } => write!(f, "memzero [{}], {}", addr, size),
+            Inst::MemCopy { dst, src, size } => write!(f, "memcopy [{}], [{}], {}", dst, src, size),
+            Inst::VaArea { offset } => write!(f, "va_area (rbp-{})", offset),
+            Inst::Loc(loc) => write!(f, "loc {}", loc)
+        }
+    }
+}
+
+impl fmt::Display for Terminator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Terminator::Jmp(dst) => write!(f, "jmp {}", dst),
+            Terminator::Br { ty, cond, then, els } => write!(f, "br {} {}, {}, {}", ty, Typed(*ty, *cond), then, els),
+            Terminator::Switch { val, cases, default } => {
+                write!(f, "switch {} [", val)?;
+                for (val, dst) in cases {
+                    write!(f, "{}: {}, ", val, dst)?;
+                }
+                write!(f, "default: {}]", default)
+            },
+            Terminator::Ret(Some((ty, val))) => write!(f, "ret {} {}", ty, Typed(*ty, *val)),
+            Terminator::Ret(None) => write!(f, "ret")
+        }
+    }
+}
+
+// an operand whose immediate is shown as a floating-point number if `ty` is
+struct Typed(Ty, Operand);
+
+impl fmt::Display for Typed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Typed(Ty::F32, Operand::Imm(bits)) => write!(f, "{:?}", f32::from_bits(*bits as u32)),
+            Typed(Ty::F64, Operand::Imm(bits)) => write!(f, "{:?}", f64::from_bits(*bits as u64)),
+            Typed(_, op) => write!(f, "{}", op)
+        }
+    }
+}
+
+impl Callee {
+    fn operand_mut(&mut self) -> Option<&mut Operand> {
+        match self {
+            Callee::Indirect(op) => Some(op),
+            Callee::Direct(_) => None
+        }
+    }
+}
+
+impl fmt::Display for Callee {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Callee::Direct(name) => write!(f, "{}", name),
+            Callee::Indirect(op) => write!(f, "(*{})", op)
+        }
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Operand::Reg(reg) => write!(f, "{}", reg),
+            Operand::Imm(val) => write!(f, "{}", val)
+        }
+    }
+}
+
+impl fmt::Display for Reg {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "%{}", self.0)
+    }
+}
+
+impl fmt::Display for BlockId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "bb{}", self.0)
+    }
+}
+
+impl fmt::Display for Ty {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Ty::I8 => "i8",
+            Ty::U8 => "u8",
+            Ty::I16 => "i16",
+            Ty::U16 => "u16",
+            Ty::I32 => "i32",
+            Ty::U32 => "u32",
+            Ty::I64 => "i64",
+            Ty::U64 => "u64",
+            Ty::F32 => "f32",
+            Ty::F64 => "f64"
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// struct 12 (i64, f64)
+impl fmt::Display for StructRegs {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let eightbytes: Vec<String> = self.eightbytes.iter().map(|ty| ty.to_string()).collect();
+        write!(f, "struct {} ({})", self.size, eightbytes.join(", "))
+    }
+}
+
+impl fmt::Display for ArgTy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ArgTy::Scalar(ty) => write!(f, "{}", ty),
+            ArgTy::Regs(regs) => write!(f, "{}", regs),
+            ArgTy::Memory(size) => write!(f, "struct {} (memory)", size)
+        }
+    }
+}
+
+impl fmt::Display for BinOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            BinOp::Add => "add",
+            BinOp::Sub => "sub",
+            BinOp::Mul => "mul",
+            BinOp::Div => "div",
+            BinOp::Rem => "rem",
+            BinOp::And => "and",
+            BinOp::Or => "or",
+            BinOp::Xor => "xor",
+            BinOp::Shl => "shl",
+            BinOp::Shr => "shr"
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl fmt::Display for Cond {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Cond::Eq => "eq",
+            Cond::Ne => "ne",
+            Cond::Lt => "lt",
+            Cond::Le => "le",
+            Cond::Gt => "gt",
+            Cond::Ge => "ge"
+        };
+        write!(f, "{}", name)
+    }
+}
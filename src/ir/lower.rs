@@ -0,0 +1,928 @@
+use super::{ Program, Function, Block, BlockId, Reg, Operand, Ty, ArgTy, StructRegs, BinOp, Cond, Inst, Callee, Terminator };
+use crate::program::{ self, Var };
+use crate::node::{ Stmt, StmtKind, Expr, ExprWrapper };
+use crate::_type::{ Type, BitField, common_type, promote };
+use crate::parser::const_eval;
+use crate::tokenizer::loc::Loc;
+use crate::diagnostic::{ Diagnostic, Severity, Span };
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+// lower the AST to the IR
+pub fn lower(prog: &program::Program) -> Result<Program, Diagnostic> {
+    let fns = prog.fns.iter()
+        .map(|func| Lowerer::new().function(func))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(Program { fns, globals: prog.globals.clone() })
+}
+
+// a block under construction. `term` is None until the block is closed
+struct Building {
+    insts: Vec<Inst>,
+    term: Option<Terminator>
+}
+
+struct Lowerer {
+    blocks: Vec<Building>,
+    // blocks in the order they are started, which is the layout of the function
+    order: Vec<BlockId>,
+    cur: BlockId,
+    nregs: usize,
+    // targets of break and continue
+    brk: Option<BlockId>,
+    cont: Option<BlockId>,
+    // the case blocks and the default block of the innermost switch
+    cases: Vec<BlockId>,
+    default: Option<BlockId>,
+    labels: HashMap<Rc<String>, BlockId>,
+    // the address to which a struct returned in memory is written
+    ret_ptr: Option<Reg>,
+    // the source location of the next instruction. it is emitted as Inst::Loc
+    // when the instruction is, so a statement without code leaves nothing
+    loc: Option<Loc>,
+    // the location of the statement being lowered, for an error in an expression without one
+    stmt_loc: Option<Loc>
+}
+
+impl Lowerer {
+    fn new() -> Self {
+        let mut lowerer = Self {
+            blocks: Vec::new(),
+            order: Vec::new(),
+            cur: BlockId(0),
+            nregs: 0,
+            brk: None,
+            cont: None,
+            cases: Vec::new(),
+            default: None,
+            labels: HashMap::new(),
+            ret_ptr: None,
+            loc: None,
+            stmt_loc: None
+        };
+        let entry = lowerer.new_block();
+        lowerer.start(entry);
+
+        lowerer
+    }
+
+    fn function(mut self, func: &program::Function) -> Result<Function, Diagnostic> {
+        // a struct returned in memory is written to the address given by the hidden first parameter
+        let mut params = Vec::new();
+        let mut ret_regs = None;
+        if let Type::Func { ret, .. } = func.ty.as_ref() {
+            match arg_ty(ret) {
+                ArgTy::Memory(_) => params.push(ArgTy::Scalar(Ty::U64)),
+                ArgTy::Regs(regs) => ret_regs = Some(regs),
+                ArgTy::Scalar(_) => {}
+            }
+        }
+        let hidden = params.len();
+        params.extend(func.params.iter().map(|var| arg_ty(&var.borrow().ty)));
+
+        // parameters are read before anything else clobbers the argument registers,
+        // and then stored to their local variables. a struct is copied to its variable at once
+        if hidden > 0 {
+            let dst = self.new_reg();
+            self.emit(Inst::Param { ty: Ty::U64, dst, idx: 0 });
+            self.ret_ptr = Some(dst);
+        }
+        let mut vals = Vec::new();
+        for (idx, var) in func.params.iter().enumerate().map(|(i, var)| (hidden + i, var)) {
+            match params[idx] {
+                ArgTy::Scalar(ty) => {
+                    let dst = self.new_reg();
+                    self.emit(Inst::Param { ty, dst, idx });
+                    vals.push((var, dst));
+                },
+                _ => self.emit(Inst::StructParam { idx, offset: var.borrow().offset.value() })
+            }
+        }
+        if let Some(var) = &func.va_area {
+            self.emit(Inst::VaArea { offset: var.borrow().offset.value() });
+        }
+        for (var, val) in vals {
+            let addr = self.var_addr(var);
+            self.emit(Inst::Store { ty: Ty::from_type(&var.borrow().ty), addr, val: Operand::Reg(val) });
+        }
+
+        for stmt in &func.nodes {
+            self.stmt(stmt)?;
+        }
+
+        Ok(Function {
+            name: Rc::clone(&func.name),
+            is_static: func.is_static,
+            params,
+            ret_regs,
+            blocks: self.finish(),
+            nregs: self.nregs,
+            stack_size: func.stack_size,
+            ty: Box::clone(&func.ty),
+            loc: func.loc.clone(),
+            param_vars: func.params.clone(),
+            // locals are stored in the reverse order of their declarations
+            local_vars: func.locals.iter().rev()
+                .filter(|var| !func.params.iter().any(|param| Rc::ptr_eq(param, var)))
+                .cloned()
+                .collect(),
+            promoted: Vec::new()
+        })
+    }
+
+    // renumber the blocks in the layout order. a block which falls off
+    // the end of the function returns
+    fn finish(&mut self) -> Vec<Block> {
+        for id in 0..self.blocks.len() {
+            if !self.order.contains(&BlockId(id)) {
+                self.order.push(BlockId(id));
+            }
+        }
+        let mut new_ids = vec![BlockId(0); self.blocks.len()];
+        for (new, old) in self.order.iter().enumerate() {
+            new_ids[old.0] = BlockId(new);
+        }
+        let remap = |id: BlockId| new_ids[id.0];
+
+        let mut blocks: Vec<Option<Building>> = self.blocks.drain(..).map(Some).collect();
+        self.order.iter().map(|id| {
+            let block = blocks[id.0].take().unwrap();
+            let term = match block.term.unwrap_or(Terminator::Ret(None)) {
+                Terminator::Jmp(dst) => Terminator::Jmp(remap(dst)),
+                Terminator::Br { ty, cond, then, els } => Terminator::Br { ty, cond, then: remap(then), els: remap(els) },
+                Terminator::Switch { val, cases, default } => Terminator::Switch {
+                    val,
+                    cases: cases.into_iter().map(|(val, dst)| (val, remap(dst))).collect(),
+                    default: remap(default)
+                },
+                ret => ret
+            };
+            Block { insts: block.insts, term }
+        }).collect()
+    }
+
+    fn new_block(&mut self) -> BlockId {
+        self.blocks.push(Building { insts: Vec::new(), term: None });
+        BlockId(self.blocks.len() - 1)
+    }
+
+    fn start(&mut self, block: BlockId) {
+        self.cur = block;
+        self.order.push(block);
+    }
+
+    fn is_closed(&self) -> bool {
+        self.blocks[self.cur.0].term.is_some()
+    }
+
+    fn new_reg(&mut self) -> Reg {
+        self.nregs += 1;
+        Reg(self.nregs - 1)
+    }
+
+    // code after a jump or a return is put in a new block, which is unreachable
+    // unless it has a label
+    fn emit(&mut self, inst: Inst) {
+        if self.is_closed() {
+            let block = self.new_block();
+            self.start(block);
+        }
+        self.emit_loc();
+        self.blocks[self.cur.0].insts.push(inst);
+    }
+
+    fn close(&mut self, term: Terminator) {
+        if self.is_closed() {
+            let block = self.new_block();
+            self.start(block);
+        }
+        self.emit_loc();
+        self.blocks[self.cur.0].term = Some(term);
+    }
+
+    // the following code comes from `loc`
+    fn mark(&mut self, loc: &Option<Loc>) {
+        if loc.is_some() {
+            self.loc = loc.clone();
+        }
+    }
+
+    fn emit_loc(&mut self) {
+        if let Some(loc) = self.loc.take() {
+            self.blocks[self.cur.0].insts.push(Inst::Loc(loc));
+        }
+    }
+
+    // jump to `block` unless the current block is already closed
+    fn jump(&mut self, block: BlockId) {
+        if !self.is_closed() {
+            self.close(Terminator::Jmp(block));
+        }
+    }
+
+    // fall through to `block`, and continue from there
+    fn fall_into(&mut self, block: BlockId) {
+        self.jump(block);
+        self.start(block);
+    }
+
+    fn branch(&mut self, cond: &ExprWrapper, then: BlockId, els: BlockId) -> Result<(), Diagnostic> {
+        let val = self.expr(cond)?;
+        self.close(Terminator::Br { ty: Ty::from_type(&cond.ty), cond: val, then, els });
+
+        Ok(())
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) -> Result<(), Diagnostic> {
+        match &stmt.kind {
+            // the statement of a label is marked by itself, after the jump to the label
+            StmtKind::Label(..) | StmtKind::Case { .. } | StmtKind::Default(_) => {},
+            _ => self.mark(&stmt.loc)
+        }
+        if stmt.loc.is_some() {
+            self.stmt_loc = stmt.loc.clone();
+        }
+
+        match &stmt.kind {
+            StmtKind::Return { val } => {
+                let ret = self.expr(val)?;
+                match *val.ty {
+                    Type::Void => self.close(Terminator::Ret(None)),
+                    // a struct is returned by its address. it is copied here if it is returned in memory
+                    Type::Struct { .. } => {
+                        let src = self.as_reg(Ty::U64, ret);
+                        let addr = match self.ret_ptr {
+                            Some(dst) => {
+                                self.emit(Inst::MemCopy { dst, src, size: val.ty.size() });
+                                dst
+                            },
+                            None => src
+                        };
+                        self.close(Terminator::Ret(Some((Ty::U64, Operand::Reg(addr)))))
+                    },
+                    _ => self.close(Terminator::Ret(Some((Ty::from_type(&val.ty), ret))))
+                }
+            }
+            StmtKind::ExprStmt { val } | StmtKind::PureExpr(val) => {
+                self.expr(val)?;
+            }
+            StmtKind::If { cond, then, els } => {
+                let then_block = self.new_block();
+                let els_block = self.new_block();
+                let end = if els.is_some() { self.new_block() } else { els_block };
+
+                self.branch(cond, then_block, els_block)?;
+                self.start(then_block);
+                self.stmt(then)?;
+                if let Some(els) = els {
+                    self.jump(end);
+                    self.start(els_block);
+                    self.stmt(els)?;
+                }
+                self.fall_into(end);
+            }
+            StmtKind::While { cond, then } => {
+                let begin = self.new_block();
+                let body = self.new_block();
+                let end = self.new_block();
+
+                self.fall_into(begin);
+                self.mark(&cond.loc);
+                self.branch(cond, body, end)?;
+                self.start(body);
+                self.loop_body(then, end, begin)?;
+                self.jump(begin);
+                self.start(end);
+            }
+            StmtKind::DoWhile { then, cond } => {
+                let body = self.new_block();
+                let cont = self.new_block();
+                let end = self.new_block();
+
+                self.fall_into(body);
+                // `continue` jumps to the condition, not to the beginning of the body
+                self.loop_body(then, end, cont)?;
+                self.fall_into(cont);
+                self.mark(&cond.loc);
+                self.branch(cond, body, end)?;
+                self.start(end);
+            }
+            StmtKind::For { init, cond, inc, then } => {
+                let begin = self.new_block();
+                let body = self.new_block();
+                let cont = self.new_block();
+                let end = self.new_block();
+
+                if let Some(init) = init.as_ref() {
+                    self.stmt(init)?;
+                }
+                self.fall_into(begin);
+                match cond {
+                    Some(cond) => {
+                        self.mark(&cond.loc);
+                        self.branch(cond, body, end)?
+                    },
+                    None => self.close(Terminator::Jmp(body))
+                }
+                self.start(body);
+                self.loop_body(then, end, cont)?;
+                self.fall_into(cont);
+                if let Some(inc) = inc.as_ref() {
+                    self.stmt(inc)?;
+                }
+                self.close(Terminator::Jmp(begin));
+                self.start(end);
+            }
+            StmtKind::Block { stmts } => {
+                for stmt in stmts {
+                    self.stmt(stmt)?;
+                }
+            }
+            StmtKind::Break => {
+                let brk = self.brk.ok_or_else(|| self.error(&stmt.loc, "stray break"))?;
+                self.close(Terminator::Jmp(brk));
+            }
+            StmtKind::Continue => {
+                let cont = self.cont.ok_or_else(|| self.error(&stmt.loc, "stray continue"))?;
+                self.close(Terminator::Jmp(cont));
+            }
+            StmtKind::Goto(name) => {
+                let block = self.label(name);
+                self.close(Terminator::Jmp(block));
+            }
+            StmtKind::Label(stmt, name) => {
+                let block = self.label(name);
+                self.fall_into(block);
+                self.stmt(stmt)?;
+            }
+            StmtKind::Switch { cond, then, cases, has_default } => {
+                let val = self.expr(cond)?;
+                let end = self.new_block();
+                let case_blocks: Vec<BlockId> = cases.iter().map(|_| self.new_block()).collect();
+                let default = if *has_default { Some(self.new_block()) } else { None };
+                self.close(Terminator::Switch {
+                    val,
+                    cases: cases.iter().map(|val| *val as i64).zip(case_blocks.iter().copied()).collect(),
+                    default: default.unwrap_or(end)
+                });
+
+                let brk = self.brk.replace(end);
+                let outer_cases = std::mem::replace(&mut self.cases, case_blocks);
+                let outer_default = std::mem::replace(&mut self.default, default);
+                self.stmt(then)?;
+                self.brk = brk;
+                self.cases = outer_cases;
+                self.default = outer_default;
+
+                self.fall_into(end);
+            }
+            StmtKind::Case { idx, stmt, .. } => {
+                let block = self.cases[*idx];
+                self.fall_into(block);
+                self.stmt(stmt)?;
+            }
+            StmtKind::Default(stmt) => {
+                let block = self.default.ok_or_else(|| self.error(&stmt.loc, "stray default"))?;
+                self.fall_into(block);
+                self.stmt(stmt)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn loop_body(&mut self, body: &Stmt, brk: BlockId, cont: BlockId) -> Result<(), Diagnostic> {
+        let brk = self.brk.replace(brk);
+        let cont = self.cont.replace(cont);
+        self.stmt(body)?;
+        self.brk = brk;
+        self.cont = cont;
+
+        Ok(())
+    }
+
+    // the block of a label, which may be referred to by a goto before its definition
+    fn label(&mut self, name: &Rc<String>) -> BlockId {
+        if let Some(block) = self.labels.get(name) {
+            return *block
+        }
+        let block = self.new_block();
+        self.labels.insert(Rc::clone(name), block);
+
+        block
+    }
+
+    fn expr(&mut self, ew: &ExprWrapper) -> Result<Operand, Diagnostic> {
+        if let Some(val) = fold(ew) {
+            return Ok(val)
+        }
+
+        let val = match ew.expr.as_ref() {
+            Expr::AddEq { var, val }
+            | Expr::PtrAddEq { var, val }
+            | Expr::SubEq { var, val }
+            | Expr::PtrSubEq { var, val }
+            | Expr::MulEq { var, val }
+            | Expr::DivEq { var, val }
+            | Expr::ModEq { var, val }
+            | Expr::BitAndEq { var, val }
+            | Expr::BitOrEq { var, val }
+            | Expr::BitXorEq { var, val }
+            | Expr::ShlEq { var, val }
+            | Expr::ShrEq { var, val } => {
+                // both operands are converted to their common type,
+                // and the result is converted back to the type of `var`
+                let addr = self.lval(var)?;
+                let cur = self.load_lval(var, addr);
+                let rhs = self.expr(val)?;
+                let res = match ew.expr.as_ref() {
+                    Expr::PtrAddEq { .. } => self.ptr_arith(BinOp::Add, cur, rhs, var.ty.base_size()),
+                    Expr::PtrSubEq { .. } => self.ptr_arith(BinOp::Sub, cur, rhs, var.ty.base_size()),
+                    expr => {
+                        let ty = match expr {
+                            // a shift is done in the promoted type of `var`
+                            Expr::ShlEq { .. } | Expr::ShrEq { .. } => promote(&var.ty),
+                            _ => arith_operand_type(var, val).unwrap_or_else(|| *var.ty.clone())
+                        };
+                        let lhs = self.convert(cur, &var.ty, &ty);
+                        let rhs = self.convert(rhs, &val.ty, &ty);
+                        let res = self.bin(bin_op(expr), Ty::from_type(&ty), lhs, rhs);
+                        self.convert(res, &ty, &var.ty)
+                    }
+                };
+                self.store_lval(var, addr, res)
+            }
+            Expr::Add { lhs, rhs }
+            | Expr::Sub { lhs, rhs }
+            | Expr::Mul { lhs, rhs }
+            | Expr::Div { lhs, rhs }
+            | Expr::Mod { lhs, rhs }
+            | Expr::BitAnd { lhs, rhs }
+            | Expr::BitOr { lhs, rhs }
+            | Expr::BitXor { lhs, rhs }
+            | Expr::Shl { lhs, rhs }
+            | Expr::Shr { lhs, rhs } => {
+                let lhs = self.expr(lhs)?;
+                let rhs = self.expr(rhs)?;
+                self.bin(bin_op(&ew.expr), Ty::from_type(&ew.ty), lhs, rhs)
+            }
+            Expr::PtrAdd { lhs, rhs } | Expr::PtrSub { lhs, rhs } => {
+                let op = bin_op(&ew.expr);
+                let lhs = self.expr(lhs)?;
+                let rhs = self.expr(rhs)?;
+                self.ptr_arith(op, lhs, rhs, ew.ty.base_size())
+            }
+            Expr::PtrDiff { lhs, rhs } => {
+                let size = lhs.ty.base_size() as i64;
+                let lhs = self.expr(lhs)?;
+                let rhs = self.expr(rhs)?;
+                let diff = self.bin(BinOp::Sub, Ty::I64, lhs, rhs);
+                self.bin(BinOp::Div, Ty::I64, diff, Operand::Imm(size))
+            }
+            Expr::Eq { lhs, rhs }
+            | Expr::Neq { lhs, rhs }
+            | Expr::Lt { lhs, rhs }
+            | Expr::Le { lhs, rhs }
+            | Expr::Gt { lhs, rhs }
+            | Expr::Ge { lhs, rhs } => {
+                let ty = cmp_operand_type(lhs, rhs);
+                let cond = match ew.expr.as_ref() {
+                    Expr::Eq { .. } => Cond::Eq,
+                    Expr::Neq { .. } => Cond::Ne,
+                    Expr::Lt { .. } => Cond::Lt,
+                    Expr::Le { .. } => Cond::Le,
+                    Expr::Gt { .. } => Cond::Gt,
+                    _ => Cond::Ge
+                };
+                let lhs = self.expr(lhs)?;
+                let rhs = self.expr(rhs)?;
+                self.cmp(cond, ty, lhs, rhs)
+            }
+            Expr::Num { val, .. } => Operand::Imm(*val as i64),
+            // a floating-point value is held as its bit pattern
+            Expr::FNum { val } => Operand::Imm(val.to_bits() as i64),
+            Expr::Cast(ty, operand) => {
+                let val = self.expr(operand)?;
+                self.convert(val, &operand.ty, ty)
+            }
+            Expr::Var(_) | Expr::Member(_, _) => {
+                let addr = self.addr(ew)?;
+                self.load_lval(ew, addr)
+            }
+            Expr::Deref { operand } => {
+                let addr = self.expr(operand)?;
+                let addr = self.as_reg(Ty::U64, addr);
+                self.load(&ew.ty, addr)
+            }
+            Expr::Addr { operand } => Operand::Reg(self.addr(operand)?),
+            Expr::Assign { var, val } => {
+                let addr = self.lval(var)?;
+                let val = self.expr(val)?;
+                self.store_lval(var, addr, val)
+            }
+            Expr::PreInc(var) | Expr::PreDec(var) | Expr::PostInc(var) | Expr::PostDec(var) => {
+                let op = match ew.expr.as_ref() {
+                    Expr::PreInc(_) | Expr::PostInc(_) => BinOp::Add,
+                    _ => BinOp::Sub
+                };
+                let addr = self.lval(var)?;
+                let cur = self.load_lval(var, addr);
+                let one = match *var.ty {
+                    Type::Float => Operand::Imm(1.0f32.to_bits() as i64),
+                    Type::Double => Operand::Imm(1.0f64.to_bits() as i64),
+                    _ if var.ty.has_base() => Operand::Imm(var.ty.base_size() as i64),
+                    _ => Operand::Imm(1)
+                };
+                let new = self.bin(op, Ty::from_type(&var.ty), cur, one);
+                let new = self.store_lval(var, addr, new);
+                match ew.expr.as_ref() {
+                    Expr::PreInc(_) | Expr::PreDec(_) => new,
+                    _ => cur
+                }
+            }
+            Expr::Comma { lhs, rhs } => {
+                self.stmt(lhs)?;
+                self.expr(rhs)?
+            }
+            Expr::FnCall { callee, args, ret_buf } => {
+                let ret = Ty::from_type(&ew.ty);
+                // a function is called by its name unless it is called through a pointer
+                let callee = match callee.expr.as_ref() {
+                    Expr::Var(var) if !var.borrow().is_local && matches!(*var.borrow().ty, Type::Func { .. }) => {
+                        Callee::Direct(var.borrow().name.clone())
+                    },
+                    _ => Callee::Indirect(self.expr(callee)?)
+                };
+
+                // a struct returned in memory is written to the address passed as the hidden first argument
+                let mut vals = Vec::new();
+                let ret_ty = arg_ty(&ew.ty);
+                if let (ArgTy::Memory(_), Some(var)) = (&ret_ty, ret_buf) {
+                    let addr = self.var_addr(var);
+                    vals.push((ArgTy::Scalar(Ty::U64), Operand::Reg(addr)));
+                }
+                for arg in args {
+                    vals.push((arg_ty(&arg.ty), self.expr(arg)?));
+                }
+
+                match (ret_ty, ret_buf) {
+                    (ArgTy::Regs(regs), Some(var)) => {
+                        let ret_buf = Some((regs, var.borrow().offset.value()));
+                        self.emit(Inst::Call { ret, dst: None, callee, args: vals, ret_buf });
+                        Operand::Reg(self.var_addr(var))
+                    },
+                    (ArgTy::Memory(_), Some(_)) => {
+                        let addr = vals[0].1;
+                        self.emit(Inst::Call { ret, dst: None, callee, args: vals, ret_buf: None });
+                        addr
+                    },
+                    _ if *ew.ty == Type::Void => {
+                        self.emit(Inst::Call { ret, dst: None, callee, args: vals, ret_buf: None });
+                        Operand::Imm(0)
+                    },
+                    _ => {
+                        let dst = self.new_reg();
+                        self.emit(Inst::Call { ret, dst: Some(dst), callee, args: vals, ret_buf: None });
+                        Operand::Reg(dst)
+                    }
+                }
+            }
+            Expr::Not(operand) => {
+                let val = self.expr(operand)?;
+                self.cmp(Cond::Eq, Ty::from_type(&operand.ty), val, Operand::Imm(0))
+            }
+            Expr::BitNot(operand) => {
+                let src = self.expr(operand)?;
+                let dst = self.new_reg();
+                self.emit(Inst::Not { ty: Ty::from_type(&ew.ty), dst, src });
+                Operand::Reg(dst)
+            }
+            Expr::LogAnd { lhs, rhs } | Expr::LogOr { lhs, rhs } => {
+                let rhs_block = self.new_block();
+                let true_block = self.new_block();
+                let false_block = self.new_block();
+                let end = self.new_block();
+                let dst = self.new_reg();
+
+                match ew.expr.as_ref() {
+                    Expr::LogAnd { .. } => self.branch(lhs, rhs_block, false_block)?,
+                    _ => self.branch(lhs, true_block, rhs_block)?
+                }
+                self.start(rhs_block);
+                self.branch(rhs, true_block, false_block)?;
+                self.start(true_block);
+                self.emit(Inst::Mov { ty: Ty::I32, dst, src: Operand::Imm(1) });
+                self.close(Terminator::Jmp(end));
+                self.start(false_block);
+                self.emit(Inst::Mov { ty: Ty::I32, dst, src: Operand::Imm(0) });
+                self.fall_into(end);
+
+                Operand::Reg(dst)
+            }
+            Expr::Cond { cond, then, els } => {
+                let then_block = self.new_block();
+                let els_block = self.new_block();
+                let end = self.new_block();
+                let ty = Ty::from_type(&ew.ty);
+                let dst = self.new_reg();
+
+                self.branch(cond, then_block, els_block)?;
+                self.start(then_block);
+                let src = self.expr(then)?;
+                self.emit(Inst::Mov { ty, dst, src });
+                self.jump(end);
+                self.start(els_block);
+                let src = self.expr(els)?;
+                self.emit(Inst::Mov { ty, dst, src });
+                self.fall_into(end);
+
+                Operand::Reg(dst)
+            }
+            Expr::Null => Operand::Imm(0),
+            // the value of the last expression statement is the value of the whole
+            Expr::StmtExpr(stmts) => {
+                let (last, stmts) = stmts.split_last().ok_or_else(|| "empty statement expression".to_string())?;
+                for stmt in stmts {
+                    self.stmt(stmt)?;
+                }
+                match &last.kind {
+                    StmtKind::PureExpr(val) => {
+                        self.mark(&last.loc);
+                        self.expr(val)?
+                    },
+                    _ => {
+                        self.stmt(last)?;
+                        Operand::Imm(0)
+                    }
+                }
+            }
+            Expr::MemZero(var) => {
+                let addr = self.var_addr(var);
+                self.emit(Inst::MemZero { addr, size: var.borrow().ty.size() });
+                Operand::Imm(0)
+            }
+        };
+
+        Ok(val)
+    }
+
+    // an error at `loc`, or at the statement if the node has no location
+    fn error(&self, loc: &Option<Loc>, msg: &str) -> Diagnostic {
+        let loc = loc.as_ref().or(self.stmt_loc.as_ref());
+        Diagnostic::new(Severity::Error, msg, loc.map(|loc| Span::new(loc.clone(), 1)))
+    }
+
+    fn lval(&mut self, ew: &ExprWrapper) -> Result<Reg, Diagnostic> {
+        if let Type::Array { .. } = *ew.ty {
+            return Err(self.error(&ew.loc, "not an lvalue"))
+        }
+        self.addr(ew)
+    }
+
+    // the address of the given node
+    fn addr(&mut self, ew: &ExprWrapper) -> Result<Reg, Diagnostic> {
+        match ew.expr.as_ref() {
+            Expr::Deref { operand } => {
+                let addr = self.expr(operand)?;
+                Ok(self.as_reg(Ty::U64, addr))
+            }
+            Expr::Var(var) => Ok(self.var_addr(var)),
+            Expr::Member(base, member) => {
+                let addr = self.addr(base)?;
+                match member.offset.value() {
+                    0 => Ok(addr),
+                    offset => {
+                        let dst = self.new_reg();
+                        let (lhs, rhs) = (Operand::Reg(addr), Operand::Imm(offset as i64));
+                        self.emit(Inst::Bin { op: BinOp::Add, ty: Ty::U64, dst, lhs, rhs });
+                        Ok(dst)
+                    }
+                }
+            }
+            // a struct value, such as the one returned by a function, is its address
+            _ if matches!(*ew.ty, Type::Struct { .. }) => {
+                let addr = self.expr(ew)?;
+                Ok(self.as_reg(Ty::U64, addr))
+            }
+            _ => Err(self.error(&ew.loc, "not an lvalue"))
+        }
+    }
+
+    fn var_addr(&mut self, var: &Rc<RefCell<Var>>) -> Reg {
+        let var = var.borrow();
+        let dst = self.new_reg();
+        if var.is_local {
+            self.emit(Inst::LocalAddr { dst, name: var.name.clone(), offset: var.offset.value() });
+        } else {
+            self.emit(Inst::GlobalAddr { dst, name: var.name.clone() });
+        }
+
+        dst
+    }
+
+    // the value at `addr`. an array or a struct is represented by its address
+    fn load(&mut self, ty: &Type, addr: Reg) -> Operand {
+        match ty {
+            Type::Array { .. } | Type::Struct { .. } | Type::Func { .. } => Operand::Reg(addr),
+            _ => {
+                let dst = self.new_reg();
+                self.emit(Inst::Load { ty: Ty::from_type(ty), dst, addr });
+                Operand::Reg(dst)
+            }
+        }
+    }
+
+    // store `val` to `addr`, and return the stored value. a struct is copied from the address `val`
+    fn store(&mut self, ty: &Type, addr: Reg, val: Operand) -> Operand {
+        let val = match ty {
+            Type::Bool => self.cmp(Cond::Ne, Ty::I64, val, Operand::Imm(0)),
+            Type::Struct { .. } => {
+                let src = self.as_reg(Ty::U64, val);
+                self.emit(Inst::MemCopy { dst: addr, src, size: ty.size() });
+                return Operand::Reg(addr)
+            },
+            _ => val
+        };
+        self.emit(Inst::Store { ty: Ty::from_type(ty), addr, val });
+
+        val
+    }
+
+    // the value of an lvalue at `addr`. a bit-field is shifted out of its storage unit
+    fn load_lval(&mut self, lval: &ExprWrapper, addr: Reg) -> Operand {
+        let bf = match bit_field(lval) {
+            Some(bf) => bf,
+            None => return self.load(&lval.ty, addr)
+        };
+        let ty = Ty::from_type(&lval.ty);
+
+        // the field is moved to the top bits, and then shifted back with its sign
+        let bits = ty.size() as i64 * 8;
+        let unit = self.load(&lval.ty, addr);
+        let val = self.bin(BinOp::Shl, ty, unit, Operand::Imm(bits - (bf.offset + bf.width) as i64));
+        self.bin(BinOp::Shr, ty, val, Operand::Imm(bits - bf.width as i64))
+    }
+
+    // store `val` to an lvalue at `addr`, and return the stored value.
+    // the other bits of the storage unit of a bit-field are kept
+    fn store_lval(&mut self, lval: &ExprWrapper, addr: Reg, val: Operand) -> Operand {
+        let bf = match bit_field(lval) {
+            Some(bf) => bf,
+            None => return self.store(&lval.ty, addr, val)
+        };
+        let ty = Ty::from_type(&lval.ty);
+
+        let val = match *lval.ty {
+            Type::Bool => self.cmp(Cond::Ne, Ty::I64, val, Operand::Imm(0)),
+            _ => val
+        };
+        let mask = (u64::MAX >> (64 - bf.width)) as i64;
+        let field = self.bin(BinOp::And, Ty::I64, val, Operand::Imm(mask));
+        let field = self.bin(BinOp::Shl, Ty::I64, field, Operand::Imm(bf.offset as i64));
+        let unit = self.load(&lval.ty, addr);
+        let unit = self.bin(BinOp::And, Ty::I64, unit, Operand::Imm(!(mask << bf.offset)));
+        let unit = self.bin(BinOp::Or, Ty::I64, unit, field);
+        self.emit(Inst::Store { ty, addr, val: unit });
+
+        // the value is truncated to the width of the field
+        let bits = ty.size() as i64 * 8;
+        let val = self.bin(BinOp::Shl, ty, val, Operand::Imm(bits - bf.width as i64));
+        self.bin(BinOp::Shr, ty, val, Operand::Imm(bits - bf.width as i64))
+    }
+
+    fn bin(&mut self, op: BinOp, ty: Ty, lhs: Operand, rhs: Operand) -> Operand {
+        let dst = self.new_reg();
+        self.emit(Inst::Bin { op, ty, dst, lhs, rhs });
+        Operand::Reg(dst)
+    }
+
+    fn cmp(&mut self, cond: Cond, ty: Ty, lhs: Operand, rhs: Operand) -> Operand {
+        let dst = self.new_reg();
+        self.emit(Inst::Cmp { cond, ty, dst, lhs, rhs });
+        Operand::Reg(dst)
+    }
+
+    // ptr + n or ptr - n. n is scaled by the size of the pointee
+    fn ptr_arith(&mut self, op: BinOp, ptr: Operand, n: Operand, size: usize) -> Operand {
+        let offset = self.bin(BinOp::Mul, Ty::I64, n, Operand::Imm(size as i64));
+        self.bin(op, Ty::U64, ptr, offset)
+    }
+
+    fn convert(&mut self, val: Operand, from: &Type, to: &Type) -> Operand {
+        match to {
+            Type::Void => return val,
+            // any nonzero value, including NaN, is true
+            Type::Bool => return self.cmp(Cond::Ne, Ty::from_type(from), val, Operand::Imm(0)),
+            _ => {}
+        }
+
+        let (from, to) = (Ty::from_type(from), Ty::from_type(to));
+        if from == to {
+            return val
+        }
+        let dst = self.new_reg();
+        self.emit(Inst::Conv { from, to, dst, src: val });
+
+        Operand::Reg(dst)
+    }
+
+    fn as_reg(&mut self, ty: Ty, val: Operand) -> Reg {
+        match val {
+            Operand::Reg(reg) => reg,
+            src => {
+                let dst = self.new_reg();
+                self.emit(Inst::Mov { ty, dst, src });
+                dst
+            }
+        }
+    }
+}
+
+// evaluate a constant subexpression at compile time
+fn fold(ew: &ExprWrapper) -> Option<Operand> {
+    match ew.expr.as_ref() {
+        Expr::Num { .. } | Expr::FNum { .. } => None,
+        _ => match ew.ty.as_ref() {
+            Type::Float => const_eval::eval_double(ew).ok().map(|val| Operand::Imm((val as f32).to_bits() as i64)),
+            Type::Double => const_eval::eval_double(ew).ok().map(|val| Operand::Imm(val.to_bits() as i64)),
+            ty if ty.is_integer() => const_eval::eval(ew).ok().map(|val| Operand::Imm(val as i64)),
+            _ => None
+        }
+    }
+}
+
+// how a value of the type is passed to a function (System V ABI).
+// a struct larger than 16 bytes is passed in memory
+fn arg_ty(ty: &Type) -> ArgTy {
+    match ty {
+        Type::Struct { size, .. } if *size > 16 => ArgTy::Memory(*size),
+        Type::Struct { size, .. } => {
+            let eightbytes = (0..size.div_ceil(8))
+                .map(|i| if is_flonum_at(ty, i * 8, i * 8 + 8, 0) { Ty::F64 } else { Ty::I64 })
+                .collect();
+            ArgTy::Regs(StructRegs { size: *size, eightbytes })
+        },
+        ty => ArgTy::Scalar(Ty::from_type(ty))
+    }
+}
+
+// whether all the members of a value at `offset` which lie in [lo, hi) are floating-point numbers
+fn is_flonum_at(ty: &Type, lo: usize, hi: usize, offset: usize) -> bool {
+    match ty {
+        Type::Struct { members, .. } => {
+            members.iter().all(|member| is_flonum_at(&member.ty, lo, hi, offset + member.offset.value()))
+        },
+        Type::Array { base, len, .. } => {
+            (0..*len).all(|i| is_flonum_at(base, lo, hi, offset + base.size() * i))
+        },
+        _ => offset < lo || hi <= offset || ty.is_flonum()
+    }
+}
+
+fn bit_field(ew: &ExprWrapper) -> Option<BitField> {
+    match ew.expr.as_ref() {
+        Expr::Member(_, member) => member.bit_field,
+        _ => None
+    }
+}
+
+fn bin_op(expr: &Expr) -> BinOp {
+    match expr {
+        Expr::Add { .. } | Expr::AddEq { .. } | Expr::PtrAdd { .. } | Expr::PtrAddEq { .. } => BinOp::Add,
+        Expr::Sub { .. } | Expr::SubEq { .. } | Expr::PtrSub { .. } | Expr::PtrSubEq { .. } => BinOp::Sub,
+        Expr::Mul { .. } | Expr::MulEq { .. } => BinOp::Mul,
+        Expr::Div { .. } | Expr::DivEq { .. } => BinOp::Div,
+        Expr::Mod { .. } | Expr::ModEq { .. } => BinOp::Rem,
+        Expr::BitAnd { .. } | Expr::BitAndEq { .. } => BinOp::And,
+        Expr::BitOr { .. } | Expr::BitOrEq { .. } => BinOp::Or,
+        Expr::BitXor { .. } | Expr::BitXorEq { .. } => BinOp::Xor,
+        Expr::Shl { .. } | Expr::ShlEq { .. } => BinOp::Shl,
+        Expr::Shr { .. } | Expr::ShrEq { .. } => BinOp::Shr,
+        _ => unreachable!()
+    }
+}
+
+// the type to which both operands of a compound assignment are converted
+fn arith_operand_type(var: &ExprWrapper, val: &ExprWrapper) -> Option<Type> {
+    if var.ty.is_arithmetic() && val.ty.is_arithmetic() {
+        Some(common_type(&var.ty, &val.ty))
+    } else {
+        None
+    }
+}
+
+// pointers are compared as unsigned integers
+fn cmp_operand_type(lhs: &ExprWrapper, rhs: &ExprWrapper) -> Ty {
+    if lhs.ty.has_base() || rhs.ty.has_base() {
+        return Ty::U64
+    }
+
+    match Ty::from_type(&common_type(&lhs.ty, &rhs.ty)) {
+        ty if ty.is_flonum() => ty,
+        ty if ty.is_unsigned() => Ty::U64,
+        _ => Ty::I64
+    }
+}
@@ -0,0 +1,153 @@
+// optimization passes over the IR.
+//
+// each pass rewrites a single function in place and tells whether it changed
+// anything. the passes can be run alone (--passes=) or as the pipeline of an
+// optimization level (-O1, -O2). -O0 runs no pass
+mod cfg;
+mod const_prop;
+mod strength_reduce;
+mod copy_prop;
+mod dce;
+mod mem2reg;
+
+pub use cfg::simplify_cfg;
+pub use const_prop::const_prop;
+pub use strength_reduce::strength_reduce;
+pub use copy_prop::copy_prop;
+pub use dce::dce;
+pub use mem2reg::mem2reg;
+
+use super::{ Program, Function };
+
+// a pipeline is repeated until it changes nothing, but not more than this
+const MAX_ROUNDS: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pass {
+    SimplifyCfg,
+    ConstProp,
+    StrengthReduce,
+    CopyProp,
+    Dce,
+    Mem2Reg
+}
+
+impl Pass {
+    pub const ALL: [Pass; 6] = [
+        Pass::SimplifyCfg,
+        Pass::ConstProp,
+        Pass::StrengthReduce,
+        Pass::CopyProp,
+        Pass::Dce,
+        Pass::Mem2Reg
+    ];
+
+    // the name used by --passes=
+    pub fn name(&self) -> &'static str {
+        match self {
+            Pass::SimplifyCfg => "simplify-cfg",
+            Pass::ConstProp => "const-prop",
+            Pass::StrengthReduce => "strength-reduce",
+            Pass::CopyProp => "copy-prop",
+            Pass::Dce => "dce",
+            Pass::Mem2Reg => "mem2reg"
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Pass::ALL.iter().find(|pass| pass.name() == name).copied()
+    }
+
+    pub fn run(&self, func: &mut Function) -> bool {
+        match self {
+            Pass::SimplifyCfg => simplify_cfg(func),
+            Pass::ConstProp => const_prop(func),
+            Pass::StrengthReduce => strength_reduce(func),
+            Pass::CopyProp => copy_prop(func),
+            Pass::Dce => dce(func),
+            Pass::Mem2Reg => mem2reg(func)
+        }
+    }
+}
+
+// the passes of an optimization level. a level above 2 is the same as 2
+pub fn pipeline(level: u32) -> Vec<Pass> {
+    let mut passes = Vec::new();
+    if level >= 2 {
+        // local variables are promoted first, so that the other passes see their values
+        passes.push(Pass::Mem2Reg);
+    }
+    if level >= 1 {
+        passes.extend(&[
+            Pass::SimplifyCfg,
+            Pass::ConstProp,
+            Pass::StrengthReduce,
+            Pass::CopyProp,
+            Pass::Dce,
+            Pass::SimplifyCfg
+        ]);
+    }
+
+    passes
+}
+
+// run the passes in order over each function, and repeat them while they change it
+pub fn optimize(prog: &mut Program, passes: &[Pass]) {
+    for func in &mut prog.fns {
+        for _ in 0..MAX_ROUNDS {
+            let mut changed = false;
+            for pass in passes {
+                changed |= pass.run(func);
+            }
+            if !changed {
+                break
+            }
+        }
+    }
+}
+
+// builders of the IR for the tests of each pass
+#[cfg(test)]
+mod test_util {
+    use crate::ir::{ Function, Block, Reg, Operand, Inst, Terminator };
+    use crate::_type::Type;
+    use std::rc::Rc;
+
+    // a function of `int f()` made of the blocks
+    pub fn function(blocks: Vec<Block>) -> Function {
+        let nregs = blocks.iter()
+            .flat_map(|block| block.insts.iter().flat_map(|inst| inst.def().into_iter().chain(inst.uses())).chain(block.term.uses()))
+            .map(|reg| reg.0 + 1)
+            .max()
+            .unwrap_or(0);
+
+        Function {
+            name: Rc::new("f".to_string()),
+            is_static: false,
+            params: Vec::new(),
+            ret_regs: None,
+            blocks,
+            nregs,
+            stack_size: 16,
+            ty: Box::new(Type::Func { ret: Box::new(Type::Int), params: Vec::new(), is_variadic: false }),
+            loc: None,
+            param_vars: Vec::new(),
+            local_vars: Vec::new(),
+            promoted: Vec::new()
+        }
+    }
+
+    pub fn block(insts: Vec<Inst>, term: Terminator) -> Block {
+        Block { insts, term }
+    }
+
+    pub fn reg(n: usize) -> Operand {
+        Operand::Reg(Reg(n))
+    }
+
+    // the blocks of a function as they are printed by --emit-ir, without the header
+    pub fn body(func: &Function) -> String {
+        let text = func.to_string();
+        text[text.find('\n').unwrap() + 1..].to_string()
+    }
+}
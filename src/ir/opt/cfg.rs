@@ -0,0 +1,224 @@
+use crate::ir::{ Function, Block, BlockId, Operand, Ty, Inst, Terminator };
+
+// simplify the control flow graph:
+//   - a branch on a constant, or to the same block twice, becomes a jump
+//   - a jump to an empty block which only jumps is redirected to its target
+//   - a block is merged into its only predecessor which jumps to it
+//   - blocks unreachable from the entry, such as the code after return or
+//     goto, are removed
+pub fn simplify_cfg(func: &mut Function) -> bool {
+    let mut changed = false;
+
+    for block in &mut func.blocks {
+        if let Some(dst) = constant_target(&block.term) {
+            block.term = Terminator::Jmp(dst);
+            changed = true;
+        }
+    }
+
+    for id in 0..func.blocks.len() {
+        let mut term = func.blocks[id].term.clone();
+        for succ in term.succs_mut() {
+            let dst = skip_empty(func, *succ);
+            if dst != *succ {
+                *succ = dst;
+                changed = true;
+            }
+        }
+        func.blocks[id].term = term;
+    }
+
+    changed |= merge_blocks(func);
+    changed |= remove_unreachable(func);
+
+    changed
+}
+
+// the only possible target of a branch
+fn constant_target(term: &Terminator) -> Option<BlockId> {
+    match term {
+        Terminator::Br { then, els, .. } if then == els => Some(*then),
+        Terminator::Br { ty, cond: Operand::Imm(val), then, els } => {
+            let taken = match ty {
+                Ty::F32 => f32::from_bits(*val as u32) != 0.0,
+                Ty::F64 => f64::from_bits(*val as u64) != 0.0,
+                _ => *val != 0
+            };
+            Some(if taken { *then } else { *els })
+        },
+        Terminator::Switch { val: Operand::Imm(val), cases, default } => {
+            let dst = cases.iter().find(|(case, _)| case == val).map(|(_, dst)| *dst);
+            Some(dst.unwrap_or(*default))
+        },
+        _ => None
+    }
+}
+
+// follow a chain of empty blocks which only jump. a block with only source
+// locations is empty. an infinite loop of empty blocks is kept as it is
+fn skip_empty(func: &Function, mut id: BlockId) -> BlockId {
+    let mut visited = vec![id];
+    while let Block { insts, term: Terminator::Jmp(dst) } = &func.blocks[id.0] {
+        if insts.iter().any(|inst| !matches!(inst, Inst::Loc(_))) || visited.contains(dst) {
+            break
+        }
+        visited.push(*dst);
+        id = *dst;
+    }
+
+    id
+}
+
+// append a block to its predecessor if the predecessor unconditionally jumps to it
+// and nothing else does. the entry block is never merged into another block
+fn merge_blocks(func: &mut Function) -> bool {
+    let mut changed = false;
+    let mut preds = func.preds();
+
+    for id in 0..func.blocks.len() {
+        while let Terminator::Jmp(dst) = func.blocks[id].term {
+            if dst.0 == 0 || dst.0 == id || preds[dst.0] != [BlockId(id)] {
+                break
+            }
+
+            // the merged block is left unreachable
+            let merged = std::mem::replace(&mut func.blocks[dst.0], Block {
+                insts: Vec::new(),
+                term: Terminator::Ret(None)
+            });
+            for succ in merged.term.succs() {
+                for pred in &mut preds[succ.0] {
+                    if *pred == dst {
+                        *pred = BlockId(id);
+                    }
+                }
+            }
+            preds[dst.0].clear();
+
+            let block = &mut func.blocks[id];
+            block.insts.extend(merged.insts);
+            block.term = merged.term;
+            changed = true;
+        }
+    }
+
+    changed
+}
+
+fn remove_unreachable(func: &mut Function) -> bool {
+    let mut reachable = vec![false; func.blocks.len()];
+    let mut stack = vec![BlockId(0)];
+    while let Some(id) = stack.pop() {
+        if reachable[id.0] {
+            continue
+        }
+        reachable[id.0] = true;
+        stack.extend(func.blocks[id.0].term.succs());
+    }
+
+    if reachable.iter().all(|r| *r) {
+        return false
+    }
+
+    // the remaining blocks keep their order
+    let mut new_ids = vec![None; func.blocks.len()];
+    let mut n = 0;
+    for (id, r) in reachable.iter().enumerate() {
+        if *r {
+            new_ids[id] = Some(BlockId(n));
+            n += 1;
+        }
+    }
+
+    let blocks = std::mem::take(&mut func.blocks);
+    func.blocks = blocks.into_iter()
+        .zip(reachable)
+        .filter(|(_, r)| *r)
+        .map(|(mut block, _)| {
+            for succ in block.term.succs_mut() {
+                *succ = new_ids[succ.0].unwrap();
+            }
+            block
+        })
+        .collect();
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::simplify_cfg;
+    use crate::ir::opt::test_util::{ function, block, reg, body };
+    use crate::ir::{ BlockId, Operand, Ty, Inst, Reg, Terminator };
+
+    #[test]
+    fn constant_branch_becomes_jump() {
+        let mut func = function(vec![
+            block(vec![], Terminator::Br { ty: Ty::I32, cond: Operand::Imm(1), then: BlockId(1), els: BlockId(2) }),
+            block(vec![Inst::Mov { ty: Ty::I32, dst: Reg(0), src: Operand::Imm(1) }], Terminator::Ret(Some((Ty::I32, reg(0))))),
+            block(vec![], Terminator::Ret(Some((Ty::I32, Operand::Imm(2)))))
+        ]);
+
+        assert!(simplify_cfg(&mut func));
+        assert_eq!(body(&func), "\
+bb0:
+  %0 = mov i32 1
+  ret i32 %0
+");
+    }
+
+    #[test]
+    fn switch_on_constant_becomes_jump() {
+        let mut func = function(vec![
+            block(vec![], Terminator::Switch { val: Operand::Imm(2), cases: vec![(1, BlockId(1)), (2, BlockId(2))], default: BlockId(1) }),
+            block(vec![], Terminator::Ret(Some((Ty::I32, Operand::Imm(1))))),
+            block(vec![], Terminator::Ret(Some((Ty::I32, Operand::Imm(2)))))
+        ]);
+
+        assert!(simplify_cfg(&mut func));
+        assert_eq!(body(&func), "\
+bb0:
+  ret i32 2
+");
+    }
+
+    #[test]
+    fn jump_skips_empty_blocks() {
+        let mut func = function(vec![
+            block(vec![Inst::Param { ty: Ty::I32, dst: Reg(0), idx: 0 }], Terminator::Br { ty: Ty::I32, cond: reg(0), then: BlockId(1), els: BlockId(3) }),
+            block(vec![], Terminator::Jmp(BlockId(2))),
+            block(vec![], Terminator::Jmp(BlockId(4))),
+            block(vec![Inst::Mov { ty: Ty::I32, dst: Reg(0), src: Operand::Imm(5) }], Terminator::Jmp(BlockId(4))),
+            block(vec![], Terminator::Ret(Some((Ty::I32, reg(0)))))
+        ]);
+
+        assert!(simplify_cfg(&mut func));
+        assert_eq!(body(&func), "\
+bb0:
+  %0 = param i32 0
+  br i32 %0, bb2, bb1
+bb1:  ; preds bb0
+  %0 = mov i32 5
+  jmp bb2
+bb2:  ; preds bb0, bb1
+  ret i32 %0
+");
+    }
+
+    // the blocks of `for (;;);` jump to themselves
+    #[test]
+    fn empty_loop_is_kept() {
+        let mut func = function(vec![
+            block(vec![], Terminator::Jmp(BlockId(1))),
+            block(vec![], Terminator::Jmp(BlockId(1)))
+        ]);
+
+        assert!(!simplify_cfg(&mut func));
+        assert_eq!(body(&func), "\
+bb0:
+  jmp bb1
+bb1:  ; preds bb0, bb1
+  jmp bb1
+");
+    }
+}
@@ -0,0 +1,359 @@
+use crate::ir::{ Function, Reg, Operand, Ty, BinOp, Cond, Inst };
+
+use std::collections::HashMap;
+
+// the value of a register at a point of the function. a register which is not
+// in the map is not written on any path reaching there
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Value {
+    Const(i64),
+    Varies
+}
+
+type State = HashMap<Reg, Value>;
+
+// constant propagation.
+// the values of the registers are propagated over the control flow graph, and
+// an operand whose register has the same constant on every path is replaced
+// with the constant. an instruction whose operands are all constants is folded
+// to a move of its result
+pub fn const_prop(func: &mut Function) -> bool {
+    let states = block_entry_states(func);
+    let mut changed = false;
+
+    for (block, state) in func.blocks.iter_mut().zip(states) {
+        // an unreachable block is left to simplify-cfg
+        let mut state = match state {
+            Some(state) => state,
+            None => continue
+        };
+
+        for inst in &mut block.insts {
+            for op in inst.operands_mut() {
+                if let Some(val) = constant(&state, *op) {
+                    *op = Operand::Imm(val);
+                    changed = true;
+                }
+            }
+            if let (Some(dst), Some(val)) = (inst.def(), fold(inst)) {
+                if !is_mov_of(inst, val) {
+                    *inst = Inst::Mov { ty: result_type(inst), dst, src: Operand::Imm(val) };
+                    changed = true;
+                }
+            }
+            transfer(&mut state, inst);
+        }
+
+        if let Some(op) = block.term.operand_mut() {
+            if let Some(val) = constant(&state, *op) {
+                *op = Operand::Imm(val);
+                changed = true;
+            }
+        }
+    }
+
+    changed
+}
+
+// the state at the beginning of each block, or None for an unreachable block
+fn block_entry_states(func: &Function) -> Vec<Option<State>> {
+    let preds = func.preds();
+    let mut ins: Vec<Option<State>> = vec![None; func.blocks.len()];
+    let mut outs: Vec<Option<State>> = vec![None; func.blocks.len()];
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (id, block) in func.blocks.iter().enumerate() {
+            let mut state = if id == 0 { Some(State::new()) } else { None };
+            for pred in &preds[id] {
+                if let Some(out) = &outs[pred.0] {
+                    state = Some(match state {
+                        Some(state) => meet(state, out),
+                        None => out.clone()
+                    });
+                }
+            }
+            let mut state = match state {
+                Some(state) => state,
+                None => continue
+            };
+
+            ins[id] = Some(state.clone());
+            for inst in &block.insts {
+                transfer(&mut state, inst);
+            }
+            if outs[id].as_ref() != Some(&state) {
+                outs[id] = Some(state);
+                changed = true;
+            }
+        }
+    }
+
+    ins
+}
+
+fn meet(mut state: State, other: &State) -> State {
+    for (reg, val) in other {
+        let met = match state.get(reg) {
+            Some(cur) if cur != val => Value::Varies,
+            _ => *val
+        };
+        state.insert(*reg, met);
+    }
+
+    state
+}
+
+fn transfer(state: &mut State, inst: &Inst) {
+    if let Some(dst) = inst.def() {
+        let val = match inst {
+            Inst::Mov { src: Operand::Reg(src), .. } => state.get(src).copied().unwrap_or(Value::Varies),
+            _ => {
+                let mut inst = inst.clone();
+                for op in inst.operands_mut() {
+                    if let Some(val) = constant(state, *op) {
+                        *op = Operand::Imm(val);
+                    }
+                }
+                fold(&inst).map_or(Value::Varies, Value::Const)
+            }
+        };
+        state.insert(dst, val);
+    }
+}
+
+fn constant(state: &State, op: Operand) -> Option<i64> {
+    match op {
+        Operand::Reg(reg) => match state.get(&reg) {
+            Some(Value::Const(val)) => Some(*val),
+            _ => None
+        },
+        Operand::Imm(_) => None
+    }
+}
+
+fn is_mov_of(inst: &Inst, val: i64) -> bool {
+    match inst {
+        Inst::Mov { src: Operand::Imm(src), .. } => *src == val,
+        _ => false
+    }
+}
+
+// the type of the value which an instruction writes
+fn result_type(inst: &Inst) -> Ty {
+    match inst {
+        Inst::Mov { ty, .. } | Inst::Bin { ty, .. } | Inst::Not { ty, .. } => *ty,
+        Inst::Conv { to, .. } => *to,
+        _ => Ty::I32
+    }
+}
+
+// the result of an instruction whose operands are all constants.
+// None if it cannot be computed at compile time, or if the machine
+// instruction would trap
+fn fold(inst: &Inst) -> Option<i64> {
+    match *inst {
+        Inst::Mov { src: Operand::Imm(val), .. } => Some(val),
+        Inst::Bin { op, ty, lhs: Operand::Imm(lhs), rhs: Operand::Imm(rhs), .. } => fold_bin(op, ty, lhs, rhs),
+        Inst::Cmp { cond, ty, lhs: Operand::Imm(lhs), rhs: Operand::Imm(rhs), .. } => Some(fold_cmp(cond, ty, lhs, rhs) as i64),
+        Inst::Not { ty, src: Operand::Imm(val), .. } => Some(canonical(!val, ty)),
+        Inst::Conv { from, to, src: Operand::Imm(val), .. } => fold_conv(from, to, val),
+        _ => None
+    }
+}
+
+fn fold_bin(op: BinOp, ty: Ty, lhs: i64, rhs: i64) -> Option<i64> {
+    match ty {
+        Ty::F32 => {
+            let (lhs, rhs) = (f32::from_bits(lhs as u32), f32::from_bits(rhs as u32));
+            let val = match op {
+                BinOp::Add => lhs + rhs,
+                BinOp::Sub => lhs - rhs,
+                BinOp::Mul => lhs * rhs,
+                BinOp::Div => lhs / rhs,
+                _ => return None
+            };
+            return Some(val.to_bits() as i64)
+        },
+        Ty::F64 => {
+            let (lhs, rhs) = (f64::from_bits(lhs as u64), f64::from_bits(rhs as u64));
+            let val = match op {
+                BinOp::Add => lhs + rhs,
+                BinOp::Sub => lhs - rhs,
+                BinOp::Mul => lhs * rhs,
+                BinOp::Div => lhs / rhs,
+                _ => return None
+            };
+            return Some(val.to_bits() as i64)
+        },
+        _ => {}
+    }
+
+    // the operation is done in 64 bits, as the generated code does
+    let val = match op {
+        BinOp::Add => lhs.wrapping_add(rhs),
+        BinOp::Sub => lhs.wrapping_sub(rhs),
+        BinOp::Mul => lhs.wrapping_mul(rhs),
+        BinOp::Div | BinOp::Rem if rhs == 0 => return None,
+        BinOp::Div | BinOp::Rem if ty.is_unsigned() => {
+            let (lhs, rhs) = (lhs as u64, rhs as u64);
+            (if op == BinOp::Div { lhs / rhs } else { lhs % rhs }) as i64
+        },
+        // i64::MIN / -1 overflows
+        BinOp::Div => lhs.checked_div(rhs)?,
+        BinOp::Rem => lhs.checked_rem(rhs)?,
+        BinOp::And => lhs & rhs,
+        BinOp::Or => lhs | rhs,
+        BinOp::Xor => lhs ^ rhs,
+        BinOp::Shl => lhs << (rhs & 63),
+        BinOp::Shr if ty.is_unsigned() => ((lhs as u64) >> (rhs & 63)) as i64,
+        BinOp::Shr => lhs >> (rhs & 63)
+    };
+
+    Some(canonical(val, ty))
+}
+
+fn fold_cmp(cond: Cond, ty: Ty, lhs: i64, rhs: i64) -> bool {
+    // None if either operand is NaN
+    let ord = match ty {
+        Ty::F32 => f32::from_bits(lhs as u32).partial_cmp(&f32::from_bits(rhs as u32)),
+        Ty::F64 => f64::from_bits(lhs as u64).partial_cmp(&f64::from_bits(rhs as u64)),
+        _ if ty.is_unsigned() => Some((lhs as u64).cmp(&(rhs as u64))),
+        _ => Some(lhs.cmp(&rhs))
+    };
+
+    match cond {
+        Cond::Eq => ord.is_some_and(|ord| ord.is_eq()),
+        Cond::Ne => ord.is_none_or(|ord| ord.is_ne()),
+        Cond::Lt => ord.is_some_and(|ord| ord.is_lt()),
+        Cond::Le => ord.is_some_and(|ord| ord.is_le()),
+        Cond::Gt => ord.is_some_and(|ord| ord.is_gt()),
+        Cond::Ge => ord.is_some_and(|ord| ord.is_ge())
+    }
+}
+
+fn fold_conv(from: Ty, to: Ty, val: i64) -> Option<i64> {
+    let float = match from {
+        Ty::F32 => Some(f32::from_bits(val as u32) as f64),
+        Ty::F64 => Some(f64::from_bits(val as u64)),
+        _ => None
+    };
+
+    match (float, to) {
+        (Some(val), Ty::F32) => Some((val as f32).to_bits() as i64),
+        (Some(val), Ty::F64) => Some(val.to_bits() as i64),
+        (Some(val), Ty::U64) if (0.0..-2.0 * i64::MIN as f64).contains(&val) => Some(val as u64 as i64),
+        // cvtt gives an undefined value for NaN or a value out of the range of i64
+        (Some(val), _) if !(i64::MIN as f64..-(i64::MIN as f64)).contains(&val) => None,
+        (Some(val), _) => Some(canonical(val as i64, to)),
+        (None, Ty::F32) if from == Ty::U64 => Some((val as u64 as f32).to_bits() as i64),
+        (None, Ty::F64) if from == Ty::U64 => Some((val as u64 as f64).to_bits() as i64),
+        (None, Ty::F32) => Some((val as f32).to_bits() as i64),
+        (None, Ty::F64) => Some((val as f64).to_bits() as i64),
+        (None, _) => Some(canonical(val, to))
+    }
+}
+
+// sign- or zero-extend the lower bits of a value according to its type
+fn canonical(val: i64, ty: Ty) -> i64 {
+    match ty {
+        Ty::I8 => val as i8 as i64,
+        Ty::U8 => val as u8 as i64,
+        Ty::I16 => val as i16 as i64,
+        Ty::U16 => val as u16 as i64,
+        Ty::I32 => val as i32 as i64,
+        Ty::U32 | Ty::F32 => val as u32 as i64,
+        Ty::I64 | Ty::U64 | Ty::F64 => val
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::const_prop;
+    use crate::ir::opt::test_util::{ function, block, reg, body };
+    use crate::ir::{ BlockId, Operand, Ty, BinOp, Cond, Inst, Reg, Terminator };
+
+    // a diamond whose arms do not write %0, as `int x = 2; if (p) ...; return x + 3;`
+    #[test]
+    fn constant_is_propagated_over_blocks() {
+        let mut func = function(vec![
+            block(vec![
+                Inst::Mov { ty: Ty::I32, dst: Reg(0), src: Operand::Imm(2) },
+                Inst::Param { ty: Ty::I32, dst: Reg(1), idx: 0 }
+            ], Terminator::Br { ty: Ty::I32, cond: reg(1), then: BlockId(1), els: BlockId(2) }),
+            block(vec![], Terminator::Jmp(BlockId(2))),
+            block(vec![
+                Inst::Bin { op: BinOp::Add, ty: Ty::I32, dst: Reg(2), lhs: reg(0), rhs: Operand::Imm(3) }
+            ], Terminator::Ret(Some((Ty::I32, reg(2)))))
+        ]);
+
+        assert!(const_prop(&mut func));
+        assert_eq!(body(&func), "\
+bb0:
+  %0 = mov i32 2
+  %1 = param i32 0
+  br i32 %1, bb1, bb2
+bb1:  ; preds bb0
+  jmp bb2
+bb2:  ; preds bb0, bb1
+  %2 = mov i32 5
+  ret i32 5
+");
+    }
+
+    #[test]
+    fn different_values_are_not_propagated() {
+        let mut func = function(vec![
+            block(vec![
+                Inst::Mov { ty: Ty::I32, dst: Reg(0), src: Operand::Imm(1) },
+                Inst::Param { ty: Ty::I32, dst: Reg(1), idx: 0 }
+            ], Terminator::Br { ty: Ty::I32, cond: reg(1), then: BlockId(1), els: BlockId(2) }),
+            block(vec![Inst::Mov { ty: Ty::I32, dst: Reg(0), src: Operand::Imm(2) }], Terminator::Jmp(BlockId(2))),
+            block(vec![], Terminator::Ret(Some((Ty::I32, reg(0)))))
+        ]);
+        let before = body(&func);
+
+        assert!(!const_prop(&mut func));
+        assert_eq!(body(&func), before);
+    }
+
+    // the division traps at run time as it does without optimization
+    #[test]
+    fn division_by_zero_is_not_folded() {
+        let mut func = function(vec![
+            block(vec![
+                Inst::Mov { ty: Ty::I32, dst: Reg(0), src: Operand::Imm(0) },
+                Inst::Bin { op: BinOp::Div, ty: Ty::I32, dst: Reg(1), lhs: Operand::Imm(7), rhs: reg(0) }
+            ], Terminator::Ret(Some((Ty::I32, reg(1)))))
+        ]);
+
+        assert!(const_prop(&mut func));
+        assert_eq!(body(&func), "\
+bb0:
+  %0 = mov i32 0
+  %1 = div i32 7, 0
+  ret i32 %1
+");
+    }
+
+    #[test]
+    fn conversion_is_folded() {
+        let mut func = function(vec![
+            block(vec![
+                Inst::Mov { ty: Ty::I32, dst: Reg(0), src: Operand::Imm(300) },
+                Inst::Conv { from: Ty::I32, to: Ty::I8, dst: Reg(1), src: reg(0) },
+                Inst::Cmp { cond: Cond::Lt, ty: Ty::I8, dst: Reg(2), lhs: reg(1), rhs: Operand::Imm(50) }
+            ], Terminator::Ret(Some((Ty::I32, reg(2)))))
+        ]);
+
+        assert!(const_prop(&mut func));
+        assert_eq!(body(&func), "\
+bb0:
+  %0 = mov i32 300
+  %1 = mov i8 44
+  %2 = mov i32 1
+  ret i32 1
+");
+    }
+}
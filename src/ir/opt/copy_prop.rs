@@ -0,0 +1,226 @@
+use crate::ir::{ Function, Block, Reg, Operand, Inst };
+
+use std::collections::HashMap;
+
+// remove moves between registers:
+//   - in a block, a register copied by `%a = mov %b` is read as %b while
+//     neither of them is written again
+//   - a value which is computed only to be moved to another register is
+//     computed in that register
+//   - a move of a register to itself is removed
+pub fn copy_prop(func: &mut Function) -> bool {
+    let mut changed = false;
+
+    for block in &mut func.blocks {
+        changed |= propagate(block);
+    }
+    changed |= coalesce(func);
+
+    for block in &mut func.blocks {
+        let len = block.insts.len();
+        block.insts.retain(|inst| match inst {
+            Inst::Mov { dst, src: Operand::Reg(src), .. } => dst != src,
+            _ => true
+        });
+        changed |= block.insts.len() != len;
+    }
+
+    changed
+}
+
+fn propagate(block: &mut Block) -> bool {
+    let mut changed = false;
+    // dst => src of the copies which are still valid
+    let mut copies: HashMap<Reg, Reg> = HashMap::new();
+
+    for inst in &mut block.insts {
+        for reg in inst.uses_mut() {
+            if let Some(src) = copies.get(reg) {
+                *reg = *src;
+                changed = true;
+            }
+        }
+
+        if let Some(dst) = inst.def() {
+            copies.retain(|copy, src| *copy != dst && *src != dst);
+            if let Inst::Mov { src: Operand::Reg(src), .. } = inst {
+                if *src != dst {
+                    copies.insert(dst, *src);
+                }
+            }
+        }
+    }
+
+    if let Some(Operand::Reg(reg)) = block.term.operand_mut() {
+        if let Some(src) = copies.get(reg) {
+            *reg = *src;
+            changed = true;
+        }
+    }
+
+    changed
+}
+
+// `%t = ...; ...; %a = mov %t` => `%a = ...; ...` if %t is written and read only
+// there, and %a is neither read nor written in between. an instruction reads
+// its operands before it writes the result, so it may read %a itself
+fn coalesce(func: &mut Function) -> bool {
+    let mut defs = vec![0; func.nregs];
+    let mut uses = vec![0; func.nregs];
+    for block in &func.blocks {
+        for inst in &block.insts {
+            if let Some(reg) = inst.def() {
+                defs[reg.0] += 1;
+            }
+            for reg in inst.uses() {
+                uses[reg.0] += 1;
+            }
+        }
+        for reg in block.term.uses() {
+            uses[reg.0] += 1;
+        }
+    }
+
+    let mut changed = false;
+    for block in &mut func.blocks {
+        let mut i = 0;
+        while i < block.insts.len() {
+            let (dst, src) = match block.insts[i] {
+                Inst::Mov { dst, src: Operand::Reg(src), .. } if dst != src && defs[src.0] == 1 && uses[src.0] == 1 => (dst, src),
+                _ => {
+                    i += 1;
+                    continue
+                }
+            };
+
+            let def = block.insts[..i].iter().rposition(|inst| inst.def() == Some(src));
+            let def = match def {
+                Some(def) => def,
+                None => {
+                    i += 1;
+                    continue
+                }
+            };
+            let touched = block.insts[def + 1..i].iter()
+                .any(|inst| inst.def() == Some(dst) || inst.uses().contains(&dst));
+            if touched {
+                i += 1;
+                continue
+            }
+
+            *block.insts[def].def_mut().unwrap() = dst;
+            block.insts.remove(i);
+            defs[src.0] = 0;
+            uses[src.0] = 0;
+            changed = true;
+        }
+    }
+
+    changed
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::copy_prop;
+    use crate::ir::opt::test_util::{ function, block, reg, body };
+    use crate::ir::{ BlockId, Operand, Ty, BinOp, Inst, Reg, Terminator };
+
+    #[test]
+    fn copy_is_read_as_its_source() {
+        let mut func = function(vec![
+            block(vec![
+                Inst::Param { ty: Ty::I32, dst: Reg(0), idx: 0 },
+                Inst::Mov { ty: Ty::I32, dst: Reg(1), src: reg(0) },
+                Inst::Bin { op: BinOp::Add, ty: Ty::I32, dst: Reg(2), lhs: reg(1), rhs: Operand::Imm(1) }
+            ], Terminator::Ret(Some((Ty::I32, reg(2)))))
+        ]);
+
+        assert!(copy_prop(&mut func));
+        assert_eq!(body(&func), "\
+bb0:
+  %0 = param i32 0
+  %1 = mov i32 %0
+  %2 = add i32 %0, 1
+  ret i32 %2
+");
+    }
+
+    // %1 still holds the old value of %0
+    #[test]
+    fn copy_ends_when_its_source_is_written() {
+        let mut func = function(vec![
+            block(vec![
+                Inst::Param { ty: Ty::I32, dst: Reg(0), idx: 0 },
+                Inst::Mov { ty: Ty::I32, dst: Reg(1), src: reg(0) },
+                Inst::Bin { op: BinOp::Add, ty: Ty::I32, dst: Reg(0), lhs: reg(0), rhs: Operand::Imm(1) },
+                Inst::Bin { op: BinOp::Mul, ty: Ty::I32, dst: Reg(2), lhs: reg(1), rhs: reg(0) }
+            ], Terminator::Ret(Some((Ty::I32, reg(2)))))
+        ]);
+        let before = body(&func);
+
+        assert!(!copy_prop(&mut func));
+        assert_eq!(body(&func), before);
+    }
+
+    // `x = p * 3;` where x is read in the next block
+    #[test]
+    fn value_is_computed_in_the_destination() {
+        let mut func = function(vec![
+            block(vec![
+                Inst::Param { ty: Ty::I32, dst: Reg(0), idx: 0 },
+                Inst::Bin { op: BinOp::Mul, ty: Ty::I32, dst: Reg(1), lhs: reg(0), rhs: Operand::Imm(3) },
+                Inst::Mov { ty: Ty::I32, dst: Reg(2), src: reg(1) }
+            ], Terminator::Jmp(BlockId(1))),
+            block(vec![], Terminator::Ret(Some((Ty::I32, reg(2)))))
+        ]);
+
+        assert!(copy_prop(&mut func));
+        assert_eq!(body(&func), "\
+bb0:
+  %0 = param i32 0
+  %2 = mul i32 %0, 3
+  jmp bb1
+bb1:  ; preds bb0
+  ret i32 %2
+");
+    }
+
+    // the destination is read between the computation and the move
+    #[test]
+    fn value_is_not_computed_in_a_destination_in_use() {
+        let mut func = function(vec![
+            block(vec![
+                Inst::Param { ty: Ty::I32, dst: Reg(0), idx: 0 },
+                Inst::Param { ty: Ty::I32, dst: Reg(2), idx: 1 },
+                Inst::Bin { op: BinOp::Mul, ty: Ty::I32, dst: Reg(1), lhs: reg(0), rhs: Operand::Imm(3) },
+                Inst::Bin { op: BinOp::Add, ty: Ty::I32, dst: Reg(3), lhs: reg(2), rhs: Operand::Imm(1) },
+                Inst::Mov { ty: Ty::I32, dst: Reg(2), src: reg(1) }
+            ], Terminator::Jmp(BlockId(1))),
+            block(vec![
+                Inst::Bin { op: BinOp::Add, ty: Ty::I32, dst: Reg(4), lhs: reg(2), rhs: reg(3) }
+            ], Terminator::Ret(Some((Ty::I32, reg(4)))))
+        ]);
+        let before = body(&func);
+
+        assert!(!copy_prop(&mut func));
+        assert_eq!(body(&func), before);
+    }
+
+    #[test]
+    fn move_to_itself_is_removed() {
+        let mut func = function(vec![
+            block(vec![
+                Inst::Param { ty: Ty::I32, dst: Reg(0), idx: 0 },
+                Inst::Mov { ty: Ty::I32, dst: Reg(0), src: reg(0) }
+            ], Terminator::Ret(Some((Ty::I32, reg(0)))))
+        ]);
+
+        assert!(copy_prop(&mut func));
+        assert_eq!(body(&func), "\
+bb0:
+  %0 = param i32 0
+  ret i32 %0
+");
+    }
+}
@@ -0,0 +1,84 @@
+use crate::ir::Function;
+
+// dead code elimination.
+// an instruction without a side effect is removed if its result is never read
+pub fn dce(func: &mut Function) -> bool {
+    let mut changed = false;
+
+    // removing an instruction may make the instructions computing its operands dead
+    loop {
+        let (_, live_out) = func.liveness();
+        let mut removed = false;
+
+        for (block, live) in func.blocks.iter_mut().zip(live_out) {
+            let mut live = live;
+            live.extend(block.term.uses());
+
+            let mut keep = vec![true; block.insts.len()];
+            for (i, inst) in block.insts.iter().enumerate().rev() {
+                if let Some(dst) = inst.def() {
+                    if inst.is_pure() && !live.contains(&dst) {
+                        keep[i] = false;
+                        continue
+                    }
+                    live.remove(&dst);
+                }
+                live.extend(inst.uses());
+            }
+
+            if keep.contains(&false) {
+                let mut keep = keep.into_iter();
+                block.insts.retain(|_| keep.next().unwrap());
+                removed = true;
+            }
+        }
+
+        if !removed {
+            return changed
+        }
+        changed = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::dce;
+    use crate::ir::opt::test_util::{ function, block, reg, body };
+    use crate::ir::{ BlockId, Operand, Ty, BinOp, Inst, Reg, Callee, Terminator };
+
+    #[test]
+    fn unused_values_are_removed() {
+        let mut func = function(vec![
+            block(vec![
+                Inst::Mov { ty: Ty::I32, dst: Reg(0), src: Operand::Imm(1) },
+                Inst::Bin { op: BinOp::Add, ty: Ty::I32, dst: Reg(1), lhs: reg(0), rhs: Operand::Imm(2) },
+                Inst::Param { ty: Ty::I32, dst: Reg(2), idx: 0 }
+            ], Terminator::Ret(Some((Ty::I32, reg(2)))))
+        ]);
+
+        assert!(dce(&mut func));
+        assert_eq!(body(&func), "\
+bb0:
+  %2 = param i32 0
+  ret i32 %2
+");
+    }
+
+    // a call and a store have side effects, and %0 is read in the next block
+    #[test]
+    fn side_effects_and_live_values_are_kept() {
+        let mut func = function(vec![
+            block(vec![
+                Inst::Mov { ty: Ty::I32, dst: Reg(0), src: Operand::Imm(1) },
+                Inst::Call { ret: Ty::I32, dst: Some(Reg(1)), callee: Callee::Direct("g".to_string()), args: vec![], ret_buf: None },
+                Inst::GlobalAddr { dst: Reg(2), name: "x".to_string() },
+                Inst::Store { ty: Ty::I32, addr: Reg(2), val: Operand::Imm(3) }
+            ], Terminator::Jmp(BlockId(1))),
+            block(vec![], Terminator::Ret(Some((Ty::I32, reg(0)))))
+        ]);
+        let before = body(&func);
+
+        assert!(!dce(&mut func));
+        assert_eq!(body(&func), before);
+    }
+}
@@ -0,0 +1,148 @@
+use crate::ir::{ Function, Reg, Operand, Ty, Inst };
+
+use std::collections::HashMap;
+
+// promote local variables to registers.
+// a local variable whose address is used only to load or store it as a single
+// type never has its address taken, so its value can be held in a register.
+// the loads and stores become moves from and to the register.
+//
+// a variable whose address escapes stays in memory. the others are promoted
+// even if a pointer to the next variable could reach them, as it is undefined
+pub fn mem2reg(func: &mut Function) -> bool {
+    let mut defs = vec![0; func.nregs];
+    // the local variable (its offset) whose address each register holds
+    let mut addrs: HashMap<Reg, usize> = HashMap::new();
+    for inst in func.blocks.iter().flat_map(|block| &block.insts) {
+        if let Some(dst) = inst.def() {
+            defs[dst.0] += 1;
+        }
+        if let Inst::LocalAddr { dst, offset, .. } = inst {
+            addrs.insert(*dst, *offset);
+        }
+    }
+
+    // a register holding an address may be overwritten with something else
+    let mut escaped: Vec<usize> = addrs.iter()
+        .filter(|(reg, _)| defs[reg.0] != 1)
+        .map(|(_, offset)| *offset)
+        .collect();
+    let mut types: HashMap<usize, Ty> = HashMap::new();
+    let mut access = |addr: Reg, ty: Ty, escaped: &mut Vec<usize>| {
+        if let Some(offset) = addrs.get(&addr) {
+            if *types.entry(*offset).or_insert(ty) != ty {
+                escaped.push(*offset);
+            }
+        }
+    };
+    for block in &func.blocks {
+        for inst in &block.insts {
+            let others = match inst {
+                Inst::Load { ty, addr, .. } => {
+                    access(*addr, *ty, &mut escaped);
+                    vec![]
+                },
+                Inst::Store { ty, addr, val } => {
+                    access(*addr, *ty, &mut escaped);
+                    val.reg().into_iter().collect()
+                },
+                _ => inst.uses()
+            };
+            escaped.extend(others.iter().filter_map(|reg| addrs.get(reg)));
+            // a local variable written without a store, such as a struct parameter
+            escaped.extend(inst.local_written());
+        }
+        escaped.extend(block.term.uses().iter().filter_map(|reg| addrs.get(reg)));
+    }
+
+    // the registers are numbered in a stable order
+    let mut offsets: Vec<usize> = types.keys().filter(|offset| !escaped.contains(offset)).copied().collect();
+    offsets.sort_unstable();
+    let mut vars: HashMap<usize, Reg> = HashMap::new();
+    for offset in offsets {
+        vars.insert(offset, Reg(func.nregs));
+        func.nregs += 1;
+        func.promoted.push(offset);
+    }
+    if vars.is_empty() {
+        return false
+    }
+
+    let var_of = |addr: &Reg| addrs.get(addr).and_then(|offset| vars.get(offset)).copied();
+    for block in &mut func.blocks {
+        block.insts.retain(|inst| match inst {
+            Inst::LocalAddr { dst, .. } => var_of(dst).is_none(),
+            _ => true
+        });
+        for inst in &mut block.insts {
+            match *inst {
+                Inst::Load { ty, dst, addr } => {
+                    if let Some(var) = var_of(&addr) {
+                        *inst = Inst::Mov { ty, dst, src: Operand::Reg(var) };
+                    }
+                },
+                Inst::Store { ty, addr, val } => {
+                    if let Some(var) = var_of(&addr) {
+                        *inst = Inst::Mov { ty, dst: var, src: val };
+                    }
+                },
+                _ => {}
+            }
+        }
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::mem2reg;
+    use crate::ir::opt::test_util::{ function, block, reg, body };
+    use crate::ir::{ Operand, Ty, BinOp, Inst, Reg, ArgTy, Callee, Terminator };
+
+    // `int x = 1, y = 2; g(&y); return x + y;`
+    #[test]
+    fn escaped_variable_stays_in_memory() {
+        let mut func = function(vec![
+            block(vec![
+                Inst::LocalAddr { dst: Reg(0), name: "x".to_string(), offset: 4 },
+                Inst::Store { ty: Ty::I32, addr: Reg(0), val: Operand::Imm(1) },
+                Inst::LocalAddr { dst: Reg(1), name: "y".to_string(), offset: 8 },
+                Inst::Store { ty: Ty::I32, addr: Reg(1), val: Operand::Imm(2) },
+                Inst::Call { ret: Ty::I32, dst: Some(Reg(2)), callee: Callee::Direct("g".to_string()), args: vec![(ArgTy::Scalar(Ty::U64), reg(1))], ret_buf: None },
+                Inst::Load { ty: Ty::I32, dst: Reg(3), addr: Reg(0) },
+                Inst::Load { ty: Ty::I32, dst: Reg(4), addr: Reg(1) },
+                Inst::Bin { op: BinOp::Add, ty: Ty::I32, dst: Reg(5), lhs: reg(3), rhs: reg(4) }
+            ], Terminator::Ret(Some((Ty::I32, reg(5)))))
+        ]);
+
+        assert!(mem2reg(&mut func));
+        assert_eq!(body(&func), "\
+bb0:
+  %6 = mov i32 1
+  %1 = local y (rbp-8)
+  store i32 [%1], 2
+  %2 = call i32 g(u64 %1)
+  %3 = mov i32 %6
+  %4 = load i32 [%1]
+  %5 = add i32 %3, %4
+  ret i32 %5
+");
+    }
+
+    // a variable read as another type, such as by a union member
+    #[test]
+    fn variable_of_two_types_stays_in_memory() {
+        let mut func = function(vec![
+            block(vec![
+                Inst::LocalAddr { dst: Reg(0), name: "u".to_string(), offset: 4 },
+                Inst::Store { ty: Ty::I32, addr: Reg(0), val: Operand::Imm(257) },
+                Inst::Load { ty: Ty::I8, dst: Reg(1), addr: Reg(0) }
+            ], Terminator::Ret(Some((Ty::I8, reg(1)))))
+        ]);
+        let before = body(&func);
+
+        assert!(!mem2reg(&mut func));
+        assert_eq!(body(&func), before);
+    }
+}
@@ -0,0 +1,105 @@
+use crate::ir::{ Function, Operand, BinOp, Inst };
+
+// replace an integer operation by a power of two with a cheaper one:
+//   x * 2^n => x << n   (the scaling of a pointer offset by the size of its base type)
+//   x / 2^n => x >> n   (unsigned)
+//   x % 2^n => x & (2^n - 1)   (unsigned)
+// and a multiplication by 0 or 1 with a move
+pub fn strength_reduce(func: &mut Function) -> bool {
+    let mut changed = false;
+
+    for block in &mut func.blocks {
+        for inst in &mut block.insts {
+            if let Some(reduced) = reduce(inst) {
+                *inst = reduced;
+                changed = true;
+            }
+        }
+    }
+
+    changed
+}
+
+fn reduce(inst: &Inst) -> Option<Inst> {
+    let (op, ty, dst, lhs, rhs) = match *inst {
+        Inst::Bin { op, ty, dst, lhs, rhs } if !ty.is_flonum() => (op, ty, dst, lhs, rhs),
+        _ => return None
+    };
+
+    match (op, lhs, rhs) {
+        (BinOp::Mul, _, Operand::Imm(0)) | (BinOp::Mul, Operand::Imm(0), _) => {
+            Some(Inst::Mov { ty, dst, src: Operand::Imm(0) })
+        },
+        (BinOp::Mul, src, Operand::Imm(1)) | (BinOp::Mul, Operand::Imm(1), src) => {
+            Some(Inst::Mov { ty, dst, src })
+        },
+        (BinOp::Mul, Operand::Reg(_), Operand::Imm(n)) | (BinOp::Mul, Operand::Imm(n), Operand::Reg(_)) => {
+            let src = if let Operand::Reg(_) = lhs { lhs } else { rhs };
+            log2(n).map(|shift| Inst::Bin { op: BinOp::Shl, ty, dst, lhs: src, rhs: Operand::Imm(shift) })
+        },
+        (BinOp::Div, Operand::Reg(_), Operand::Imm(n)) if ty.is_unsigned() => {
+            log2(n).map(|shift| Inst::Bin { op: BinOp::Shr, ty, dst, lhs, rhs: Operand::Imm(shift) })
+        },
+        (BinOp::Rem, Operand::Reg(_), Operand::Imm(n)) if ty.is_unsigned() => {
+            log2(n).map(|_| Inst::Bin { op: BinOp::And, ty, dst, lhs, rhs: Operand::Imm(n - 1) })
+        },
+        _ => None
+    }
+}
+
+// n if the value is 2^n. a negative value is not a power of two
+fn log2(val: i64) -> Option<i64> {
+    if val > 0 && val & (val - 1) == 0 {
+        return Some(val.trailing_zeros() as i64)
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::strength_reduce;
+    use crate::ir::opt::test_util::{ function, block, reg };
+    use crate::ir::{ Operand, Ty, BinOp, Inst, Reg, Terminator };
+
+    // each instruction computes %1 from the parameter %0
+    fn reduce(op: BinOp, ty: Ty, lhs: Operand, rhs: Operand) -> Option<String> {
+        let mut func = function(vec![
+            block(vec![
+                Inst::Param { ty, dst: Reg(0), idx: 0 },
+                Inst::Bin { op, ty, dst: Reg(1), lhs, rhs }
+            ], Terminator::Ret(Some((ty, reg(1)))))
+        ]);
+
+        if strength_reduce(&mut func) {
+            Some(func.blocks[0].insts[1].to_string())
+        } else {
+            None
+        }
+    }
+
+    #[test]
+    fn power_of_two_is_reduced() {
+        assert_eq!(reduce(BinOp::Mul, Ty::I64, reg(0), Operand::Imm(8)), Some("%1 = shl i64 %0, 3".to_string()));
+        assert_eq!(reduce(BinOp::Mul, Ty::I64, Operand::Imm(4), reg(0)), Some("%1 = shl i64 %0, 2".to_string()));
+        assert_eq!(reduce(BinOp::Div, Ty::U32, reg(0), Operand::Imm(16)), Some("%1 = shr u32 %0, 4".to_string()));
+        assert_eq!(reduce(BinOp::Rem, Ty::U64, reg(0), Operand::Imm(8)), Some("%1 = and u64 %0, 7".to_string()));
+    }
+
+    #[test]
+    fn multiplication_by_zero_or_one_is_move() {
+        assert_eq!(reduce(BinOp::Mul, Ty::I32, reg(0), Operand::Imm(0)), Some("%1 = mov i32 0".to_string()));
+        assert_eq!(reduce(BinOp::Mul, Ty::I32, Operand::Imm(1), reg(0)), Some("%1 = mov i32 %0".to_string()));
+    }
+
+    // a signed division rounds toward zero, but a shift rounds down
+    #[test]
+    fn others_are_kept() {
+        assert_eq!(reduce(BinOp::Div, Ty::I32, reg(0), Operand::Imm(4)), None);
+        assert_eq!(reduce(BinOp::Rem, Ty::I32, reg(0), Operand::Imm(4)), None);
+        assert_eq!(reduce(BinOp::Mul, Ty::I32, reg(0), Operand::Imm(6)), None);
+        assert_eq!(reduce(BinOp::Mul, Ty::I32, reg(0), Operand::Imm(-8)), None);
+        assert_eq!(reduce(BinOp::Div, Ty::U32, Operand::Imm(16), reg(0)), None);
+        assert_eq!(reduce(BinOp::Mul, Ty::F64, reg(0), Operand::Imm(2f64.to_bits() as i64)), None);
+    }
+}
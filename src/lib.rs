@@ -3,6 +3,12 @@ pub mod tokenizer;
 pub mod node;
 pub mod parser;
 pub mod codegen;
+pub mod ir;
 pub mod program;
 pub mod _type;
 pub mod scopes;
+pub mod preprocessor;
+pub mod diagnostic;
+pub mod warning;
+pub mod dump;
+pub mod c_printer;
@@ -1,46 +1,488 @@
 // extern crate rust_chibicc;
 use rust_chibicc::tokenizer::Tokenizer;
+use rust_chibicc::token::Token;
+use rust_chibicc::preprocessor::{ self, Preprocessor };
 use rust_chibicc::parser::Parser;
-use rust_chibicc::codegen::CodeGenerator;
+use rust_chibicc::codegen::{ CodeGenerator, CodegenError };
+use rust_chibicc::ir;
+use rust_chibicc::ir::opt::{ self, Pass };
+use rust_chibicc::diagnostic::{ self, Diagnostic, SourceMap };
+use rust_chibicc::{ c_printer, dump };
+use rust_chibicc::warning::{ self, WarningOptions };
 
 use std::env;
-use std::fs;
+use std::fs::{ self, File };
+use std::io::{ self, Read };
+use std::path::{ Path, PathBuf };
+use std::process::{ self, Command, Stdio };
 
-fn read_file(path: impl Into<String>) -> String {
-    let path_str = path.into();
-    match fs::read_to_string(&path_str) {
-        Ok(content) => content,
-        Err(e) => {
-            let msg = format!("cannot read {}, reason: {}", &path_str, e);
-            panic!("{}", msg);
+const USAGE: &str = "\
+usage: rust_chibicc [options] file...
+  -o <file>    write the output to <file>
+  -S           compile only; do not assemble or link
+  -c           compile and assemble, but do not link
+  -E           preprocess only
+  --emit-ir    print the intermediate representation instead of the assembly
+  --dump-tokens
+               print the preprocessed tokens with their locations
+  --dump-ast   print the syntax tree with the types and the variable offsets
+  --emit-c     print the program as C source regenerated from the syntax tree
+  -O<level>    optimize the code (-O0, -O1, -O2)
+  -g           write DWARF debug information for a debugger such as gdb
+  --passes=<pass>,...
+               run the given optimization passes instead of those of -O<level>
+               (simplify-cfg, const-prop, strength-reduce, copy-prop, dce, mem2reg)
+  --peephole   optimize the generated assembly (enabled by -O1 and above)
+  --peephole-stats
+               print the number of instructions before and after --peephole
+  -I <dir>     add <dir> to the include search paths
+  -D <name>[=<val>]
+               define a macro
+  -U <name>    undefine a macro
+  -W<warning>  enable a warning (-Wall, -Wextra, -Werror, -Wno-<warning>, ...)
+  -l<lib>, -L<dir>, -static, -pie, -no-pie, -Wl,<args>
+               passed to the linker
+  -f<option>, -std=<standard>, -m64, -pthread
+               accepted for compatibility with cc, and ignored
+  a file named `-` is read from the standard input as C source";
+
+// command-line options
+#[derive(Debug, Default)]
+struct Options {
+    output: Option<String>,
+    // -S, -c and -E
+    emit_asm: bool,
+    compile_only: bool,
+    preprocess_only: bool,
+    emit_ir: bool,
+    // --dump-tokens, --dump-ast and --emit-c
+    dump_tokens: bool,
+    dump_ast: bool,
+    emit_c: bool,
+    opt_level: u32,
+    // --passes overrides the passes of opt_level
+    passes: Option<Vec<Pass>>,
+    // --peephole, and --peephole-stats which implies it
+    peephole: bool,
+    peephole_stats: bool,
+    // -g
+    debug_info: bool,
+    include_paths: Vec<PathBuf>,
+    // (name, Some(val)) for -D and (name, None) for -U, in the given order
+    macros: Vec<(String, Option<String>)>,
+    warning_opts: WarningOptions,
+    link_args: Vec<String>,
+    inputs: Vec<String>,
+    // options passed through to the compiler proper (-cc1)
+    cc1_args: Vec<String>,
+    // run as the compiler proper, which compiles a single file and writes
+    // the assembly (or the preprocessed source for -E) to stdout
+    cc1: bool
+}
+
+// the argument of an option such as `-o`, given either as `-ofile` or `-o file`
+fn take_arg(arg: &str, opt: &str, args: &mut impl Iterator<Item = String>) -> Result<String, String> {
+    if arg.len() > opt.len() {
+        return Ok(arg[opt.len()..].to_string())
+    }
+
+    args.next().ok_or_else(|| format!("missing argument to '{}'", opt))
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut opts = Options::default();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            },
+            "-cc1" => opts.cc1 = true,
+            "-S" => opts.emit_asm = true,
+            "-c" => opts.compile_only = true,
+            "-E" => {
+                opts.preprocess_only = true;
+                opts.cc1_args.push(arg);
+            },
+            "--emit-ir" => {
+                opts.emit_ir = true;
+                opts.cc1_args.push(arg);
+            },
+            "--dump-tokens" => {
+                opts.dump_tokens = true;
+                opts.cc1_args.push(arg);
+            },
+            "--dump-ast" => {
+                opts.dump_ast = true;
+                opts.cc1_args.push(arg);
+            },
+            "--emit-c" => {
+                opts.emit_c = true;
+                opts.cc1_args.push(arg);
+            },
+            "--peephole" => {
+                opts.peephole = true;
+                opts.cc1_args.push(arg);
+            },
+            "--peephole-stats" => {
+                opts.peephole = true;
+                opts.peephole_stats = true;
+                opts.cc1_args.push(arg);
+            },
+            "-g" => {
+                opts.debug_info = true;
+                opts.cc1_args.push(arg);
+            },
+            "-static" | "-pie" | "-no-pie" => opts.link_args.push(arg),
+            // the code is position independent, and C11 with some extensions is accepted
+            "-m64" | "-pthread" => {},
+            _ if arg.starts_with("-f") || arg.starts_with("-std=") => {},
+            _ if arg.starts_with("-O") => {
+                // -O is -O1, and -Os and -Og are treated as it
+                opts.opt_level = match &arg[2..] {
+                    "" | "s" | "g" => 1,
+                    "fast" => 2,
+                    level => level.parse().map_err(|_| format!("invalid optimization level: {}", arg))?
+                };
+                opts.cc1_args.push(arg);
+            },
+            _ if arg.starts_with("--passes=") => {
+                let passes = arg["--passes=".len()..].split(',')
+                    .filter(|name| !name.is_empty())
+                    .map(|name| Pass::from_name(name).ok_or_else(|| format!("unknown pass: {}", name)))
+                    .collect::<Result<Vec<_>, _>>()?;
+                opts.passes = Some(passes);
+                opts.cc1_args.push(arg);
+            },
+            "-" => opts.inputs.push(arg),
+            _ if arg.starts_with("-o") => opts.output = Some(take_arg(&arg, "-o", &mut args)?),
+            _ if arg.starts_with("-I") => {
+                let dir = take_arg(&arg, "-I", &mut args)?;
+                opts.cc1_args.push(format!("-I{}", dir));
+                opts.include_paths.push(PathBuf::from(dir));
+            },
+            _ if arg.starts_with("-D") => {
+                let def = take_arg(&arg, "-D", &mut args)?;
+                opts.cc1_args.push(format!("-D{}", def));
+                // -DFOO is the same as -DFOO=1, and -D'F(x)=x*2' defines a function-like macro
+                let (name, val) = match def.find('=') {
+                    Some(i) => (&def[..i], &def[i + 1..]),
+                    None => (def.as_str(), "1")
+                };
+                opts.macros.push((name.to_string(), Some(val.to_string())));
+            },
+            _ if arg.starts_with("-U") => {
+                let name = take_arg(&arg, "-U", &mut args)?;
+                opts.cc1_args.push(format!("-U{}", name));
+                opts.macros.push((name, None));
+            },
+            _ if arg.starts_with("-Wl,") => opts.link_args.push(arg),
+            // unknown options are not passed to cc1 so that they are reported once
+            _ if arg.starts_with("-W") => match opts.warning_opts.apply(&arg) {
+                Ok(()) => opts.cc1_args.push(arg),
+                Err(e) => eprintln!("rust_chibicc: warning: {}", e)
+            },
+            _ if arg.starts_with("-l") || arg.starts_with("-L") => opts.link_args.push(arg),
+            _ if arg.starts_with('-') => return Err(format!("unknown argument: {}", arg)),
+            _ => opts.inputs.push(arg)
         }
     }
+
+    if opts.inputs.is_empty() {
+        return Err("no input files".to_string())
+    }
+    if opts.cc1 && opts.inputs.len() != 1 {
+        return Err("-cc1 takes exactly one input file".to_string())
+    }
+    let no_link = opts.emit_asm || opts.compile_only || opts.prints_text();
+    if no_link && opts.output.is_some() && opts.inputs.len() > 1 {
+        return Err("cannot specify '-o' with '-c', '-S', '-E', '--emit-ir', '--dump-*' or '--emit-c' with multiple files".to_string())
+    }
+
+    Ok(opts)
 }
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        eprintln!("引数の個数が正しくありません");
-        return
+impl Options {
+    // the compiler proper prints something other than the assembly
+    fn prints_text(&self) -> bool {
+        self.preprocess_only || self.emit_ir || self.dump_tokens || self.dump_ast || self.emit_c
+    }
+}
+
+// print diagnostics to stderr, and exit if there is an error
+fn report(diags: &[Diagnostic], sources: &SourceMap) {
+    let mut diags = diags.to_vec();
+    diagnostic::sort_by_location(&mut diags);
+    for diag in &diags {
+        eprint!("{}", diag.render(sources));
     }
 
-    let filename = args.get(1).expect("expect 1 arguments");
+    let errors = diags.iter().filter(|diag| diag.is_error()).count();
+    if errors > 0 {
+        if errors > 1 {
+            eprintln!("{} errors generated.", errors);
+        }
+        process::exit(1);
+    }
+}
 
-    let user_input = read_file(filename);
-    let mut tokenizer = Tokenizer::new(user_input);
+// print preprocessed tokens. a line break and spaces between tokens are kept
+fn print_tokens(tokens: &[Token]) {
+    let mut out = String::new();
+    for tok in tokens.iter().take_while(|tok| !tok.token_type.at_eof()) {
+        if tok.at_bol && !out.is_empty() {
+            out.push('\n');
+        } else if tok.has_space {
+            out.push(' ');
+        }
+        out.push_str(&tok.token_type.tk_str());
+    }
+    println!("{}", out);
+}
+
+// the compiler proper. compile a single file and write the result to stdout
+fn cc1(opts: &Options) {
+    let input = &opts.inputs[0];
+    let (filename, content) = if input == "-" {
+        let mut content = String::new();
+        if let Err(e) = io::stdin().read_to_string(&mut content) {
+            report(&[Diagnostic::error(format!("cannot read stdin, reason: {}", e))], &SourceMap::new());
+        }
+        ("<stdin>", content)
+    } else {
+        match fs::read_to_string(input) {
+            Ok(content) => (input.as_str(), content),
+            Err(e) => {
+                let msg = format!("cannot read {}, reason: {}", input, e);
+                return report(&[Diagnostic::error(msg)], &SourceMap::new())
+            }
+        }
+    };
+
+    let mut sources = SourceMap::new();
+    sources.add(filename, content.as_str());
+
+    let mut tokenizer = Tokenizer::new(content, filename);
     let tokens = match tokenizer.tokenize() {
         Ok(tokens) => tokens,
-        Err(e) => {
-            eprintln!("{}:{}:{}:{}", filename, tokenizer.row_number(), tokenizer.col_number(), e);
-            return
+        Err(e) => return report(&[e], &sources)
+    };
+
+    // -I directories are searched before the default ones
+    let include_paths = opts.include_paths.iter().cloned()
+        .chain(preprocessor::default_include_paths())
+        .collect();
+    let mut preprocessor = Preprocessor::new(include_paths);
+    for (name, val) in &opts.macros {
+        match val {
+            Some(val) => {
+                if let Err(e) = preprocessor.define(name, val) {
+                    report(&[e], &sources);
+                }
+            },
+            None => preprocessor.undef(name)
         }
+    }
+    let tokens = match preprocessor.preprocess(tokens, Path::new(filename)) {
+        Ok(tokens) => tokens,
+        Err(e) => return report(&[e], &sources)
     };
 
+    if opts.preprocess_only {
+        return print_tokens(&tokens)
+    }
+    if opts.dump_tokens {
+        return print!("{}", dump::tokens(&tokens))
+    }
+
     let mut parser = Parser::new(&tokens);
     let parsed = parser.parse();
 
+    let mut warnings = std::mem::take(&mut parser.warnings);
     match parsed {
-        Err(msg) => { eprintln!("{}:{}", filename, msg); },
-        Ok(ast) => { CodeGenerator::new(&ast).codegen() }
+        Err(errors) => {
+            warnings.extend(errors);
+            report(&opts.warning_opts.filter(warnings), &sources)
+        },
+        Ok(ast) => {
+            warnings.extend(warning::check(&ast));
+            report(&opts.warning_opts.filter(warnings), &sources);
+            if opts.dump_ast {
+                return print!("{}", dump::ast(&ast))
+            }
+            if opts.emit_c {
+                return print!("{}", c_printer::print(&ast))
+            }
+            let mut prog = match ir::lower(&ast) {
+                Ok(prog) => prog,
+                Err(e) => return report(&[e], &sources)
+            };
+            let passes = opts.passes.clone().unwrap_or_else(|| opt::pipeline(opts.opt_level));
+            opt::optimize(&mut prog, &passes);
+            if opts.emit_ir {
+                return print!("{}", prog)
+            }
+            let stdout = io::stdout();
+            let mut gen = CodeGenerator::new(&prog, stdout.lock());
+            if opts.peephole || opts.opt_level >= 1 {
+                gen.enable_peephole();
+            }
+            if opts.debug_info {
+                let comp_dir = env::current_dir().map(|dir| dir.display().to_string()).unwrap_or_default();
+                gen.enable_debug_info(filename, &comp_dir);
+            }
+            match gen.codegen() {
+                Err(CodegenError::Invalid(e)) => return report(&[e], &sources),
+                Err(e) => return report(&[Diagnostic::error(e.to_string())], &sources),
+                Ok(()) => {}
+            }
+            if let (true, Some(stats)) = (opts.peephole_stats, gen.peephole_stats()) {
+                eprintln!("{}: peephole: {} -> {} instructions", filename, stats.before, stats.after);
+            }
+        }
+    };
+}
+
+fn run(cmd: &mut Command) -> Result<(), String> {
+    let status = cmd.status().map_err(|e| format!("cannot run {:?}, reason: {}", cmd, e))?;
+    if !status.success() {
+        return Err(format!("{:?} failed", cmd))
+    }
+
+    Ok(())
+}
+
+// run this program itself as the compiler proper. the result is written to `output`,
+// or stdout if None
+fn run_cc1(opts: &Options, input: &str, output: Option<&Path>) -> Result<(), String> {
+    let exe = env::current_exe().map_err(|e| e.to_string())?;
+    let mut cmd = Command::new(exe);
+    cmd.arg("-cc1").args(&opts.cc1_args).arg(input);
+
+    if let Some(output) = output {
+        let file = File::create(output).map_err(|e| format!("cannot open {}, reason: {}", output.display(), e))?;
+        cmd.stdout(Stdio::from(file));
+    }
+
+    // errors are already reported by the compiler proper
+    run(&mut cmd).map_err(|_| String::new())
+}
+
+fn assemble(input: &Path, output: &Path) -> Result<(), String> {
+    run(Command::new("as").arg("-o").arg(output).arg(input))
+}
+
+fn link(inputs: &[PathBuf], output: &Path, link_args: &[String]) -> Result<(), String> {
+    run(Command::new("cc").arg("-o").arg(output).args(inputs).args(link_args))
+}
+
+// foo/bar.c -> bar.o
+fn replace_extension(input: &str, extension: &str) -> PathBuf {
+    let filename = Path::new(input).file_name().unwrap_or_else(|| input.as_ref());
+    Path::new(filename).with_extension(extension)
+}
+
+// temporary files are removed when the driver exits
+struct TempFiles {
+    files: Vec<PathBuf>
+}
+
+impl TempFiles {
+    fn create(&mut self, extension: &str) -> PathBuf {
+        let name = format!("rust_chibicc-{}-{}.{}", process::id(), self.files.len(), extension);
+        let path = env::temp_dir().join(name);
+        self.files.push(path.clone());
+        path
+    }
+
+    fn remove_all(&self) {
+        for file in &self.files {
+            let _ = fs::remove_file(file);
+        }
+    }
+}
+
+fn drive(opts: &Options, tmp: &mut TempFiles) -> Result<(), String> {
+    let output = opts.output.as_ref().map(PathBuf::from);
+    // `-o -` writes the result of -S or -E to stdout
+    let to_stdout = opts.output.as_deref() == Some("-");
+    let mut ld_inputs = Vec::new();
+
+    for input in &opts.inputs {
+        if input.ends_with(".o") || input.ends_with(".a") || input.ends_with(".so") {
+            ld_inputs.push(PathBuf::from(input));
+            continue
+        }
+
+        // assembly
+        if input.ends_with(".s") {
+            if opts.compile_only {
+                assemble(Path::new(input), &output.clone().unwrap_or_else(|| replace_extension(input, "o")))?;
+            } else if !opts.emit_asm && !opts.prints_text() {
+                let obj = tmp.create("o");
+                assemble(Path::new(input), &obj)?;
+                ld_inputs.push(obj);
+            }
+            continue
+        }
+
+        if !input.ends_with(".c") && input != "-" {
+            return Err(format!("unknown file extension: {}", input))
+        }
+
+        if opts.prints_text() {
+            run_cc1(opts, input, output.as_deref().filter(|_| !to_stdout))?;
+            continue
+        }
+
+        if opts.emit_asm {
+            let asm = output.clone().unwrap_or_else(|| replace_extension(input, "s"));
+            run_cc1(opts, input, Some(asm.as_path()).filter(|_| !to_stdout))?;
+            continue
+        }
+
+        let asm = tmp.create("s");
+        run_cc1(opts, input, Some(&asm))?;
+
+        if opts.compile_only {
+            assemble(&asm, &output.clone().unwrap_or_else(|| replace_extension(input, "o")))?;
+            continue
+        }
+
+        let obj = tmp.create("o");
+        assemble(&asm, &obj)?;
+        ld_inputs.push(obj);
+    }
+
+    if !(ld_inputs.is_empty() || opts.emit_asm || opts.compile_only || opts.prints_text()) {
+        link(&ld_inputs, &output.unwrap_or_else(|| PathBuf::from("a.out")), &opts.link_args)?;
+    }
+
+    Ok(())
+}
+
+fn main() {
+    let opts = match parse_args(env::args().skip(1)) {
+        Ok(opts) => opts,
+        Err(e) => {
+            eprintln!("rust_chibicc: error: {}", e);
+            process::exit(1);
+        }
     };
+
+    if opts.cc1 {
+        return cc1(&opts)
+    }
+
+    let mut tmp = TempFiles { files: Vec::new() };
+    let result = drive(&opts, &mut tmp);
+    tmp.remove_all();
+
+    if let Err(e) = result {
+        if !e.is_empty() {
+            eprintln!("rust_chibicc: error: {}", e);
+        }
+        process::exit(1);
+    }
 }
@@ -1,14 +1,29 @@
 use crate::program::Var;
-use crate::_type::{ Type, Member };
+use crate::_type::{ Type, Member, common_type };
 use crate::_type::Type::{ Int, Ptr };
+use crate::tokenizer::loc::Loc;
 
 use std::rc::Rc;
 use std::cell::RefCell;
 use std::fmt;
 use std::fmt::Display;
 
+// a statement with the location of its first token. a statement made by
+// the compiler, such as the initialization of a local variable, has no location
 #[derive(PartialEq, Debug, Clone)]
-pub enum Stmt {
+pub struct Stmt {
+    pub kind: StmtKind,
+    pub loc: Option<Loc>
+}
+
+impl Stmt {
+    pub fn new(kind: StmtKind) -> Self {
+        Self { kind, loc: None }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum StmtKind {
     Return {
         val: ExprWrapper
     },
@@ -24,6 +39,10 @@ pub enum Stmt {
         cond: ExprWrapper,
         then: Box<Stmt>
     },
+    DoWhile {
+        then: Box<Stmt>,
+        cond: ExprWrapper
+    },
     For {
         init: Box<Option<Stmt>>, // only ExprStmt
         cond: Option<ExprWrapper>,
@@ -37,19 +56,35 @@ pub enum Stmt {
     Break,
     Continue,
     Goto(Rc<String>),
-    Label(Box<Stmt>, Rc<String>)
+    Label(Box<Stmt>, Rc<String>),
+    Switch {
+        cond: ExprWrapper,
+        then: Box<Stmt>,
+        cases: Vec<isize>, // values of the case labels in then
+        has_default: bool
+    },
+    Case {
+        val: isize,
+        idx: usize, // index in the cases of the enclosing switch
+        stmt: Box<Stmt>
+    },
+    Default(Box<Stmt>)
 }
 
 #[derive(PartialEq, Debug, Clone)]
 pub struct ExprWrapper {
     pub ty: Box<Type>,
-    pub expr: Box<Expr>
+    pub expr: Box<Expr>,
+    // location of the first token of the expression
+    pub loc: Option<Loc>
 }
 
 impl ExprWrapper {
+    // the location is taken from the first operand. the parser sets it for the others
     pub fn new(expr: Expr) -> Self {
         Self {
             ty: expr.detect_type(),
+            loc: expr.first_loc(),
             expr: Box::new(expr)
         }
     }
@@ -97,6 +132,10 @@ pub enum Expr {
         lhs: ExprWrapper,
         rhs: ExprWrapper
     },
+    Mod {
+        lhs: ExprWrapper,
+        rhs: ExprWrapper
+    },
     BitAnd {
         lhs: ExprWrapper,
         rhs: ExprWrapper
@@ -109,8 +148,20 @@ pub enum Expr {
         lhs: ExprWrapper,
         rhs: ExprWrapper
     },
+    Shl {
+        lhs: ExprWrapper,
+        rhs: ExprWrapper
+    },
+    Shr {
+        lhs: ExprWrapper,
+        rhs: ExprWrapper
+    },
     Num {
-        val: isize
+        val: isize,
+        ty: Box<Type>
+    },
+    FNum { // floating-point literal
+        val: f64
     },
     Cast(Box<Type>, ExprWrapper),
     Var(Rc<RefCell<Var>>),
@@ -146,13 +197,45 @@ pub enum Expr {
         var: ExprWrapper,
         val: ExprWrapper
     },
+    ModEq {
+        var: ExprWrapper,
+        val: ExprWrapper
+    },
+    BitAndEq {
+        var: ExprWrapper,
+        val: ExprWrapper
+    },
+    BitOrEq {
+        var: ExprWrapper,
+        val: ExprWrapper
+    },
+    BitXorEq {
+        var: ExprWrapper,
+        val: ExprWrapper
+    },
+    ShlEq {
+        var: ExprWrapper,
+        val: ExprWrapper
+    },
+    ShrEq {
+        var: ExprWrapper,
+        val: ExprWrapper
+    },
     Comma {
         lhs: Stmt,
         rhs: ExprWrapper
     },
+    Cond { // cond ? then : els
+        cond: ExprWrapper,
+        then: ExprWrapper,
+        els: ExprWrapper
+    },
     FnCall {
-        fn_name: Rc<String>,
-        args: Vec<ExprWrapper>
+        // a function, or a pointer to a function
+        callee: ExprWrapper,
+        args: Vec<ExprWrapper>,
+        // the local variable to which a struct is returned
+        ret_buf: Option<Rc<RefCell<Var>>>
     },
     Addr {
         operand: ExprWrapper
@@ -184,7 +267,8 @@ pub enum Expr {
     },
     Null,
     StmtExpr(Vec<Stmt>), // GNU C extension Null
-    Member(ExprWrapper, Member) // struct member
+    Member(ExprWrapper, Member), // struct member
+    MemZero(Rc<RefCell<Var>>) // zero-clear a local variable before initialization
 }
 
 impl Expr {
@@ -196,19 +280,27 @@ impl Expr {
             | Expr::Ge { .. }
             | Expr::Lt { .. }
             | Expr::Le { .. }
-            | Expr::Add { .. }
-            | Expr::Sub { .. }
-            | Expr::Mul { .. }
-            | Expr::Div { .. }
-            | Expr::Num { .. }
-            | Expr::PtrDiff { .. }
-            | Expr::FnCall { .. }
             | Expr::Not(_)
-            | Expr::BitAnd { .. }
-            | Expr::BitOr { .. }
-            | Expr::BitXor { .. }
             | Expr::LogAnd { .. }
-            | Expr::LogOr { .. } => Box::new(Type::Long),
+            | Expr::LogOr { .. } => Box::new(Type::Int),
+            // the return type is set by the parser. this is for an implicitly declared function
+            Expr::FnCall { .. } => Box::new(Type::Int),
+            Expr::PtrDiff { .. } => Box::new(Type::Long),
+            Expr::Num { ty, .. } => Box::clone(ty),
+            // the operands have been converted to their common type by the parser
+            Expr::Add { lhs, rhs }
+            | Expr::Sub { lhs, rhs }
+            | Expr::Mul { lhs, rhs }
+            | Expr::Div { lhs, rhs }
+            | Expr::Mod { lhs, rhs }
+            | Expr::BitAnd { lhs, rhs }
+            | Expr::BitOr { lhs, rhs }
+            | Expr::BitXor { lhs, rhs } => Box::new(common_type(&lhs.ty, &rhs.ty)),
+            // the result has the type of the promoted left operand
+            Expr::Shl { lhs, .. }
+            | Expr::Shr { lhs, .. } => Box::clone(&lhs.ty),
+            Expr::FNum { .. } => Box::new(Type::Double),
+            Expr::MemZero(_) => Box::new(Type::Void),
             Expr::Cast(ty, ..) => Box::clone(ty),
             Expr::PtrAdd { lhs, rhs: _ }
             | Expr::PtrSub { lhs, rhs: _ } => Box::clone(&lhs.ty),
@@ -234,23 +326,43 @@ impl Expr {
             Expr::MulEq { var, .. } => {
                 Box::clone(&var.ty)
             },
-            Expr::DivEq { var, .. } => {
+            Expr::DivEq { var, .. }
+            | Expr::ModEq { var, .. }
+            | Expr::BitAndEq { var, .. }
+            | Expr::BitOrEq { var, .. }
+            | Expr::BitXorEq { var, .. }
+            | Expr::ShlEq { var, .. }
+            | Expr::ShrEq { var, .. } => {
                 Box::clone(&var.ty)
             },
             Expr::Comma { rhs, .. } => {
                 Box::clone(&rhs.ty)
             },
+            Expr::Cond { then, els, .. } => {
+                match (then.ty.as_ref(), els.ty.as_ref()) {
+                    // an array is converted to a pointer to its first element
+                    (Type::Ptr { base }, _)
+                    | (Type::Array { base, .. }, _) => Box::new(Ptr { base: Box::clone(base) }),
+                    (_, Type::Ptr { base })
+                    | (_, Type::Array { base, .. }) => Box::new(Ptr { base: Box::clone(base) }),
+                    (then_ty, els_ty) if then_ty.is_arithmetic() && els_ty.is_arithmetic() => {
+                        Box::new(common_type(then_ty, els_ty))
+                    },
+                    _ => Box::clone(&then.ty)
+                }
+            },
             Expr::Addr { operand } => {
                 let ty = operand.ty.as_ref();
                 match ty {
-                    Type::Array { base, .. } => Box::clone(base),
+                    Type::Array { base, .. } => Box::new(Ptr { base: Box::clone(base) }),
                     _ => Box::new(Ptr { base: Box::clone(&operand.ty) })
                 }
             },
             Expr::Deref { operand } => {
                 match operand.ty.as_ref() {
+                   // the pointer may have been made before the struct is completed
                    Type::Ptr { base }
-                   | Type::Array { base, .. } => Box::clone(base),
+                   | Type::Array { base, .. } => base.resolve(),
                    Type::Void => panic!("derefierencing a void pointer"),
                    _ => Box::clone(&operand.ty)
                 }
@@ -261,8 +373,8 @@ impl Expr {
             },
             Expr::Null => Box::new(Int),
             Expr::StmtExpr(stmts) => { // stmt.lastはPureExprのはず
-                match stmts.last() {
-                    Some(Stmt::PureExpr(expr)) => Box::clone(&expr.ty),
+                match stmts.last().map(|stmt| &stmt.kind) {
+                    Some(StmtKind::PureExpr(expr)) => Box::clone(&expr.ty),
                     _ => unreachable!("stmts.last can only be expr_stmt")
                 }
             },
@@ -272,6 +384,60 @@ impl Expr {
         }
     }
 
+    fn first_loc(&self) -> Option<Loc> {
+        match self {
+            Expr::Eq { lhs, .. }
+            | Expr::Neq { lhs, .. }
+            | Expr::Gt { lhs, .. }
+            | Expr::Ge { lhs, .. }
+            | Expr::Lt { lhs, .. }
+            | Expr::Le { lhs, .. }
+            | Expr::Add { lhs, .. }
+            | Expr::Sub { lhs, .. }
+            | Expr::Mul { lhs, .. }
+            | Expr::Div { lhs, .. }
+            | Expr::Mod { lhs, .. }
+            | Expr::BitAnd { lhs, .. }
+            | Expr::BitOr { lhs, .. }
+            | Expr::BitXor { lhs, .. }
+            | Expr::Shl { lhs, .. }
+            | Expr::Shr { lhs, .. }
+            | Expr::LogAnd { lhs, .. }
+            | Expr::LogOr { lhs, .. }
+            | Expr::PtrAdd { lhs, .. }
+            | Expr::PtrSub { lhs, .. }
+            | Expr::PtrDiff { lhs, .. }
+            | Expr::Assign { var: lhs, .. }
+            | Expr::AddEq { var: lhs, .. }
+            | Expr::PtrAddEq { var: lhs, .. }
+            | Expr::SubEq { var: lhs, .. }
+            | Expr::PtrSubEq { var: lhs, .. }
+            | Expr::MulEq { var: lhs, .. }
+            | Expr::DivEq { var: lhs, .. }
+            | Expr::ModEq { var: lhs, .. }
+            | Expr::BitAndEq { var: lhs, .. }
+            | Expr::BitOrEq { var: lhs, .. }
+            | Expr::BitXorEq { var: lhs, .. }
+            | Expr::ShlEq { var: lhs, .. }
+            | Expr::ShrEq { var: lhs, .. }
+            | Expr::Cast(_, lhs)
+            | Expr::PreInc(lhs)
+            | Expr::PreDec(lhs)
+            | Expr::PostInc(lhs)
+            | Expr::PostDec(lhs)
+            | Expr::Addr { operand: lhs }
+            | Expr::Deref { operand: lhs }
+            | Expr::Not(lhs)
+            | Expr::BitNot(lhs)
+            | Expr::Member(lhs, _)
+            | Expr::Cond { cond: lhs, .. }
+            | Expr::FnCall { callee: lhs, .. } => lhs.loc.clone(),
+            Expr::Comma { lhs, .. } => lhs.loc.clone(),
+            Expr::StmtExpr(stmts) => stmts.first().and_then(|stmt| stmt.loc.clone()),
+            Expr::Num { .. } | Expr::FNum { .. } | Expr::Var(_) | Expr::Null | Expr::MemZero(_) => None
+        }
+    }
+
     pub fn to_expr_wrapper(self) -> ExprWrapper {
         ExprWrapper::new(self)
     }
@@ -299,10 +465,14 @@ impl Display for Expr {
             Expr::Sub { .. } => write!(f, "Sub"),
             Expr::Mul { .. } => write!(f, "Mul"),
             Expr::Div { .. } => write!(f, "Div"),
+            Expr::Mod { .. } => write!(f, "Mod"),
             Expr::BitAnd { .. } => write!(f, "BitAnd"),
             Expr::BitOr { .. } => write!(f, "BitOr"),
             Expr::BitXor { .. } => write!(f, "BitXor"),
+            Expr::Shl { .. } => write!(f, "Shl"),
+            Expr::Shr { .. } => write!(f, "Shr"),
             Expr::Num { .. } => write!(f, "Num"),
+            Expr::FNum { .. } => write!(f, "FNum"),
             Expr::Cast { .. } => write!(f, "Cast"),
             Expr::Var(_) => write!(f, "Var"),
             Expr::Assign { .. } => write!(f, "Assign"),
@@ -310,13 +480,20 @@ impl Display for Expr {
             Expr::PreDec(_) => write!(f, "PreDec"),
             Expr::PostInc(_) => write!(f, "PostInc"),
             Expr::PostDec(_) => write!(f, "PostDec"),
-            Expr::AddEq { .. } => write!(f, "PostDec"),
-            Expr::PtrAddEq { .. } => write!(f, "PostDec"),
-            Expr::SubEq { .. } => write!(f, "PostDec"),
-            Expr::PtrSubEq { .. } => write!(f, "PostDec"),
-            Expr::MulEq { .. } => write!(f, "PostDec"),
-            Expr::DivEq { .. } => write!(f, "PostDec"),
+            Expr::AddEq { .. } => write!(f, "AddEq"),
+            Expr::PtrAddEq { .. } => write!(f, "PtrAddEq"),
+            Expr::SubEq { .. } => write!(f, "SubEq"),
+            Expr::PtrSubEq { .. } => write!(f, "PtrSubEq"),
+            Expr::MulEq { .. } => write!(f, "MulEq"),
+            Expr::DivEq { .. } => write!(f, "DivEq"),
+            Expr::ModEq { .. } => write!(f, "ModEq"),
+            Expr::BitAndEq { .. } => write!(f, "BitAndEq"),
+            Expr::BitOrEq { .. } => write!(f, "BitOrEq"),
+            Expr::BitXorEq { .. } => write!(f, "BitXorEq"),
+            Expr::ShlEq { .. } => write!(f, "ShlEq"),
+            Expr::ShrEq { .. } => write!(f, "ShrEq"),
             Expr::Comma { .. } => write!(f, "Comma"),
+            Expr::Cond { .. } => write!(f, "Cond"),
             Expr::FnCall { .. } => write!(f, "FnCall"),
             Expr::Addr { .. } => write!(f, "Addr"),
             Expr::Deref { .. } => write!(f, "Deref"),
@@ -329,25 +506,36 @@ impl Display for Expr {
             Expr::PtrDiff { .. } => write!(f, "PtrDiff"),
             Expr::StmtExpr(_) => write!(f, "StmtExpr"),
             Expr::Null => write!(f, "Null"),
-            Expr::Member(_, _) =>  write!(f, "Member")
+            Expr::Member(_, _) =>  write!(f, "Member"),
+            Expr::MemZero(_) =>  write!(f, "MemZero")
         }
     }
 }
 
 impl Display for Stmt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> std::result::Result<(), std::fmt::Error> {
+        write!(f, "{}", self.kind)
+    }
+}
+
+impl Display for StmtKind {
     fn fmt(&self, f: &mut fmt::Formatter) -> std::result::Result<(), std::fmt::Error> {
         match self {
-            Stmt::Return { .. } => write!(f, "Return"),
-            Stmt::ExprStmt { .. } => write!(f, "ExprStmt"),
-            Stmt::If { .. } => write!(f, "If"),
-            Stmt::While { .. } => write!(f, "While"),
-            Stmt::For { .. } => write!(f, "For"),
-            Stmt::Block { .. } => write!(f, "Block"),
-            Stmt::PureExpr { .. } => write!(f, "PureExpr"),
-            Stmt::Break => write!(f, "Break"),
-            Stmt::Continue => write!(f, "Continue"),
-            Stmt::Goto(_) => write!(f, "Goto"),
-            Stmt::Label(_, _) => write!(f, "Label")
+            StmtKind::Return { .. } => write!(f, "Return"),
+            StmtKind::ExprStmt { .. } => write!(f, "ExprStmt"),
+            StmtKind::If { .. } => write!(f, "If"),
+            StmtKind::While { .. } => write!(f, "While"),
+            StmtKind::DoWhile { .. } => write!(f, "DoWhile"),
+            StmtKind::For { .. } => write!(f, "For"),
+            StmtKind::Block { .. } => write!(f, "Block"),
+            StmtKind::PureExpr { .. } => write!(f, "PureExpr"),
+            StmtKind::Break => write!(f, "Break"),
+            StmtKind::Continue => write!(f, "Continue"),
+            StmtKind::Goto(_) => write!(f, "Goto"),
+            StmtKind::Label(_, _) => write!(f, "Label"),
+            StmtKind::Switch { .. } => write!(f, "Switch"),
+            StmtKind::Case { .. } => write!(f, "Case"),
+            StmtKind::Default(_) => write!(f, "Default")
         }
     }
 }
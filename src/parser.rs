@@ -1,14 +1,19 @@
-use crate::node::{ Stmt, Expr, ExprWrapper };
+use crate::node::{ Stmt, StmtKind, Expr, ExprWrapper };
 use crate::token::{ Token, TokenIter, TokenType };
-use crate::program::{ Function, Var, Program };
+use crate::program::{ Function, Var, Program, VA_AREA_SIZE };
 use crate::_type::Type;
 use crate::token::token_type::*;
 use crate::scopes::{ TagScope, VarScope, ScopeElement };
+use crate::diagnostic::{ Diagnostic, Span };
+use crate::tokenizer::loc::Loc;
 
 use std::rc::Rc;
 use std::cell::RefCell;
 
 mod parser_helper;
+mod initializer;
+mod conversion;
+pub(crate) mod const_eval;
 
 // 優先順位
 // == !=
@@ -17,7 +22,7 @@ mod parser_helper;
 // * /
 // 単項+ 単項-
 // ()
-const TYPE_NAMES: [&str; 7] = ["int", "short", "long", "char", "struct", "void", "_Bool"];
+const TYPE_NAMES: [&str; 12] = ["int", "short", "long", "char", "struct", "union", "void", "_Bool", "signed", "unsigned", "float", "double"];
 
 pub struct Parser<'a> {
     pub input: &'a Vec<Token>,
@@ -31,7 +36,27 @@ pub struct Parser<'a> {
     // the other is for struct tags.
     pub var_scope: Vec<VarScope>,
     pub tag_scope: Vec<TagScope>,
-    pub label_cnt: usize
+    pub label_cnt: usize,
+    // case labels of the switch statement being parsed
+    pub current_switch: Option<SwitchCases>,
+    // return type of the function being parsed
+    pub current_ret_ty: Option<Box<Type>>,
+    // errors reported so far. the parser recovers from an error and continues
+    pub errors: Vec<Diagnostic>,
+    // warnings found while parsing. they are filtered by the -W options later
+    pub warnings: Vec<Diagnostic>,
+    // location of the identifier read by the last declarator
+    decl_loc: Option<Loc>,
+    // parameters of the last function declarator, which a function definition uses
+    func_params: Vec<Rc<RefCell<Var>>>
+}
+
+#[derive(Debug, Default)]
+pub struct SwitchCases {
+    pub vals: Vec<isize>,
+    // where each value is written, for the note of a duplicate case
+    pub spans: Vec<Option<Span>>,
+    pub has_default: bool
 }
 
 impl<'a> Parser<'a> {
@@ -43,26 +68,71 @@ impl<'a> Parser<'a> {
             globals: Vec::new(),
             var_scope: Vec::new(),
             tag_scope: Vec::new(),
-            label_cnt: 0
+            label_cnt: 0,
+            current_switch: None,
+            current_ret_ty: None,
+            errors: Vec::new(),
+            warnings: Vec::new(),
+            decl_loc: None,
+            func_params: Vec::new()
+        }
+    }
+
+    // all errors in the input are returned
+    pub fn parse(&mut self) -> Result<Program, Vec<Diagnostic>> {
+        let prog = self.program();
+        if let Err(e) = prog {
+            self.report(e);
+            return Err(std::mem::take(&mut self.errors))
+        }
+        if !self.errors.is_empty() {
+            return Err(std::mem::take(&mut self.errors))
         }
+
+        prog.map_err(|_| Vec::new())
+    }
+
+    // record an error. an error without a location is reported at the current token
+    fn report(&mut self, mut diag: Diagnostic) {
+        if diag.span.is_none() {
+            diag.span = self.error_here("").span;
+        }
+        self.errors.push(diag);
     }
 
-    pub fn parse(&mut self) -> Result<Program, String> {
-        match self.program() {
-            Ok(prog) => Ok(prog),
-            Err(e) => {
-                let msg = self.peekable.peek()
-                    .map(|tok| {
-                        tok.error_message(&*e)
-                    })
-                    .unwrap_or("eof detected".to_string());
-                Err(msg)
+    // record an error and skip tokens to the next `;`, or to the `}` which
+    // closes the current block, so that parsing can be resumed from there
+    fn recover(&mut self, diag: Diagnostic) {
+        self.report(diag);
+
+        let mut depth = 0;
+        while let Some(tok) = self.peekable.peek() {
+            match &tok.token_type {
+                TokenType::Eof => return,
+                TokenType::Symbol(Symbol { sym, .. }) => match sym.as_str() {
+                    ";" if depth == 0 => {
+                        self.peekable.next();
+                        return
+                    },
+                    "{" => depth += 1,
+                    "}" if depth == 0 => return,
+                    "}" => {
+                        depth -= 1;
+                        if depth == 0 {
+                            self.peekable.next();
+                            return
+                        }
+                    },
+                    _ => {}
+                },
+                _ => {}
             }
+            self.peekable.next();
         }
     }
 
     // program := (global-var | function)*
-    fn program(&mut self) -> Result<Program, String> {
+    fn program(&mut self) -> Result<Program, Diagnostic> {
         let mut nodes: Vec<Function> = Vec::new();
 
         while let Some(token) = self.peekable.peek() {
@@ -70,12 +140,15 @@ impl<'a> Parser<'a> {
             if let TokenType::Eof = token.token_type {
                 break
             }
-            if self.is_function() {
-                if let Some(f) = self.function()? {
-                    nodes.push(f);
-                }
+            let result = if self.is_function() {
+                self.function().map(|f| nodes.extend(f))
             } else {
-                self.global_var()?;
+                self.global_var()
+            };
+            if let Err(e) = result {
+                self.recover(e);
+                // a stray "}" at the top level
+                let _ = self.expect_next_symbol("}");
             }
         };
 
@@ -85,10 +158,10 @@ impl<'a> Parser<'a> {
         })
     }
 
-    // function := basetype declarator "(" params? ")" ("{" stmt* "}" | ";")
-    // params := param ("," param)*
-    // param := basetype declarator type-suffix
-    fn function(&mut self) -> Result<Option<Function>, String> {
+    // function := basetype declarator ("{" stmt* "}" | ";")
+    //
+    // the declarator has a function type, whose parameters are read by read_type_suffix
+    fn function(&mut self) -> Result<Option<Function>, Diagnostic> {
         self.locals.clear();
 
         let mut sclass = None;
@@ -96,17 +169,39 @@ impl<'a> Parser<'a> {
         let name = &mut String::new();
 
         ty = self.declarator(&mut ty, name)?;
+        let loc = self.decl_loc.take();
+        let params = std::mem::take(&mut self.func_params);
+        let (ret, is_variadic) = match ty.as_ref() {
+            Type::Func { ret, is_variadic, .. } => (Box::clone(ret), *is_variadic),
+            _ => unreachable!("is_function accepts only a function type")
+        };
 
         // add function type to the scope
-        self.new_gvar(name, Box::new(Type::Func(ty)), None, false);
+        let func = self.new_gvar(name, Box::clone(&ty), None, false);
 
         // clone scope for saving current scope
         let sc = self.enter_scope();
 
-        // parse params
-        let params = self.read_func_params()?;
+        // the parameters are visible in the body
+        for param in &params {
+            let name = Rc::new(param.borrow().name.clone());
+            self.push_scope_with_var(&name, param);
+        }
         self.locals = params.clone();
 
+        // the argument registers of a variadic function are saved to __va_area__,
+        // which va_start reads
+        let va_area = if is_variadic {
+            let ty = Type::Array { base: Box::new(Type::Long), is_incomplete: false, len: VA_AREA_SIZE / 8 };
+            let var = self.new_var(&"__va_area__".to_string(), Box::new(ty), true);
+            self.locals.push(Rc::clone(&var));
+            Some(var)
+        } else {
+            None
+        };
+
+        self.current_ret_ty = Some(ret);
+
         // prototype declaration
         if let Ok(_) = self.expect_next_symbol(";") {
             self.leave_scope(sc);
@@ -116,25 +211,29 @@ impl<'a> Parser<'a> {
         // read function body
         self.expect_next_symbol("{".to_string())?;
 
-        let mut nodes = Vec::new();
-
-        while let Err(_) = self.expect_next_symbol("}") {
-            nodes.push(self.stmt()?);
-        };
+        let nodes = self.compound_stmt()?;
 
         self.leave_scope(sc);
 
         let locals = self.locals.to_vec();
+        let func_ty = Box::clone(&func.borrow().ty);
 
         // construct function object
-        Ok(Some(Function::new(Rc::new(name.to_string()), nodes, locals, params, sclass.map_or(false, |sc| sc.is_static()))))
+        let mut function = Function::new(Rc::new(name.to_string()), nodes, locals, params, func_ty, sclass.is_some_and(|sc| sc.is_static()), loc);
+        function.va_area = va_area;
+
+        Ok(Some(function))
     }
 
     // stmt := expr ";"
     //       | "return" expr ";"
     //       | "if" "(" expr ")" stmt ("else" stmt)?
     //       | "while" "(" expr ")" stmt
+    //       | "do" stmt "while" "(" expr ")" ";"
     //       | "for" "(" (expr? | declaration) ";" expr? ";" expr? ")" stmt
+    //       | "switch" "(" expr ")" stmt
+    //       | "case" const-expr ":" stmt
+    //       | "default" ":" stmt
     //       | "{" stmt "}"
     //       | "break" ";"
     //       | "continue" ";"
@@ -142,31 +241,39 @@ impl<'a> Parser<'a> {
     //       | ident ":" stmt
     //       | declaration
     //       | expr ";"
-    fn stmt(&mut self) -> Result<Stmt, String> {
+    //
+    // the statement is located at its first token
+    fn stmt(&mut self) -> Result<Stmt, Diagnostic> {
+        let loc = self.peek_loc();
+        let stmt = self.unlocated_stmt()?;
+
+        Ok(Stmt { loc, ..stmt })
+    }
+
+    fn unlocated_stmt(&mut self) -> Result<Stmt, Diagnostic> {
         match self.peekable.peek() {
             Some(tok) => {
                 match tok.token_type.tk_str().as_str() {
                     "return" => {
                         self.peekable.next();
 
-                        let expr = self.expr()?;
+                        let mut expr = self.expr()?;
                         self.expect_next_symbol(";")?;
 
-                        Ok(Stmt::Return { val: expr })
+                        if let Some(ret_ty) = &self.current_ret_ty {
+                            expr = Parser::new_cast(expr, ret_ty);
+                        }
+
+                        Ok(Stmt::new(StmtKind::Return { val: expr }))
                     }
                     "{" => {
                         self.peekable.next();
-                        let mut stmts: Vec<Stmt> = Vec::new();
 
                         let sc = self.enter_scope();
-                        while let Err(_) = self.expect_next_symbol("}".to_string()) {
-                            let stmt = self.stmt()?;
-                            stmts.push(stmt);
-                        }
-
+                        let stmts = self.compound_stmt()?;
                         self.leave_scope(sc);
 
-                        Ok(Stmt::Block { stmts })
+                        Ok(Stmt::new(StmtKind::Block { stmts }))
                     }
                     "if" => {
                         self.if_stmt()
@@ -174,23 +281,35 @@ impl<'a> Parser<'a> {
                     "while" => {
                         self.while_stmt()
                     }
+                    "do" => {
+                        self.do_while_stmt()
+                    }
                     "for" => {
                         self.for_stmt()
                     }
+                    "switch" => {
+                        self.switch_stmt()
+                    }
+                    "case" => {
+                        self.case_stmt()
+                    }
+                    "default" => {
+                        self.default_stmt()
+                    }
                     "break" => {
                         self.peekable.next();
                         self.expect_next_symbol(";")?;
-                        return Ok(Stmt::Break)
+                        Ok(Stmt::new(StmtKind::Break))
                     }
                     "continue" => {
                         self.peekable.next();
                         self.expect_next_symbol(";")?;
-                        return Ok(Stmt::Continue)
+                        Ok(Stmt::new(StmtKind::Continue))
                     }
                     "goto" => {
                         self.peekable.next();
                         let tok = self.expect_next_ident()?;
-                        let goto = Stmt::Goto(tok.token_type.tk_str());
+                        let goto = Stmt::new(StmtKind::Goto(tok.token_type.tk_str()));
                         self.expect_next_symbol(";")?;
 
                         Ok(goto)
@@ -199,7 +318,7 @@ impl<'a> Parser<'a> {
                         let pos = self.peekable.current_position();
                         if let Ok(tk) = self.expect_next_ident() {
                             if let Ok(_) = self.expect_next_symbol(":") {
-                                let node = Stmt::Label(Box::new(self.stmt()?), tk.token_type.tk_str());
+                                let node = Stmt::new(StmtKind::Label(Box::new(self.stmt()?), tk.token_type.tk_str()));
                                 return Ok(node)
                             } else {
                                 let _ = self.peekable.back_to(pos);
@@ -208,46 +327,61 @@ impl<'a> Parser<'a> {
                         if self.is_typename() {
                             return self.declaration()
                         }
-                        let expr_stmt = self.expr_stmt();
+                        let expr_stmt = self.expr_stmt()?;
                         self.expect_next_symbol(";")?;
 
-                        expr_stmt
+                        Ok(expr_stmt)
                     }
                 }
             }
             _ => {
-                Err("token not found".to_string())
+                Err(self.error_here("token not found"))
             }
         }
     }
 
+    // compound-stmt := stmt* "}"
+    // an error in a statement is recorded, and parsing is resumed from the next statement
+    fn compound_stmt(&mut self) -> Result<Vec<Stmt>, Diagnostic> {
+        let mut stmts = Vec::new();
+
+        while self.expect_next_symbol("}").is_err() {
+            if self.at_eof() {
+                return Err(self.error_here("expected '}'"))
+            }
+            match self.stmt() {
+                Ok(stmt) => stmts.push(stmt),
+                Err(e) => self.recover(e)
+            }
+        }
+
+        Ok(stmts)
+    }
+
     // expr := assign ("," assign)*
-    fn expr(&mut self) -> Result<ExprWrapper, String> {
+    fn expr(&mut self) -> Result<ExprWrapper, Diagnostic> {
         let mut node = self.assign()?;
 
         while let Ok(_) = self.expect_next_symbol(",") {
-            let lhs = Stmt::ExprStmt { val: node };
+            let lhs = Stmt { loc: node.loc.clone(), kind: StmtKind::ExprStmt { val: node } };
             node = Expr::Comma { lhs, rhs: self.assign()? }.to_expr_wrapper();
         }
 
         Ok(node)
     }
 
-    // assign    := logor (assign-op assign)?
-    // assign-op := "=" | "+=" | "-=" | "*=" | "/="
-    fn assign(&mut self) -> Result<ExprWrapper, String> {
-        let var = self.logor()?;
+    // assign    := conditional (assign-op assign)?
+    // assign-op := "=" | "+=" | "-=" | "*=" | "/=" | "%=" | "&=" | "|=" | "^=" | "<<=" | ">>="
+    fn assign(&mut self) -> Result<ExprWrapper, Diagnostic> {
+        let var = self.conditional()?;
 
         if let Ok(_) = self.expect_next_reserved("=") {
-            let val = self.expr()?;
-            return Ok(Expr::Assign {
-                var,
-                val
-            }.to_expr_wrapper())
+            let val = self.assign()?;
+            return Ok(Parser::new_assign(var, val))
         }
 
         if let Ok(_) = self.expect_next_reserved("*=") {
-            let val = self.expr()?;
+            let val = self.assign()?;
             return Ok(Expr::MulEq {
                 var,
                 val
@@ -255,7 +389,7 @@ impl<'a> Parser<'a> {
         }
 
         if let Ok(_) = self.expect_next_reserved("/=") {
-            let val = self.expr()?;
+            let val = self.assign()?;
             return Ok(Expr::DivEq {
                 var,
                 val
@@ -263,7 +397,7 @@ impl<'a> Parser<'a> {
         }
 
         if let Ok(_) = self.expect_next_reserved("+=") {
-            let val = self.expr()?;
+            let val = self.assign()?;
             if var.ty.has_base() {
                 return Ok(Expr::PtrAddEq {
                     var,
@@ -277,7 +411,7 @@ impl<'a> Parser<'a> {
         }
 
         if let Ok(_) = self.expect_next_reserved("-=") {
-            let val = self.expr()?;
+            let val = self.assign()?;
             if var.ty.has_base() {
                 return Ok(Expr::PtrSubEq {
                     var,
@@ -290,11 +424,90 @@ impl<'a> Parser<'a> {
             }.to_expr_wrapper())
         }
 
+        if self.expect_next_reserved("%=").is_ok() {
+            let val = self.assign()?;
+            return Ok(Expr::ModEq {
+                var,
+                val
+            }.to_expr_wrapper())
+        }
+
+        if self.expect_next_reserved("&=").is_ok() {
+            let val = self.assign()?;
+            return Ok(Expr::BitAndEq {
+                var,
+                val
+            }.to_expr_wrapper())
+        }
+
+        if self.expect_next_reserved("|=").is_ok() {
+            let val = self.assign()?;
+            return Ok(Expr::BitOrEq {
+                var,
+                val
+            }.to_expr_wrapper())
+        }
+
+        if self.expect_next_reserved("^=").is_ok() {
+            let val = self.assign()?;
+            return Ok(Expr::BitXorEq {
+                var,
+                val
+            }.to_expr_wrapper())
+        }
+
+        if self.expect_next_reserved("<<=").is_ok() {
+            let val = self.assign()?;
+            return Ok(Expr::ShlEq {
+                var,
+                val
+            }.to_expr_wrapper())
+        }
+
+        if self.expect_next_reserved(">>=").is_ok() {
+            let val = self.assign()?;
+            return Ok(Expr::ShrEq {
+                var,
+                val
+            }.to_expr_wrapper())
+        }
+
         Ok(var)
     }
 
+    // conditional := logor ("?" expr ":" conditional)?
+    fn conditional(&mut self) -> Result<ExprWrapper, Diagnostic> {
+        let cond = self.logor()?;
+        if self.expect_next_reserved("?").is_err() {
+            return Ok(cond)
+        }
+
+        let then = self.expr()?;
+        self.expect_next_symbol(":")?;
+        let els = self.conditional()?;
+        // both arms are converted to their common type
+        let (then, els) = Parser::usual_arith_conv(then, els);
+
+        Ok(Expr::Cond {
+            cond,
+            then,
+            els
+        }.to_expr_wrapper())
+    }
+
+    // const-expr := conditional
+    fn const_expr(&mut self) -> Result<isize, Diagnostic> {
+        let tok = self.peekable.peek().cloned();
+        let node = self.conditional()?;
+
+        const_eval::eval(&node).map_err(|msg| match &tok {
+            Some(tok) => Diagnostic::error_at(tok, msg),
+            None => Diagnostic::error(msg)
+        })
+    }
+
     // logor := logand ("||" logand)*
-    fn logor(&mut self) -> Result<ExprWrapper, String> {
+    fn logor(&mut self) -> Result<ExprWrapper, Diagnostic> {
         let mut lhs = self.logand()?;
         while let Some(TokenType::Reserved(Reserved { op, .. })) = self.peekable.peek().map(|tok| &tok.token_type) {
             if op.as_ref() == "||" {
@@ -311,7 +524,7 @@ impl<'a> Parser<'a> {
         Ok(lhs)
     }
     // logand := bitor ("&&" bitor)*
-    fn logand(&mut self) -> Result<ExprWrapper, String> {
+    fn logand(&mut self) -> Result<ExprWrapper, Diagnostic> {
         let mut lhs = self.bitor()?;
         while let Some(TokenType::Reserved(Reserved { op, .. })) = self.peekable.peek().map(|tok| &tok.token_type) {
             if op.as_ref() == "&&" {
@@ -329,14 +542,15 @@ impl<'a> Parser<'a> {
     }
 
     // bitor := bitxor ("|" bitxor)*
-    fn bitor(&mut self) -> Result<ExprWrapper, String> {
+    fn bitor(&mut self) -> Result<ExprWrapper, Diagnostic> {
         let mut lhs = self.bitxor()?;
         while let Some(TokenType::Reserved(Reserved { op, .. })) = self.peekable.peek().map(|tok| &tok.token_type) {
             if op.as_ref() == "|" {
                 self.peekable.next();
+                let (lhs_conv, rhs) = Parser::usual_arith_conv(lhs, self.bitxor()?);
                 lhs = Expr::BitOr {
-                    lhs,
-                    rhs: self.bitxor()?
+                    lhs: lhs_conv,
+                    rhs
                 }.to_expr_wrapper()
             } else {
                 break
@@ -347,14 +561,15 @@ impl<'a> Parser<'a> {
     }
 
     // bitxor := bitand ("^" bitand)*
-    fn bitxor(&mut self) -> Result<ExprWrapper, String> {
+    fn bitxor(&mut self) -> Result<ExprWrapper, Diagnostic> {
         let mut lhs = self.bitand()?;
         while let Some(TokenType::Reserved(Reserved { op, .. })) = self.peekable.peek().map(|tok| &tok.token_type) {
             if op.as_ref() == "^" {
                 self.peekable.next();
+                let (lhs_conv, rhs) = Parser::usual_arith_conv(lhs, self.bitand()?);
                 lhs = Expr::BitXor {
-                    lhs,
-                    rhs: self.bitand()?
+                    lhs: lhs_conv,
+                    rhs
                 }.to_expr_wrapper()
             } else {
                 break
@@ -365,14 +580,15 @@ impl<'a> Parser<'a> {
     }
 
     // bitand := equality ("&" equality)*
-    fn bitand(&mut self) -> Result<ExprWrapper, String> {
+    fn bitand(&mut self) -> Result<ExprWrapper, Diagnostic> {
         let mut lhs = self.equality()?;
         while let Some(TokenType::Reserved(Reserved { op, .. })) = self.peekable.peek().map(|tok| &tok.token_type) {
             if op.as_ref() == "&" {
                 self.peekable.next();
+                let (lhs_conv, rhs) = Parser::usual_arith_conv(lhs, self.equality()?);
                 lhs = Expr::BitAnd {
-                    lhs,
-                    rhs: self.equality()?
+                    lhs: lhs_conv,
+                    rhs
                 }.to_expr_wrapper()
             } else {
                 break
@@ -383,7 +599,7 @@ impl<'a> Parser<'a> {
     }
 
     // equality := relational ("==" relational | "!=" relational)*
-    fn equality(&mut self) -> Result<ExprWrapper, String> {
+    fn equality(&mut self) -> Result<ExprWrapper, Diagnostic> {
         let mut node = self.relational()?;
 
         while let Some(token) = self.peekable.peek() {
@@ -392,8 +608,10 @@ impl<'a> Parser<'a> {
                     self.peekable.next();
 
                     let rhs = self.relational()?;
+                    self.check_ptr_int_cmp(token, &node, &rhs);
+                    let (lhs, rhs) = Parser::usual_arith_conv(node, rhs);
                     node = Expr::Eq {
-                        lhs: node,
+                        lhs,
                         rhs
                     }.to_expr_wrapper();
                 }
@@ -401,8 +619,10 @@ impl<'a> Parser<'a> {
                     self.peekable.next();
 
                     let rhs = self.relational()?;
+                    self.check_ptr_int_cmp(token, &node, &rhs);
+                    let (lhs, rhs) = Parser::usual_arith_conv(node, rhs);
                     node = Expr::Neq {
-                        lhs: node,
+                        lhs,
                         rhs
                     }.to_expr_wrapper();
                 }
@@ -413,45 +633,57 @@ impl<'a> Parser<'a> {
         Ok(node)
     }
 
-    // relational := add ("<" add | "<=" add | ">" add | ">=" add)*
-    fn relational(&mut self) -> Result<ExprWrapper, String> {
-        let mut node = self.add()?;
+    // relational := shift ("<" shift | "<=" shift | ">" shift | ">=" shift)*
+    fn relational(&mut self) -> Result<ExprWrapper, Diagnostic> {
+        let mut node = self.shift()?;
 
-        while let Some(TokenType::Reserved(Reserved { op, .. })) = self.peekable.peek().map(|tok| &tok.token_type) {
+        while let Some(token) = self.peekable.peek() {
+            let op = match &token.token_type {
+                TokenType::Reserved(Reserved { op, .. }) => op,
+                _ => break
+            };
             match op.as_str() {
                 "<" => {
                     self.peekable.next();
                     let rhs = self.add()?;
+                    self.check_ptr_int_cmp(token, &node, &rhs);
 
+                    let (lhs, rhs) = Parser::usual_arith_conv(node, rhs);
                     node = Expr::Lt {
-                        lhs: node,
+                        lhs,
                         rhs
                     }.to_expr_wrapper();
                 },
                 "<=" => {
                     self.peekable.next();
                     let rhs = self.add()?;
+                    self.check_ptr_int_cmp(token, &node, &rhs);
 
+                    let (lhs, rhs) = Parser::usual_arith_conv(node, rhs);
                     node = Expr::Le {
-                        lhs: node,
+                        lhs,
                         rhs
                     }.to_expr_wrapper();
                 },
                 ">" => {
                     self.peekable.next();
                     let rhs = self.add()?;
+                    self.check_ptr_int_cmp(token, &node, &rhs);
 
+                    let (lhs, rhs) = Parser::usual_arith_conv(node, rhs);
                     node = Expr::Gt {
-                        lhs: node,
+                        lhs,
                         rhs
                     }.to_expr_wrapper();
                 },
                 ">=" => {
                     self.peekable.next();
                     let rhs = self.add()?;
+                    self.check_ptr_int_cmp(token, &node, &rhs);
 
+                    let (lhs, rhs) = Parser::usual_arith_conv(node, rhs);
                     node = Expr::Ge {
-                        lhs: node,
+                        lhs,
                         rhs
                     }.to_expr_wrapper();
                 },
@@ -462,8 +694,40 @@ impl<'a> Parser<'a> {
         Ok(node)
     }
 
+    // shift := add ("<<" add | ">>" add)*
+    // the operands are promoted separately, and the result has the type of the left one
+    fn shift(&mut self) -> Result<ExprWrapper, Diagnostic> {
+        let mut node = self.add()?;
+
+        while let Some(TokenType::Reserved(Reserved { op, .. })) = self.peekable.peek().map(|tok| &tok.token_type) {
+            match op.as_str() {
+                "<<" => {
+                    self.peekable.next();
+                    let rhs = self.add()?;
+
+                    node = Expr::Shl {
+                        lhs: Parser::promote(node),
+                        rhs: Parser::promote(rhs)
+                    }.to_expr_wrapper();
+                },
+                ">>" => {
+                    self.peekable.next();
+                    let rhs = self.add()?;
+
+                    node = Expr::Shr {
+                        lhs: Parser::promote(node),
+                        rhs: Parser::promote(rhs)
+                    }.to_expr_wrapper();
+                },
+                _ => break
+            }
+        }
+
+        Ok(node)
+    }
+
     // add := mul ("+" | "-")*
-    fn add(&mut self) -> Result<ExprWrapper, String> {
+    fn add(&mut self) -> Result<ExprWrapper, Diagnostic> {
         let mut node = self.mul()?;
 
         while let Some(TokenType::Reserved(Reserved { op, .. })) = self.peekable.peek().map(|tok| &tok.token_type) {
@@ -493,8 +757,8 @@ impl<'a> Parser<'a> {
         Ok(node)
     }
 
-    // mul := cast ("*" cast | "/" cast)*
-    fn mul(&mut self) -> Result<ExprWrapper, String> {
+    // mul := cast ("*" cast | "/" cast | "%" cast)*
+    fn mul(&mut self) -> Result<ExprWrapper, Diagnostic> {
         let mut node = self.cast()?;
 
         while let Some(TokenType::Reserved(Reserved { op, .. })) = self.peekable.peek().map(|tok| &tok.token_type) {
@@ -503,8 +767,9 @@ impl<'a> Parser<'a> {
                     self.peekable.next();
                     let rhs = self.cast()?;
 
+                    let (lhs, rhs) = Parser::usual_arith_conv(node, rhs);
                     node = Expr::Mul {
-                        lhs: node,
+                        lhs,
                         rhs
                     }.to_expr_wrapper();
                 },
@@ -512,8 +777,19 @@ impl<'a> Parser<'a> {
                     self.peekable.next();
                     let rhs = self.unary()?;
 
-                   node = Expr::Div {
-                        lhs: node,
+                    let (lhs, rhs) = Parser::usual_arith_conv(node, rhs);
+                    node = Expr::Div {
+                        lhs,
+                        rhs
+                    }.to_expr_wrapper();
+                },
+                "%" => {
+                    self.peekable.next();
+                    let rhs = self.cast()?;
+
+                    let (lhs, rhs) = Parser::usual_arith_conv(node, rhs);
+                    node = Expr::Mod {
+                        lhs,
                         rhs
                     }.to_expr_wrapper();
                 },
@@ -525,14 +801,16 @@ impl<'a> Parser<'a> {
     }
 
     // cast := "(" type-name ")" cast | unary
-    fn cast(&mut self) -> Result<ExprWrapper, String> {
+    fn cast(&mut self) -> Result<ExprWrapper, Diagnostic> {
         let pos = self.peekable.current_position();
+        let loc = self.peek_loc();
 
         if let Ok(_) = self.expect_next_symbol("(") {
             if self.is_typename() {
                 let ty = self.type_name()?;
                 self.expect_next_symbol(")")?;
-                return Ok(Expr::Cast(ty, self.cast()?).to_expr_wrapper())
+                let node = Expr::Cast(ty, self.cast()?).to_expr_wrapper();
+                return Ok(ExprWrapper { loc, ..node })
             }
             let _ = self.peekable.back_to(pos);
         }
@@ -543,23 +821,22 @@ impl<'a> Parser<'a> {
     // unary := ("+" | "-" | "*" | "&" | "!" | "~")? cast
     //        | ("++" | "--") unary
     //        | postfix
-    fn unary(&mut self) -> Result<ExprWrapper, String> {
+    fn unary(&mut self) -> Result<ExprWrapper, Diagnostic> {
         let tk = self.peekable.peek();
+        let loc = self.peek_loc();
 
-        match tk.map(|tok| &tok.token_type) {
+        let node = match tk.map(|tok| &tok.token_type) {
             Some(TokenType::Reserved(Reserved { op, .. })) => {
                 match op.as_str() {
                     "+" => {
                         self.peekable.next();
-                        self.cast()
+                        Ok(Parser::promote(self.cast()?))
                     },
                     "-" => {
                         self.peekable.next();
                         let rhs = self.cast()?;
-                        Ok(Expr::Sub {
-                            lhs: Expr::Num { val: 0 }.to_expr_wrapper(),
-                            rhs
-                         }.to_expr_wrapper())
+                        let zero = Expr::Num { val: 0, ty: Box::new(Type::Int) }.to_expr_wrapper();
+                        Parser::new_sub(zero, rhs)
                     },
                     "*" => {
                         self.peekable.next();
@@ -575,13 +852,13 @@ impl<'a> Parser<'a> {
                     }
                     "~" => {
                         self.peekable.next();
-                        Ok(Expr::BitNot(self.cast()?).to_expr_wrapper())
+                        Ok(Expr::BitNot(Parser::promote(self.cast()?)).to_expr_wrapper())
                     }
                     "++" => {
                         self.peekable.next();
                         let unary = self.unary()?;
                         if !unary.expr.is_lvalue() {
-                            return Err(format!("{:?} is not an lvalue", unary))
+                            return Err(Diagnostic::error_at(tk.unwrap(), "lvalue required as increment operand"))
                         }
                         Ok(Expr::PreInc(unary).to_expr_wrapper())
                     },
@@ -589,7 +866,7 @@ impl<'a> Parser<'a> {
                         self.peekable.next();
                         let unary = self.unary()?;
                         if !unary.expr.is_lvalue() {
-                            return Err(format!("{:?} is not an lvalue", unary))
+                            return Err(Diagnostic::error_at(tk.unwrap(), "lvalue required as decrement operand"))
                         }
                         Ok(Expr::PreDec(unary).to_expr_wrapper())
                     }
@@ -597,14 +874,23 @@ impl<'a> Parser<'a> {
                 }
             },
             _ => self.postfix()
-        }
+        }?;
+
+        Ok(ExprWrapper { loc, ..node })
     }
 
-    // postfix := primary ("[" expr "]" | "." ident | "->" ident | "++" | "--")*
-    fn postfix(&mut self) -> Result<ExprWrapper, String> {
+    // postfix := primary ("[" expr "]" | "(" func-args ")" | "." ident | "->" ident | "++" | "--")*
+    fn postfix(&mut self) -> Result<ExprWrapper, Diagnostic> {
+        let loc = self.peek_loc();
         let mut node = self.primary()?;
+        node.loc = loc;
 
         loop {
+            if self.expect_next_symbol("(").is_ok() {
+                node = self.func_call(node)?;
+                continue
+            }
+
             if let Ok(_) = self.expect_next_symbol("[") {
                 // x[y] is short for *(x + y)
                 let expr = self.expr()?;
@@ -617,7 +903,7 @@ impl<'a> Parser<'a> {
                     Ok(_) => {
                         node = Expr::Deref { operand: exp }.to_expr_wrapper();
                     },
-                    _ => return Err("expect ] after [ expr".to_string())
+                    _ => return Err(self.error_here("expect ] after [ expr"))
                 }
 
                 continue;
@@ -651,10 +937,11 @@ impl<'a> Parser<'a> {
     // primary := "(" "{" stmt-expr-tail
     //          | "(" expr ")"
     //          | "sizeof" unary
-    //          | ident func-args?
+    //          | "__builtin_reg_class" "(" type-name ")"
+    //          | ident
     //          | str
     //          | num
-    fn primary(&mut self) -> Result<ExprWrapper, String> {
+    fn primary(&mut self) -> Result<ExprWrapper, Diagnostic> {
         let token = self.peekable.peek();
 
         match token.map(|tok| &tok.token_type) {
@@ -665,10 +952,10 @@ impl<'a> Parser<'a> {
                     return self.stmt_expr()
                 }
 
-                let expr = self.expr();
+                let expr = self.expr()?;
                 self.expect_next_symbol(")".to_string())?;
 
-                expr
+                Ok(expr)
             }
             // sizeof
             Some(TokenType::Reserved(Reserved { op, .. })) if op.as_str() == "sizeof" => {
@@ -679,13 +966,13 @@ impl<'a> Parser<'a> {
                     if self.is_typename() {
                         let ty = self.type_name()?;
                         if ty.is_incomplete() {
-                            return Err("incomplete type".to_string())
+                            return Err(self.error_here("incomplete type"))
                         }
 
                         let size = ty.size();
                         self.expect_next_symbol(")")?;
 
-                        return Ok(Expr::Num { val: size as isize }.to_expr_wrapper())
+                        return Ok(Expr::Num { val: size as isize, ty: Box::new(Type::ULong) }.to_expr_wrapper())
                     };
                     // typeof unaryとして扱うため一つ戻す
                     // "(" expression ")" を扱えるようにしたい
@@ -695,55 +982,66 @@ impl<'a> Parser<'a> {
                 // unary at here => "*"* (ident) | "(" expression ")" | num
                 let node = self.unary()?;
                 if node.ty.is_incomplete() {
-                    return Err("incomplete type".to_string())
+                    return Err(self.error_here("incomplete type"))
                 }
                 let size = node.ty.size();
 
-                Ok(Expr::Num { val: size as isize }.to_expr_wrapper())
+                Ok(Expr::Num { val: size as isize, ty: Box::new(Type::ULong) }.to_expr_wrapper())
             }
             // num
-            Some(TokenType::Num(Num { val, .. })) => {
+            Some(TokenType::Num(Num { val, fval, ty, .. })) => {
                 self.peekable.next();
-                Ok(Expr::Num { val: *val }.to_expr_wrapper())
+                if ty.is_flonum() {
+                    // floating-point literals are treated as double
+                    let num = Expr::FNum { val: *fval }.to_expr_wrapper();
+                    if let Type::Float = ty {
+                        return Ok(Expr::Cast(Box::new(Type::Float), num).to_expr_wrapper())
+                    }
+                    return Ok(num)
+                }
+
+                Ok(Expr::Num { val: *val, ty: Box::new(ty.clone()) }.to_expr_wrapper())
             }
-            // local var
-            Some(TokenType::Ident(Ident { name, .. })) => {
-                // function call
+            // how a value of the type is passed as an argument, which va_arg needs:
+            // 0 on the stack, 1 in a general purpose register and 2 in an xmm register
+            Some(TokenType::Ident(Ident { name, .. })) if name.as_str() == "__builtin_reg_class" => {
                 self.peekable.next();
-                if let Ok(_) = self.expect_next_symbol("(") {
-                    let args = self.parse_args()?;
-                    let expr = Box::new(Expr::FnCall { fn_name: Rc::clone(&name), args });
-
-                    let ty = match self.find_func(&*name)? {
-                        Some(ret_type) => {
-                            ret_type
-                        },
-                        _ => Box::new(Type::Int)
-                    };
+                self.expect_next_symbol("(")?;
+                let ty = self.type_name()?;
+                self.expect_next_symbol(")")?;
 
-                    return Ok(ExprWrapper {
-                        ty,
-                        expr
-                    })
-                 }
-                // variable
-                if let Some(VarScope { target, .. }) = self.find_var(&name) {
-                    match target {
-                        ScopeElement::Var(var) => {
-                            Ok(Expr::Var(Rc::clone(var)).to_expr_wrapper())
-                        },
-                        ScopeElement::Enum(_, val) => {
-                            Ok(Expr::Num { val: *val }.to_expr_wrapper())
-                        },
-                        _ => {
-                            let msg = format!("undefined variable: {}", name);
-                            Err(msg)
-                        }
-                    }
-                } else {
-                    Err(format!("undefined variable: {}", name))
+                let val = match *ty {
+                    Type::Struct { .. } => 0,
+                    _ if ty.is_flonum() => 2,
+                    _ => 1
+                };
+
+                Ok(Expr::Num { val, ty: Box::new(Type::Int) }.to_expr_wrapper())
+            }
+            // variable, enum constant or function
+            Some(TokenType::Ident(Ident { name, .. })) => {
+                self.peekable.next();
+                match self.find_var(name).map(|sc| &sc.target) {
+                    Some(ScopeElement::Var(var)) => {
+                        Ok(Expr::Var(Rc::clone(var)).to_expr_wrapper())
+                    },
+                    Some(ScopeElement::Enum(_, val)) => {
+                        Ok(Expr::Num { val: *val, ty: Box::new(Type::Int) }.to_expr_wrapper())
+                    },
+                    // a function called without a declaration is assumed to return int
+                    None if self.is_next_symbol("(") => {
+                        let msg = format!("implicit declaration of function '{}'", name);
+                        let span = token.map(Span::from_token);
+                        self.warnings.push(Diagnostic::warning("implicit-function-declaration", msg, span));
+
+                        let ty = Type::Func { ret: Box::new(Type::Int), params: Vec::new(), is_variadic: false };
+                        let func = self.new_gvar(name, Box::new(ty), None, false);
+                        Ok(Expr::Var(func).to_expr_wrapper())
+                    },
+                    _ => Err(Diagnostic::error_at(token.unwrap(), format!("undefined variable: {}", name)))
                 }
             }
+            Some(TokenType::PpNum(PpNum { error, .. })) => Err(self.error_here(error.as_str())),
             // str
             Some(TokenType::Str(Str { bytes, .. })) => {
                 self.peekable.next();
@@ -760,10 +1058,157 @@ impl<'a> Parser<'a> {
                 Ok(Expr::Var(var).to_expr_wrapper())
             }
             // unexpected
-            unexpected => {
-                let msg = format!("{:?} is unexpected token at primary", unexpected);
-                Err(msg)
-            }
+            _ => Err(self.error_here("expected an expression"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenizer::Tokenizer;
+    use crate::diagnostic::Severity;
+
+    fn parse(input: &str) -> Result<Program, Vec<Diagnostic>> {
+        let tokens = Tokenizer::new(input.to_string(), "test.c").tokenize().expect("cannot tokenize");
+
+        Parser::new(&tokens).parse()
+    }
+
+    // the value of the last return statement of the last function
+    fn returned(input: &str) -> ExprWrapper {
+        let prog = parse(input).unwrap_or_else(|errors| panic!("cannot parse {:?}: {}", input, errors[0].message));
+        let func = prog.fns.last().expect("no function");
+        let val = func.nodes.iter().rev().find_map(|stmt| match &stmt.kind {
+            StmtKind::Return { val } => Some(val.clone()),
+            _ => None
+        });
+
+        val.expect("no return statement")
+    }
+
+    // the tree as an s-expression, e.g. (Sub (Sub a b) c)
+    fn shape(ew: &ExprWrapper) -> String {
+        match ew.expr.as_ref() {
+            Expr::Num { val, .. } => val.to_string(),
+            Expr::Var(var) => var.borrow().name.clone(),
+            Expr::Cast(ty, operand) => format!("(Cast {:?} {})", ty, shape(operand)),
+            Expr::FnCall { callee, args, .. } => {
+                let args: Vec<String> = args.iter().map(shape).collect();
+                format!("(FnCall {} [{}])", shape(callee), args.join(" "))
+            },
+            Expr::Deref { operand } | Expr::Addr { operand } => format!("({} {})", ew.expr, shape(operand)),
+            Expr::Eq { lhs, rhs }
+            | Expr::Neq { lhs, rhs }
+            | Expr::Gt { lhs, rhs }
+            | Expr::Ge { lhs, rhs }
+            | Expr::Lt { lhs, rhs }
+            | Expr::Le { lhs, rhs }
+            | Expr::Add { lhs, rhs }
+            | Expr::Sub { lhs, rhs }
+            | Expr::Mul { lhs, rhs }
+            | Expr::Div { lhs, rhs }
+            | Expr::Mod { lhs, rhs }
+            | Expr::Shl { lhs, rhs }
+            | Expr::Shr { lhs, rhs }
+            | Expr::PtrAdd { lhs, rhs } => format!("({} {} {})", ew.expr, shape(lhs), shape(rhs)),
+            Expr::Assign { var, val }
+            | Expr::ModEq { var, val }
+            | Expr::ShlEq { var, val } => format!("({} {} {})", ew.expr, shape(var), shape(val)),
+            expr => format!("({} ..)", expr)
         }
     }
+
+    #[test]
+    fn left_associative() {
+        let ew = returned("int main() { int a; int b; int c; return a-b-c; }");
+        assert_eq!(shape(&ew), "(Sub (Sub a b) c)");
+        let ew = returned("int main() { int a; int b; int c; return a/b*c; }");
+        assert_eq!(shape(&ew), "(Mul (Div a b) c)");
+    }
+
+    #[test]
+    fn precedence() {
+        let ew = returned("int main() { int a; return a<<2+3; }");
+        assert_eq!(shape(&ew), "(Shl a (Add 2 3))");
+        let ew = returned("int main() { int a; int b; return a+b%3<b; }");
+        assert_eq!(shape(&ew), "(Lt (Add a (Mod b 3)) b)");
+        let ew = returned("int main() { int a; int b; return (a+b)*2; }");
+        assert_eq!(shape(&ew), "(Mul (Add a b) 2)");
+    }
+
+    #[test]
+    fn comparison_keeps_operands() {
+        let ew = returned("int main() { int x; int y; return x>y; }");
+        assert_eq!(shape(&ew), "(Gt x y)");
+        let ew = returned("int main() { int x; int y; return x>=y; }");
+        assert_eq!(shape(&ew), "(Ge x y)");
+    }
+
+    #[test]
+    fn usual_arithmetic_conversions() {
+        let ew = returned("long main() { int i; long l; return i+l; }");
+        assert_eq!(shape(&ew), "(Add (Cast Long i) l)");
+        assert_eq!(*ew.ty, Type::Long);
+
+        let ew = returned("int main() { char c; short s; return c*s; }");
+        assert_eq!(shape(&ew), "(Mul (Cast Int c) (Cast Int s))");
+        assert_eq!(*ew.ty, Type::Int);
+
+        // the type of a shift is the promoted left operand
+        let ew = returned("int main() { int i; long l; return i<<l; }");
+        assert_eq!(*ew.ty, Type::Int);
+    }
+
+    #[test]
+    fn assignments() {
+        let ew = returned("int main() { int a; return a=a%=3; }");
+        assert_eq!(shape(&ew), "(Assign a (ModEq a 3))");
+        let ew = returned("int main() { int a; return a<<=1; }");
+        assert_eq!(shape(&ew), "(ShlEq a 1)");
+    }
+
+    #[test]
+    fn function_pointer_call() {
+        let ew = returned("int add(int a, int b) { return a+b; } int main() { int (*fp)(int, int) = add; return fp(1, 2); }");
+        assert_eq!(shape(&ew), "(FnCall fp [1 2])");
+        assert_eq!(*ew.ty, Type::Int);
+
+        let ew = returned("int one() { return 1; } int main() { return one(); }");
+        assert_eq!(shape(&ew), "(FnCall one [])");
+    }
+
+    #[test]
+    fn errors() {
+        assert!(parse("int main() { return 1 }").is_err());
+        assert!(parse("int main() { return x; }").is_err());
+        assert!(parse("int main() { return (1; }").is_err());
+    }
+
+    fn span(diag: &Diagnostic) -> (usize, usize, usize) {
+        let span = diag.span.as_ref().unwrap_or_else(|| panic!("no location: {}", diag.message));
+        (span.loc.row, span.loc.col, span.len)
+    }
+
+    #[test]
+    fn initializer_errors_are_located() {
+        let errors = parse("int x;\nint y = x + 1;\nchar c = (long)&x;\nint z = 1;").unwrap_err();
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].message, "not a compile-time constant");
+        assert_eq!(span(&errors[0]), (2, 9, 5));
+        assert_eq!(errors[1].message, "initializer element is not computable at load time");
+        assert_eq!(span(&errors[1]), (3, 10, 8));
+    }
+
+    #[test]
+    fn duplicate_case_has_a_note() {
+        let errors = parse("int main() { switch (1) { case 1: return 0; case 2 - 1: return 1; } }").unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "duplicate case value: 1");
+        assert_eq!(span(&errors[0]), (1, 50, 5));
+        assert_eq!(errors[0].notes.len(), 1);
+        assert_eq!(errors[0].notes[0].severity, Severity::Note);
+        assert_eq!(errors[0].notes[0].message, "previous case is here");
+        assert_eq!(span(&errors[0].notes[0]), (1, 32, 1));
+    }
 }
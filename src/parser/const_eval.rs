@@ -0,0 +1,233 @@
+use crate::node::{ Stmt, StmtKind, Expr, ExprWrapper };
+use crate::_type::{ Type, common_type };
+
+// evaluate a given node as a constant expression
+pub(crate) fn eval(ew: &ExprWrapper) -> Result<isize, String> {
+    let mut label = None;
+    let val = eval_reloc(ew, &mut label)?;
+    if label.is_some() {
+        return Err("not a compile-time constant".to_string())
+    }
+
+    Ok(val)
+}
+
+// a constant expression is either just a number or ptr+n where ptr
+// is a pointer to a global variable and n is a positive/negative number.
+// the latter form is accepted only as an initialization expression for
+// a global variable, and the name of the variable is set to `label`
+pub(in super) fn eval_reloc(ew: &ExprWrapper, label: &mut Option<String>) -> Result<isize, String> {
+    // a floating-point value is truncated toward zero
+    if ew.ty.is_flonum() {
+        return Ok(eval_double(ew)? as isize)
+    }
+
+    let val = match ew.expr.as_ref() {
+        Expr::Num { val, .. } => Ok(*val),
+        Expr::Add { lhs, rhs } => Ok(eval_reloc(lhs, label)?.wrapping_add(eval(rhs)?)),
+        Expr::Sub { lhs, rhs } => Ok(eval_reloc(lhs, label)?.wrapping_sub(eval(rhs)?)),
+        Expr::PtrAdd { lhs, rhs } => {
+            let base_size = lhs.ty.base_size() as isize;
+            Ok(eval_reloc(lhs, label)?.wrapping_add(eval(rhs)?.wrapping_mul(base_size)))
+        },
+        Expr::PtrSub { lhs, rhs } => {
+            let base_size = lhs.ty.base_size() as isize;
+            Ok(eval_reloc(lhs, label)?.wrapping_sub(eval(rhs)?.wrapping_mul(base_size)))
+        },
+        Expr::Mul { lhs, rhs } => Ok(eval(lhs)?.wrapping_mul(eval(rhs)?)),
+        Expr::Div { lhs, rhs } => {
+            let r = eval(rhs)?;
+            if r == 0 {
+                return Err("division by zero".to_string())
+            }
+            if ew.ty.is_unsigned() {
+                return Ok(((eval(lhs)? as u64) / (r as u64)) as isize)
+            }
+            Ok(eval(lhs)?.wrapping_div(r))
+        },
+        Expr::Mod { lhs, rhs } => {
+            let r = eval(rhs)?;
+            if r == 0 {
+                return Err("division by zero".to_string())
+            }
+            if ew.ty.is_unsigned() {
+                return Ok(((eval(lhs)? as u64) % (r as u64)) as isize)
+            }
+            Ok(eval(lhs)?.wrapping_rem(r))
+        },
+        Expr::BitAnd { lhs, rhs } => Ok(eval(lhs)? & eval(rhs)?),
+        Expr::BitOr { lhs, rhs } => Ok(eval(lhs)? | eval(rhs)?),
+        Expr::BitXor { lhs, rhs } => Ok(eval(lhs)? ^ eval(rhs)?),
+        Expr::Shl { lhs, rhs } => Ok(eval(lhs)?.wrapping_shl(eval(rhs)? as u32)),
+        Expr::Shr { lhs, rhs } if ew.ty.is_unsigned() => Ok(((eval(lhs)? as u64).wrapping_shr(eval(rhs)? as u32)) as isize),
+        Expr::Shr { lhs, rhs } => Ok(eval(lhs)?.wrapping_shr(eval(rhs)? as u32)),
+        Expr::Eq { lhs, rhs }
+        | Expr::Neq { lhs, rhs }
+        | Expr::Lt { lhs, rhs }
+        | Expr::Le { lhs, rhs }
+        | Expr::Gt { lhs, rhs }
+        | Expr::Ge { lhs, rhs } => {
+            let ty = common_type(&lhs.ty, &rhs.ty);
+            // None if either operand is NaN
+            let ord = if ty.is_flonum() {
+                eval_double(lhs)?.partial_cmp(&eval_double(rhs)?)
+            } else if ty.is_unsigned() {
+                Some((eval(lhs)? as u64).cmp(&(eval(rhs)? as u64)))
+            } else {
+                Some(eval(lhs)?.cmp(&eval(rhs)?))
+            };
+            let val = match ew.expr.as_ref() {
+                Expr::Eq { .. } => ord.is_some_and(|ord| ord.is_eq()),
+                Expr::Neq { .. } => ord.is_none_or(|ord| ord.is_ne()),
+                Expr::Lt { .. } => ord.is_some_and(|ord| ord.is_lt()),
+                Expr::Le { .. } => ord.is_some_and(|ord| ord.is_le()),
+                Expr::Gt { .. } => ord.is_some_and(|ord| ord.is_gt()),
+                _ => ord.is_some_and(|ord| ord.is_ge())
+            };
+            Ok(val as isize)
+        },
+        Expr::LogAnd { lhs, rhs } => Ok((is_true(lhs)? && is_true(rhs)?) as isize),
+        Expr::LogOr { lhs, rhs } => Ok((is_true(lhs)? || is_true(rhs)?) as isize),
+        Expr::Not(operand) => Ok(!is_true(operand)? as isize),
+        Expr::BitNot(operand) => Ok(!eval(operand)?),
+        Expr::Cond { cond, then, els } => {
+            if is_true(cond)? {
+                eval_reloc(then, label)
+            } else {
+                eval_reloc(els, label)
+            }
+        },
+        Expr::Comma { lhs, rhs } => {
+            discard(lhs)?;
+            eval_reloc(rhs, label)
+        },
+        Expr::Cast(ty, operand) => {
+            if let Type::Bool = ty.as_ref() {
+                return Ok(is_true(operand)? as isize)
+            }
+            let val = eval_reloc_as(operand, ty, label)?;
+            if label.is_some() || !ty.is_integer() {
+                return Ok(val)
+            }
+            Ok(truncate(val, ty))
+        },
+        Expr::Addr { operand } => eval_addr(operand, label),
+        // an array is converted to a pointer to its first element, and a function to a pointer to it
+        Expr::Var(_) | Expr::Member(_, _) | Expr::Deref { .. } if decays(&ew.ty) => {
+            eval_addr(ew, label)
+        },
+        _ => Err("not a compile-time constant".to_string())
+    }?;
+
+    // the result wraps around as the value of its type
+    if label.is_none() && ew.ty.is_integer() {
+        return Ok(truncate(val, &ew.ty))
+    }
+
+    Ok(val)
+}
+
+// evaluate a given node converted to `ty`, as it is assigned to a variable of the type
+pub(in super) fn eval_reloc_as(ew: &ExprWrapper, ty: &Type, label: &mut Option<String>) -> Result<isize, String> {
+    // a value above i64::MAX is representable only as an unsigned 64-bit integer
+    if ew.ty.is_flonum() && ty.is_unsigned() && ty.size() == 8 {
+        return Ok(eval_double(ew)? as u64 as isize)
+    }
+
+    eval_reloc(ew, label)
+}
+
+// evaluate a given node as a floating-point constant expression
+pub(crate) fn eval_double(ew: &ExprWrapper) -> Result<f64, String> {
+    if ew.ty.is_integer() {
+        let val = eval(ew)?;
+        if ew.ty.is_unsigned() {
+            return Ok(val as u64 as f64)
+        }
+        return Ok(val as f64)
+    }
+
+    let val = match ew.expr.as_ref() {
+        Expr::FNum { val } => Ok(*val),
+        Expr::Add { lhs, rhs } => Ok(eval_double(lhs)? + eval_double(rhs)?),
+        Expr::Sub { lhs, rhs } => Ok(eval_double(lhs)? - eval_double(rhs)?),
+        Expr::Mul { lhs, rhs } => Ok(eval_double(lhs)? * eval_double(rhs)?),
+        Expr::Div { lhs, rhs } => Ok(eval_double(lhs)? / eval_double(rhs)?),
+        Expr::Comma { lhs, rhs } => {
+            discard(lhs)?;
+            eval_double(rhs)
+        },
+        Expr::Cond { cond, then, els } => {
+            if is_true(cond)? {
+                eval_double(then)
+            } else {
+                eval_double(els)
+            }
+        },
+        Expr::Cast(_, operand) => eval_double(operand),
+        _ => Err("not a compile-time constant".to_string())
+    }?;
+
+    // the result is rounded to the precision of its type
+    if let Type::Float = ew.ty.as_ref() {
+        return Ok(val as f32 as f64)
+    }
+
+    Ok(val)
+}
+
+fn is_true(ew: &ExprWrapper) -> Result<bool, String> {
+    if ew.ty.is_flonum() {
+        return Ok(eval_double(ew)? != 0.0)
+    }
+
+    Ok(eval(ew)? != 0)
+}
+
+// the value of the left operand of "," is discarded, but it must be
+// a constant as well so that no side effect is lost by the evaluation
+fn discard(stmt: &Stmt) -> Result<(), String> {
+    match &stmt.kind {
+        StmtKind::ExprStmt { val } if val.ty.is_flonum() => eval_double(val).map(|_| ()),
+        StmtKind::ExprStmt { val } => eval_reloc(val, &mut None).map(|_| ()),
+        _ => Err("not a compile-time constant".to_string())
+    }
+}
+
+// evaluate the address of a global variable (and its members or elements)
+fn eval_addr(ew: &ExprWrapper, label: &mut Option<String>) -> Result<isize, String> {
+    match ew.expr.as_ref() {
+        Expr::Var(var) => {
+            let var = var.borrow();
+            if var.is_local {
+                return Err("not a compile-time constant".to_string())
+            }
+            *label = Some(var.name.clone());
+
+            Ok(0)
+        },
+        Expr::Deref { operand } => eval_reloc(operand, label),
+        Expr::Member(base, member) => Ok(eval_addr(base, label)? + member.offset.value() as isize),
+        _ => Err("invalid initializer".to_string())
+    }
+}
+
+fn decays(ty: &Type) -> bool {
+    matches!(ty, Type::Array { .. } | Type::Func { .. })
+}
+
+// truncate a value to the size of an integer type, as if it is stored to a variable of the type
+pub(in super) fn truncate(val: isize, ty: &Type) -> isize {
+    match ty {
+        Type::Bool => (val != 0) as isize,
+        _ => match (ty.size(), ty.is_unsigned()) {
+            (1, false) => val as i8 as isize,
+            (1, true) => val as u8 as isize,
+            (2, false) => val as i16 as isize,
+            (2, true) => val as u16 as isize,
+            (4, false) => val as i32 as isize,
+            (4, true) => val as u32 as isize,
+            _ => val
+        }
+    }
+}
@@ -0,0 +1,44 @@
+use crate::parser::Parser;
+use crate::node::{ Expr, ExprWrapper };
+use crate::_type::{ Type, common_type, promote };
+
+// implicit conversions between arithmetic types.
+// a conversion is represented as an Expr::Cast node, so codegen and
+// sizeof see the converted type
+impl<'a> Parser<'a> {
+    // convert `ew` to `ty` as if by assignment
+    pub(in super) fn new_cast(ew: ExprWrapper, ty: &Type) -> ExprWrapper {
+        if ew.ty.is_arithmetic() && ty.is_arithmetic() && *ew.ty != *ty {
+            return Expr::Cast(Box::new(ty.clone()), ew).to_expr_wrapper()
+        }
+
+        ew
+    }
+
+    // the usual arithmetic conversions.
+    // both operands are converted to their common type
+    pub(in super) fn usual_arith_conv(lhs: ExprWrapper, rhs: ExprWrapper) -> (ExprWrapper, ExprWrapper) {
+        if !(lhs.ty.is_arithmetic() && rhs.ty.is_arithmetic()) {
+            return (lhs, rhs)
+        }
+
+        let ty = common_type(&lhs.ty, &rhs.ty);
+        (Parser::new_cast(lhs, &ty), Parser::new_cast(rhs, &ty))
+    }
+
+    // the integer promotion for an operand of an unary operator
+    pub(in super) fn promote(ew: ExprWrapper) -> ExprWrapper {
+        if !ew.ty.is_integer() {
+            return ew
+        }
+
+        let ty = promote(&ew.ty);
+        Parser::new_cast(ew, &ty)
+    }
+
+    // the value is converted to the type of the variable
+    pub(in super) fn new_assign(var: ExprWrapper, val: ExprWrapper) -> ExprWrapper {
+        let val = Parser::new_cast(val, &var.ty);
+        Expr::Assign { var, val }.to_expr_wrapper()
+    }
+}
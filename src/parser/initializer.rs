@@ -0,0 +1,511 @@
+use crate::parser::Parser;
+use crate::parser::const_eval::{ self, eval_reloc_as, eval_double, truncate };
+use crate::node::{ Stmt, StmtKind, Expr, ExprWrapper };
+use crate::token::TokenType;
+use crate::token::token_type::*;
+use crate::program::{ Var, Initializer };
+use crate::_type::{ Type, BitField };
+use crate::diagnostic::{ Diagnostic, Severity, Span };
+
+use std::rc::Rc;
+use std::cell::RefCell;
+
+// initializer of a variable, which has the same tree structure as its type.
+// for example, `int x[2][3] = {{1, 2, 3}, {4}};` is
+//
+// int[2][3] --+-- int[3] --+-- 1
+//             |            +-- 2
+//             |            +-- 3
+//             +-- int[3] --+-- 4
+//                          +-- (none)
+//                          +-- (none)
+//
+// elements which are not initialized explicitly are zero.
+pub(in super) struct InitNode {
+    pub ty: Box<Type>,
+    pub expr: Option<ExprWrapper>,
+    // where `expr` is written, for the errors found when it is evaluated
+    pub span: Option<Span>,
+    pub children: Vec<InitNode>
+}
+
+impl InitNode {
+    fn new(ty: &Type) -> Self {
+        let children = match ty {
+            Type::Array { base, len, .. } => (0 .. *len).map(|_| InitNode::new(base)).collect(),
+            Type::Struct { members, .. } => members.iter().map(|mem| InitNode::new(&mem.ty)).collect(),
+            _ => Vec::new()
+        };
+
+        Self { ty: Box::new(ty.clone()), expr: None, span: None, children }
+    }
+
+    // an array whose length is determined by its initializer (e.g. `int x[] = {1, 2}`)
+    fn is_flexible(&self) -> bool {
+        self.ty.is_incomplete()
+    }
+
+    // make sure the idx-th element of a flexible array exists
+    fn reserve(&mut self, idx: usize) {
+        if let Type::Array { base, .. } = self.ty.as_ref() {
+            let base = base.clone();
+            while self.is_flexible() && self.children.len() <= idx {
+                self.children.push(InitNode::new(&base));
+            }
+        }
+    }
+
+    fn is_union(&self) -> bool {
+        match self.ty.as_ref() {
+            Type::Struct { is_union, .. } => *is_union,
+            _ => false
+        }
+    }
+
+    // only one member of a union is initialized. the last designated one wins
+    fn select(&mut self, idx: usize) {
+        if self.is_union() {
+            for (_, child) in self.children.iter_mut().enumerate().filter(|(i, _)| *i != idx) {
+                *child = InitNode::new(&child.ty);
+            }
+        }
+    }
+
+    // fix the length of a flexible array
+    fn complete(&mut self) {
+        if let Type::Array { base, is_incomplete: true, .. } = self.ty.as_ref() {
+            *self.ty = Type::Array { base: base.clone(), is_incomplete: false, len: self.children.len() };
+        }
+    }
+}
+
+impl<'a> Parser<'a> {
+    // initializer := "{" initializer ("," initializer)* ","? "}"
+    //              | designation
+    //              | assign
+    //
+    // returns the initializer tree, whose type has a fixed array length
+    // if the given type is a flexible array
+    pub(in super) fn initializer(&mut self, ty: &Type) -> Result<InitNode, Diagnostic> {
+        let mut init = InitNode::new(ty);
+        self.initializer2(&mut init)?;
+
+        Ok(init)
+    }
+
+    fn initializer2(&mut self, init: &mut InitNode) -> Result<(), Diagnostic> {
+        match init.ty.as_ref().clone() {
+            Type::Array { ref base, .. } if (**base == Type::Char || **base == Type::UChar) && self.is_next_str() => {
+                self.string_initializer(init)
+            },
+            Type::Array { .. } => {
+                if self.expect_next_symbol("{").is_ok() {
+                    self.array_initializer1(init)
+                } else {
+                    self.array_initializer2(init, 0)
+                }
+            },
+            Type::Struct { .. } => {
+                if self.expect_next_symbol("{").is_ok() {
+                    return self.struct_initializer1(init)
+                }
+
+                // a struct can be initialized with another struct
+                let pos = self.peekable.current_position();
+                let expr = self.assign()?;
+                if let Type::Struct { .. } = expr.ty.as_ref() {
+                    init.expr = Some(expr);
+                    init.span = self.span_from(pos);
+                    return Ok(())
+                }
+                let _ = self.peekable.back_to(pos);
+
+                self.struct_initializer2(init, 0)
+            },
+            _ => {
+                // scalar can be enclosed in braces: `int x = {3};`
+                if self.expect_next_symbol("{").is_ok() {
+                    self.initializer2(init)?;
+                    if !self.consume_end() {
+                        return Err(self.error_here("excess elements in scalar initializer"))
+                    }
+                    return Ok(())
+                }
+
+                let start = self.peekable.current_position();
+                init.expr = Some(self.assign()?);
+                init.span = self.span_from(start);
+                Ok(())
+            }
+        }
+    }
+
+    // string-initializer := str
+    // e.g. `char x[4] = "foo";` is same as `char x[4] = {'f', 'o', 'o', '\0'};`
+    fn string_initializer(&mut self, init: &mut InitNode) -> Result<(), Diagnostic> {
+        let bytes = match self.peekable.next().map(|tok| &tok.token_type) {
+            Some(TokenType::Str(Str { bytes, .. })) => bytes.clone(),
+            _ => return Err(self.error_here("expect string literal"))
+        };
+
+        if init.is_flexible() {
+            init.reserve(bytes.len() - 1);
+        }
+
+        for (child, byte) in init.children.iter_mut().zip(bytes.iter()) {
+            child.expr = Some(Expr::Num { val: *byte as isize, ty: Box::new(Type::Int) }.to_expr_wrapper());
+        }
+        init.complete();
+
+        Ok(())
+    }
+
+    // array-initializer1 := "{" initializer ("," initializer)* ","? "}"
+    // the opening "{" has already been consumed
+    fn array_initializer1(&mut self, init: &mut InitNode) -> Result<(), Diagnostic> {
+        let mut i = 0;
+        let mut first = true;
+
+        while !self.consume_end() {
+            if !first {
+                self.expect_next_symbol(",")?;
+            }
+            first = false;
+
+            // designator: [idx] = initializer
+            if self.expect_next_symbol("[").is_ok() {
+                i = self.array_designator(init)?;
+                init.reserve(i);
+                match init.children.get_mut(i) {
+                    Some(child) => self.designation(child)?,
+                    None => return Err(self.error_here("array designator index exceeds array bounds"))
+                }
+                i += 1;
+                continue
+            }
+
+            init.reserve(i);
+            match init.children.get_mut(i) {
+                Some(child) => self.initializer2(child)?,
+                // excess elements are evaluated and ignored
+                None => { self.initializer(&Type::Long)?; }
+            }
+            i += 1;
+        }
+        init.complete();
+
+        Ok(())
+    }
+
+    // array-initializer2 := initializer ("," initializer)*
+    // an array initializer whose braces are omitted, e.g. the inner arrays of
+    // `int x[2][2] = {1, 2, 3, 4};`
+    fn array_initializer2(&mut self, init: &mut InitNode, start: usize) -> Result<(), Diagnostic> {
+        let mut i = start;
+
+        while (init.is_flexible() || i < init.children.len()) && !self.is_end() {
+            let pos = self.peekable.current_position();
+            if i > start {
+                self.expect_next_symbol(",")?;
+            }
+
+            // a designator belongs to the enclosing initializer
+            if self.is_next_symbol("[") || self.is_next_symbol(".") {
+                let _ = self.peekable.back_to(pos);
+                break
+            }
+
+            init.reserve(i);
+            self.initializer2(&mut init.children[i])?;
+            i += 1;
+        }
+        init.complete();
+
+        Ok(())
+    }
+
+    // struct-initializer1 := "{" initializer ("," initializer)* ","? "}"
+    // the opening "{" has already been consumed
+    fn struct_initializer1(&mut self, init: &mut InitNode) -> Result<(), Diagnostic> {
+        let mut i = 0;
+        let mut first = true;
+
+        while !self.consume_end() {
+            if !first {
+                self.expect_next_symbol(",")?;
+            }
+            first = false;
+
+            // designator: .member = initializer
+            if self.expect_next_symbol(".").is_ok() {
+                i = self.struct_designator(init)?;
+                init.select(i);
+                self.designation(&mut init.children[i])?;
+                i += 1;
+                continue
+            }
+
+            if init.is_union() && i > 0 {
+                return Err(self.error_here("excess elements in union initializer"))
+            }
+            match init.children.get_mut(i) {
+                Some(child) => self.initializer2(child)?,
+                None => return Err(self.error_here("excess elements in struct initializer"))
+            }
+            i += 1;
+        }
+
+        Ok(())
+    }
+
+    // struct-initializer2 := initializer ("," initializer)*
+    // a struct initializer whose braces are omitted
+    fn struct_initializer2(&mut self, init: &mut InitNode, start: usize) -> Result<(), Diagnostic> {
+        let mut i = start;
+        // the first member of a union, unless a designator is given
+        let len = if init.is_union() { 1 } else { init.children.len() };
+
+        while i < len && !self.is_end() {
+            let pos = self.peekable.current_position();
+            if i > start {
+                self.expect_next_symbol(",")?;
+            }
+
+            if self.is_next_symbol("[") || self.is_next_symbol(".") {
+                let _ = self.peekable.back_to(pos);
+                break
+            }
+
+            self.initializer2(&mut init.children[i])?;
+            i += 1;
+        }
+
+        Ok(())
+    }
+
+    // designation := ("[" num "]" | "." ident)* "=" initializer
+    // the first designator has already been consumed by the caller
+    fn designation(&mut self, init: &mut InitNode) -> Result<(), Diagnostic> {
+        if self.expect_next_symbol("[").is_ok() {
+            if let Type::Array { .. } = init.ty.as_ref() {
+                let i = self.array_designator(init)?;
+                init.reserve(i);
+                match init.children.get_mut(i) {
+                    Some(child) => self.designation(child)?,
+                    None => return Err(self.error_here("array designator index exceeds array bounds"))
+                }
+                return self.array_initializer2(init, i + 1)
+            }
+            return Err(self.error_here("array index in non-array initializer"))
+        }
+
+        if self.expect_next_symbol(".").is_ok() {
+            let i = self.struct_designator(init)?;
+            init.select(i);
+            self.designation(&mut init.children[i])?;
+            return self.struct_initializer2(init, i + 1)
+        }
+
+        self.expect_next_reserved("=")?;
+        self.initializer2(init)
+    }
+
+    // array-designator := "[" const-expr "]"
+    // the opening "[" has already been consumed
+    fn array_designator(&mut self, init: &InitNode) -> Result<usize, Diagnostic> {
+        let idx = self.const_expr()?;
+        self.expect_next_symbol("]")?;
+
+        if idx < 0 || (!init.is_flexible() && idx as usize >= init.children.len()) {
+            return Err(self.error_here("array designator index exceeds array bounds"))
+        }
+
+        Ok(idx as usize)
+    }
+
+    // struct-designator := "." ident
+    // the opening "." has already been consumed
+    fn struct_designator(&mut self, init: &InitNode) -> Result<usize, Diagnostic> {
+        let tok = self.expect_next_ident()?;
+        let name = tok.token_type.tk_str();
+
+        match init.ty.as_ref() {
+            Type::Struct { members, .. } => {
+                members.iter()
+                    .position(|mem| mem.name == name.as_str())
+                    .ok_or_else(|| Diagnostic::error_at(&tok, format!("struct has no member named {}", name)))
+            },
+            _ => Err(self.error_here("field name not in struct initializer"))
+        }
+    }
+
+    // a local variable initializer is converted to assignments.
+    // the variable is zero-cleared first, then each initialized element is assigned
+    //
+    // `int x[3] = {1, 2};` becomes `memzero(x); x[0] = 1; x[1] = 2;`
+    pub(in super) fn lvar_initializer(&self, var: &Rc<RefCell<Var>>, init: &InitNode) -> Result<Stmt, Diagnostic> {
+        let mut stmts = vec![Stmt::new(StmtKind::ExprStmt { val: Expr::MemZero(Rc::clone(var)).to_expr_wrapper() })];
+        let lval = Expr::Var(Rc::clone(var)).to_expr_wrapper();
+
+        create_lvar_init(init, lval, &mut stmts)?;
+
+        Ok(Stmt::new(StmtKind::Block { stmts }))
+    }
+
+    // a global variable initializer is evaluated at compile time
+    pub(in super) fn gvar_initializer(&self, init: &InitNode) -> Result<Vec<Initializer>, Diagnostic> {
+        let mut data = Vec::<Initializer>::new();
+        let mut cur = 0;
+
+        write_gvar_data(init, 0, &mut cur, &mut data)?;
+
+        let size = init.ty.size();
+        if size > cur {
+            data.push(Initializer::Zero(size - cur));
+        }
+
+        Ok(data)
+    }
+
+    // true if the next token is "}" or "," "}"
+    fn is_end(&mut self) -> bool {
+        let pos = self.peekable.current_position();
+        let is_end = self.consume_end();
+        let _ = self.peekable.back_to(pos);
+
+        is_end
+    }
+
+    pub(in super) fn is_next_symbol(&self, sym: &str) -> bool {
+        match self.peekable.peek().map(|tok| &tok.token_type) {
+            Some(TokenType::Symbol(Symbol { sym: s, .. })) => s.as_str() == sym,
+            _ => false
+        }
+    }
+
+    fn is_next_str(&self) -> bool {
+        matches!(self.peekable.peek().map(|tok| &tok.token_type), Some(TokenType::Str(_)))
+    }
+}
+
+fn create_lvar_init(init: &InitNode, lval: ExprWrapper, stmts: &mut Vec<Stmt>) -> Result<(), Diagnostic> {
+    if let Some(expr) = &init.expr {
+        let assign = Parser::new_assign(lval, expr.clone());
+        stmts.push(Stmt::new(StmtKind::ExprStmt { val: assign }));
+        return Ok(())
+    }
+
+    match init.ty.as_ref() {
+        Type::Array { .. } => {
+            for (i, child) in init.children.iter().enumerate() {
+                // x[i]
+                let idx = Expr::Num { val: i as isize, ty: Box::new(Type::Long) }.to_expr_wrapper();
+                let elem = Parser::new_add(lval.clone(), idx)?;
+                create_lvar_init(child, Expr::Deref { operand: elem }.to_expr_wrapper(), stmts)?;
+            }
+        },
+        Type::Struct { members, .. } => {
+            for (child, member) in init.children.iter().zip(members.iter()) {
+                let mem = Expr::Member(lval.clone(), member.clone()).to_expr_wrapper();
+                create_lvar_init(child, mem, stmts)?;
+            }
+        },
+        _ => {}
+    }
+
+    Ok(())
+}
+
+// write the initial data of `init` placed at `offset` bytes from the beginning
+// of the variable. `cur` is the offset up to which data has been written
+fn write_gvar_data(init: &InitNode, offset: usize, cur: &mut usize, data: &mut Vec<Initializer>) -> Result<(), Diagnostic> {
+    if let Some(expr) = &init.expr {
+        let sz = init.ty.size();
+        let error = |msg: &str| Diagnostic::new(Severity::Error, msg, init.span.clone());
+
+        if offset > *cur {
+            data.push(Initializer::Zero(offset - *cur));
+        }
+
+        // a floating-point value is written as its bit pattern
+        if init.ty.is_flonum() {
+            let val = eval_double(expr).map_err(|e| error(&e))?;
+            let bits = match init.ty.as_ref() {
+                Type::Float => (val as f32).to_bits() as isize,
+                _ => val.to_bits() as isize
+            };
+            data.push(Initializer::Val { sz, val: bits });
+            *cur = offset + sz;
+
+            return Ok(())
+        }
+
+        let mut label = None;
+        let val = eval_reloc_as(expr, &init.ty, &mut label).map_err(|e| error(&e))?;
+
+        match label {
+            Some(name) => {
+                if sz != 8 || !(init.ty.has_base() || init.ty.is_integer()) {
+                    return Err(error("initializer element is not computable at load time"))
+                }
+                data.push(Initializer::Label { name, addend: val });
+            },
+            None => {
+                if !(init.ty.has_base() || init.ty.is_integer() || *init.ty == Type::Enum) {
+                    return Err(error("initializer element is not constant"))
+                }
+                data.push(Initializer::Val { sz, val: truncate(val, &init.ty) });
+            }
+        }
+        *cur = offset + sz;
+
+        return Ok(())
+    }
+
+    match init.ty.as_ref() {
+        Type::Array { base, .. } => {
+            for (i, child) in init.children.iter().enumerate() {
+                write_gvar_data(child, offset + i * base.size(), cur, data)?;
+            }
+        },
+        Type::Struct { members, .. } => {
+            for (child, member) in init.children.iter().zip(members.iter()) {
+                match (member.bit_field, &child.expr) {
+                    (Some(bf), Some(expr)) => write_bit_field(expr, child.span.clone(), bf, offset + member.offset.value(), cur, data)?,
+                    _ => write_gvar_data(child, offset + member.offset.value(), cur, data)?
+                }
+            }
+        },
+        _ => {}
+    }
+
+    Ok(())
+}
+
+// a bit-field is written byte by byte, since bit-fields and other members
+// may share a storage unit. the bytes shared with the previous member are merged
+fn write_bit_field(expr: &ExprWrapper, span: Option<Span>, bf: BitField, offset: usize, cur: &mut usize, data: &mut Vec<Initializer>) -> Result<(), Diagnostic> {
+    let error = |msg: &str| Diagnostic::new(Severity::Error, msg, span.clone());
+    let mask = (u64::MAX >> (64 - bf.width)) as i128;
+    let val = (const_eval::eval(expr).map_err(|e| error(&e))? as i128 & mask) << bf.offset;
+
+    for byte in bf.offset / 8 ..= (bf.offset + bf.width - 1) / 8 {
+        let bits = ((val >> (byte * 8)) & 0xff) as isize;
+        let pos = offset + byte;
+
+        if pos >= *cur {
+            if pos > *cur {
+                data.push(Initializer::Zero(pos - *cur));
+            }
+            data.push(Initializer::Val { sz: 1, val: bits });
+            *cur = pos + 1;
+            continue
+        }
+        match data.last_mut() {
+            Some(Initializer::Val { sz: 1, val: prev }) if pos + 1 == *cur => *prev |= bits,
+            _ => return Err(error("initializer of overlapping bit-fields is not supported"))
+        }
+    }
+
+    Ok(())
+}
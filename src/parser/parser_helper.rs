@@ -1,23 +1,32 @@
-use crate::parser::{ Parser, TYPE_NAMES };
-use crate::node::{ Stmt, ExprWrapper, Expr };
+use crate::parser::{ Parser, SwitchCases, TYPE_NAMES };
+use crate::node::{ Stmt, StmtKind, ExprWrapper, Expr };
 use crate::token::{ Token, TokenType };
 use crate::token::token_type::*;
 use crate::program::{ Var, Offset, align_to };
-use crate::_type::{ Type, Member, TypeCounter };
+use crate::_type::{ Type, Member, BitField, StructTag, TypeCounter };
 use crate::scopes::{ TagScope, VarScope, Scope, ScopeElement };
+use crate::diagnostic::{ Diagnostic, Severity, Span };
+use crate::tokenizer::loc::Loc;
 
 use std::rc::Rc;
 use std::cell::RefCell;
 
+#[derive(PartialEq)]
 pub(in super) enum StorageClass {
     TypeDef,
     Static,
+    Extern,
 }
 
+const TYPE_QUALIFIERS: [&str; 3] = ["const", "volatile", "restrict"];
+
+// the parameters of a function, and whether it is variadic
+type FuncParams = (Vec<Rc<RefCell<Var>>>, bool);
+
 impl StorageClass {
     pub fn is_static(&self) -> bool {
         match self {
-            StorageClass::TypeDef => false,
+            StorageClass::TypeDef | StorageClass::Extern => false,
             StorageClass::Static => true
         }
     }
@@ -46,38 +55,13 @@ impl<'a> Parser<'a> {
         }
     }
 
-    // TODO: refactor, use `and_then`, `map` etc
-    // return 'return type' of a function
-    // 3 patterns
-    //   - get function -> return Ok(ret_type)
-    //   - fail find var -> return Ok(None)
-    //   - success find var but not function Err
-    pub(in super) fn find_func(&self, name: &String) -> Result<Option<Box<Type>>, String> {
-        let func = self.find_var(name);
-        match func {
-            Some(vsc) => {
-                match vsc.target {
-                    ScopeElement::Var(ref var) => {
-                        match var.borrow().ty.as_ref() {
-                            Type::Func(ret_type) => {
-                                Ok(Some(Box::clone(ret_type)))
-                            },
-                            _ => Err(format!("{} is not a function", name))
-                        }
-                    },
-                    _ => Ok(None)
-                }
-            },
-            _ => Ok(None)
-        }
-    }
-
     pub(in super) fn find_tag(&self, tag_name: impl AsRef<String>) -> Option<&TagScope> {
-        self.tag_scope.iter()
+        // the innermost tag shadows the outer ones
+        self.tag_scope.iter().rev()
             .find(|tag| tag.name.as_str() == tag_name.as_ref().as_str())
     }
 
-    pub(in super) fn if_stmt(&mut self) -> Result<Stmt, String> {
+    pub(in super) fn if_stmt(&mut self) -> Result<Stmt, Diagnostic> {
         self.peekable.next();
 
         // primaryだと()なしでも動くようになるが, Cコンパイラではなくなる
@@ -93,32 +77,47 @@ impl<'a> Parser<'a> {
             _ => None
         };
 
-        Ok(Stmt::If {
+        Ok(Stmt::new(StmtKind::If {
             cond,
             then: Box::new(then),
             els: els.map(|x| Box::new(x)),
-        })
+        }))
     }
 
-    pub(in super) fn while_stmt(&mut self) -> Result<Stmt, String> {
+    pub(in super) fn while_stmt(&mut self) -> Result<Stmt, Diagnostic> {
         self.peekable.next();
 
         let cond = self.primary()?;
         let then = self.stmt()?;
 
-        Ok(Stmt::While {
+        Ok(Stmt::new(StmtKind::While {
             cond,
             then: Box::new(then)
-        })
+        }))
     }
 
-    pub(in super) fn for_stmt(&mut self) -> Result<Stmt, String> {
+    pub(in super) fn do_while_stmt(&mut self) -> Result<Stmt, Diagnostic> {
+        self.peekable.next();
+
+        let then = self.stmt()?;
+        self.expect_next_reserved("while")?;
+        let cond = self.primary()?;
+        self.expect_next_symbol(";")?;
+
+        Ok(Stmt::new(StmtKind::DoWhile {
+            then: Box::new(then),
+            cond
+        }))
+    }
+
+    pub(in super) fn for_stmt(&mut self) -> Result<Stmt, Diagnostic> {
         self.peekable.next();
 
         self.expect_next_symbol("(")?;
         let sc = self.enter_scope();
 
         // 初期化，条件，処理後はない場合がある
+        let loc = self.peek_loc();
         let init = if self.is_typename() {
             self.declaration().ok()
         } else {
@@ -126,6 +125,7 @@ impl<'a> Parser<'a> {
             self.expect_next_symbol(";")?;
             init_stmt
         };
+        let init = init.map(|stmt| Stmt { loc, ..stmt });
 
         let cond = self.expr().ok();
         self.expect_next_symbol(";")?;
@@ -137,23 +137,80 @@ impl<'a> Parser<'a> {
 
         self.leave_scope(sc);
 
-        Ok(Stmt::For {
+        Ok(Stmt::new(StmtKind::For {
             init: Box::new(init),
             cond,
             inc: Box::new(inc),
             then: Box::new(then)
-        })
+        }))
+    }
+
+    pub(in super) fn switch_stmt(&mut self) -> Result<Stmt, Diagnostic> {
+        self.peekable.next();
+
+        let cond = self.primary()?;
+
+        // switch statements can be nested
+        let outer = self.current_switch.replace(SwitchCases::default());
+        let then = self.stmt();
+        let cases = std::mem::replace(&mut self.current_switch, outer).unwrap_or_default();
+
+        Ok(Stmt::new(StmtKind::Switch {
+            cond,
+            then: Box::new(then?),
+            cases: cases.vals,
+            has_default: cases.has_default
+        }))
+    }
+
+    pub(in super) fn case_stmt(&mut self) -> Result<Stmt, Diagnostic> {
+        self.peekable.next();
+
+        let start = self.peekable.current_position();
+        let val = self.const_expr()?;
+        let span = self.span_from(start);
+        self.expect_next_symbol(":")?;
+
+        let idx = match self.current_switch.as_mut() {
+            Some(cases) => {
+                if let Some(i) = cases.vals.iter().position(|v| *v == val) {
+                    let note = Diagnostic::new(Severity::Note, "previous case is here", cases.spans[i].clone());
+                    return Err(Diagnostic::new(Severity::Error, format!("duplicate case value: {}", val), span).with_note(note))
+                }
+                cases.vals.push(val);
+                cases.spans.push(span);
+                cases.vals.len() - 1
+            },
+            None => return Err(self.error_here("stray case"))
+        };
+
+        Ok(Stmt::new(StmtKind::Case { val, idx, stmt: Box::new(self.stmt()?) }))
+    }
+
+    pub(in super) fn default_stmt(&mut self) -> Result<Stmt, Diagnostic> {
+        self.peekable.next();
+        self.expect_next_symbol(":")?;
+
+        match self.current_switch.as_mut() {
+            Some(SwitchCases { has_default: true, .. }) => {
+                return Err(self.error_here("multiple default labels in one switch"))
+            },
+            Some(cases) => cases.has_default = true,
+            None => return Err(self.error_here("stray default"))
+        }
+
+        Ok(Stmt::new(StmtKind::Default(Box::new(self.stmt()?))))
     }
 
     // variable declaration
-    // declaration := basetype declarator type-suffix ("=" expr)? ";"
+    // declaration := basetype declarator type-suffix ("=" initializer)? ";"
     //              | basetype ";"
-    pub(in super) fn declaration(&mut self) -> Result<Stmt, String> {
+    pub(in super) fn declaration(&mut self) -> Result<Stmt, Diagnostic> {
         let sclass = &mut None;
         let mut ty = self.base_type(sclass)?;
 
         if let Ok(()) = self.expect_next_symbol(";") {
-            return Ok(Stmt::ExprStmt { val: Expr::Null.to_expr_wrapper() })
+            return Ok(Stmt::new(StmtKind::ExprStmt { val: Expr::Null.to_expr_wrapper() }))
         }
 
         let name = &mut String::new();
@@ -165,46 +222,56 @@ impl<'a> Parser<'a> {
             self.expect_next_symbol(";")?;
             self.push_scope_with_typedef(&Rc::new(name.to_string()), &ty);
 
-            return Ok(Stmt::ExprStmt {
+            return Ok(Stmt::new(StmtKind::ExprStmt {
                 val: ExprWrapper::new(Expr::Null)
-            })
+            }))
         }
 
         if let Type::Void = ty.as_ref() {
-            return Err("variable declared void".to_string())
+            return Err(self.error_here("variable declared void"))
         }
 
         let var = self.new_var(name, Box::clone(&ty), true);
-        if ty.is_incomplete() {
-            return Err("incomplete type".to_string())
-        }
 
-        let expr =
-            if let Err(_) = self.expect_next_reserved("=") {
-                self.locals.push(var);
-                self.expect_next_symbol(";".to_string())?;
+        if self.expect_next_reserved("=").is_err() {
+            if ty.is_incomplete() {
+                return Err(self.error_here("incomplete type"))
+            }
+            self.locals.push(var);
+            self.expect_next_symbol(";".to_string())?;
 
-                Expr::Null
-            } else {
-                self.locals.push(Rc::clone(&var));
+            return Ok(Stmt::new(StmtKind::ExprStmt { val: ExprWrapper { ty: Box::clone(&ty), expr: Box::new(Expr::Null), loc: None } }))
+        }
 
-                let rhs = self.expr()?;
-                self.expect_next_symbol(";".to_string())?;
+        let init = self.initializer(&ty)?;
+        self.expect_next_symbol(";".to_string())?;
 
-                Expr::Assign { var: Expr::Var(var).to_expr_wrapper(), val: rhs }
-            };
+        // the length of `int x[] = {...}` is determined by the initializer
+        var.borrow_mut().ty = Box::clone(&init.ty);
+        if init.ty.is_incomplete() {
+            return Err(self.error_here("incomplete type"))
+        }
+        self.locals.push(Rc::clone(&var));
 
-        Ok(Stmt::ExprStmt { val: ExprWrapper { ty: Box::clone(&ty), expr: Box::new(expr) } })
+        match init.expr {
+            // scalar is initialized by a simple assignment
+            Some(rhs) => {
+                let val = Parser::new_assign(Expr::Var(var).to_expr_wrapper(), rhs);
+                Ok(Stmt::new(StmtKind::ExprStmt { val }))
+            },
+            None => self.lvar_initializer(&var, &init)
+        }
     }
 
-    pub(in super) fn expr_stmt(&mut self) -> Result<Stmt, String> {
-        Ok(Stmt::ExprStmt { val: self.expr()? })
+    pub(in super) fn expr_stmt(&mut self) -> Result<Stmt, Diagnostic> {
+        let val = self.expr()?;
+        Ok(Stmt { loc: val.loc.clone(), kind: StmtKind::ExprStmt { val } })
     }
 
     // statement expression is a GNU C extension
     // stmt_expr := "(" "{" stmt stmt* "}" ")"
     // 呼び出し側で "(" "{" はすでに消費されている
-    pub(in super) fn stmt_expr(&mut self) -> Result<ExprWrapper, String> {
+    pub(in super) fn stmt_expr(&mut self) -> Result<ExprWrapper, Diagnostic> {
         let sc = self.enter_scope();
 
         let mut stmts = Vec::<Stmt>::new();
@@ -219,18 +286,18 @@ impl<'a> Parser<'a> {
             // 最後のExprStmtをPureExprに変換する
             // StmtExprとして扱うと誤ったスタック操作になるため
             Some(last) => {
-                if let Stmt::ExprStmt { val } = last {
-                    *last = Stmt::PureExpr(val.clone());
+                if let StmtKind::ExprStmt { val } = &last.kind {
+                    last.kind = StmtKind::PureExpr(val.clone());
                     Ok(Expr::StmtExpr(stmts).to_expr_wrapper())
                 } else {
-                    Err("stmt expr returning void is not supported".to_string())
+                    Err(self.error_here("stmt expr returning void is not supported"))
                 }
             }
-            _ => Err("stmt expr returning void is not supported".to_string())
+            _ => Err(self.error_here("stmt expr returning void is not supported"))
         }
     }
 
-    pub(in super) fn expect_next_symbol(&mut self, word: impl Into<String>) -> Result<(), String> {
+    pub(in super) fn expect_next_symbol(&mut self, word: impl Into<String>) -> Result<(), Diagnostic> {
         let tk = self.peekable.peek();
         let expected = word.into();
 
@@ -239,14 +306,11 @@ impl<'a> Parser<'a> {
                 self.peekable.next();
                 Ok(())
             },
-            _ => {
-                let msg = format!("expect symbol {}, but found {:?}", expected, tk);
-                Err(msg)
-            }
+            _ => Err(self.error_here(format!("expected '{}'", expected)))
         }
     }
 
-    pub(in super) fn expect_next_reserved(&mut self, word: impl Into<String>) -> Result<(), String> {
+    pub(in super) fn expect_next_reserved(&mut self, word: impl Into<String>) -> Result<(), Diagnostic> {
         let tk = self.peekable.peek();
         let expected = word.into();
 
@@ -255,15 +319,56 @@ impl<'a> Parser<'a> {
                 self.peekable.next();
                 Ok(())
             },
-            _ => {
-                let msg = format!("expect reserved {}, but found {:?}", expected, tk);
-                Err(msg)
-            }
+            _ => Err(self.error_here(format!("expected '{}'", expected)))
         }
     }
 
     // 関数呼び出しにおける引数をparseする
-    pub(in super) fn parse_args(&mut self) -> Result<Vec<ExprWrapper>, String> {
+    // func-call := postfix "(" func-args ")"
+    // the opening "(" has already been consumed. the callee is a function or a pointer to a function
+    pub(in super) fn func_call(&mut self, callee: ExprWrapper) -> Result<ExprWrapper, Diagnostic> {
+        let (ret, param_tys, is_variadic) = match callee.ty.as_ref() {
+            Type::Func { ret, params, is_variadic } => (Box::clone(ret), params.clone(), *is_variadic),
+            Type::Ptr { base } => match base.as_ref() {
+                Type::Func { ret, params, is_variadic } => (Box::clone(ret), params.clone(), *is_variadic),
+                _ => return Err(self.error_here("called object is not a function"))
+            },
+            _ => return Err(self.error_here("called object is not a function"))
+        };
+        let args = self.parse_args()?;
+
+        // a function declared without parameters takes any arguments
+        if !param_tys.is_empty() && args.len() < param_tys.len() {
+            return Err(self.error_here("too few arguments to function call"))
+        }
+        if !param_tys.is_empty() && args.len() > param_tys.len() && !is_variadic {
+            return Err(self.error_here("too many arguments to function call"))
+        }
+
+        // arguments are converted to the parameter types.
+        // if there is no parameter for an argument, the default
+        // argument promotions are applied
+        let args = args.into_iter().enumerate().map(|(i, arg)| {
+            match param_tys.get(i) {
+                Some(param_ty) => Parser::new_cast(arg, param_ty),
+                None if *arg.ty == Type::Float => Parser::new_cast(arg, &Type::Double),
+                None => Parser::promote(arg)
+            }
+        }).collect();
+        // a struct is returned to a temporary local variable
+        let ret_buf = match *ret {
+            Type::Struct { .. } => Some(self.new_temp_var(Box::clone(&ret))),
+            _ => None
+        };
+
+        Ok(ExprWrapper {
+            ty: ret,
+            loc: callee.loc.clone(),
+            expr: Box::new(Expr::FnCall { callee, args, ret_buf })
+        })
+    }
+
+    pub(in super) fn parse_args(&mut self) -> Result<Vec<ExprWrapper>, Diagnostic> {
         // no arguments
         if let Ok(_) = self.expect_next_symbol(")") {
             return Ok(vec![])
@@ -279,7 +384,7 @@ impl<'a> Parser<'a> {
         Ok(args)
     }
 
-    pub(in super) fn read_func_param(&mut self) -> Result<Rc<RefCell<Var>>, String> {
+    pub(in super) fn read_func_param(&mut self) -> Result<Rc<RefCell<Var>>, Diagnostic> {
         let mut ty = self.base_type(&mut None)?;
         let name = &mut String::new();
 
@@ -288,32 +393,44 @@ impl<'a> Parser<'a> {
 
         // "array of T" is converted to "pointer to T" only in the parameter
         // context. For example, *argv[] is converted to **argv by this.
-        if let Type::Array { base, .. } = *ty {
-            ty = Box::new(Type::Ptr { base });
+        // likewise "function" is converted to "pointer to function"
+        match *ty {
+            Type::Array { base, .. } => ty = Box::new(Type::Ptr { base }),
+            Type::Func { .. } => ty = Box::new(Type::Ptr { base: ty }),
+            _ => {}
         }
 
         Ok(self.new_var(name, Box::clone(&ty), true))
     }
 
-    // function = basetype declarator "(" params? ")" ("{" stmt* "}" | ";")
-    // params   = param ("," param)*
-    // param    = basetype declarator type-suffix
-    pub(in super) fn read_func_params(&mut self) -> Result<Vec<Rc<RefCell<Var>>>, String> {
-        self.expect_next_symbol("(".to_string())?;
-
+    // func-params := "(" ("void" | param ("," param)* ("," "...")?)? ")"
+    // param       := basetype declarator type-suffix
+    //
+    // the opening "(" has already been consumed
+    pub(in super) fn read_func_params(&mut self) -> Result<FuncParams, Diagnostic> {
         let mut params = Vec::<Rc<RefCell<Var>>>::new();
         if self.expect_next_symbol(")".to_string()).is_ok() {
-            return Ok(params)
+            return Ok((params, false))
         }
+        let pos = self.peekable.current_position();
+        if self.expect_next_reserved("void").is_ok() && self.expect_next_symbol(")").is_ok() {
+            return Ok((params, false))
+        }
+        let _ = self.peekable.back_to(pos);
         params.push(self.read_func_param()?);
 
+        let mut is_variadic = false;
         while let Ok(_) = self.expect_next_symbol(",".to_string()) {
+            if self.expect_next_reserved("...").is_ok() {
+                is_variadic = true;
+                break
+            }
             params.push(self.read_func_param()?);
         }
 
         self.expect_next_symbol(")".to_string())?;
 
-        Ok(params)
+        Ok((params, is_variadic))
     }
 
     // base-type = buildin-type | struct-decl | typedef-name | enum-specifier
@@ -321,9 +438,9 @@ impl<'a> Parser<'a> {
     //
     // Note that "typedef" can appear anywhere in a basetype.
     // "int" can appear anywhere if type is short, long or long long
-    pub(in super) fn base_type(&mut self, sclass: &mut Option<StorageClass>) -> Result<Box<Type>, String> {
+    pub(in super) fn base_type(&mut self, sclass: &mut Option<StorageClass>) -> Result<Box<Type>, Diagnostic> {
         if !self.is_typename() {
-            return Err("typename expected".to_string())
+            return Err(self.error_here("typename expected"))
         }
 
         let mut ty = Box::new(Type::Int);
@@ -336,35 +453,37 @@ impl<'a> Parser<'a> {
         while let (true, Some(tok)) = (self.is_typename(), self.peekable.peek()) {
             let tk_str = tok.token_type.tk_str();
             // handle storage class specifiers
-            if tk_str.as_str() == "typedef" {
-                if let None = sclass {
-                    *sclass = Some(StorageClass::TypeDef);
-                } else if let Some(StorageClass::Static) = sclass {
-                    return Err("typedef and static may not be used together".to_string())
-                }
+            // type qualifiers do not change the code, and are ignored
+            if TYPE_QUALIFIERS.contains(&tk_str.as_str()) {
                 self.peekable.next();
                 continue
             }
-            else if tk_str.as_str() == "static" {
-                if let None = sclass {
-                    *sclass = Some(StorageClass::Static);
-                } else if let Some(StorageClass::TypeDef) = sclass {
-                    return Err("typedef and static may not be used together".to_string())
+
+            let class = match tk_str.as_str() {
+                "typedef" => Some(StorageClass::TypeDef),
+                "static" => Some(StorageClass::Static),
+                "extern" => Some(StorageClass::Extern),
+                _ => None
+            };
+            if let Some(class) = class {
+                if sclass.as_ref().is_some_and(|sc| *sc != class) {
+                    return Err(self.error_here("typedef, static and extern may not be used together"))
                 }
+                *sclass = Some(class);
                 self.peekable.next();
                 continue
             }
 
-            if !["void", "_Bool", "char", "short", "int", "long"].contains(&tk_str.as_str()) {
+            if !["void", "_Bool", "char", "short", "int", "long", "signed", "unsigned", "float", "double"].contains(&tk_str.as_str()) {
                 if counter > 0 {
                     break
                 }
 
                 match tk_str.as_str() {
-                    "struct" => ty = self.struct_decl()?,
+                    "struct" | "union" => ty = self.struct_decl()?,
                     "enum" => ty = self.enum_specifier()?,
                     _ => {
-                        ty = self.find_typedef(tok).unwrap();
+                        ty = self.find_typedef(tok).unwrap().resolve();
                         self.peekable.next();
                     }
                 }
@@ -386,11 +505,13 @@ impl<'a> Parser<'a> {
     // 😵
     // this function is hard for me.
     // original is https://github.com/rui314/chibicc/commit/d51097dc0f7049e3e1fd00f9021e95686ecfddf3
-    pub(in super) fn declarator(&mut self, ty: &mut Box<Type>, name: &mut String) -> Result<Box<Type>, String> {
+    pub(in super) fn declarator(&mut self, ty: &mut Box<Type>, name: &mut String) -> Result<Box<Type>, Diagnostic> {
         while let Some(TokenType::Reserved(Reserved { op, .. })) = self.peekable.peek().map(|tok| &tok.token_type) {
             if op.as_str() == "*" {
                 *ty = Box::new(Type::Ptr { base: Box::clone(&ty) });
                 self.peekable.next();
+            } else if TYPE_QUALIFIERS.contains(&op.as_str()) {
+                self.peekable.next();
             } else {
                 break
             }
@@ -402,15 +523,27 @@ impl<'a> Parser<'a> {
 
             self.expect_next_symbol(")")?;
 
+            // a function definition takes the parameters next to its name, not those of its return type
+            let func_params = std::mem::take(&mut self.func_params);
             dummy.replace_ptr_to(*self.read_type_suffix(Box::clone(&ty))?);
+            self.func_params = func_params;
 
             return Ok(Box::clone(&dummy))
         }
 
+        // the name of a parameter can be omitted, e.g. `int (*fn)(int, int)` or `char[8]`
+        if self.is_next_symbol(",") || self.is_next_symbol(")") || self.is_next_symbol("[") {
+            return self.read_type_suffix(Box::clone(ty))
+        }
+
         let tk = self.expect_next_ident()?;
         *name = tk.token_type.tk_str().to_string();
 
-        self.read_type_suffix(Box::clone(&ty))
+        // the parameters of a function declarator are declared in between
+        let ty = self.read_type_suffix(Box::clone(ty))?;
+        self.decl_loc = Some(tk.loc);
+
+        Ok(ty)
     }
 
     // abstract-declarator := "*"* ("(" abstract-declarator ")")? type-suffix
@@ -431,11 +564,13 @@ impl<'a> Parser<'a> {
     //   inner abstract-declarator -> int*
     //   type_suffix               -> {abstract-declarator}[4]
     //   return                    -> int*[4]
-    pub(in super) fn abstract_declarator(&mut self, ty: &mut Box<Type>) -> Result<Box<Type>, String> {
+    pub(in super) fn abstract_declarator(&mut self, ty: &mut Box<Type>) -> Result<Box<Type>, Diagnostic> {
         while let Some(TokenType::Reserved(Reserved { op, .. })) = self.peekable.peek().map(|tok| &tok.token_type) {
             if op.as_str() == "*" {
                 *ty = Box::new(Type::Ptr { base: Box::clone(&ty) });
                 self.peekable.next();
+            } else if TYPE_QUALIFIERS.contains(&op.as_str()) {
+                self.peekable.next();
             } else {
                 break
             }
@@ -462,7 +597,9 @@ impl<'a> Parser<'a> {
                     offset: Offset::Unset,
                     ty: Box::clone(&ty),
                     is_local,
-                    contents: None
+                    contents: None,
+                    init: None,
+                    loc: self.decl_loc.take()
                 }
             )
         );
@@ -472,6 +609,26 @@ impl<'a> Parser<'a> {
         var
     }
 
+    // a local variable made by the compiler, which has no name
+    pub(in super) fn new_temp_var(&mut self, ty: Box<Type>) -> Rc<RefCell<Var>> {
+        let var = Rc::new(
+            RefCell::new(
+                Var {
+                    name: String::new(),
+                    offset: Offset::Unset,
+                    ty,
+                    is_local: true,
+                    contents: None,
+                    init: None,
+                    loc: None
+                }
+            )
+        );
+        self.locals.push(Rc::clone(&var));
+
+        var
+    }
+
     pub(in super) fn new_gvar(&mut self, name: &String, ty: Box<Type>, contents: Option<Vec<u8>>, emit: bool) -> Rc<RefCell<Var>> {
         let var = Rc::new(
             RefCell::new(
@@ -480,7 +637,9 @@ impl<'a> Parser<'a> {
                     offset: Offset::Unset,
                     ty: Box::clone(&ty),
                     is_local: false,
-                    contents
+                    contents,
+                    init: None,
+                    loc: None
                 }
             )
         );
@@ -494,30 +653,71 @@ impl<'a> Parser<'a> {
         var
     }
 
-    // global-var := basetype declarator type-suffix ";"
-    pub(in super) fn global_var(&mut self) -> Result<(), String> {
+    // global-var := basetype declarator type-suffix ("=" gvar-initializer)? ";"
+    //             | basetype ";"
+    pub(in super) fn global_var(&mut self) -> Result<(), Diagnostic> {
         let sclass = &mut None;
         let mut base_ty = self.base_type(sclass)?;
+        // a declaration of a struct tag or enum constants only
+        if let Ok(()) = self.expect_next_symbol(";") {
+            return Ok(())
+        }
         let name = &mut String::new();
         let base_ty = self.declarator(&mut base_ty, name)?;
 
         let ty = self.read_type_suffix(base_ty)?;
-        self.expect_next_symbol(";")?;
 
         if let Some(StorageClass::TypeDef) = *sclass {
+            self.expect_next_symbol(";")?;
             self.push_scope_with_typedef(&Rc::new(name.to_string()), &ty);
-        } else {
+            return Ok(())
+        }
+
+        if self.expect_next_reserved("=").is_err() {
+            self.expect_next_symbol(";")?;
+            // an extern declaration refers to a variable defined elsewhere
+            if let Some(StorageClass::Extern) = *sclass {
+                self.new_gvar(name, ty, None, false);
+                return Ok(())
+            }
             if ty.is_incomplete() {
-                return Err("incomplete type".to_string())
+                return Err(self.error_here("incomplete type"))
             }
             self.new_gvar(name, ty, None, true);
+            return Ok(())
+        }
+
+        let var = self.new_gvar(name, Box::clone(&ty), None, true);
+        let init = self.initializer(&ty)?;
+
+        if init.ty.is_incomplete() {
+            return Err(self.error_here("incomplete type"))
         }
+        // evaluated before ";" is read, so that the parser recovers from an error at the ";"
+        let data = self.gvar_initializer(&init)?;
+        self.expect_next_symbol(";")?;
+
+        let mut var = var.borrow_mut();
+        var.ty = init.ty;
+        var.init = Some(data);
 
         Ok(())
     }
 
-    // type-suffix := ("[" num? "]" type-suffix)?
-    pub(in super) fn read_type_suffix(&mut self, base: Box<Type>) -> Result<Box<Type>, String> {
+    // type-suffix := "(" func-params
+    //              | ("[" const-expr? "]" type-suffix)?
+    pub(in super) fn read_type_suffix(&mut self, base: Box<Type>) -> Result<Box<Type>, Diagnostic> {
+        // the parameters are declared in their own scope
+        if self.expect_next_symbol("(").is_ok() {
+            let sc = self.enter_scope();
+            let (params, is_variadic) = self.read_func_params()?;
+            self.leave_scope(sc);
+
+            let param_tys = params.iter().map(|param| *param.borrow().ty.clone()).collect();
+            self.func_params = params;
+            return Ok(Box::new(Type::Func { ret: base, params: param_tys, is_variadic }))
+        }
+
         match self.expect_next_symbol("[".to_string()) {
             Ok(_) => {
                 let mut is_incomplete = true;
@@ -525,18 +725,17 @@ impl<'a> Parser<'a> {
                 match self.expect_next_symbol("]") {
                     Ok(_) => {},
                     _ => {
-                        if let Some(TokenType::Num(Num { val, .. })) = self.peekable.next().map(|tok| &tok.token_type) {
-                            self.expect_next_symbol("]")?;
-                            is_incomplete = false;
-                            sz = *val;
-                        } else {
-                            return Err("expect num after [".to_string())
+                        sz = self.const_expr()?;
+                        if sz < 0 {
+                            return Err(self.error_here("size of array is negative"))
                         }
+                        self.expect_next_symbol("]")?;
+                        is_incomplete = false;
                     }
                 }
                 let nested_base = self.read_type_suffix(base)?;
                 if nested_base.is_incomplete() {
-                    return Err("incomplete element type".to_string());
+                    return Err(self.error_here("incomplete element type"));
                 }
 
                 Ok(Box::new(Type::Array { base: nested_base, is_incomplete, len: sz as usize }))
@@ -547,16 +746,17 @@ impl<'a> Parser<'a> {
     }
 
     // type-name := base-type abstract-declarator type-suffix
-    pub(in super) fn type_name(&mut self) -> Result<Box<Type>, String> {
+    pub(in super) fn type_name(&mut self) -> Result<Box<Type>, Diagnostic> {
         let mut ty = self.base_type(&mut None)?;
         ty = self.abstract_declarator(&mut ty)?;
 
         self.read_type_suffix(ty)
     }
 
-    pub(in super) fn new_add(lhs: ExprWrapper, rhs: ExprWrapper) -> Result<ExprWrapper, String> {
+    pub(in super) fn new_add(lhs: ExprWrapper, rhs: ExprWrapper) -> Result<ExprWrapper, Diagnostic> {
         match (lhs.ty.as_ref(), rhs.ty.as_ref()) {
-            (l, r) if l.is_integer() && r.is_integer() => {
+            (l, r) if l.is_arithmetic() && r.is_arithmetic() => {
+                let (lhs, rhs) = Parser::usual_arith_conv(lhs, rhs);
                 Ok(Expr::Add { lhs, rhs }.to_expr_wrapper())
             },
             (l, r) if l.has_base() && r.is_integer() => {
@@ -566,14 +766,15 @@ impl<'a> Parser<'a> {
                 Ok(Expr::PtrAdd { lhs: rhs, rhs: lhs }.to_expr_wrapper())
             },
             (_, _) => {
-                return Err("invalid operands at +".to_string());
+                Err(Diagnostic::error("invalid operands at +"))
             }
         }
     }
 
-    pub(in super) fn new_sub(lhs: ExprWrapper, rhs: ExprWrapper) -> Result<ExprWrapper, String> {
+    pub(in super) fn new_sub(lhs: ExprWrapper, rhs: ExprWrapper) -> Result<ExprWrapper, Diagnostic> {
        match (lhs.ty.as_ref(), rhs.ty.as_ref()) {
-            (l, r) if l.is_integer() && r.is_integer() => {
+            (l, r) if l.is_arithmetic() && r.is_arithmetic() => {
+                let (lhs, rhs) = Parser::usual_arith_conv(lhs, rhs);
                 Ok(Expr::Sub { lhs, rhs }.to_expr_wrapper())
             },
             (l, r) if l.has_base() && r.is_integer() => {
@@ -583,26 +784,61 @@ impl<'a> Parser<'a> {
                 Ok(Expr::PtrDiff { lhs, rhs }.to_expr_wrapper())
             },
             (_, _) => {
-                return Err("invalid operands at -".to_string());
+                Err(Diagnostic::error("invalid operands at -"))
             }
         }
     }
 
-    pub(in super) fn expect_next_ident(&mut self) -> Result<Token, String> {
-        if let Some(TokenType::Ident { .. }) = self.peekable.peek().map(|tok| &tok.token_type) {
-            let tk = self.peekable.next().unwrap();
-            Ok(tk.clone())
+    // an error reported at the current token
+    pub(in super) fn error_here(&self, msg: impl Into<String>) -> Diagnostic {
+        match self.peekable.peek() {
+            Some(tok) => Diagnostic::error_at(tok, msg),
+            None => Diagnostic::error(msg)
+        }
+    }
+
+    // the location of the next token
+    pub(in super) fn peek_loc(&self) -> Option<Loc> {
+        self.peekable.peek().map(|tok| tok.loc.clone())
+    }
+
+    // the span from the token at `start` to the last token read. it ends at the
+    // end of the first line if the tokens span multiple lines
+    pub(in super) fn span_from(&self, start: usize) -> Option<Span> {
+        let first = self.input.get(start)?;
+        let last = self.input.get(self.peekable.current_position().checked_sub(1)?)?;
+        let first_len = Span::from_token(first).len;
+        let len = if last.loc.file == first.loc.file && last.loc.row == first.loc.row {
+            (last.loc.col + Span::from_token(last).len).saturating_sub(first.loc.col).max(first_len)
         } else {
-            Err("expect identifier".to_string())
+            first_len
+        };
+
+        Some(Span::new(first.loc.clone(), len))
+    }
+
+    pub(in super) fn at_eof(&self) -> bool {
+        self.peekable.peek().is_none_or(|tok| tok.token_type.at_eof())
+    }
+
+    // warn a comparison between a pointer and an integer. 0 is a null pointer constant
+    pub(in super) fn check_ptr_int_cmp(&mut self, op: &Token, lhs: &ExprWrapper, rhs: &ExprWrapper) {
+        let is_null = |ew: &ExprWrapper| matches!(ew.expr.as_ref(), Expr::Num { val: 0, .. });
+
+        let mismatch = (lhs.ty.has_base() && rhs.ty.is_integer() && !is_null(rhs))
+            || (lhs.ty.is_integer() && rhs.ty.has_base() && !is_null(lhs));
+        if mismatch {
+            let msg = "comparison between pointer and integer";
+            self.warnings.push(Diagnostic::warning("pointer-integer-compare", msg, Some(Span::from_token(op))));
         }
     }
 
-    pub(in super) fn expect_next_num(&mut self) -> Result<isize, String> {
-        if let Some(TokenType::Num(Num { val, .. })) = self.peekable.peek().map(|tok| &tok.token_type) {
-            self.peekable.next();
-            Ok(*val)
+    pub(in super) fn expect_next_ident(&mut self) -> Result<Token, Diagnostic> {
+        if let Some(TokenType::Ident { .. }) = self.peekable.peek().map(|tok| &tok.token_type) {
+            let tk = self.peekable.next().unwrap();
+            Ok(tk.clone())
         } else {
-            Err("expect num".to_string())
+            Err(self.error_here("expected an identifier"))
         }
     }
 
@@ -620,15 +856,15 @@ impl<'a> Parser<'a> {
         };
         let name = &mut String::new();
 
-        match self.declarator(base, name) {
+        let ty = match self.declarator(base, name) {
+            Ok(ty) => ty,
             Err(_) => {
                 let _ = self.peekable.back_to(pos);
                 return false
-            },
-            _ => {}
+            }
         };
 
-        let is_fn = !name.is_empty() && self.expect_next_symbol("(".to_string()).is_ok();
+        let is_fn = !name.is_empty() && matches!(ty.as_ref(), Type::Func { .. });
         let _ = self.peekable.back_to(pos);
 
         is_fn
@@ -641,7 +877,9 @@ impl<'a> Parser<'a> {
                 TYPE_NAMES.contains(&op.as_str()) ||
                 op_str == "typedef" ||
                 op_str == "enum" ||
-                op_str == "static"
+                op_str == "static" ||
+                op_str == "extern" ||
+                TYPE_QUALIFIERS.contains(&op_str)
             } else {
                 self.find_typedef(tk).is_some()
             }
@@ -655,48 +893,80 @@ impl<'a> Parser<'a> {
         return label;
     }
 
-    // struct-decl := "struct" ident
-    // struct-decl := "struct" ident? "{" struct-member "}"
-    //              | struct ident
-    //              | struct ident { .. }
-    //              | struct {}
-    pub(in super) fn struct_decl(&mut self) -> Result<Box<Type>, String> {
-        self.peekable.next();
+    // struct-decl := ("struct" | "union") ident
+    //              | ("struct" | "union") ident? "{" struct-member* "}"
+    //
+    // a tag used before its members are declared is an incomplete struct,
+    // which is completed when the members appear in the same translation unit
+    pub(in super) fn struct_decl(&mut self) -> Result<Box<Type>, Diagnostic> {
+        let is_union = self.peekable.next().is_some_and(|tok| tok.token_type.tk_str().as_str() == "union");
         // read a struct tag.
         let tag = self.expect_next_ident().ok();
 
         let lbrace = self.expect_next_symbol("{").ok();
-        match (&tag, lbrace) {
-            (Some(t), None) => {
-                let sc = self.find_tag(t.token_type.tk_str());
-
-                return sc
-                    .map(|scope_tag| Box::clone(&scope_tag.ty))
-                    .ok_or("unknown struct type".to_string())
-                    .and_then(|scope_tag| {
-                        if let Type::Struct { .. } = *scope_tag {
-                            Ok(scope_tag)
-                        } else {
-                            Err("not a struct tag".to_string())
-                        }
-                    })
-            },
-            _ => {}
+        if let (Some(t), None) = (&tag, lbrace) {
+            let ty = match self.find_tag(t.token_type.tk_str()) {
+                Some(sc) => Box::clone(&sc.ty),
+                // a forward declaration, or a pointer to a struct declared later
+                None => {
+                    let ty = Box::new(Type::IncompleteStruct(StructTag::new(t.token_type.tk_str())));
+                    self.push_tag_scope(t, Box::clone(&ty));
+                    return Ok(ty)
+                }
+            };
+
+            return match *ty {
+                Type::Struct { .. } => Ok(ty),
+                Type::IncompleteStruct(_) => Ok(ty.resolve()),
+                _ => Err(Diagnostic::error_at(t, "not a struct tag"))
+            }
         }
 
+        // the tag is visible in its own members, e.g. `struct node { struct node *next; }`
+        let incomplete = match tag.as_ref().and_then(|t| self.find_tag(t.token_type.tk_str())).map(|sc| sc.ty.as_ref()) {
+            Some(Type::IncompleteStruct(st)) if st.get().is_none() => Some(st.clone()),
+            _ => tag.as_ref().map(|t| {
+                let st = StructTag::new(t.token_type.tk_str());
+                self.push_tag_scope(t, Box::new(Type::IncompleteStruct(st.clone())));
+                st
+            })
+        };
+
         let mut members = Vec::<Member>::new();
-        let mut offset = 0;
-        let mut align = 0;
+        // the layout is computed in bits for bit-fields
+        let mut bits = 0;
+        let mut align = 1;
 
         while let Err(_) = self.expect_next_symbol("}") {
-            let mut member = self.struct_member()?;
+            let (mut member, width) = self.struct_member()?;
             if member.ty.is_incomplete() {
-                return Err("incomplete element type".to_string())
+                return Err(self.error_here("incomplete element type"))
+            }
+            let unit = member.ty.size() * 8;
+
+            match width {
+                // a bit-field is placed in the storage unit of its type
+                // which has room for it, e.g. `int a : 20; int b : 20;` takes two ints
+                Some(width) => {
+                    if is_union {
+                        bits = 0;
+                    } else if bits / unit != (bits + width - 1) / unit {
+                        bits = align_to(bits, unit);
+                    }
+                    member.offset = Offset::Value(bits / unit * unit / 8);
+                    member.bit_field = Some(BitField { offset: bits % unit, width });
+                    bits += width;
+                },
+                None => {
+                    if is_union {
+                        bits = 0;
+                    }
+                    // offsetのインクリメントとmembers.pushが逆の場合,pushが走った時点でmemberの所有権はmembersにあるためエラーになる
+                    bits = align_to(bits, member.ty.align() * 8);
+                    member.offset = Offset::Value(bits / 8);
+                    bits += unit;
+                }
             }
-            offset = align_to(offset, member.ty.align());
-            member.offset = Offset::Value(offset);
-            // offsetのインクリメントとmembers.pushが逆の場合,pushが走った時点でmemberの所有権はmembersにあるためエラーになる
-            offset += member.ty.size();
 
             if align < member.ty.align() {
                 align = member.ty.align();
@@ -705,36 +975,59 @@ impl<'a> Parser<'a> {
             members.push(member);
         }
 
-        let ty = Box::new(
-            Type::Struct {
-               members,
-               size: align_to(offset, align),
-               align
+        // a union is as large as its largest member
+        let size = if is_union {
+            members.iter().map(|member| member.ty.size()).max().unwrap_or(0)
+        } else {
+            bits.div_ceil(8)
+        };
+        let ty = Box::new(Type::Struct {
+            members,
+            size: align_to(size, align),
+            align,
+            is_union,
+            name: tag.as_ref().map(|t| t.token_type.tk_str())
+        });
+
+        // the declarations so far see the members as well
+        if let Some(st) = incomplete {
+            st.complete(ty.as_ref().clone());
+            for sc in self.tag_scope.iter_mut().filter(|sc| *sc.ty == Type::IncompleteStruct(st.clone())) {
+                sc.ty = Box::clone(&ty);
             }
-        );
-
-        if let Some(t) = tag {
-            self.push_tag_scope(&t, Box::clone(&ty));
         }
 
         Ok(ty)
     }
 
-    //  struct-member := basetype ident ("[" num "]") ";"
-    pub(in super) fn struct_member(&mut self) -> Result<Member, String> {
+    // struct-member := basetype declarator type-suffix (":" const-expr)? ";"
+    // returns the member and the width of a bit-field
+    pub(in super) fn struct_member(&mut self) -> Result<(Member, Option<usize>), Diagnostic> {
         let mut ty = self.base_type(&mut None)?;
         let name = &mut String::new();
 
         ty = self.declarator(&mut ty, name)?;
         let ty_with_suffix = &mut self.read_type_suffix(Box::clone(&ty))?;
 
+        let mut width = None;
+        if self.expect_next_symbol(":").is_ok() {
+            if !ty_with_suffix.is_integer() {
+                return Err(self.error_here("bit-field has non-integer type"))
+            }
+            let bits = self.const_expr()?;
+            if bits <= 0 || bits as usize > ty_with_suffix.size() * 8 {
+                return Err(self.error_here(format!("invalid width of bit-field {}: {}", name, bits)))
+            }
+            width = Some(bits as usize);
+        }
+
         let _ = self.expect_next_symbol(";")?;
 
-        Ok(Member::new(Box::clone(&ty_with_suffix), name.as_str()))
+        Ok((Member::new(Box::clone(ty_with_suffix), name.as_str()), width))
     }
 
-    pub(in super) fn struct_ref(&mut self, expr_wrapper: ExprWrapper) -> Result<ExprWrapper, String> {
-        let ty = expr_wrapper.expr.detect_type();
+    pub(in super) fn struct_ref(&mut self, expr_wrapper: ExprWrapper) -> Result<ExprWrapper, Diagnostic> {
+        let ty = expr_wrapper.ty.resolve();
         if let Type::Struct { members, .. } = ty.as_ref() {
             let ident = self.expect_next_ident()?.token_type;
             let name = ident.tk_str();
@@ -744,14 +1037,14 @@ impl<'a> Parser<'a> {
 
             Ok(Expr::Member(expr_wrapper, member.clone()).to_expr_wrapper())
         } else {
-            Err("not_a struct".to_string())
+            Err(self.error_here("not_a struct"))
         }
     }
 
     // some types of list can end with an optional "," followed by "}"
     // to allow a trailing comma. this function returns true if it looks
     // like we are at the end of such list.
-    fn consume_end(&mut self) -> bool {
+    pub(in super) fn consume_end(&mut self) -> bool {
         let cur = self.peekable.current_position();
 
         if self.expect_next_symbol("}").is_ok()
@@ -766,8 +1059,8 @@ impl<'a> Parser<'a> {
     // enum-specifier := "enum" ident
     //                 | "enum" ident? "{" enum-list? "}"
     //
-    // enum-list := ident ("=" num)? ("," ident ("=" num)?)* ","?
-    pub(in super) fn enum_specifier(&mut self) -> Result<Box<Type>, String> {
+    // enum-list := ident ("=" const-expr)? ("," ident ("=" const-expr)?)* ","?
+    pub(in super) fn enum_specifier(&mut self) -> Result<Box<Type>, Diagnostic> {
         self.expect_next_reserved("enum")?;
         let ty = Box::new(Type::Enum);
 
@@ -782,11 +1075,11 @@ impl<'a> Parser<'a> {
                         return Ok(Box::clone(&tag_scope.ty))
 
                     } else {
-                        return Err(format!("{}: not an enum tag", tag_name))
+                        return Err(self.error_here(format!("{}: not an enum tag", tag_name)))
                     }
                 },
                 None => {
-                    return Err(format!("{}: unknown enum type", tag_name))
+                    return Err(self.error_here(format!("{}: unknown enum type", tag_name)))
                 }
             }
         } else {
@@ -795,7 +1088,7 @@ impl<'a> Parser<'a> {
             loop {
                 let ident = self.expect_next_ident()?.token_type.tk_str();
                 if let Ok(_) = self.expect_next_reserved("=") {
-                    cnt = self.expect_next_num()?;
+                    cnt = self.const_expr()?;
                 }
 
                 self.push_scope_with_enum(&ident, &ty, cnt);
@@ -0,0 +1,859 @@
+mod cond_expr;
+
+use crate::token::{ Token, TokenType };
+use crate::token::token_type::*;
+use crate::tokenizer::Tokenizer;
+use crate::preprocessor::cond_expr::CondExprEvaluator;
+use crate::_type::Type;
+use crate::diagnostic::Diagnostic;
+use crate::tokenizer::loc::Loc;
+
+use std::collections::{ HashMap, VecDeque };
+use std::fs;
+use std::path::{ Path, PathBuf };
+use std::rc::Rc;
+
+// #include can be nested up to this depth.
+// this prevents a header which includes itself from looping forever
+const MAX_INCLUDE_DEPTH: usize = 200;
+
+const SYSTEM_INCLUDE_PATHS: [&str; 3] = [
+    "/usr/local/include",
+    "/usr/include/x86_64-linux-gnu",
+    "/usr/include"
+];
+
+// the headers of this compiler, such as stdarg.h, come before the system ones.
+// they are looked up next to the executable, which is either installed with
+// include/ beside it or built in target/<profile>/ of the source tree.
+// the source tree at build time is the last resort
+pub fn default_include_paths() -> Vec<PathBuf> {
+    let exe_dir = std::env::current_exe().ok()
+        .and_then(|exe| exe.canonicalize().ok())
+        .and_then(|exe| exe.parent().map(Path::to_path_buf));
+    let own = exe_dir.iter()
+        .flat_map(|dir| [dir.join("include"), dir.join("../../include")])
+        .find(|dir| dir.join("stdarg.h").is_file())
+        .unwrap_or_else(|| PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/include")));
+
+    std::iter::once(own)
+        .chain(SYSTEM_INCLUDE_PATHS.iter().map(PathBuf::from))
+        .collect()
+}
+
+// object-like macro   := "#define" ident replacement-list
+// function-like macro := "#define" ident "(" params? ")" replacement-list
+//   (no space is allowed between ident and "(")
+#[derive(Debug, Clone)]
+pub enum Macro {
+    Object(Vec<Token>),
+    Function {
+        params: Vec<String>,
+        is_variadic: bool,
+        body: Vec<Token>
+    }
+}
+
+// a token with its hideset.
+// hideset is the set of macro names which must not be expanded on this token any more.
+// (Dave Prosser's algorithm: https://www.spinellis.gr/blog/20060626/cpp.algo.pdf)
+#[derive(Debug, Clone)]
+struct PpToken {
+    token: Token,
+    hideset: Vec<Rc<String>>
+}
+
+impl PpToken {
+    fn new(token: Token) -> Self {
+        Self { token, hideset: Vec::new() }
+    }
+
+    fn in_hideset(&self, name: &str) -> bool {
+        self.hideset.iter().any(|n| n.as_str() == name)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum CondCtx {
+    Then,
+    Elif,
+    Else
+}
+
+// state of an #if/#ifdef/#ifndef block
+struct CondIncl {
+    ctx: CondCtx,
+    // true if one of the groups in this block has already been included
+    included: bool
+}
+
+pub struct Preprocessor {
+    macros: HashMap<String, Macro>,
+    include_paths: Vec<PathBuf>,
+    // the file which is currently being preprocessed. used for
+    // `#include "..."` resolution and __FILE__
+    current_file: PathBuf,
+    include_depth: usize
+}
+
+impl Preprocessor {
+    pub fn new(include_paths: Vec<PathBuf>) -> Self {
+        let mut pp = Self {
+            macros: HashMap::new(),
+            include_paths,
+            current_file: PathBuf::new(),
+            include_depth: 0
+        };
+
+        // the limits of wchar_t are given as gcc does, since the headers cannot compute them without wide characters
+        let builtins = [
+            ("__STDC__", "1"), ("__x86_64__", "1"), ("__LP64__", "1"), ("__linux__", "1"),
+            ("__WCHAR_MAX__", "2147483647"), ("__WCHAR_MIN__", "(-2147483647 - 1)")
+        ];
+        for (name, val) in builtins.iter() {
+            // builtin macros are always valid
+            let _ = pp.define(name, val);
+        }
+
+        pp
+    }
+
+    // define a macro, the same as `#define name val`.
+    // name may have parameters such as `F(x)`, which makes a function-like macro
+    pub fn define(&mut self, name: &str, val: &str) -> Result<(), Diagnostic> {
+        let line = tokenize_str(&format!("#define {} {}\n", name, val))?;
+        self.preprocess_tokens(line, Path::new("<built-in>"))?;
+
+        Ok(())
+    }
+
+    pub fn undef(&mut self, name: &str) {
+        self.macros.remove(name);
+    }
+
+    pub fn is_defined(&self, name: &str) -> bool {
+        self.macros.contains_key(name) || name == "__LINE__" || name == "__FILE__"
+    }
+
+    // tokenize and preprocess a file. the result ends with Eof
+    pub fn preprocess_file(&mut self, path: &Path) -> Result<Vec<Token>, Diagnostic> {
+        let tokens = tokenize_file(path)?;
+
+        self.preprocess(tokens, path)
+    }
+
+    // preprocess tokens of `path`. the result ends with Eof
+    pub fn preprocess(&mut self, tokens: Vec<Token>, path: &Path) -> Result<Vec<Token>, Diagnostic> {
+        let eof = tokens.last()
+            .filter(|tok| tok.token_type.at_eof())
+            .cloned();
+
+        let mut output = self.preprocess_tokens(tokens, path)?;
+        // a malformed number is allowed in a skipped group or a header name
+        let malformed = output.iter().find_map(|tok| match &tok.token_type {
+            TokenType::PpNum(num) => Some((tok, num)),
+            _ => None
+        });
+        if let Some((tok, num)) = malformed {
+            return Err(self.error_at(tok, &num.error))
+        }
+
+        output.push(eof.unwrap_or_else(|| {
+            let last = output.last().map(|tok| tok.loc.clone()).unwrap_or_else(|| Loc::new(Rc::new(path.display().to_string()), 1, 1));
+            Token::new(TokenType::Eof, last)
+        }));
+
+        Ok(output)
+    }
+
+    // preprocess tokens of a file without a trailing Eof.
+    // #include is handled by calling this function recursively
+    fn preprocess_tokens(&mut self, tokens: Vec<Token>, path: &Path) -> Result<Vec<Token>, Diagnostic> {
+        let prev_file = std::mem::replace(&mut self.current_file, path.to_path_buf());
+
+        let mut input: VecDeque<PpToken> = tokens.into_iter()
+            .filter(|tok| !tok.token_type.at_eof())
+            .map(PpToken::new)
+            .collect();
+        let mut output = Vec::<Token>::new();
+        let mut conds = Vec::<CondIncl>::new();
+
+        while let Some(pt) = input.pop_front() {
+            // if it is a macro, expand it and push the result back to the input
+            if self.expand_macro(&pt, &mut input)? {
+                continue
+            }
+
+            if !(pt.token.at_bol && is_hash(&pt.token)) {
+                output.push(pt.token);
+                continue
+            }
+
+            // null directive `#`
+            let directive = match input.front() {
+                Some(next) if !next.token.at_bol => input.pop_front().unwrap().token,
+                _ => continue
+            };
+
+            match directive.token_type.tk_str().as_str() {
+                "include" => {
+                    let line = read_line(&mut input);
+                    let (name, is_quote) = self.include_filename(line, &directive)?;
+                    let resolved = self.search_include(&name, is_quote)
+                        .ok_or_else(|| self.error_at(&directive, &format!("{}: cannot open file", name)))?;
+
+                    if self.include_depth >= MAX_INCLUDE_DEPTH {
+                        return Err(self.error_at(&directive, "#include nested too deeply"))
+                    }
+
+                    self.include_depth += 1;
+                    let included = tokenize_file(&resolved)
+                        .and_then(|tokens| self.preprocess_tokens(tokens, &resolved));
+                    self.include_depth -= 1;
+
+                    output.extend(included?);
+                },
+                "define" => {
+                    let line = read_line(&mut input);
+                    self.read_macro_definition(line, &directive)?;
+                },
+                "undef" => {
+                    let line = read_line(&mut input);
+                    let name = self.macro_name(line.first(), &directive)?;
+                    self.undef(&name);
+                },
+                "if" => {
+                    let line = read_line(&mut input);
+                    let val = self.eval_const_expr(line, &directive)?;
+                    conds.push(CondIncl { ctx: CondCtx::Then, included: val });
+                    if !val {
+                        skip_cond_incl(&mut input);
+                    }
+                },
+                "ifdef" | "ifndef" => {
+                    let line = read_line(&mut input);
+                    let name = self.macro_name(line.first(), &directive)?;
+                    let defined = self.is_defined(&name);
+                    let val = if directive.token_type.tk_str().as_str() == "ifdef" { defined } else { !defined };
+
+                    conds.push(CondIncl { ctx: CondCtx::Then, included: val });
+                    if !val {
+                        skip_cond_incl(&mut input);
+                    }
+                },
+                "elif" => {
+                    let line = read_line(&mut input);
+                    let included = match conds.last() {
+                        Some(CondIncl { ctx: CondCtx::Else, .. }) | None => {
+                            return Err(self.error_at(&directive, "stray #elif"))
+                        },
+                        Some(cond) => cond.included
+                    };
+
+                    // the expression is not evaluated if a preceding group has been included
+                    let val = !included && self.eval_const_expr(line, &directive)?;
+                    let cond = conds.last_mut().unwrap();
+                    cond.ctx = CondCtx::Elif;
+                    if val {
+                        cond.included = true;
+                    } else {
+                        skip_cond_incl(&mut input);
+                    }
+                },
+                "else" => {
+                    read_line(&mut input);
+                    match conds.last_mut() {
+                        Some(CondIncl { ctx: CondCtx::Else, .. }) | None => {
+                            return Err(self.error_at(&directive, "stray #else"))
+                        },
+                        Some(cond) => {
+                            cond.ctx = CondCtx::Else;
+                            if cond.included {
+                                skip_cond_incl(&mut input);
+                            }
+                            cond.included = true;
+                        }
+                    }
+                },
+                "endif" => {
+                    read_line(&mut input);
+                    if conds.pop().is_none() {
+                        return Err(self.error_at(&directive, "stray #endif"))
+                    }
+                },
+                "error" => {
+                    let line = read_line(&mut input);
+                    let msg = join_tokens(&line);
+                    return Err(self.error_at(&directive, &format!("#error {}", msg)))
+                },
+                // #pragma and #line are accepted and ignored
+                "pragma" | "line" => {
+                    read_line(&mut input);
+                },
+                _ => {
+                    return Err(self.error_at(&directive, "invalid preprocessor directive"))
+                }
+            }
+        }
+
+        if !conds.is_empty() {
+            let msg = format!("{}: unterminated conditional directive", self.current_file.display());
+            return Err(Diagnostic::error(msg))
+        }
+
+        self.current_file = prev_file;
+
+        Ok(output)
+    }
+
+    // if `pt` is a macro name, expand it, push the expansion back to the front of
+    // `input` and return true. otherwise return false
+    fn expand_macro(&mut self, pt: &PpToken, input: &mut VecDeque<PpToken>) -> Result<bool, Diagnostic> {
+        let name = match &pt.token.token_type {
+            TokenType::Ident(Ident { name, .. }) => Rc::clone(name),
+            _ => return Ok(false)
+        };
+
+        if pt.in_hideset(&name) {
+            return Ok(false)
+        }
+
+        // dynamic builtin macros
+        match name.as_str() {
+            "__LINE__" => {
+                let val = pt.token.loc.row as isize;
+                let token_type = TokenType::Num(Num { val, fval: 0.0, ty: Type::Long, tk_str: Rc::new(val.to_string()) });
+                input.push_front(PpToken { token: self.new_token_from(token_type, &pt.token), hideset: pt.hideset.clone() });
+
+                return Ok(true)
+            },
+            "__FILE__" => {
+                let file = self.current_file.display().to_string();
+                let token = self.new_token_from(new_str_token_type(&file), &pt.token);
+                input.push_front(PpToken { token, hideset: pt.hideset.clone() });
+
+                return Ok(true)
+            },
+            _ => {}
+        }
+
+        let mac = match self.macros.get(name.as_str()) {
+            Some(mac) => mac.clone(),
+            None => return Ok(false)
+        };
+
+        match mac {
+            Macro::Object(body) => {
+                let mut hideset = pt.hideset.clone();
+                hideset.push(Rc::clone(&name));
+
+                let expanded = body.into_iter()
+                    .map(|tok| PpToken { token: tok, hideset: hideset.clone() })
+                    .collect();
+
+                self.push_expansion(expanded, &pt.token, input);
+            },
+            Macro::Function { params, is_variadic, body } => {
+                // a function-like macro name not followed by "(" is not a macro call
+                match input.front() {
+                    Some(next) if is_symbol(&next.token, "(") => { input.pop_front(); },
+                    _ => return Ok(false)
+                }
+
+                let (args, rparen) = self.read_macro_args(input, &params, is_variadic, &pt.token)?;
+
+                // hideset of the expansion := (HS(macro name) & HS(rparen)) + {macro name}
+                let mut hideset: Vec<Rc<String>> = pt.hideset.iter()
+                    .filter(|n| rparen.in_hideset(n))
+                    .cloned()
+                    .collect();
+                hideset.push(Rc::clone(&name));
+
+                let expanded = self.subst(&body, &params, is_variadic, &args)?
+                    .into_iter()
+                    .map(|mut t| {
+                        for n in hideset.iter() {
+                            if !t.in_hideset(n) {
+                                t.hideset.push(Rc::clone(n));
+                            }
+                        }
+                        t
+                    })
+                    .collect();
+
+                self.push_expansion(expanded, &pt.token, input);
+            }
+        }
+
+        Ok(true)
+    }
+
+    // push the expanded tokens to the front of `input`.
+    // the expanded tokens are located at the macro name
+    fn push_expansion(&self, expanded: Vec<PpToken>, origin: &Token, input: &mut VecDeque<PpToken>) {
+        let len = expanded.len();
+        for (i, mut pt) in expanded.into_iter().rev().enumerate() {
+            pt.token.loc = origin.loc.clone();
+            pt.token.at_bol = false;
+            if i == len - 1 {
+                pt.token.has_space = origin.has_space;
+            }
+            input.push_front(pt);
+        }
+    }
+
+    // read macro arguments. the opening "(" has already been consumed.
+    // returns the arguments and the closing ")"
+    fn read_macro_args(
+        &self,
+        input: &mut VecDeque<PpToken>,
+        params: &[String],
+        is_variadic: bool,
+        macro_tok: &Token
+    ) -> Result<(Vec<Vec<PpToken>>, PpToken), Diagnostic> {
+        let mut args = vec![Vec::<PpToken>::new()];
+        let mut depth = 0;
+
+        loop {
+            let pt = input.pop_front()
+                .ok_or_else(|| self.error_at(macro_tok, "unterminated macro argument list"))?;
+
+            if depth == 0 && is_symbol(&pt.token, ")") {
+                // F() is a call with zero arguments when F takes no parameters
+                if params.is_empty() && !is_variadic && args.len() == 1 && args[0].is_empty() {
+                    args.clear();
+                }
+                // the variadic argument may be omitted
+                if is_variadic && args.len() == params.len() {
+                    args.push(Vec::new());
+                }
+
+                let expected = params.len() + if is_variadic { 1 } else { 0 };
+                if args.len() < expected {
+                    return Err(self.error_at(macro_tok, "too few arguments"))
+                }
+                if args.len() > expected {
+                    return Err(self.error_at(macro_tok, "too many arguments"))
+                }
+
+                return Ok((args, pt))
+            }
+
+            // the variadic argument takes all remaining arguments including commas
+            let is_va_args = is_variadic && args.len() > params.len();
+            if depth == 0 && is_symbol(&pt.token, ",") && !is_va_args {
+                args.push(Vec::new());
+                continue
+            }
+
+            if is_symbol(&pt.token, "(") {
+                depth += 1;
+            } else if is_symbol(&pt.token, ")") {
+                depth -= 1;
+            }
+
+            args.last_mut().unwrap().push(pt);
+        }
+    }
+
+    // replace parameters in the macro body with the arguments
+    fn subst(
+        &mut self,
+        body: &[Token],
+        params: &[String],
+        is_variadic: bool,
+        args: &[Vec<PpToken>]
+    ) -> Result<Vec<PpToken>, Diagnostic> {
+        let param_index = |tok: &Token| -> Option<usize> {
+            if let TokenType::Ident(Ident { name, .. }) = &tok.token_type {
+                if is_variadic && name.as_str() == "__VA_ARGS__" {
+                    return Some(params.len())
+                }
+                params.iter().position(|p| p == name.as_str())
+            } else {
+                None
+            }
+        };
+
+        let mut out = Vec::<PpToken>::new();
+        let mut i = 0;
+
+        while i < body.len() {
+            let tok = &body[i];
+            let next = body.get(i + 1);
+
+            // "#" param is replaced with the stringized argument
+            if is_hash(tok) {
+                if let Some(idx) = next.and_then(&param_index) {
+                    let mut token = stringize(&args[idx]);
+                    token.has_space = tok.has_space;
+                    out.push(PpToken::new(token));
+                    i += 2;
+                    continue
+                }
+            }
+
+            // x ## y is replaced with the concatenation of x and y
+            if is_reserved(tok, "##") {
+                let rhs = next.ok_or_else(|| self.error_at(tok, "'##' cannot appear at end of macro expansion"))?;
+                let lhs = out.pop().ok_or_else(|| self.error_at(tok, "'##' cannot appear at start of macro expansion"))?;
+
+                if let Some(idx) = param_index(rhs) {
+                    match args[idx].split_first() {
+                        Some((first, rest)) => {
+                            out.push(self.paste(lhs, &first.token)?);
+                            out.extend(rest.iter().cloned());
+                        },
+                        None => out.push(lhs)
+                    }
+                } else {
+                    out.push(self.paste(lhs, rhs)?);
+                }
+
+                i += 2;
+                continue
+            }
+
+            if let Some(idx) = param_index(tok) {
+                // an operand of ## is not macro-expanded
+                if next.is_some_and(|t| is_reserved(t, "##")) {
+                    if args[idx].is_empty() {
+                        // empty ## y is just y
+                        match body.get(i + 2).and_then(&param_index) {
+                            Some(rhs_idx) => {
+                                out.extend(args[rhs_idx].iter().cloned());
+                                i += 3;
+                            },
+                            None => i += 2
+                        }
+                    } else {
+                        out.extend(args[idx].iter().cloned());
+                        i += 1;
+                    }
+                    continue
+                }
+
+                let expanded = self.expand_all(args[idx].clone())?;
+                out.extend(expanded);
+                i += 1;
+                continue
+            }
+
+            out.push(PpToken::new(tok.clone()));
+            i += 1;
+        }
+
+        Ok(out)
+    }
+
+    // concatenate two tokens and create a new token
+    fn paste(&self, lhs: PpToken, rhs: &Token) -> Result<PpToken, Diagnostic> {
+        let joined = format!("{}{}", lhs.token.token_type.tk_str(), rhs.token_type.tk_str());
+        let mut tokens = tokenize_str(&joined)
+            .map_err(|e| self.error_at(&lhs.token, &e.message))?;
+
+        if tokens.len() != 1 {
+            let msg = format!("pasting forms '{}', an invalid token", joined);
+            return Err(self.error_at(&lhs.token, &msg))
+        }
+
+        let mut token = tokens.remove(0);
+        token.loc = lhs.token.loc.clone();
+        token.has_space = lhs.token.has_space;
+
+        Ok(PpToken { token, hideset: lhs.hideset })
+    }
+
+    // fully macro-expand the given tokens. directives are not processed
+    fn expand_all(&mut self, tokens: Vec<PpToken>) -> Result<Vec<PpToken>, Diagnostic> {
+        let mut input: VecDeque<PpToken> = tokens.into_iter().collect();
+        let mut out = Vec::<PpToken>::new();
+
+        while let Some(pt) = input.pop_front() {
+            if self.expand_macro(&pt, &mut input)? {
+                continue
+            }
+            out.push(pt);
+        }
+
+        Ok(out)
+    }
+
+    fn read_macro_definition(&mut self, line: Vec<PpToken>, directive: &Token) -> Result<(), Diagnostic> {
+        let name = self.macro_name(line.first(), directive)?;
+        let mut rest = line.into_iter().skip(1).map(|pt| pt.token).peekable();
+
+        let is_function = rest.peek().is_some_and(|tok| is_symbol(tok, "(") && !tok.has_space);
+        if !is_function {
+            self.macros.insert(name, Macro::Object(rest.collect()));
+            return Ok(())
+        }
+
+        // read parameters
+        rest.next();
+        let mut params = Vec::<String>::new();
+        let mut is_variadic = false;
+        loop {
+            let tok = rest.next().ok_or_else(|| self.error_at(directive, "expected ')'"))?;
+            if is_symbol(&tok, ")") && params.is_empty() && !is_variadic {
+                break
+            }
+
+            match &tok.token_type {
+                TokenType::Reserved(Reserved { op, .. }) if op.as_str() == "..." => {
+                    is_variadic = true;
+                },
+                TokenType::Ident(Ident { name, .. }) => {
+                    params.push(name.to_string());
+                },
+                _ => return Err(self.error_at(&tok, "expected an identifier"))
+            }
+
+            let tok = rest.next().ok_or_else(|| self.error_at(directive, "expected ')'"))?;
+            if is_symbol(&tok, ")") {
+                break
+            }
+            if is_variadic || !is_symbol(&tok, ",") {
+                return Err(self.error_at(&tok, "expected ')'"))
+            }
+        }
+
+        self.macros.insert(name, Macro::Function { params, is_variadic, body: rest.collect() });
+
+        Ok(())
+    }
+
+    fn macro_name(&self, tok: Option<&PpToken>, directive: &Token) -> Result<String, Diagnostic> {
+        match tok.map(|pt| &pt.token.token_type) {
+            Some(TokenType::Ident(Ident { name, .. })) => Ok(name.to_string()),
+            _ => Err(self.error_at(directive, "macro name must be an identifier"))
+        }
+    }
+
+    // read the operand of #include.
+    //   #include "foo.h"  -> ("foo.h", true)
+    //   #include <foo.h>  -> ("foo.h", false)
+    //   #include FOO      -> FOO is macro-expanded, then one of the above
+    fn include_filename(&mut self, line: Vec<PpToken>, directive: &Token) -> Result<(String, bool), Diagnostic> {
+        match line.first().map(|pt| &pt.token.token_type) {
+            Some(TokenType::Str(Str { bytes, .. })) => {
+                let name = String::from_utf8_lossy(&bytes[.. bytes.len() - 1]).to_string();
+                Ok((name, true))
+            },
+            Some(TokenType::Reserved(Reserved { op, .. })) if op.as_str() == "<" => {
+                let name: String = line.iter()
+                    .skip(1)
+                    .take_while(|pt| !is_reserved(&pt.token, ">"))
+                    .map(|pt| pt.token.token_type.tk_str().to_string())
+                    .collect();
+
+                if !line.iter().skip(1).any(|pt| is_reserved(&pt.token, ">")) {
+                    return Err(self.error_at(directive, "expected '>'"))
+                }
+
+                Ok((name, false))
+            },
+            Some(TokenType::Ident(_)) => {
+                let expanded = self.expand_all(line)?;
+                if let Some(TokenType::Ident(_)) = expanded.first().map(|pt| &pt.token.token_type) {
+                    return Err(self.error_at(directive, "expected a filename"))
+                }
+                self.include_filename(expanded, directive)
+            }
+            _ => Err(self.error_at(directive, "expected a filename"))
+        }
+    }
+
+    fn search_include(&self, name: &str, is_quote: bool) -> Option<PathBuf> {
+        let path = Path::new(name);
+        if path.is_absolute() {
+            return Some(path.to_path_buf()).filter(|p| p.exists())
+        }
+
+        // "..." is searched from the directory of the current file first
+        let current_dir = self.current_file.parent().map(|dir| dir.to_path_buf());
+        let quote_dir = if is_quote { current_dir } else { None };
+
+        quote_dir.iter()
+            .chain(self.include_paths.iter())
+            .map(|dir| dir.join(name))
+            .find(|p| p.exists())
+    }
+
+    // read and evaluate a constant expression of #if or #elif
+    fn eval_const_expr(&mut self, line: Vec<PpToken>, directive: &Token) -> Result<bool, Diagnostic> {
+        // replace `defined(foo)` and `defined foo` with 1 or 0
+        let mut replaced = Vec::<PpToken>::new();
+        let mut iter = line.into_iter().peekable();
+        while let Some(pt) = iter.next() {
+            if !is_ident(&pt.token, "defined") {
+                replaced.push(pt);
+                continue
+            }
+
+            let has_paren = iter.peek().is_some_and(|next| is_symbol(&next.token, "("));
+            if has_paren {
+                iter.next();
+            }
+            let name = self.macro_name(iter.next().as_ref(), directive)?;
+            if has_paren && !iter.next().is_some_and(|next| is_symbol(&next.token, ")")) {
+                return Err(self.error_at(directive, "expected ')'"))
+            }
+
+            let val = if self.is_defined(&name) { 1 } else { 0 };
+            let token_type = TokenType::Num(Num { val, fval: 0.0, ty: Type::Long, tk_str: Rc::new(val.to_string()) });
+            replaced.push(PpToken::new(self.new_token_from(token_type, &pt.token)));
+        }
+
+        // identifiers remaining after macro expansion are replaced with 0
+        let tokens: Vec<Token> = self.expand_all(replaced)?
+            .into_iter()
+            .map(|pt| {
+                if let TokenType::Ident(_) = pt.token.token_type {
+                    let token_type = TokenType::Num(Num { val: 0, fval: 0.0, ty: Type::Long, tk_str: Rc::new("0".to_string()) });
+                    self.new_token_from(token_type, &pt.token)
+                } else {
+                    pt.token
+                }
+            })
+            .collect();
+
+        if tokens.is_empty() {
+            return Err(self.error_at(directive, "no expression"))
+        }
+
+        CondExprEvaluator::new(&tokens)
+            .eval()
+            .map(|val| val != 0)
+            .map_err(|e| self.error_at(directive, &e))
+    }
+
+    fn new_token_from(&self, token_type: TokenType, origin: &Token) -> Token {
+        let mut token = Token::new(token_type, origin.loc.clone());
+        token.has_space = origin.has_space;
+
+        token
+    }
+
+    fn error_at(&self, tok: &Token, msg: &str) -> Diagnostic {
+        Diagnostic::error_at(tok, msg)
+    }
+}
+
+fn tokenize_file(path: &Path) -> Result<Vec<Token>, Diagnostic> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| Diagnostic::error(format!("cannot read {}, reason: {}", path.display(), e)))?;
+
+    Tokenizer::new(content, path.display().to_string()).tokenize()
+}
+
+// tokenize a string without the trailing Eof
+fn tokenize_str(s: &str) -> Result<Vec<Token>, Diagnostic> {
+    let mut tokens = Tokenizer::new(s.to_string(), "<built-in>").tokenize()?;
+    tokens.pop();
+
+    Ok(tokens)
+}
+
+// read tokens until the end of the current line
+fn read_line(input: &mut VecDeque<PpToken>) -> Vec<PpToken> {
+    let mut line = Vec::new();
+    while input.front().is_some_and(|pt| !pt.token.at_bol) {
+        line.push(input.pop_front().unwrap());
+    }
+
+    line
+}
+
+// skip until the next #elif, #else or #endif of the current block.
+// nested #if blocks are skipped entirely
+fn skip_cond_incl(input: &mut VecDeque<PpToken>) {
+    let mut depth = 0;
+
+    while let Some(pt) = input.front() {
+        let directive = if pt.token.at_bol && is_hash(&pt.token) {
+            input.get(1)
+                .filter(|next| !next.token.at_bol)
+                .map(|next| next.token.token_type.tk_str())
+        } else {
+            None
+        };
+
+        match directive.as_ref().map(|d| d.as_str()) {
+            Some("if") | Some("ifdef") | Some("ifndef") => depth += 1,
+            Some("elif") | Some("else") if depth == 0 => return,
+            Some("endif") => {
+                if depth == 0 {
+                    return
+                }
+                depth -= 1;
+            },
+            _ => {}
+        }
+
+        input.pop_front();
+    }
+}
+
+fn stringize(tokens: &[PpToken]) -> Token {
+    let text = join_tokens(tokens);
+    let token_type = new_str_token_type(&text);
+    let loc = tokens.first()
+        .map(|pt| pt.token.loc.clone())
+        .unwrap_or_else(|| Loc::new(Rc::new("<built-in>".to_string()), 1, 1));
+
+    Token::new(token_type, loc)
+}
+
+// join the spellings of tokens, inserting a space where the source has one
+fn join_tokens(tokens: &[PpToken]) -> String {
+    let mut text = String::new();
+    for (i, pt) in tokens.iter().enumerate() {
+        if i > 0 && pt.token.has_space {
+            text.push(' ');
+        }
+        text.push_str(pt.token.token_type.tk_str().as_str());
+    }
+
+    text
+}
+
+fn new_str_token_type(s: &str) -> TokenType {
+    let mut bytes = s.as_bytes().to_vec();
+    bytes.push(0);
+
+    let escaped: String = s.chars()
+        .map(|c| match c {
+            '"' => "\\\"".to_string(),
+            '\\' => "\\\\".to_string(),
+            _ => c.to_string()
+        })
+        .collect();
+
+    TokenType::Str(Str { bytes, tk_str: Rc::new(format!("\"{}\"", escaped)) })
+}
+
+fn is_hash(tok: &Token) -> bool {
+    is_reserved(tok, "#")
+}
+
+fn is_reserved(tok: &Token, s: &str) -> bool {
+    match &tok.token_type {
+        TokenType::Reserved(Reserved { op, .. }) => op.as_str() == s,
+        _ => false
+    }
+}
+
+fn is_symbol(tok: &Token, s: &str) -> bool {
+    match &tok.token_type {
+        TokenType::Symbol(Symbol { sym, .. }) => sym.as_str() == s,
+        _ => false
+    }
+}
+
+fn is_ident(tok: &Token, s: &str) -> bool {
+    match &tok.token_type {
+        TokenType::Ident(Ident { name, .. }) => name.as_str() == s,
+        _ => false
+    }
+}
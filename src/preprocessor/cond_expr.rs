@@ -0,0 +1,231 @@
+use crate::token::{ Token, TokenType };
+use crate::token::token_type::*;
+
+// evaluator for the constant expression of #if and #elif.
+// `defined` and macros have already been replaced by the caller,
+// so the tokens consist of numbers, operators and parentheses
+//
+// cond-expr := conditional
+// conditional := logor ("?" cond-expr ":" conditional)?
+// logor     := logand ("||" logand)*
+// logand    := bitor ("&&" bitor)*
+// bitor     := bitxor ("|" bitxor)*
+// bitxor    := bitand ("^" bitand)*
+// bitand    := equality ("&" equality)*
+// equality  := relational ("==" relational | "!=" relational)*
+// relational := shift ("<" shift | "<=" shift | ">" shift | ">=" shift)*
+// shift     := add ("<<" add | ">>" add)*
+// add       := mul ("+" mul | "-" mul)*
+// mul       := unary ("*" unary | "/" unary | "%" unary)*
+// unary     := ("+" | "-" | "!" | "~") unary | primary
+// primary   := "(" cond-expr ")" | num
+pub struct CondExprEvaluator<'a> {
+    tokens: &'a [Token],
+    pos: usize
+}
+
+impl<'a> CondExprEvaluator<'a> {
+    pub fn new(tokens: &'a [Token]) -> Self {
+        Self { tokens, pos: 0 }
+    }
+
+    pub fn eval(&mut self) -> Result<isize, String> {
+        let val = self.conditional()?;
+        if self.pos < self.tokens.len() {
+            return Err("extra token".to_string())
+        }
+
+        Ok(val)
+    }
+
+    fn conditional(&mut self) -> Result<isize, String> {
+        let cond = self.logor()?;
+        if !self.consume("?") {
+            return Ok(cond)
+        }
+
+        let then = self.conditional()?;
+        if !self.consume(":") {
+            return Err("expected ':'".to_string())
+        }
+        let els = self.conditional()?;
+
+        Ok(if cond != 0 { then } else { els })
+    }
+
+    fn logor(&mut self) -> Result<isize, String> {
+        let mut val = self.logand()?;
+        while self.consume("||") {
+            let rhs = self.logand()?;
+            val = (val != 0 || rhs != 0) as isize;
+        }
+
+        Ok(val)
+    }
+
+    fn logand(&mut self) -> Result<isize, String> {
+        let mut val = self.bitor()?;
+        while self.consume("&&") {
+            let rhs = self.bitor()?;
+            val = (val != 0 && rhs != 0) as isize;
+        }
+
+        Ok(val)
+    }
+
+    fn bitor(&mut self) -> Result<isize, String> {
+        let mut val = self.bitxor()?;
+        while self.consume("|") {
+            val |= self.bitxor()?;
+        }
+
+        Ok(val)
+    }
+
+    fn bitxor(&mut self) -> Result<isize, String> {
+        let mut val = self.bitand()?;
+        while self.consume("^") {
+            val ^= self.bitand()?;
+        }
+
+        Ok(val)
+    }
+
+    fn bitand(&mut self) -> Result<isize, String> {
+        let mut val = self.equality()?;
+        while self.consume("&") {
+            val &= self.equality()?;
+        }
+
+        Ok(val)
+    }
+
+    fn equality(&mut self) -> Result<isize, String> {
+        let mut val = self.relational()?;
+        loop {
+            if self.consume("==") {
+                val = (val == self.relational()?) as isize;
+            } else if self.consume("!=") {
+                val = (val != self.relational()?) as isize;
+            } else {
+                return Ok(val)
+            }
+        }
+    }
+
+    fn relational(&mut self) -> Result<isize, String> {
+        let mut val = self.shift()?;
+        loop {
+            if self.consume("<") {
+                val = (val < self.shift()?) as isize;
+            } else if self.consume("<=") {
+                val = (val <= self.shift()?) as isize;
+            } else if self.consume(">") {
+                val = (val > self.shift()?) as isize;
+            } else if self.consume(">=") {
+                val = (val >= self.shift()?) as isize;
+            } else {
+                return Ok(val)
+            }
+        }
+    }
+
+    fn shift(&mut self) -> Result<isize, String> {
+        let mut val = self.add()?;
+        loop {
+            if self.consume("<<") {
+                val = val.wrapping_shl(self.add()? as u32);
+            } else if self.consume(">>") {
+                val = val.wrapping_shr(self.add()? as u32);
+            } else {
+                return Ok(val)
+            }
+        }
+    }
+
+    fn add(&mut self) -> Result<isize, String> {
+        let mut val = self.mul()?;
+        loop {
+            if self.consume("+") {
+                val = val.wrapping_add(self.mul()?);
+            } else if self.consume("-") {
+                val = val.wrapping_sub(self.mul()?);
+            } else {
+                return Ok(val)
+            }
+        }
+    }
+
+    fn mul(&mut self) -> Result<isize, String> {
+        let mut val = self.unary()?;
+        loop {
+            if self.consume("*") {
+                val = val.wrapping_mul(self.unary()?);
+            } else if self.consume("/") {
+                let rhs = self.unary()?;
+                if rhs == 0 {
+                    return Err("division by zero".to_string())
+                }
+                val = val.wrapping_div(rhs);
+            } else if self.consume("%") {
+                let rhs = self.unary()?;
+                if rhs == 0 {
+                    return Err("division by zero".to_string())
+                }
+                val = val.wrapping_rem(rhs);
+            } else {
+                return Ok(val)
+            }
+        }
+    }
+
+    fn unary(&mut self) -> Result<isize, String> {
+        if self.consume("+") {
+            return self.unary()
+        }
+        if self.consume("-") {
+            return Ok(self.unary()?.wrapping_neg())
+        }
+        if self.consume("!") {
+            return Ok((self.unary()? == 0) as isize)
+        }
+        if self.consume("~") {
+            return Ok(!self.unary()?)
+        }
+
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<isize, String> {
+        if self.consume("(") {
+            let val = self.conditional()?;
+            if !self.consume(")") {
+                return Err("expected ')'".to_string())
+            }
+            return Ok(val)
+        }
+
+        match self.tokens.get(self.pos).map(|tok| &tok.token_type) {
+            Some(TokenType::Num(Num { val, .. })) => {
+                self.pos += 1;
+                Ok(*val)
+            },
+            Some(token_type) => Err(format!("invalid token in constant expression: {}", token_type.tk_str())),
+            None => Err("unexpected end of constant expression".to_string())
+        }
+    }
+
+    fn consume(&mut self, op: &str) -> bool {
+        let matched = match self.tokens.get(self.pos).map(|tok| &tok.token_type) {
+            Some(TokenType::Reserved(Reserved { op: o, .. })) => o.as_str() == op,
+            Some(TokenType::Symbol(Symbol { sym, .. })) => sym.as_str() == op,
+            _ => false
+        };
+
+        if matched {
+            self.pos += 1;
+        }
+
+        matched
+    }
+}
@@ -1,5 +1,6 @@
 use crate::node::Stmt;
 use crate::_type::Type;
+use crate::tokenizer::loc::Loc;
 use std::rc::Rc;
 use std::cell::RefCell;
 
@@ -15,6 +16,11 @@ pub fn align_to(n: usize, align: usize) -> usize {
     (n + align - 1) & !(align - 1)
 }
 
+// the size of __va_area__ of a variadic function. it begins with a va_list
+// (gp_offset, fp_offset, overflow_arg_area and reg_save_area: 24 bytes),
+// followed by the register save area: rdi - r9 (48 bytes) and xmm0 - xmm7 (16 bytes each)
+pub const VA_AREA_SIZE: usize = 200;
+
 #[derive(Debug)]
 pub struct Program {
     pub fns: Vec<Function>,
@@ -33,7 +39,13 @@ pub struct Function {
     pub locals: Vec<Rc<RefCell<Var>>>,
     pub params: Vec<Rc<RefCell<Var>>>,
     pub stack_size: usize,
-    pub is_static: bool
+    // Type::Func(return type, param types)
+    pub ty: Box<Type>,
+    pub is_static: bool,
+    // location of the function name
+    pub loc: Option<Loc>,
+    // __va_area__ of a variadic function
+    pub va_area: Option<Rc<RefCell<Var>>>
 }
 
 impl Function {
@@ -42,7 +54,9 @@ impl Function {
         nodes: Vec<Stmt>,
         locals: Vec<Rc<RefCell<Var>>>,
         params: Vec<Rc<RefCell<Var>>>,
-        is_static: bool
+        ty: Box<Type>,
+        is_static: bool,
+        loc: Option<Loc>
     ) -> Self {
         let (fixed_locals, offset) = Self::calc_offsets(&locals);
 
@@ -52,7 +66,10 @@ impl Function {
             stack_size: align_to(offset, 8),
             locals: fixed_locals,
             params,
-            is_static
+            ty,
+            is_static,
+            loc,
+            va_area: None
         }
     }
 
@@ -94,7 +111,31 @@ pub struct Var {
     // global variables
     // Vec<u8> とかで持ったほうが良いかも
     // CStringも結局の所null文字をつかいたいだけなので
-    pub contents: Option<Vec<u8>>
+    pub contents: Option<Vec<u8>>,
+    // initial data of a global variable which has an initializer
+    pub init: Option<Vec<Initializer>>,
+    // location of the declarator. None for variables made by the compiler
+    pub loc: Option<Loc>
+}
+
+// global variable initializer, evaluated at compile time.
+// `int x[3] = {1, 2}; int *p = &x[1];` becomes
+// x -> [Val { sz: 4, val: 1 }, Val { sz: 4, val: 2 }, Zero(4)]
+// p -> [Label { name: "x", addend: 4 }]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Initializer {
+    // `sz` bytes integer
+    Val {
+        sz: usize,
+        val: isize
+    },
+    // address of another global variable
+    Label {
+        name: String,
+        addend: isize
+    },
+    // zero-filled bytes
+    Zero(usize)
 }
 
 #[derive(Debug, Clone, PartialEq)]
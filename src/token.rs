@@ -2,6 +2,7 @@ use crate::tokenizer::loc::Loc;
 
 pub mod token_type {
     use std::rc::Rc;
+    use crate::_type::Type;
 
     #[derive(Debug, Clone, PartialEq)]
     pub struct Reserved {
@@ -12,6 +13,17 @@ pub mod token_type {
     #[derive(Debug, Clone, PartialEq)]
     pub struct Num {
         pub val: isize,
+        pub fval: f64, // value of a floating-point literal
+        pub ty: Type, // type of the literal decided by its value and suffix
+        pub tk_str: Rc<String>
+    }
+
+    // a preprocessing number which is not a valid number, such as `32.h` in
+    // `#include <gnu/stubs-32.h>`. it is an error only if it is left after
+    // preprocessing
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct PpNum {
+        pub error: Rc<String>,
         pub tk_str: Rc<String>
     }
 
@@ -34,13 +46,14 @@ pub mod token_type {
     }
 }
 
-use token_type::{ Reserved, Num, Ident, Symbol, Str };
+use token_type::{ Reserved, Num, PpNum, Ident, Symbol, Str };
 use std::rc::Rc;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum TokenType {
     Reserved(Reserved),
     Num(Num),
+    PpNum(PpNum),
     Ident(Ident),
     Symbol(Symbol),
     Str(Str),
@@ -59,6 +72,7 @@ impl TokenType {
         match self {
             TokenType::Reserved(reserved) => Rc::clone(&reserved.tk_str),
             TokenType::Num(num) => Rc::clone(&num.tk_str),
+            TokenType::PpNum(num) => Rc::clone(&num.tk_str),
             TokenType::Ident(ident) => Rc::clone(&ident.tk_str),
             TokenType::Symbol(sym) => Rc::clone(&sym.tk_str),
             TokenType::Str(str_content) => Rc::clone(&str_content.tk_str),
@@ -70,16 +84,16 @@ impl TokenType {
 #[derive(Debug, Clone, PartialEq)]
 pub struct Token {
     pub token_type: TokenType,
-    pub loc: Loc
+    pub loc: Loc,
+    // true if this token is at the beginning of a line (used by the preprocessor)
+    pub at_bol: bool,
+    // true if this token follows a space character
+    pub has_space: bool
 }
 
 impl Token {
     pub fn new(token_type: TokenType, loc: Loc) -> Self {
-        Token { token_type, loc }
-    }
-
-    pub fn error_message<'a>(&self, msg: &'a str) -> String {
-        format!("{} {}", self.loc, msg)
+        Token { token_type, loc, at_bol: false, has_space: false }
     }
 }
 
@@ -3,34 +3,54 @@ pub mod loc;
 use crate::token::{ Token, TokenType };
 use crate::tokenizer::loc::Loc;
 use crate::token::token_type::*;
+use crate::_type::Type;
+use crate::diagnostic::{ Diagnostic, Severity, Span };
 
 use std::rc::Rc;
 
 // TODO: LexerErrorの定義
-const KEYWORDS: [&str; 19] = [
+const KEYWORDS: [&str; 32] = [
     "return",
     "if",
     "while",
+    "do",
     "else",
     "for",
     "int",
     "short",
     "long",
+    "signed",
+    "unsigned",
+    "float",
+    "double",
     "char",
     "void",
     "_Bool",
     "sizeof",
     "struct",
+    "union",
     "typedef",
     "enum",
     "static",
+    "extern",
+    "const",
+    "volatile",
+    "restrict",
     "break",
     "continue",
-    "goto"
+    "goto",
+    "switch",
+    "case",
+    "default"
 ];
 
 // multi-letter punctuator
-const MULTI_LETTER_PUNCTUACTORS: [&str; 13] = [
+const MULTI_LETTER_PUNCTUACTORS: [&str; 23] = [
+    "...",
+    "<<=",
+    ">>=",
+    "<<",
+    ">>",
     "==",
     "!=",
     "<=",
@@ -42,38 +62,64 @@ const MULTI_LETTER_PUNCTUACTORS: [&str; 13] = [
     "-=",
     "*=",
     "/=",
+    "%=",
+    "&=",
+    "|=",
+    "^=",
     "&&",
-    "||"
+    "||",
+    "##"
 ];
 
 pub struct Tokenizer {
     user_input: String,
+    // the characters of user_input, which are looked up by the position
+    chars: Vec<char>,
+    // name of the file being tokenized, which is recorded to `Loc`
+    filename: Rc<String>,
     current_col_index: usize,
     current_row_index: usize,
-    pos: usize
+    pos: usize,
+    // the next token is at the beginning of a line
+    at_bol: bool,
+    // the next token follows a space character
+    has_space: bool
 }
 
 impl<'a> Tokenizer {
-    pub fn new(user_input: String) -> Self {
+    pub fn new(user_input: String, filename: impl Into<String>) -> Self {
         Self {
-            user_input, current_col_index: 0, current_row_index: 0,
+            chars: user_input.chars().collect(),
+            user_input,
+            filename: Rc::new(filename.into()),
+            current_col_index: 0, current_row_index: 0,
             pos: 0,
+            at_bol: true,
+            has_space: false
         }
     }
 
-    pub fn tokenize(&mut self) -> Result<Vec<Token>, String> {
+    // an error is reported at the position where tokenizing failed
+    pub fn tokenize(&mut self) -> Result<Vec<Token>, Diagnostic> {
+        self.read_tokens().map_err(|msg| {
+            let loc = Loc::new(Rc::clone(&self.filename), self.row_number(), self.col_number());
+            Diagnostic::new(Severity::Error, msg, Some(Span::new(loc, 1)))
+        })
+    }
+
+    fn read_tokens(&mut self) -> Result<Vec<Token>, String> {
         let mut tokens = Vec::<Token>::new();
 
-        while self.pos <= self.user_input.len() - 1 {
+        while self.pos < self.user_input.len() {
             // line comment
+            // the trailing '\n' is left for the newline handling below
             if self.multi_get(2)
                 .map(|line_comment| line_comment == "//")
                 .unwrap_or(false) {
-                    while self.current().unwrap() != '\n' {
+                    while self.current().is_some_and(|c| c != '\n') {
                         self.increment_pos(1);
                     }
-                    self.current_col_index = 0;
-                    self.current_row_index += 1;
+                    self.has_space = true;
 
                     continue
             }
@@ -82,9 +128,21 @@ impl<'a> Tokenizer {
                 .map(|block_comment| block_comment == "/*")
                 .unwrap_or(false) {
                     self.block_comment()?;
+                    self.has_space = true;
 
                 continue
             }
+            // line continuation
+            if self.multi_get(2)
+                .map(|backslash_newline| backslash_newline == "\\\n")
+                .unwrap_or(false) {
+                    self.increment_pos(2);
+                    self.current_col_index = 0;
+                    self.current_row_index += 1;
+                    self.has_space = true;
+
+                    continue
+            }
 
             if let Some(punct) = self.starts_with_multi_letter_punct() {
                 self.increment_pos(punct.len());
@@ -102,7 +160,7 @@ impl<'a> Tokenizer {
 
             let c = self.current().expect("pos is out of user_input range");
             match c {
-                '=' | '!' | '<' | '>' | '+' | '-' | '*' | '&' | '/' | '~' | '|' | '^' => {
+                '=' | '!' | '<' | '>' | '+' | '-' | '*' | '&' | '/' | '%' | '~' | '|' | '^' | '#' | '?' => {
                     self.increment_pos(1);
 
                     let op = c.to_string();
@@ -114,6 +172,12 @@ impl<'a> Tokenizer {
                     let token_type = TokenType::Reserved(reserved);
                     tokens.push(self.new_token(token_type));
                 },
+                // floating-point literal starting with a period. e.g. .5
+                '.' if self.user_input[self.pos + 1 ..].starts_with(|c: char| c.is_ascii_digit()) => {
+                    let token_type = self.read_float_literal()?;
+
+                    tokens.push(self.new_token(token_type));
+                },
                 // symbol
                 '(' | ')' | ';' | '{' | '}' | '.' | ',' | '[' | ']' | ':' => {
                     self.increment_pos(1);
@@ -129,22 +193,18 @@ impl<'a> Tokenizer {
                 },
                 // string literal
                 '"' => {
+                    let start = self.pos;
                     let contents = self.read_string_literal();
                     match contents {
-                        Ok(c) => {
-                            let bytes = c.clone();
-                            let contents_string = String::from_utf8(c);
-                            match contents_string {
-                                Ok(cs) => {
-                                    let str_type = Str {
-                                        bytes,
-                                        tk_str: Rc::new(cs)
-                                    };
-
-                                    tokens.push(self.new_token(TokenType::Str(str_type)));
-                                },
-                                Err(e) => return Err(e.to_string())
-                            }
+                        Ok(bytes) => {
+                            // tk_str keeps the spelling in the source (with quotes and escapes)
+                            // so that the preprocessor can stringize it again
+                            let str_type = Str {
+                                bytes,
+                                tk_str: Rc::new(String::from(&self.user_input[start .. self.pos]))
+                            };
+
+                            tokens.push(self.new_token(TokenType::Str(str_type)));
                         },
                         Err(e) => {
                             let msg = format!("error occured in tokenizing string: {}", e);
@@ -154,11 +214,14 @@ impl<'a> Tokenizer {
                 },
                 // character literal
                 '\'' => {
+                    let start = self.pos;
                     let c = self.read_char_literal()?;
                     let token_type = TokenType::Num(
                         Num {
                             val: c as isize,
-                            tk_str: Rc::new(format!("'{}'", c as char))
+                            fval: 0.0,
+                            ty: Type::Int,
+                            tk_str: Rc::new(String::from(&self.user_input[start .. self.pos]))
                         }
                     );
 
@@ -166,7 +229,11 @@ impl<'a> Tokenizer {
                 }
                 // num
                 '0' ..= '9' => {
-                    let token_type = self.read_int_literal()?;
+                    let start = self.pos;
+                    let token_type = match self.read_number() {
+                        Ok(token_type) => token_type,
+                        Err(error) => self.read_pp_number(start, error)
+                    };
 
                     tokens.push(self.new_token(token_type));
                 },
@@ -175,10 +242,12 @@ impl<'a> Tokenizer {
                     self.increment_pos(1);
                     self.current_col_index = 0;
                     self.current_row_index += 1;
-
+                    self.at_bol = true;
+                    self.has_space = false;
                 }
                 ws if ws.is_whitespace() => {
                     self.increment_pos(1);
+                    self.has_space = true;
                     continue
                 },
                 // ident or reserved
@@ -215,13 +284,13 @@ impl<'a> Tokenizer {
     }
 
     fn multi_get(&self, n: usize) -> Option<&str> {
-        if self.pos + n >= self.user_input.len() { return None }
+        if self.pos + n > self.user_input.len() { return None }
 
         Some(&self.user_input[self.pos .. (self.pos + n)])
     }
 
     fn current(&self) -> Option<char> {
-        self.user_input.chars().nth(self.pos)
+        self.chars.get(self.pos).copied()
     }
 
     fn read_string_literal(&mut self) -> Result<Vec<u8>, String> {
@@ -237,7 +306,7 @@ impl<'a> Tokenizer {
                     self.increment_pos(1);
                     // push '\0'
                     str_content.push(0);
-                    break
+                    return Ok(str_content)
                 },
                 // escaped
                 '\\' => {
@@ -252,7 +321,7 @@ impl<'a> Tokenizer {
             }
         }
 
-        Ok(str_content)
+        Err("unclosed string literal".to_string())
     }
 
     fn read_escaped_literal(&mut self) -> u8 {
@@ -309,7 +378,9 @@ impl<'a> Tokenizer {
             self.increment_pos(1);
         }
 
-        isize::from_str_radix(&self.user_input[start .. self.pos], base)
+        // literals up to u64::MAX are allowed. e.g. 0xffffffffffffffff
+        u64::from_str_radix(&self.user_input[start .. self.pos], base)
+            .map(|val| val as isize)
             .or(Err("failed parsing num".to_string()))
     }
 
@@ -333,15 +404,21 @@ impl<'a> Tokenizer {
 
     fn block_comment(&mut self) -> Result<(), String> {
         while let Some(false) = self.multi_get(2).map(|char_slice| char_slice == "*/") {
+            if let Some('\n') = self.current() {
+                self.current_col_index = 0;
+                self.current_row_index += 1;
+                self.pos += 1;
+                continue
+            }
             self.increment_pos(1);
         }
 
-        self.increment_pos(2);
-
-        if self.pos >= self.user_input.len() - 1 {
+        if self.multi_get(2).is_none() {
             return Err("unclosed block comment".to_string())
         }
 
+        self.increment_pos(2);
+
         Ok(())
     }
 
@@ -359,8 +436,7 @@ impl<'a> Tokenizer {
     }
 
     fn is_ascii_alphanumeric(&self, pos: usize) -> bool {
-        self.user_input.chars()
-            .nth(self.pos + pos)
+        self.chars.get(self.pos + pos)
             .map(|c| c.is_ascii_alphanumeric())
             .unwrap_or(false)
     }
@@ -377,6 +453,97 @@ impl<'a> Tokenizer {
         }
     }
 
+    // a decimal number is a floating-point literal if it has a fraction or an exponent
+    fn read_number(&mut self) -> Result<TokenType, String> {
+        if let 2 | 16 = self.get_int_base_num() {
+            return self.read_int_literal()
+        }
+
+        let rest = &self.user_input[self.pos ..];
+        let digits = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+        match rest[digits ..].chars().next() {
+            Some('.') | Some('e') | Some('E') => self.read_float_literal(),
+            _ => self.read_int_literal()
+        }
+    }
+
+    // float-literal := digit* ("." digit*)? (("e" | "E") ("+" | "-")? digit+)? ("f" | "F" | "l" | "L")?
+    fn read_float_literal(&mut self) -> Result<TokenType, String> {
+        let start = self.pos;
+        self.skip_digits();
+        if let Some('.') = self.current() {
+            self.increment_pos(1);
+            self.skip_digits();
+        }
+        if let Some('e') | Some('E') = self.current() {
+            self.increment_pos(1);
+            if let Some('+') | Some('-') = self.current() {
+                self.increment_pos(1);
+            }
+            if !self.current().is_some_and(|c| c.is_ascii_digit()) {
+                return Err("exponent has no digits".to_string())
+            }
+            self.skip_digits();
+        }
+
+        let fval = self.user_input[start .. self.pos].parse::<f64>()
+            .or(Err("failed parsing floating-point number".to_string()))?;
+
+        let ty = match self.current() {
+            Some('f') | Some('F') => {
+                self.increment_pos(1);
+                Type::Float
+            },
+            // long double is treated as double
+            Some('l') | Some('L') => {
+                self.increment_pos(1);
+                Type::Double
+            },
+            _ => Type::Double
+        };
+
+        if self.current().is_some_and(|c| c.is_ascii_alphanumeric()) {
+            return Err("invalid digit".to_string())
+        }
+
+        Ok(TokenType::Num(
+            Num {
+                val: fval as isize,
+                fval,
+                ty,
+                tk_str: Rc::new(String::from(&self.user_input[start .. self.pos]))
+            }
+        ))
+    }
+
+    // pp-number := digit (digit | letter | "_" | "." | [eEpP] [+-])*
+    // the whole spelling of a number which failed to be read from `start`
+    fn read_pp_number(&mut self, start: usize, error: String) -> TokenType {
+        self.current_col_index -= self.pos - start;
+        self.pos = start;
+        while let Some(c) = self.current() {
+            let exponent = matches!(c, 'e' | 'E' | 'p' | 'P');
+            if exponent && self.user_input[self.pos + 1 ..].starts_with(&['+', '-'][..]) {
+                self.increment_pos(2);
+            } else if c.is_ascii_alphanumeric() || c == '_' || c == '.' {
+                self.increment_pos(1);
+            } else {
+                break
+            }
+        }
+
+        TokenType::PpNum(PpNum {
+            error: Rc::new(error),
+            tk_str: Rc::new(String::from(&self.user_input[start .. self.pos]))
+        })
+    }
+
+    fn skip_digits(&mut self) {
+        while self.current().is_some_and(|c| c.is_ascii_digit()) {
+            self.increment_pos(1);
+        }
+    }
+
     fn read_int_literal(&mut self) -> Result<TokenType, String> {
         let start = self.pos;
         let base = self.get_int_base_num();
@@ -386,6 +553,7 @@ impl<'a> Tokenizer {
         };
 
         let val = self.strtol(base as u32)?;
+        let ty = self.read_int_suffix(val, base)?;
 
         if self.current().map_or(false, |c| c.is_ascii_alphanumeric()) {
             return Err("invalid digit".to_string())
@@ -393,12 +561,54 @@ impl<'a> Tokenizer {
             Ok(TokenType::Num(
                 Num {
                     val,
+                    fval: 0.0,
+                    ty,
                     tk_str: Rc::new(String::from(&self.user_input[start .. self.pos]))
                 }
             ))
         }
     }
 
+    // read the suffix (u, l, ll and their combinations) of an integer literal
+    // and decide the type of the literal
+    fn read_int_suffix(&mut self, val: isize, base: usize) -> Result<Type, String> {
+        let start = self.pos;
+        while let Some('u') | Some('U') | Some('l') | Some('L') = self.current() {
+            self.increment_pos(1);
+        }
+        let suffix = &self.user_input[start .. self.pos];
+
+        let lower = suffix.to_lowercase();
+        if !["", "u", "l", "ll", "ul", "lu", "ull", "llu"].contains(&lower.as_str())
+            || suffix.contains("lL")
+            || suffix.contains("Ll") {
+            return Err(format!("invalid integer suffix: {}", suffix))
+        }
+        let is_unsigned = lower.contains('u');
+        let is_long = lower.contains('l');
+
+        let val = val as u64;
+        let ty = if is_unsigned && is_long {
+            Type::ULong
+        } else if is_long {
+            if base != 10 && val >> 63 > 0 { Type::ULong } else { Type::Long }
+        } else if is_unsigned {
+            if val >> 32 > 0 { Type::ULong } else { Type::UInt }
+        } else if base == 10 {
+            if val >> 31 > 0 { Type::Long } else { Type::Int }
+        } else if val >> 63 > 0 {
+            Type::ULong
+        } else if val >> 32 > 0 {
+            Type::Long
+        } else if val >> 31 > 0 {
+            Type::UInt
+        } else {
+            Type::Int
+        };
+
+        Ok(ty)
+    }
+
     pub fn col_number(&self) -> usize {
         self.current_col_index + 1
     }
@@ -407,12 +617,20 @@ impl<'a> Tokenizer {
         self.current_row_index + 1
     }
 
-    fn new_token(&self, token_type: TokenType) -> Token {
-        Token::new(
+    // called just after reading a token, so the location goes back by the length of it
+    fn new_token(&mut self, token_type: TokenType) -> Token {
+        let len = if token_type.at_eof() { 0 } else { token_type.tk_str().len() };
+        let col = self.col_number().saturating_sub(len).max(1);
+        let mut token = Token::new(
             token_type,
-            Loc::new
-            (self.row_number(), self.col_number())
-        )
+            Loc::new(Rc::clone(&self.filename), self.row_number(), col)
+        );
+        token.at_bol = self.at_bol;
+        token.has_space = self.has_space;
+        self.at_bol = false;
+        self.has_space = false;
+
+        token
     }
 
     fn increment_pos(&mut self, count: usize) {
@@ -420,3 +638,151 @@ impl<'a> Tokenizer {
         self.current_col_index += count;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token_types(input: &str) -> Vec<TokenType> {
+        let tokens = Tokenizer::new(input.to_string(), "test.c").tokenize()
+            .unwrap_or_else(|e| panic!("cannot tokenize {:?}: {}", input, e.message));
+
+        tokens.into_iter().map(|tok| tok.token_type).collect()
+    }
+
+    fn reserved(op: &str) -> TokenType {
+        TokenType::Reserved(Reserved { op: Rc::new(op.to_string()), tk_str: Rc::new(op.to_string()) })
+    }
+
+    fn ident(name: &str) -> TokenType {
+        TokenType::Ident(Ident { name: Rc::new(name.to_string()), tk_str: Rc::new(name.to_string()) })
+    }
+
+    fn symbol(sym: &str) -> TokenType {
+        TokenType::Symbol(Symbol { sym: Rc::new(sym.to_string()), tk_str: Rc::new(sym.to_string()) })
+    }
+
+    fn int(val: isize, ty: Type, tk_str: &str) -> TokenType {
+        TokenType::Num(Num { val, fval: 0.0, ty, tk_str: Rc::new(tk_str.to_string()) })
+    }
+
+    fn float(fval: f64, ty: Type, tk_str: &str) -> TokenType {
+        TokenType::Num(Num { val: fval as isize, fval, ty, tk_str: Rc::new(tk_str.to_string()) })
+    }
+
+    fn string(bytes: &[u8], tk_str: &str) -> TokenType {
+        TokenType::Str(Str { bytes: bytes.to_vec(), tk_str: Rc::new(tk_str.to_string()) })
+    }
+
+    // a malformed number is kept as a single token, which is an error only if it is left after preprocessing
+    fn assert_pp_number(input: &str) {
+        match token_types(input).as_slice() {
+            [TokenType::PpNum(PpNum { tk_str, .. }), TokenType::Eof] => assert_eq!(tk_str.as_str(), input),
+            types => panic!("{:?} is not a pp-number: {:?}", input, types)
+        }
+    }
+
+    #[test]
+    fn empty_input_is_eof() {
+        assert_eq!(token_types(""), vec![TokenType::Eof]);
+        assert_eq!(token_types(" \n\t"), vec![TokenType::Eof]);
+    }
+
+    #[test]
+    fn longest_punctuator_wins() {
+        assert_eq!(
+            token_types("a<<=b>>c<d"),
+            vec![ident("a"), reserved("<<="), ident("b"), reserved(">>"), ident("c"), reserved("<"), ident("d"), TokenType::Eof]
+        );
+        assert_eq!(
+            token_types("x%=y%z"),
+            vec![ident("x"), reserved("%="), ident("y"), reserved("%"), ident("z"), TokenType::Eof]
+        );
+        assert_eq!(
+            token_types("p->q...&&||"),
+            vec![ident("p"), reserved("->"), ident("q"), reserved("..."), reserved("&&"), reserved("||"), TokenType::Eof]
+        );
+        assert_eq!(token_types("a+++b"), vec![ident("a"), reserved("++"), reserved("+"), ident("b"), TokenType::Eof]);
+    }
+
+    #[test]
+    fn keywords_are_reserved() {
+        for keyword in KEYWORDS.iter() {
+            assert_eq!(token_types(keyword), vec![reserved(keyword), TokenType::Eof]);
+        }
+        assert_eq!(token_types("int_ returnx"), vec![ident("int_"), ident("returnx"), TokenType::Eof]);
+    }
+
+    #[test]
+    fn symbols() {
+        assert_eq!(
+            token_types("f(a[0]);{}"),
+            vec![
+                ident("f"), symbol("("), ident("a"), symbol("["), int(0, Type::Int, "0"), symbol("]"),
+                symbol(")"), symbol(";"), symbol("{"), symbol("}"), TokenType::Eof
+            ]
+        );
+    }
+
+    #[test]
+    fn int_literals() {
+        assert_eq!(token_types("42"), vec![int(42, Type::Int, "42"), TokenType::Eof]);
+        assert_eq!(token_types("0x1f"), vec![int(31, Type::Int, "0x1f"), TokenType::Eof]);
+        assert_eq!(token_types("0b101"), vec![int(5, Type::Int, "0b101"), TokenType::Eof]);
+        assert_eq!(token_types("017"), vec![int(15, Type::Int, "017"), TokenType::Eof]);
+        assert_eq!(token_types("2147483648"), vec![int(2147483648, Type::Long, "2147483648"), TokenType::Eof]);
+        assert_eq!(token_types("0xffffffff"), vec![int(0xffffffff, Type::UInt, "0xffffffff"), TokenType::Eof]);
+    }
+
+    #[test]
+    fn int_suffixes() {
+        assert_eq!(token_types("1u"), vec![int(1, Type::UInt, "1u"), TokenType::Eof]);
+        assert_eq!(token_types("1L"), vec![int(1, Type::Long, "1L"), TokenType::Eof]);
+        assert_eq!(token_types("1ll"), vec![int(1, Type::Long, "1ll"), TokenType::Eof]);
+        assert_eq!(token_types("1LLU"), vec![int(1, Type::ULong, "1LLU"), TokenType::Eof]);
+        assert_eq!(token_types("1ul"), vec![int(1, Type::ULong, "1ul"), TokenType::Eof]);
+        assert_pp_number("1lL");
+        assert_pp_number("1uu");
+        assert_pp_number("12ab");
+    }
+
+    #[test]
+    fn float_literals() {
+        assert_eq!(token_types("1.5"), vec![float(1.5, Type::Double, "1.5"), TokenType::Eof]);
+        assert_eq!(token_types(".5f"), vec![float(0.5, Type::Float, ".5f"), TokenType::Eof]);
+        assert_eq!(token_types("1e3"), vec![float(1000.0, Type::Double, "1e3"), TokenType::Eof]);
+        assert_eq!(token_types("2.5E-1L"), vec![float(0.25, Type::Double, "2.5E-1L"), TokenType::Eof]);
+        assert_pp_number("1e+");
+        assert_pp_number("32.h");
+    }
+
+    #[test]
+    fn char_literals() {
+        assert_eq!(token_types("'a'"), vec![int(97, Type::Int, "'a'"), TokenType::Eof]);
+        assert_eq!(token_types("'\\n'"), vec![int(10, Type::Int, "'\\n'"), TokenType::Eof]);
+    }
+
+    #[test]
+    fn string_literals() {
+        // the bytes include the terminating nul
+        assert_eq!(token_types("\"abc\""), vec![string(b"abc\0", "\"abc\""), TokenType::Eof]);
+        assert_eq!(token_types("\"a\\tb\\\"\""), vec![string(b"a\tb\"\0", "\"a\\tb\\\"\""), TokenType::Eof]);
+        assert!(Tokenizer::new("\"abc".to_string(), "test.c").tokenize().is_err());
+    }
+
+    #[test]
+    fn comments_are_skipped() {
+        assert_eq!(
+            token_types("a // b\n/* c\nd */ e"),
+            vec![ident("a"), ident("e"), TokenType::Eof]
+        );
+        assert!(Tokenizer::new("/* a".to_string(), "test.c").tokenize().is_err());
+    }
+
+    #[test]
+    fn line_flags() {
+        let tokens = Tokenizer::new("a b\n c".to_string(), "test.c").tokenize().unwrap();
+        let flags: Vec<(bool, bool)> = tokens.iter().map(|tok| (tok.at_bol, tok.has_space)).collect();
+        assert_eq!(flags[.. 3], [(true, false), (false, true), (true, true)]);
+    }
+}
@@ -1,21 +1,24 @@
 use std::fmt;
 use std::fmt::Display;
+use std::rc::Rc;
 
-// token location
+// token location. `row` and `col` start from 1 and point to the first
+// character of the token
 #[derive(Debug, Clone, PartialEq)]
 pub struct Loc {
+    pub file: Rc<String>,
     pub row: usize,
     pub col: usize
 }
 
 impl Loc {
-    pub fn new(row: usize, col: usize) -> Self {
-        Self { row, col }
+    pub fn new(file: Rc<String>, row: usize, col: usize) -> Self {
+        Self { file, row, col }
     }
 }
 
 impl Display for Loc {
     fn fmt(&self, f: &mut fmt::Formatter) -> std::result::Result<(), std::fmt::Error> {
-        write!(f, "{}:{}:", self.row, self.col)
+        write!(f, "{}:{}:{}", self.file, self.row, self.col)
     }
 }
@@ -0,0 +1,437 @@
+use crate::node::{ Stmt, StmtKind, Expr, ExprWrapper };
+use crate::program::{ Program, Function, Var };
+use crate::diagnostic::{ Diagnostic, Severity, Span };
+use crate::_type::Type;
+
+use std::collections::HashSet;
+use std::rc::Rc;
+use std::cell::RefCell;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Warning {
+    UnusedVariable,
+    UnusedParameter,
+    ImplicitFunctionDeclaration,
+    ReturnType,
+    PointerIntegerCompare,
+    UnreachableCode
+}
+
+impl Warning {
+    pub const ALL: [Warning; 6] = [
+        Warning::UnusedVariable,
+        Warning::UnusedParameter,
+        Warning::ImplicitFunctionDeclaration,
+        Warning::ReturnType,
+        Warning::PointerIntegerCompare,
+        Warning::UnreachableCode
+    ];
+
+    // the name used by -W<name> and -Wno-<name>
+    pub fn name(&self) -> &'static str {
+        match self {
+            Warning::UnusedVariable => "unused-variable",
+            Warning::UnusedParameter => "unused-parameter",
+            Warning::ImplicitFunctionDeclaration => "implicit-function-declaration",
+            Warning::ReturnType => "return-type",
+            Warning::PointerIntegerCompare => "pointer-integer-compare",
+            Warning::UnreachableCode => "unreachable-code"
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Warning::ALL.iter().find(|w| w.name() == name).copied()
+    }
+
+    // enabled without any -W option
+    fn is_default(&self) -> bool {
+        matches!(self, Warning::ImplicitFunctionDeclaration | Warning::PointerIntegerCompare)
+    }
+
+    fn in_wall(&self) -> bool {
+        !matches!(self, Warning::UnusedParameter | Warning::UnreachableCode)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct WarningOptions {
+    enabled: HashSet<Warning>,
+    // -Werror turns all warnings into errors
+    pub werror: bool
+}
+
+impl Default for WarningOptions {
+    fn default() -> Self {
+        Self {
+            enabled: Warning::ALL.iter().filter(|w| w.is_default()).copied().collect(),
+            werror: false
+        }
+    }
+}
+
+impl WarningOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // apply an option such as -Wall, -Wextra, -Werror, -W<name> or -Wno-<name>.
+    // an unknown -Wno-<name> is ignored as gcc does, and an unknown -W<name> is
+    // returned as an error, which the driver reports as a warning
+    pub fn apply(&mut self, opt: &str) -> Result<(), String> {
+        let name = opt.strip_prefix("-W").ok_or_else(|| format!("not a warning option: {}", opt))?;
+
+        match name {
+            "all" => self.enabled.extend(Warning::ALL.iter().filter(|w| w.in_wall())),
+            // -Wextra enables the rest in addition to -Wall
+            "extra" => self.enabled.extend(Warning::ALL.iter()),
+            "error" => self.werror = true,
+            "no-error" => self.werror = false,
+            _ => {
+                let (name, enable) = match name.strip_prefix("no-") {
+                    Some(name) => (name, false),
+                    None => (name, true)
+                };
+                match Warning::from_name(name) {
+                    Some(warning) if enable => { self.enabled.insert(warning); },
+                    Some(warning) => { self.enabled.remove(&warning); },
+                    None if enable => return Err(format!("unknown warning option: {}", opt)),
+                    None => {}
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn is_enabled(&self, warning: Warning) -> bool {
+        self.enabled.contains(&warning)
+    }
+
+    // drop disabled warnings. with -Werror the remaining ones become errors
+    pub fn filter(&self, diags: Vec<Diagnostic>) -> Vec<Diagnostic> {
+        diags.into_iter()
+            .filter(|diag| {
+                diag.severity != Severity::Warning
+                    || diag.flag.and_then(Warning::from_name).is_none_or(|w| self.is_enabled(w))
+            })
+            .map(|mut diag| {
+                if self.werror && diag.severity == Severity::Warning {
+                    diag.severity = Severity::Error;
+                }
+                diag
+            })
+            .collect()
+    }
+}
+
+// find warnings in the program. all kinds of warnings are reported regardless of
+// the options, so the result should be passed to `WarningOptions::filter`
+pub fn check(prog: &Program) -> Vec<Diagnostic> {
+    let mut diags = Vec::new();
+
+    for func in &prog.fns {
+        check_unused(func, &mut diags);
+        check_return(func, &mut diags);
+        check_unreachable(func, &func.nodes, &mut diags);
+    }
+
+    diags
+}
+
+fn var_span(var: &Var) -> Option<Span> {
+    var.loc.as_ref().map(|loc| Span::new(loc.clone(), var.name.len()))
+}
+
+fn func_span(func: &Function) -> Option<Span> {
+    func.loc.as_ref().map(|loc| Span::new(loc.clone(), func.name.len()))
+}
+
+// the first character of the statement
+fn stmt_span(stmt: &Stmt) -> Option<Span> {
+    stmt.loc.as_ref().map(|loc| Span::new(loc.clone(), 1))
+}
+
+fn check_unused(func: &Function, diags: &mut Vec<Diagnostic>) {
+    let mut used = HashSet::new();
+    for stmt in &func.nodes {
+        collect_used_stmt(stmt, &mut used);
+    }
+
+    // locals are stored in the reverse order of their declarations
+    for var in func.locals.iter().rev() {
+        if used.contains(&Rc::as_ptr(var)) {
+            continue
+        }
+
+        let is_param = func.params.iter().any(|param| Rc::ptr_eq(param, var));
+        let var = var.borrow();
+        // such as __va_area__, which is made by the compiler
+        if var.loc.is_none() {
+            continue
+        }
+        if is_param {
+            let msg = format!("unused parameter '{}'", var.name);
+            diags.push(Diagnostic::warning(Warning::UnusedParameter.name(), msg, var_span(&var)));
+        } else {
+            let msg = format!("unused variable '{}'", var.name);
+            diags.push(Diagnostic::warning(Warning::UnusedVariable.name(), msg, var_span(&var)));
+        }
+    }
+}
+
+// collect variables whose values are used.
+// storing to a variable (including its initialization) is not a use
+fn collect_used_stmt(stmt: &Stmt, used: &mut HashSet<*const RefCell<Var>>) {
+    match &stmt.kind {
+        StmtKind::Return { val }
+        | StmtKind::ExprStmt { val }
+        | StmtKind::PureExpr(val) => collect_used(val, used),
+        StmtKind::If { cond, then, els } => {
+            collect_used(cond, used);
+            collect_used_stmt(then, used);
+            if let Some(els) = els {
+                collect_used_stmt(els, used);
+            }
+        },
+        StmtKind::While { cond, then }
+        | StmtKind::DoWhile { then, cond }
+        | StmtKind::Switch { cond, then, .. } => {
+            collect_used(cond, used);
+            collect_used_stmt(then, used);
+        },
+        StmtKind::For { init, cond, inc, then } => {
+            if let Some(init) = init.as_ref() {
+                collect_used_stmt(init, used);
+            }
+            if let Some(cond) = cond {
+                collect_used(cond, used);
+            }
+            if let Some(inc) = inc.as_ref() {
+                collect_used_stmt(inc, used);
+            }
+            collect_used_stmt(then, used);
+        },
+        StmtKind::Block { stmts } => stmts.iter().for_each(|stmt| collect_used_stmt(stmt, used)),
+        StmtKind::Label(stmt, _)
+        | StmtKind::Case { stmt, .. }
+        | StmtKind::Default(stmt) => collect_used_stmt(stmt, used),
+        StmtKind::Break | StmtKind::Continue | StmtKind::Goto(_) => {}
+    }
+}
+
+fn collect_used(ew: &ExprWrapper, used: &mut HashSet<*const RefCell<Var>>) {
+    match ew.expr.as_ref() {
+        Expr::Var(var) => {
+            used.insert(Rc::as_ptr(var));
+        },
+        // the variable itself is not read by `x = ...`
+        Expr::Assign { var, val } => {
+            if let Expr::Var(_) = var.expr.as_ref() {} else {
+                collect_used(var, used);
+            }
+            collect_used(val, used);
+        },
+        Expr::Eq { lhs, rhs }
+        | Expr::Neq { lhs, rhs }
+        | Expr::Gt { lhs, rhs }
+        | Expr::Ge { lhs, rhs }
+        | Expr::Lt { lhs, rhs }
+        | Expr::Le { lhs, rhs }
+        | Expr::Add { lhs, rhs }
+        | Expr::Sub { lhs, rhs }
+        | Expr::Mul { lhs, rhs }
+        | Expr::Div { lhs, rhs }
+        | Expr::Mod { lhs, rhs }
+        | Expr::BitAnd { lhs, rhs }
+        | Expr::BitOr { lhs, rhs }
+        | Expr::BitXor { lhs, rhs }
+        | Expr::Shl { lhs, rhs }
+        | Expr::Shr { lhs, rhs }
+        | Expr::LogAnd { lhs, rhs }
+        | Expr::LogOr { lhs, rhs }
+        | Expr::PtrAdd { lhs, rhs }
+        | Expr::PtrSub { lhs, rhs }
+        | Expr::PtrDiff { lhs, rhs }
+        | Expr::AddEq { var: lhs, val: rhs }
+        | Expr::PtrAddEq { var: lhs, val: rhs }
+        | Expr::SubEq { var: lhs, val: rhs }
+        | Expr::PtrSubEq { var: lhs, val: rhs }
+        | Expr::MulEq { var: lhs, val: rhs }
+        | Expr::DivEq { var: lhs, val: rhs }
+        | Expr::ModEq { var: lhs, val: rhs }
+        | Expr::BitAndEq { var: lhs, val: rhs }
+        | Expr::BitOrEq { var: lhs, val: rhs }
+        | Expr::BitXorEq { var: lhs, val: rhs }
+        | Expr::ShlEq { var: lhs, val: rhs }
+        | Expr::ShrEq { var: lhs, val: rhs } => {
+            collect_used(lhs, used);
+            collect_used(rhs, used);
+        },
+        Expr::Cast(_, operand)
+        | Expr::PreInc(operand)
+        | Expr::PreDec(operand)
+        | Expr::PostInc(operand)
+        | Expr::PostDec(operand)
+        | Expr::Not(operand)
+        | Expr::BitNot(operand)
+        | Expr::Addr { operand }
+        | Expr::Deref { operand }
+        | Expr::Member(operand, _) => collect_used(operand, used),
+        Expr::Comma { lhs, rhs } => {
+            collect_used_stmt(lhs, used);
+            collect_used(rhs, used);
+        },
+        Expr::Cond { cond, then, els } => {
+            collect_used(cond, used);
+            collect_used(then, used);
+            collect_used(els, used);
+        },
+        Expr::FnCall { callee, args, .. } => {
+            collect_used(callee, used);
+            args.iter().for_each(|arg| collect_used(arg, used));
+        },
+        Expr::StmtExpr(stmts) => stmts.iter().for_each(|stmt| collect_used_stmt(stmt, used)),
+        Expr::Num { .. } | Expr::FNum { .. } | Expr::Null | Expr::MemZero(_) => {}
+    }
+}
+
+fn check_return(func: &Function, diags: &mut Vec<Diagnostic>) {
+    // main returns 0 if it reaches the end (C99 5.1.2.2.3)
+    if func.name.as_str() == "main" {
+        return
+    }
+    if let Type::Func { ret, .. } = func.ty.as_ref() {
+        if let Type::Void = ret.as_ref() {
+            return
+        }
+    }
+
+    if falls_through(&func.nodes) {
+        let msg = format!("control reaches end of non-void function '{}'", func.name);
+        diags.push(Diagnostic::warning(Warning::ReturnType.name(), msg, func_span(func)));
+    }
+}
+
+// true if the control may reach the end of the statements
+fn falls_through(stmts: &[Stmt]) -> bool {
+    let mut reachable = true;
+    for stmt in stmts {
+        // a labeled statement can be reached by a jump
+        if reachable || is_labeled(stmt) {
+            reachable = stmt_falls_through(stmt);
+        }
+    }
+
+    reachable
+}
+
+fn stmt_falls_through(stmt: &Stmt) -> bool {
+    match &stmt.kind {
+        StmtKind::Return { .. } | StmtKind::Goto(_) | StmtKind::Break | StmtKind::Continue => false,
+        StmtKind::Block { stmts } => falls_through(stmts),
+        StmtKind::If { then, els: Some(els), .. } => stmt_falls_through(then) || stmt_falls_through(els),
+        // an infinite loop is left only by break
+        StmtKind::While { cond, then } if is_true_const(cond) => has_break(then),
+        StmtKind::For { cond: None, then, .. } => has_break(then),
+        StmtKind::For { cond: Some(cond), then, .. } if is_true_const(cond) => has_break(then),
+        StmtKind::DoWhile { cond, then } if is_true_const(cond) => has_break(then),
+        StmtKind::Switch { then, has_default: true, .. } => stmt_falls_through(then) || has_break(then),
+        StmtKind::Label(stmt, _)
+        | StmtKind::Case { stmt, .. }
+        | StmtKind::Default(stmt) => stmt_falls_through(stmt),
+        _ => true
+    }
+}
+
+fn is_labeled(stmt: &Stmt) -> bool {
+    matches!(&stmt.kind, StmtKind::Label(_, _) | StmtKind::Case { .. } | StmtKind::Default(_))
+}
+
+fn is_true_const(ew: &ExprWrapper) -> bool {
+    match ew.expr.as_ref() {
+        Expr::Num { val, .. } => *val != 0,
+        _ => false
+    }
+}
+
+// true if `stmt` has a break which leaves `stmt` itself
+fn has_break(stmt: &Stmt) -> bool {
+    match &stmt.kind {
+        StmtKind::Break => true,
+        StmtKind::Block { stmts } => {
+            // an unreachable break, such as `return x; break;`, does not count
+            let mut reachable = true;
+            for stmt in stmts {
+                if reachable || is_labeled(stmt) {
+                    if has_break(stmt) {
+                        return true
+                    }
+                    reachable = stmt_falls_through(stmt);
+                }
+            }
+            false
+        },
+        StmtKind::If { then, els, .. } => has_break(then) || els.as_ref().is_some_and(|els| has_break(els)),
+        StmtKind::Label(stmt, _)
+        | StmtKind::Case { stmt, .. }
+        | StmtKind::Default(stmt) => has_break(stmt),
+        // break in a nested loop or switch leaves it instead
+        _ => false
+    }
+}
+
+// warn a statement after return, goto or continue in the same block.
+// it is reported once per block
+fn check_unreachable(func: &Function, stmts: &[Stmt], diags: &mut Vec<Diagnostic>) {
+    let mut jump = None;
+    for stmt in stmts {
+        if is_labeled(stmt) {
+            jump = None;
+        } else if let Some(keyword) = jump {
+            // a declaration without an initializer is not code, and
+            // `return x; break;` is common in switch statements
+            let is_code = match &stmt.kind {
+                StmtKind::ExprStmt { val } => *val.expr != Expr::Null,
+                StmtKind::Break => false,
+                _ => true
+            };
+            if is_code {
+                let msg = format!("unreachable code after '{}' in function '{}'", keyword, func.name);
+                let span = stmt_span(stmt).or_else(|| func_span(func));
+                diags.push(Diagnostic::warning(Warning::UnreachableCode.name(), msg, span));
+                break
+            }
+        }
+
+        jump = match &stmt.kind {
+            StmtKind::Return { .. } => Some("return"),
+            StmtKind::Goto(_) => Some("goto"),
+            StmtKind::Continue => Some("continue"),
+            _ => jump
+        };
+    }
+
+    for stmt in stmts {
+        check_unreachable_stmt(func, stmt, diags);
+    }
+}
+
+fn check_unreachable_stmt(func: &Function, stmt: &Stmt, diags: &mut Vec<Diagnostic>) {
+    match &stmt.kind {
+        StmtKind::Block { stmts } => check_unreachable(func, stmts, diags),
+        StmtKind::If { then, els, .. } => {
+            check_unreachable_stmt(func, then, diags);
+            if let Some(els) = els {
+                check_unreachable_stmt(func, els, diags);
+            }
+        },
+        StmtKind::While { then, .. }
+        | StmtKind::DoWhile { then, .. }
+        | StmtKind::For { then, .. }
+        | StmtKind::Switch { then, .. }
+        | StmtKind::Label(then, _)
+        | StmtKind::Case { stmt: then, .. }
+        | StmtKind::Default(then) => check_unreachable_stmt(func, then, diags),
+        _ => {}
+    }
+}
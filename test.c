@@ -6,14 +6,49 @@
  * This is a block comment.
  */
 
-int printf();
-int exit();
+#include "test/test.h"
+#include "test/include1.h"
+#include <stdarg.h>
 
 int g1;
 int g2[4];
 
 typedef int MyInt;
 
+char g3 = 3;
+short g4 = 4;
+int g5 = 5;
+long g6 = 6;
+int g7[3] = {0, 1, 2};
+struct {char a; int b;} g8[2] = {{1, 2}, {3, 4}};
+struct {int a[2];} g9[2] = {{{1, 2}}};
+int g10[] = {1, 2, 3};
+char g11[] = "foobar";
+char *g12 = g11 + 3;
+char *g13 = "abc";
+int *g14 = &g7[1];
+int *g15 = g7 + 2;
+long g16 = (long)&g5;
+int g17[3] = {[1] = 5, 6};
+struct {int a; int b; int c;} g18 = {.c = 3, .a = 1};
+_Bool g19 = 5;
+int g20 = 3 * 4 + 1;
+int g21[2][2] = {1, 2, 3};
+int g22 = 1 ? 5 : 6;
+unsigned char g23 = 200;
+int g24 = (unsigned)-1 > 0;
+double g25 = 1.5;
+float g26 = 2.5f;
+int g27 = 2.9;
+double g28[3] = {1, 2.5, 3 / 2.0};
+unsigned g29 = 4294967295u + 2;
+long g30 = 2147483647 + 1u;
+int g_carr[sizeof(g7) / sizeof(int) + 7];
+enum { G_TWO = 2 } g_enum;
+int g_cinit = (char)(G_TWO + 257);
+unsigned long g_u64 = 1e19;
+unsigned long g_u64_cast = (unsigned long)1.5e19 / 2;
+
 int assert(long expected, long actual, char *code) {
   if (expected == actual) {
     printf("%s => %ld\n", code, actual);
@@ -56,6 +91,30 @@ int sub_long(long a, long b, long c) {
   return a - b - c;
 }
 
+double add_double(double x, double y) {
+  return x + y;
+}
+
+float add_float(float x, float y) {
+  return x + y;
+}
+
+double add_mixed(int a, double b, long c, float d) {
+  return a + b + c + d;
+}
+
+int double_to_int(double x) {
+  return x;
+}
+
+double int_to_double(int x) {
+  return x;
+}
+
+int strcmp();
+int snprintf();
+double strtod();
+
 int *g1_ptr() {
   return &g1;
 }
@@ -70,6 +129,85 @@ static int static_fn() { return 3; }
 
 int param_decay(int x[]) { return x[0]; }
 
+int add10(int a, int b, int c, int d, int e, int f, int g, int h, int i, int j) {
+  return a+b*2+c*3+d*4+e*5+f*6+g*7+h*8+i*9+j*10;
+}
+
+double add_stack_mixed(double a, double b, double c, double d, double e, double f, double g, double h,
+                       float i, long j, long k, long l, long m, long n, long o, char p) {
+  return a+b+c+d+e+f+g+h+i*10+j+k+l+m+n+o+p*100;
+}
+
+int sum_va(int n, ...) {
+  va_list ap;
+  va_start(ap, n);
+  int sum = 0;
+  for (int i = 0; i < n; i++)
+    sum = sum + va_arg(ap, int);
+  va_end(ap);
+  return sum;
+}
+
+double fsum_va(int n, ...) {
+  va_list ap;
+  va_start(ap, n);
+  double sum = 0;
+  for (int i = 0; i < n; i++)
+    sum = sum + va_arg(ap, double) * va_arg(ap, long);
+  va_end(ap);
+  return sum;
+}
+
+int vsprintf();
+
+char *fmt_va(char *buf, char *fmt, ...) {
+  va_list ap;
+  va_start(ap, fmt);
+  vsprintf(buf, fmt, ap);
+  va_end(ap);
+  return buf;
+}
+
+struct tree;
+struct tree *root;
+struct tree { int val; struct tree *lhs; struct tree *rhs; };
+union IntChars { int i; char c[4]; };
+union IntChars gunion1 = { 0x01020304 };
+union IntChars gunion2 = { .c = {1, 2} };
+struct { unsigned a : 3; int b : 5; char c; long d : 40; _Bool e : 1; } gbits = { 5, -3, 7, 123456789012, 1 };
+
+int tree_sum(struct tree *t) { return t ? t->val + tree_sum(t->lhs) + tree_sum(t->rhs) : 0; }
+
+int fp_add(int a, int b) { return a + b; }
+int fp_sub(int a, int b) { return a - b; }
+int fp_apply(int (*f)(int, int), int a, int b) { return f(a, b); }
+int (*fp_pick(int i))(int, int) { return i ? fp_sub : fp_add; }
+int fp_void(void) { return 7; }
+int (*g_fp)(void) = fp_void;
+int (*g_fp_addr)(int, int) = &fp_add;
+int (*g_fp_arr[2])(int, int) = {fp_add, fp_sub};
+struct { int (*op)(int, int); } g_fp_struct = {fp_sub};
+
+typedef struct { int a; int b; } IntPair;
+typedef struct { char a; double d; } CharDouble;
+typedef struct { float x; float y; float z; } Float3;
+typedef struct { long a; long b; long c; } Long3;
+typedef struct { char c[3]; } Char3;
+
+IntPair mk_pair(int a, int b) { IntPair p; p.a = a; p.b = b; return p; }
+int pair_diff(IntPair p) { return p.a - p.b; }
+CharDouble mk_char_double(char a, double d) { CharDouble x; x.a = a; x.d = d; return x; }
+double char_double_sum(CharDouble x) { return x.a + x.d; }
+Float3 mk_float3(float x) { Float3 f; f.x = x; f.y = x * 2; f.z = x * 3; return f; }
+float float3_sum(Float3 f) { return f.x + f.y + f.z; }
+Long3 mk_long3(long a) { Long3 l; l.a = a; l.b = a + 1; l.c = a + 2; return l; }
+long long3_sum(Long3 l) { return l.a + l.b * 10 + l.c * 100; }
+Char3 mk_char3(char a) { Char3 c; c.c[0] = a; c.c[1] = a + 1; c.c[2] = a + 2; return c; }
+int char3_sum(Char3 c) { return c.c[0] + c.c[1] * 2 + c.c[2] * 3; }
+long struct_stack(int a, int b, int c, int d, int e, IntPair p, IntPair q, Long3 l, Char3 x) {
+  return a + b + c + d + e + p.a * 10 + q.b * 100 + l.c * 1000 + x.c[2] * 10000;
+}
+
 int main() {
   assert(8, ({ int a=3; int z=5; a+z; }), "int a=3; int z=5; a+z;");
 
@@ -180,7 +318,7 @@ int main() {
   assert(4, ({ int x[3][4]; sizeof(**x); }), "int x[3][4]; sizeof(**x);");
   assert(5, ({ int x[3][4]; sizeof(**x) + 1; }), "int x[3][4]; sizeof(**x) + 1;");
   assert(5, ({ int x[3][4]; sizeof **x + 1; }), "int x[3][4]; sizeof **x + 1;");
-  assert(8, ({ int x[3][4]; sizeof(**x + 1); }), "int x[3][4]; sizeof(**x + 1);");
+  assert(4, ({ int x[3][4]; sizeof(**x + 1); }), "int x[3][4]; sizeof(**x + 1);");
 
   assert(0, g1, "g1");
   g1=3;
@@ -442,6 +580,488 @@ int main() {
   assert(2, ({ int i=0; goto e; d: i++; e: i++; f: i++; i; }), "int i=0; goto d; d: i++; e: i++; f: i++; i;");
   assert(1, ({ int i=0; goto i; g: i++; h: i++; i: i++; i; }), "int i=0; goto g; h: i++; i: i++; j: i++; i;");
 
+  assert(5, INCLUDE1, "INCLUDE1");
+
+#define M1 3
+  assert(3, M1, "M1");
+#define M1 4
+  assert(4, M1, "M1");
+#undef M1
+  assert(1, ({ int M1=1; M1; }), "int M1=1; M1;");
+
+#define M2(x, y) x + y
+  assert(7, M2(3, 4), "M2(3, 4)");
+  assert(11, M2(3, 4) * 2, "M2(3, 4) * 2");
+  assert(14, (M2(3, 4)) * 2, "(M2(3, 4)) * 2");
+  assert(10, M2((1, 2), 8), "M2((1, 2), 8)");
+
+#define M3() 5
+  assert(5, M3(), "M3()");
+  assert(3, ({ int M3=3; M3; }), "int M3=3; M3;");
+
+  int M4=2;
+#define M4 M4 + 1
+  assert(3, M4, "M4");
+#undef M4
+
+#define M5(x) x * 2
+  assert(12, M5(M5(3)), "M5(M5(3))");
+
+#define M6 M7
+#define M7(x) x * 2
+  assert(8, M6(4), "M6(4)");
+
+  ASSERT(97, "a"[0]);
+
+#define CAT(x, y) x##y
+  assert(5, ({ int foo=5; CAT(fo, o); }), "int foo=5; CAT(fo, o);");
+  assert(12, CAT(1, 2), "CAT(1, 2)");
+  assert(3, ({ int x=1; x CAT(+, =) 2; x; }), "int x=1; x CAT(+, =) 2; x;");
+
+#define STR(x) #x
+  assert(0, STR(abc)[3], "STR(abc)[3]");
+  assert(99, STR(abc)[2], "STR(abc)[2]");
+  assert(34, STR("a")[0], "STR(\"a\")[0]");
+  assert(6, sizeof(STR(a  +  b)), "sizeof(STR(a  +  b))");
+
+#define VA(...) add6(__VA_ARGS__)
+  assert(21, VA(1, 2, 3, 4, 5, 6), "VA(1, 2, 3, 4, 5, 6)");
+#define VA2(x, ...) x + add6(__VA_ARGS__)
+  assert(31, VA2(10, 1, 2, 3, 4, 5, 6), "VA2(10, 1, 2, 3, 4, 5, 6)");
+
+  int m = 0;
+#if 1
+  m = 1;
+#endif
+  assert(1, m, "#if 1");
+
+#if 0
+  m = 2;
+#if 1
+  m = 3;
+#endif
+  this is not compiled #
+#endif
+  assert(1, m, "#if 0");
+
+#if 1 + 1 == 3
+  m = 4;
+#elif 2 * (3 - 1) == 4 && !0
+  m = 5;
+#elif 1
+  m = 6;
+#else
+  m = 7;
+#endif
+  assert(5, m, "#elif");
+
+#if 0
+  m = 8;
+#else
+  m = 9;
+#endif
+  assert(9, m, "#else");
+
+#ifdef M2
+  m = 10;
+#endif
+  assert(10, m, "#ifdef");
+
+#ifndef M2
+  m = 11;
+#else
+  m = 12;
+#endif
+  assert(12, m, "#ifndef");
+
+#if defined(M2) && defined M3 && !defined(M1) && UNDEFINED_NAME == 0
+  m = 13;
+#endif
+  assert(13, m, "defined");
+
+#define ONE 1
+#if ONE + ONE == 2
+  m = 14;
+#endif
+  assert(14, m, "#if with macro");
+
+#if ONE ? 0 : 1
+  m = 15;
+#elif (ONE ? 2 : 3) == 2
+  m = 16;
+#endif
+  assert(16, m, "#if with ?:");
+
+  assert(__LINE__, __LINE__, "__LINE__");
+  assert(95, STR(__LINE__)[0], "STR(__LINE__)[0]");
+
+  assert(1, ({ int x[3]={1,2,3}; x[0]; }), "int x[3]={1,2,3}; x[0];");
+  assert(2, ({ int x[3]={1,2,3}; x[1]; }), "int x[3]={1,2,3}; x[1];");
+  assert(3, ({ int x[3]={1,2,3}; x[2]; }), "int x[3]={1,2,3}; x[2];");
+  assert(2, ({ int x[2][3]={{1,2,3},{4,5,6}}; x[0][1]; }), "int x[2][3]={{1,2,3},{4,5,6}}; x[0][1];");
+  assert(4, ({ int x[2][3]={{1,2,3},{4,5,6}}; x[1][0]; }), "int x[2][3]={{1,2,3},{4,5,6}}; x[1][0];");
+  assert(6, ({ int x[2][3]={{1,2,3},{4,5,6}}; x[1][2]; }), "int x[2][3]={{1,2,3},{4,5,6}}; x[1][2];");
+  assert(0, ({ int x[3]={}; x[0]; }), "int x[3]={}; x[0];");
+  assert(0, ({ int x[3]={1}; x[2]; }), "int x[3]={1}; x[2];");
+  assert(0, ({ int x[2][3]={{1,2}}; x[1][2]; }), "int x[2][3]={{1,2}}; x[1][2];");
+  assert(4, ({ int x[2][2]={1,2,3,4}; x[1][1]; }), "int x[2][2]={1,2,3,4}; x[1][1];");
+  assert(3, ({ int x[]={1,2,3}; x[2]; }), "int x[]={1,2,3}; x[2];");
+  assert(12, ({ int x[]={1,2,3}; sizeof(x); }), "int x[]={1,2,3}; sizeof(x);");
+  assert(3, ({ int x = {3}; x; }), "int x = {3}; x;");
+
+  assert('a', ({ char x[4]="abc"; x[0]; }), "char x[4]=\"abc\"; x[0];");
+  assert('c', ({ char x[4]="abc"; x[2]; }), "char x[4]=\"abc\"; x[2];");
+  assert(0, ({ char x[4]="abc"; x[3]; }), "char x[4]=\"abc\"; x[3];");
+  assert('b', ({ char x[2][4]={"abc","def"}; x[0][1]; }), "char x[2][4]={\"abc\",\"def\"}; x[0][1];");
+  assert('f', ({ char x[2][4]={"abc","def"}; x[1][2]; }), "char x[2][4]={\"abc\",\"def\"}; x[1][2];");
+  assert(4, ({ char x[]="foo"; sizeof(x); }), "char x[]=\"foo\"; sizeof(x);");
+
+  assert(1, ({ struct {int a; int b; int c;} x={1,2,3}; x.a; }), "struct {int a; int b; int c;} x={1,2,3}; x.a;");
+  assert(3, ({ struct {int a; int b; int c;} x={1,2,3}; x.c; }), "struct {int a; int b; int c;} x={1,2,3}; x.c;");
+  assert(0, ({ struct {int a; int b; int c;} x={1}; x.b; }), "struct {int a; int b; int c;} x={1}; x.b;");
+  assert(2, ({ struct {int a; int b;} x[2]={{1,2},{3,4}}; x[0].b; }), "struct {int a; int b;} x[2]={{1,2},{3,4}}; x[0].b;");
+  assert(3, ({ struct {int a; int b;} x[2]={1,2,3,4}; x[1].a; }), "struct {int a; int b;} x[2]={1,2,3,4}; x[1].a;");
+  assert(0, ({ struct {int a; int b;} x[2]={{1,2}}; x[1].b; }), "struct {int a; int b;} x[2]={{1,2}}; x[1].b;");
+  assert(2, ({ struct P {int x; int y;}; struct P p={1,2}; p.y; }), "struct P {int x; int y;}; struct P p={1,2}; p.y;");
+
+  assert(5, ({ int x[4]={[2]=5}; x[2]; }), "int x[4]={[2]=5}; x[2];");
+  assert(6, ({ int x[4]={[1]=5,6}; x[2]; }), "int x[4]={[1]=5,6}; x[2];");
+  assert(0, ({ int x[4]={[1]=5,6}; x[0]; }), "int x[4]={[1]=5,6}; x[0];");
+  assert(24, ({ int x[]={[5]=1}; sizeof(x); }), "int x[]={[5]=1}; sizeof(x);");
+  assert(7, ({ int x[2][3]={[1][2]=7}; x[1][2]; }), "int x[2][3]={[1][2]=7}; x[1][2];");
+  assert(3, ({ struct {int a; int b; int c;} x={.c=3,.a=1}; x.c; }), "struct {int a; int b; int c;} x={.c=3,.a=1}; x.c;");
+  assert(0, ({ struct {int a; int b; int c;} x={.c=3,.a=1}; x.b; }), "struct {int a; int b; int c;} x={.c=3,.a=1}; x.b;");
+  assert(5, ({ struct {int a; int b; int c;} x={.b=4,5}; x.c; }), "struct {int a; int b; int c;} x={.b=4,5}; x.c;");
+  assert(9, ({ struct {int a; struct {int b; int c;} d;} x={.d.c=9}; x.d.c; }), "struct {int a; struct {int b; int c;} d;} x={.d.c=9}; x.d.c;");
+  assert(8, ({ struct {int a; int b;} x[2]={[1].b=8}; x[1].b; }), "struct {int a; int b;} x[2]={[1].b=8}; x[1].b;");
+
+  assert(3, g3, "g3");
+  assert(4, g4, "g4");
+  assert(5, g5, "g5");
+  assert(6, g6, "g6");
+  assert(0, g7[0], "g7[0]");
+  assert(2, g7[2], "g7[2]");
+  assert(1, g8[0].a, "g8[0].a");
+  assert(4, g8[1].b, "g8[1].b");
+  assert(2, g9[0].a[1], "g9[0].a[1]");
+  assert(0, g9[1].a[0], "g9[1].a[0]");
+  assert(12, sizeof(g10), "sizeof(g10)");
+  assert(3, g10[2], "g10[2]");
+  assert(7, sizeof(g11), "sizeof(g11)");
+  assert('b', g11[3], "g11[3]");
+  assert('b', *g12, "*g12");
+  assert('c', g13[2], "g13[2]");
+  assert(1, *g14, "*g14");
+  assert(2, *g15, "*g15");
+  assert(5, *(int *)g16, "*(int *)g16");
+  assert(0, g17[0], "g17[0]");
+  assert(5, g17[1], "g17[1]");
+  assert(6, g17[2], "g17[2]");
+  assert(1, g18.a, "g18.a");
+  assert(0, g18.b, "g18.b");
+  assert(3, g18.c, "g18.c");
+  assert(1, g19, "g19");
+  assert(13, g20, "g20");
+  assert(3, g21[1][0], "g21[1][0]");
+  assert(0, g21[1][1], "g21[1][1]");
+
+  assert(5, ({ int i=0; switch(0) { case 0:i=5;break; case 1:i=6;break; case 2:i=7;break; } i; }), "int i=0; switch(0) { case 0:i=5;break; case 1:i=6;break; case 2:i=7;break; } i;");
+  assert(6, ({ int i=0; switch(1) { case 0:i=5;break; case 1:i=6;break; case 2:i=7;break; } i; }), "int i=0; switch(1) { case 0:i=5;break; case 1:i=6;break; case 2:i=7;break; } i;");
+  assert(7, ({ int i=0; switch(2) { case 0:i=5;break; case 1:i=6;break; case 2:i=7;break; } i; }), "int i=0; switch(2) { case 0:i=5;break; case 1:i=6;break; case 2:i=7;break; } i;");
+  assert(0, ({ int i=0; switch(3) { case 0:i=5;break; case 1:i=6;break; case 2:i=7;break; } i; }), "int i=0; switch(3) { case 0:i=5;break; case 1:i=6;break; case 2:i=7;break; } i;");
+  assert(5, ({ int i=0; switch(0) { case 0:i=5;break; default:i=7; } i; }), "int i=0; switch(0) { case 0:i=5;break; default:i=7; } i;");
+  assert(7, ({ int i=0; switch(1) { case 0:i=5;break; default:i=7; } i; }), "int i=0; switch(1) { case 0:i=5;break; default:i=7; } i;");
+  assert(2, ({ int i=0; switch(1) { case 0: 0; case 1: 0; case 2: 0; i=2; } i; }), "int i=0; switch(1) { case 0: 0; case 1: 0; case 2: 0; i=2; } i;");
+  assert(0, ({ int i=0; switch(3) { case 0: 0; case 1: 0; case 2: 0; i=2; } i; }), "int i=0; switch(3) { case 0: 0; case 1: 0; case 2: 0; i=2; } i;");
+  assert(6, ({ int i=0; switch(1) { case 1: i=i+1; case 2: i=i+2; case 3: i=i+3; } i; }), "int i=0; switch(1) { case 1: i=i+1; case 2: i=i+2; case 3: i=i+3; } i;");
+  assert(3, ({ int i=0; switch(5) { default: i=i+1; case 3: i=i+2; } i; }), "int i=0; switch(5) { default: i=i+1; case 3: i=i+2; } i;");
+  assert(12, ({ int i=0; switch(1) { case 1: switch(2) { case 2: i=10; break; } i=i+2; break; case 2: i=20; } i; }), "int i=0; switch(1) { case 1: switch(2) { case 2: i=10; break; } i=i+2; break; case 2: i=20; } i;");
+  assert(33, ({ int i=0; switch(4) { case 1:i=11;break; case 2:i=22;break; case 4:i=33;break; case 5:i=44;break; case 6:i=55;break; } i; }), "int i=0; switch(4) { case 1:i=11;break; case 2:i=22;break; case 4:i=33;break; case 5:i=44;break; case 6:i=55;break; } i;");
+  assert(9, ({ int i=9; switch(3) { case 1:i=11;break; case 2:i=22;break; case 4:i=33;break; case 5:i=44;break; case 6:i=55;break; } i; }), "int i=9; switch(3) { case 1:i=11;break; case 2:i=22;break; case 4:i=33;break; case 5:i=44;break; case 6:i=55;break; } i;");
+  assert(9, ({ int i=9; switch(-8) { case 1:i=11;break; case 2:i=22;break; case 4:i=33;break; case 5:i=44;break; case 6:i=55;break; } i; }), "int i=9; switch(-8) { case 1:i=11;break; case 2:i=22;break; case 4:i=33;break; case 5:i=44;break; case 6:i=55;break; } i;");
+  assert(7, ({ int i=0; switch(100) { case -2:i=1;break; case -1:i=2;break; case 0:i=3;break; case 1:i=4;break; default:i=7; } i; }), "int i=0; switch(100) { case -2:i=1;break; case -1:i=2;break; case 0:i=3;break; case 1:i=4;break; default:i=7; } i;");
+  assert(2, ({ int i=0; switch(-1) { case -2:i=1;break; case -1:i=2;break; case 0:i=3;break; case 1:i=4;break; default:i=7; } i; }), "int i=0; switch(-1) { case -2:i=1;break; case -1:i=2;break; case 0:i=3;break; case 1:i=4;break; default:i=7; } i;");
+  assert(3, ({ int i=0; switch(1000) { case 10:i=1;break; case 1000:i=3;break; case 100000:i=4;break; case 10000000:i=5;break; } i; }), "int i=0; switch(1000) { case 10:i=1;break; case 1000:i=3;break; case 100000:i=4;break; case 10000000:i=5;break; } i;");
+  assert(6, ({ int i=0; switch(1+2) { case 1+1:i=5;break; case 2*3/2:i=6;break; } i; }), "int i=0; switch(1+2) { case 1+1:i=5;break; case 2*3/2:i=6;break; } i;");
+  assert(10, ({ int j=0; for (int i=0; i<10; i=i+1) { switch(i) { case 3: continue; default: j=j+1; } j=j+0; } j+1; }), "int j=0; for (int i=0; i<10; i=i+1) { switch(i) { case 3: continue; default: j=j+1; } j=j+0; } j+1;");
+
+  assert(7, ({ int i=0; int j=0; do { j=j+1; } while (i=i+1, i<7); j; }), "int i=0; int j=0; do { j=j+1; } while (i=i+1, i<7); j;");
+  assert(1, ({ int i=0; do { i=i+1; } while (0); i; }), "int i=0; do { i=i+1; } while (0); i;");
+  assert(4, ({ int i=0; int j=0; do { i=i+1; if (i==4) break; j=j+1; } while (i<10); i; }), "int i=0; int j=0; do { i=i+1; if (i==4) break; j=j+1; } while (i<10); i;");
+  assert(5, ({ int i=0; int j=0; do { i=i+1; if (i-i/2*2==0) continue; j=j+1; } while (i<10); j; }), "int i=0; int j=0; do { i=i+1; if (i-i/2*2==0) continue; j=j+1; } while (i<10); j;");
+  assert(2, 0?1:2, "0?1:2");
+  assert(1, 1?1:2, "1?1:2");
+  assert(-1, 0?-2:-1, "0?-2:-1");
+  assert(-2, 1?-2:-1, "1?-2:-1");
+  assert(4, sizeof(0?(int)1:(int)2), "sizeof(0?(int)1:(int)2)");
+  assert(8, sizeof(0?(long)1:(long)2), "sizeof(0?(long)1:(long)2)");
+  assert(4, sizeof(0?(char)1:(short)2), "sizeof(0?(char)1:(short)2)");
+  assert(8, sizeof(0?(char)1:(long)2), "sizeof(0?(char)1:(long)2)");
+  assert(8, ({ int x[3]; sizeof(1?x:0); }), "int x[3]; sizeof(1?x:0);");
+  assert(3, ({ int x[3]; x[1]=3; *(0?0:x+1); }), "int x[3]; x[1]=3; *(0?0:x+1);");
+  assert(3, 1?2?3:4:5, "1?2?3:4:5");
+  assert(5, 0?2?3:4:5, "0?2?3:4:5");
+  assert(4, 1?0?3:4:5, "1?0?3:4:5");
+  assert(6, ({ int x=0; 1?(x=6):(x=7); x; }), "int x=0; 1?(x=6):(x=7); x;");
+  assert(7, ({ int x=0; 0?(x=6):(x=7); x; }), "int x=0; 0?(x=6):(x=7); x;");
+  assert(3, ({ int i=0; switch(3) { case 1?3:4: i=3; break; case 2: i=2; } i; }), "int i=0; switch(3) { case 1?3:4: i=3; break; case 2: i=2; } i;");
+  assert(1, ({ int x[4]={0, 1, 2, 3}; x[1?1:2]; }), "int x[4]={0, 1, 2, 3}; x[1?1:2];");
+  assert(5, g22, "g22");
+
+  assert(1, sizeof(unsigned char), "sizeof(unsigned char)");
+  assert(1, sizeof(signed char), "sizeof(signed char)");
+  assert(2, sizeof(unsigned short), "sizeof(unsigned short)");
+  assert(2, sizeof(unsigned short int), "sizeof(unsigned short int)");
+  assert(4, sizeof(unsigned), "sizeof(unsigned)");
+  assert(4, sizeof(signed), "sizeof(signed)");
+  assert(4, sizeof(unsigned int), "sizeof(unsigned int)");
+  assert(8, sizeof(unsigned long), "sizeof(unsigned long)");
+  assert(8, sizeof(unsigned long long int), "sizeof(unsigned long long int)");
+  assert(8, sizeof(signed long long), "sizeof(signed long long)");
+  assert(255, ({ unsigned char x=255; x; }), "unsigned char x=255; x;");
+  assert(-1, ({ signed char x=255; x; }), "signed char x=255; x;");
+  assert(65535, ({ unsigned short x=65535; x; }), "unsigned short x=65535; x;");
+  assert(4294967295, ({ unsigned x=-1; x; }), "unsigned x=-1; x;");
+  assert(255, (unsigned char)-1, "(unsigned char)-1");
+  assert(65535, (unsigned short)-1, "(unsigned short)-1");
+  assert(4294967295, (unsigned)-1, "(unsigned)-1");
+  assert(1, ({ unsigned x=-1; x > 5; }), "unsigned x=-1; x > 5;");
+  assert(0, ({ int x=-1; x > 5; }), "int x=-1; x > 5;");
+  assert(1, ({ unsigned long x=-1; unsigned long y=1; y < x; }), "unsigned long x=-1; unsigned long y=1; y < x;");
+  assert(0, ({ long x=-1; long y=1; y < x; }), "long x=-1; long y=1; y < x;");
+  assert(1, ({ unsigned x=-1; unsigned y=2; x >= y; }), "unsigned x=-1; unsigned y=2; x >= y;");
+  assert(0, ({ unsigned x=-1; unsigned y=2; x <= y; }), "unsigned x=-1; unsigned y=2; x <= y;");
+  assert(2147483647, ({ unsigned x=-1; unsigned y=2; x / y; }), "unsigned x=-1; unsigned y=2; x / y;");
+  assert(0, ({ int x=-1; int y=2; x / y; }), "int x=-1; int y=2; x / y;");
+  assert(4611686018427387903, ({ unsigned long x=-1; unsigned long y=4; x / y; }), "unsigned long x=-1; unsigned long y=4; x / y;");
+  assert(2147483647, ({ unsigned x=-1; x /= 2u; x; }), "unsigned x=-1; x /= 2u; x;");
+  assert(1, ({ unsigned x=0; unsigned y=1; x - y == -1; }), "unsigned x=0; unsigned y=1; x - y == -1;");
+  assert(1, ({ unsigned x=0; unsigned y=1; x - y == 4294967295; }), "unsigned x=0; unsigned y=1; x - y == 4294967295;");
+  assert(4, sizeof(1u), "sizeof(1u)");
+  assert(8, sizeof(1ul), "sizeof(1ul)");
+  assert(8, sizeof(1LU), "sizeof(1LU)");
+  assert(8, sizeof(1ull), "sizeof(1ull)");
+  assert(8, sizeof(1l), "sizeof(1l)");
+  assert(8, sizeof(1LL), "sizeof(1LL)");
+  assert(8, sizeof(4294967296u), "sizeof(4294967296u)");
+  assert(1, 0xffffffffffffffff > 0, "0xffffffffffffffff > 0");
+  assert(0, -1 < 1ul, "-1 < 1ul");
+  assert(2147483647, 0xffffffffu / 2, "0xffffffffu / 2");
+  assert(3, ({ unsigned char x[]="abc"; sizeof(x)-1; }), "unsigned char x[]=\"abc\"; sizeof(x)-1;");
+  assert(200, g23, "g23");
+  assert(1, g24, "g24");
+
+  assert(4, sizeof(float), "sizeof(float)");
+  assert(8, sizeof(double), "sizeof(double)");
+  assert(8, sizeof(long double), "sizeof(long double)");
+  assert(8, sizeof(1.5), "sizeof(1.5)");
+  assert(4, sizeof(1.5f), "sizeof(1.5f)");
+  assert(8, sizeof(1.5L), "sizeof(1.5L)");
+  assert(8, sizeof(1 + 1.5f + 1.5), "sizeof(1 + 1.5f + 1.5)");
+  assert(4, sizeof(1 + 1.5f), "sizeof(1 + 1.5f)");
+  assert(35, (float)(char)35, "(float)(char)35");
+  assert(35, (double)(long)35, "(double)(long)35");
+  assert(3, (int)3.99, "(int)3.99");
+  assert(-3, (int)-3.99, "(int)-3.99");
+  assert(255, (unsigned char)255.5, "(unsigned char)255.5");
+  assert(1, (_Bool)0.1, "(_Bool)0.1");
+  assert(0, (_Bool)0.0, "(_Bool)0.0");
+  assert(2, (long)(0.5 + 1.5), "(long)(0.5 + 1.5)");
+  assert(1, 0.1 + 0.2 > 0.3, "0.1 + 0.2 > 0.3");
+  assert(0, 0.1 + 0.2 == 0.3, "0.1 + 0.2 == 0.3");
+  assert(1, 0.1f + 0.2f == 0.3f, "0.1f + 0.2f == 0.3f");
+  assert(1, 0.5 == 0.5, "0.5 == 0.5");
+  assert(0, 0.5 != 0.5, "0.5 != 0.5");
+  assert(1, 0.5 < 1, "0.5 < 1");
+  assert(0, 1 < 0.5, "1 < 0.5");
+  assert(1, 1.0 <= 1, "1.0 <= 1");
+  assert(1, 2 > 1.5f, "2 > 1.5f");
+  assert(0, 1.5f > 2, "1.5f > 2");
+  assert(1, 1.5 >= 1.5f, "1.5 >= 1.5f");
+  assert(6, 1.5 * 4, "1.5 * 4");
+  assert(2, 5.0 / 2, "5.0 / 2");
+  assert(-1, .5 - 1.5, ".5 - 1.5");
+  assert(100, 1e2, "1e2");
+  assert(1, 1000e-3, "1000e-3");
+  assert(1, -0.5 < 0, "-0.5 < 0");
+  assert(0, !0.5, "!0.5");
+  assert(1, !0.0, "!0.0");
+  assert(1, 0.5 && 2, "0.5 && 2");
+  assert(0, 0.0 || 0.0f, "0.0 || 0.0f");
+  assert(5, ({ double x=2.5; x*2; }), "double x=2.5; x*2;");
+  assert(7, ({ float x=3.5; x*2; }), "float x=3.5; x*2;");
+  assert(3, ({ double x=1; x+=2; x; }), "double x=1; x+=2; x;");
+  assert(5, ({ float x=10; x/=2; x; }), "float x=10; x/=2; x;");
+  assert(2, ({ int x=5; x*=0.5; x; }), "int x=5; x*=0.5; x;");
+  assert(3, ({ double x=1.5; x++; x++; (int)x; }), "double x=1.5; x++; x++; (int)x;");
+  assert(1, ({ float x=1.5; --x; x == 0.5; }), "float x=1.5; --x; x == 0.5;");
+  assert(2, ({ double x=0.5; int i=0; while (x) { x=x-0.25; i=i+1; } i; }), "double x=0.5; int i=0; while (x) { x=x-0.25; i=i+1; } i;");
+  assert(1, ({ double x=0.5; x ? 1 : 2; }), "double x=0.5; x ? 1 : 2;");
+  assert(2, ({ int x=0; x ? 1 : 2.5; }), "int x=0; x ? 1 : 2.5;");
+  assert(8, ({ int x=0; sizeof(x ? 1 : 2.5); }), "int x=0; sizeof(x ? 1 : 2.5);");
+  assert(2, ({ double x[2]={1.5, 2.5}; x[1]; }), "double x[2]={1.5, 2.5}; x[1];");
+  assert(6, add_double(2.5, 3.5), "add_double(2.5, 3.5)");
+  assert(6, add_float(2.5, 3.5), "add_float(2.5, 3.5)");
+  assert(6, add_double(2, 4), "add_double(2, 4)");
+  assert(10, add_mixed(1, 2.5, 3, 3.5), "add_mixed(1, 2.5, 3, 3.5)");
+  assert(3, double_to_int(3.7), "double_to_int(3.7)");
+  assert(1, int_to_double(3) == 3.0, "int_to_double(3) == 3.0");
+  assert(1, strtod("1.25", 0) == 1.25, "strtod(\"1.25\", 0) == 1.25");
+  assert(0, ({ char buf[20]; snprintf(buf, 20, "%.2f %d %.1f", 1.25, 3, 0.5f); strcmp(buf, "1.25 3 0.5"); }), "snprintf(buf, 20, \"%.2f %d %.1f\", 1.25, 3, 0.5f)");
+  assert(1, g25 == 1.5, "g25 == 1.5");
+  assert(1, g26 == 2.5, "g26 == 2.5");
+  assert(2, g27, "g27");
+  assert(1, g28[0] == 1 && g28[1] == 2.5 && g28[2] == 1.5, "g28");
+  assert(1, (double)18446744073709551615ul > 1e19, "(double)18446744073709551615ul > 1e19");
+  assert(1, ({ double x=1e19; (unsigned long)x == 10000000000000000000ul; }), "double x=1e19; (unsigned long)x == 10000000000000000000ul;");
+  assert(9999999, ({ float x=1e19; (unsigned long)x / 1000000000000; }), "float x=1e19; (unsigned long)x / 1000000000000;");
+  assert(3, ({ double x=3.7; (unsigned long)x; }), "double x=3.7; (unsigned long)x;");
+
+  assert(4, sizeof(1), "sizeof(1)");
+  assert(8, sizeof(2147483648), "sizeof(2147483648)");
+  assert(4, ({ char x; sizeof(x+x); }), "({ char x; sizeof(x+x); })");
+  assert(4, ({ short x; sizeof(-x); }), "({ short x; sizeof(-x); })");
+  assert(4, ({ char x; sizeof(~x); }), "({ char x; sizeof(~x); })");
+  assert(4, ({ char x; sizeof(+x); }), "({ char x; sizeof(+x); })");
+  assert(4, ({ char x; sizeof(x==1); }), "({ char x; sizeof(x==1); })");
+  assert(4, sizeof(1?(char)1:(short)2), "sizeof(1?(char)1:(short)2)");
+  assert(8, ({ int x; long y; sizeof(x+y); }), "({ int x; long y; sizeof(x+y); })");
+  assert(4, ({ enum { zero, one } x; sizeof(x+1); }), "({ enum { zero, one } x; sizeof(x+1); })");
+  assert(2, ({ enum { zero, one } x=one; x+1; }), "({ enum { zero, one } x=one; x+1; })");
+  assert(256, ({ unsigned char x=255; x+1; }), "({ unsigned char x=255; x+1; })");
+  assert(128, ({ char x=127; x+1; }), "({ char x=127; x+1; })");
+  assert(-65535, ({ unsigned short x=65535; -x; }), "({ unsigned short x=65535; -x; })");
+  assert(0, ({ unsigned x=4294967295; x+1; }), "({ unsigned x=4294967295; x+1; })");
+  assert(2147483648, ({ int x=2147483647; unsigned y=1; x+y; }), "({ int x=2147483647; unsigned y=1; x+y; })");
+  assert(1410065408, ({ unsigned x=100000; x*x; }), "({ unsigned x=100000; x*x; })");
+  assert(0, ({ int x=-1; unsigned y=1; x<y; }), "({ int x=-1; unsigned y=1; x<y; })");
+  assert(0, ({ int x=-1; unsigned long y=1; x<y; }), "({ int x=-1; unsigned long y=1; x<y; })");
+  assert(1, ({ int x=-1; long y=1; x<y; }), "({ int x=-1; long y=1; x<y; })");
+  assert(1, ({ unsigned short x=1; int y=-1; y<x; }), "({ unsigned short x=1; int y=-1; y<x; })");
+  assert(4294967295, -1u, "-1u");
+  assert(4294967295, ~0u, "~0u");
+  assert(-1294967296, (int)3000000000, "(int)3000000000");
+  assert(-1, ({ char x=-1; int y=x; y; }), "({ char x=-1; int y=x; y; })");
+
+  assert(1, g29, "g29");
+  assert(2147483648, g30, "g30");
+
+  assert(55, 1+(2+(3+(4+(5+(6+(7+(8+(9+10)))))))), "1+(2+(3+(4+(5+(6+(7+(8+(9+10))))))))");
+  assert(775, add2(1+(2+(3+(4+(5+(6+add2(7,(8+(9+10)))))))), 2*(3*(4*(5*(6*(7-add2(1,5))))))), "add2(1+(2+(3+(4+(5+(6+add2(7,(8+(9+10)))))))), 2*(3*(4*(5*(6*(7-add2(1,5)))))))");
+  assert(132, 1.5+(2.5*(3.0+(4.0+(5.0+(6.0+add_double(7.0,(8.0+(9.0+10.5)))))))), "1.5+(2.5*(3.0+(4.0+(5.0+(6.0+add_double(7.0,(8.0+(9.0+10.5))))))))");
+  assert(30, ({ char c; c = 1+(2+(3+(4+(5+(6+(7+(8+250))))))); c; }), "({ char c; c = 1+(2+(3+(4+(5+(6+(7+(8+250))))))); c; })");
+  assert(0, 1 < (2 < (3 < (4 < (5 < (6 < (7 > 2)))))), "1 < (2 < (3 < (4 < (5 < (6 < (7 > 2))))))");
+  assert(35, ({ int x[10]; x[1+(1+(1+(1+(1+(1+1)))))]=7; x[1+(1+(1+(1+(1+(1+1)))))] += 1+(2+(3+(4+(5+(6+7))))); x[7]; }), "({ int x[10]; x[1+(1+(1+(1+(1+(1+1)))))]=7; x[1+(1+(1+(1+(1+(1+1)))))] += 1+(2+(3+(4+(5+(6+7))))); x[7]; })");
+  assert(84, ({ int t = 1+(2+(3+(4+(5+(6+7))))); t * 2; }) + (1+(2+(3+(4+(5+(6+({ 7; }))))))), "({ int t = 1+(2+(3+(4+(5+(6+7))))); t * 2; }) + (1+(2+(3+(4+(5+(6+({ 7; })))))))");
+
+  assert(8, ({ int x[2*4]; sizeof(x)/sizeof(*x); }), "({ int x[2*4]; sizeof(x)/sizeof(*x); })");
+  assert(12, ({ char x[sizeof(int)*3]; sizeof(x); }), "({ char x[sizeof(int)*3]; sizeof(x); })");
+  assert(6, ({ int x[1?2:3][3]; sizeof(x)/sizeof(int); }), "({ int x[1?2:3][3]; sizeof(x)/sizeof(int); })");
+  assert(3, ({ enum { two=2, three }; char x[three]; sizeof(x); }), "({ enum { two=2, three }; char x[three]; sizeof(x); })");
+  assert(9, ({ enum { a=2*4, b, c=a|1 }; c; }), "({ enum { a=2*4, b, c=a|1 }; c; })");
+  assert(-1, ({ enum { a=-1, b=a+1 }; a; }), "({ enum { a=-1, b=a+1 }; a; })");
+  assert(4, ({ enum { a=sizeof(int), b=(char)260 }; a; }), "({ enum { a=sizeof(int), b=(char)260 }; a; })");
+  assert(4, ({ enum { a=sizeof(int), b=(char)260 }; b; }), "({ enum { a=sizeof(int), b=(char)260 }; b; })");
+  assert(7, ({ enum { a=3 }; int i=0; switch(6) { case a*2: i=7; break; case a: i=3; } i; }), "({ enum { a=3 }; int i=0; switch(6) { case a*2: i=7; break; case a: i=3; } i; })");
+  assert(2, ({ int i=0; switch(2) { case (char)258: i=2; break; case 1: i=1; } i; }), "({ int i=0; switch(2) { case (char)258: i=2; break; case 1: i=1; } i; })");
+  assert(5, ({ int x[3]={[1+1]=5}; x[2]; }), "({ int x[3]={[1+1]=5}; x[2]; })");
+  assert(40, sizeof(g_carr), "sizeof(g_carr)");
+  assert(3, g_cinit, "g_cinit");
+  assert(1, ({ int x=5; (x=7, 3) == 3 && x == 7; }), "({ int x=5; (x=7, 3) == 3 && x == 7; })");
+  assert(-2147483648, ({ int x = 2147483647 + 1; x; }), "({ int x = 2147483647 + 1; x; })");
+  assert(255, ({ unsigned char x = (unsigned char)-1; x; }), "({ unsigned char x = (unsigned char)-1; x; })");
+  assert(2, ({ 7 / 3; }), "({ 7 / 3; })");
+  assert(1, ({ 0.1 + 0.2 > 0.3; }), "({ 0.1 + 0.2 > 0.3; })");
+  assert(1, (unsigned long)1e19 == ({ double x=1e19; (unsigned long)x; }), "(unsigned long)1e19 == ({ double x=1e19; (unsigned long)x; })");
+  assert(1, g_u64 == ({ double x=1e19; (unsigned long)x; }), "g_u64 == ({ double x=1e19; (unsigned long)x; })");
+  assert(1, g_u64_cast == ({ double x=1.5e19; (unsigned long)x / 2; }), "g_u64_cast == ({ double x=1.5e19; (unsigned long)x / 2; })");
+  assert(1, g_u64 == 10000000000000000000ul, "g_u64 == 10000000000000000000ul");
+
+  assert(385, add10(1,2,3,4,5,6,7,8,9,10), "add10(1,2,3,4,5,6,7,8,9,10)");
+  assert(306, add_stack_mixed(1,2,3,4,5,6,7,8,9.5,10,11,12,13,14,15,1), "add_stack_mixed(1,2,3,4,5,6,7,8,9.5,10,11,12,13,14,15,1)");
+  assert(0, sum_va(0), "sum_va(0)");
+  assert(6, sum_va(3, 1, 2, 3), "sum_va(3, 1, 2, 3)");
+  assert(55, sum_va(10, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10), "sum_va(10, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10)");
+  assert(-3, sum_va(2, -1, (char)-2), "sum_va(2, -1, (char)-2)");
+  assert(89, fsum_va(9, 0.5,2L, 1.5,2L, 2.5,2L, 3.5,2L, 4.5,2L, 5.5,2L, 6.5,2L, 7.5,2L, 12.5,2L), "fsum_va(9, 0.5,2L, 1.5,2L, 2.5,2L, 3.5,2L, 4.5,2L, 5.5,2L, 6.5,2L, 7.5,2L, 12.5,2L)");
+  assert(0, ({ char buf[100]; strcmp(fmt_va(buf, "%d %s %.1f %d %d %d %d", 1, "two", 3.5, 4, 5, 6, 7), "1 two 3.5 4 5 6 7"); }), "({ char buf[100]; strcmp(fmt_va(buf, \"%d %s %.1f %d %d %d %d\", 1, \"two\", 3.5, 4, 5, 6, 7), \"1 two 3.5 4 5 6 7\"); })");
+
+  assert(-1, pair_diff(mk_pair(3, 4)), "pair_diff(mk_pair(3, 4))");
+  assert(4, ({ IntPair p = mk_pair(3, 4); p.b; }), "({ IntPair p = mk_pair(3, 4); p.b; })");
+  assert(9, char_double_sum(mk_char_double(7, 2.5)), "char_double_sum(mk_char_double(7, 2.5))");
+  assert(7, ({ CharDouble x = mk_char_double(7, 2.5); x.a; }), "({ CharDouble x = mk_char_double(7, 2.5); x.a; })");
+  assert(9, float3_sum(mk_float3(1.5)), "float3_sum(mk_float3(1.5))");
+  assert(3, ({ Float3 f = mk_float3(1.5); f.y; }), "({ Float3 f = mk_float3(1.5); f.y; })");
+  assert(654, long3_sum(mk_long3(4)), "long3_sum(mk_long3(4))");
+  assert(68, char3_sum(mk_char3(10)), "char3_sum(mk_char3(10))");
+  assert(12, ({ Char3 c = mk_char3(10); c.c[2]; }), "({ Char3 c = mk_char3(10); c.c[2]; })");
+  assert(126245, struct_stack(1, 2, 3, 4, 5, mk_pair(3, 4), mk_pair(1, 2), mk_long3(4), mk_char3(10)), "struct_stack(1, 2, 3, 4, 5, mk_pair(3, 4), mk_pair(1, 2), mk_long3(4), mk_char3(10))");
+  assert(3, ({ IntPair p = mk_pair(3, 4); IntPair q; q = p; p.a = 9; q.a; }), "({ IntPair p = mk_pair(3, 4); IntPair q; q = p; p.a = 9; q.a; })");
+  assert(6, ({ Long3 l = mk_long3(4); Long3 m = l; l.c = 100; m.c; }), "({ Long3 l = mk_long3(4); Long3 m = l; l.c = 100; m.c; })");
+  assert(5, ({ typedef struct {char a; short b; char c;} T; T x; T y; x.a = 1; x.b = 5; x.c = 3; y = x; y.b; }), "({ typedef struct {char a; short b; char c;} T; T x; T y; x.a = 1; x.b = 5; x.c = 3; y = x; y.b; })");
+
+  assert(6, ({ struct tree a; struct tree b; struct tree c; a.val = 1; b.val = 2; c.val = 3; a.lhs = &b; a.rhs = &c; b.lhs = b.rhs = c.lhs = c.rhs = 0; root = &a; tree_sum(root); }), "({ struct tree a; struct tree b; struct tree c; a.val = 1; b.val = 2; c.val = 3; a.lhs = &b; a.rhs = &c; b.lhs = b.rhs = c.lhs = c.rhs = 0; root = &a; tree_sum(root); })");
+  assert(3, ({ struct node; struct node *p; struct node { int x; struct node *next; } n; n.x = 3; n.next = &n; p = &n; p->next->next->x; }), "({ struct node; struct node *p; struct node { int x; struct node *next; } n; n.x = 3; n.next = &n; p = &n; p->next->next->x; })");
+  assert(16, ({ typedef struct list List; struct list { List *next; long v; }; sizeof(List); }), "({ typedef struct list List; struct list { List *next; long v; }; sizeof(List); })");
+  assert(8, ({ struct p; struct q { struct p *p; }; struct p { int x; }; sizeof(struct q); }), "({ struct p; struct q { struct p *p; }; struct p { int x; }; sizeof(struct q); })");
+  assert(8, ({ union { int a; char b[6]; } x; sizeof(x); }), "({ union { int a; char b[6]; } x; sizeof(x); })");
+  assert(3, ({ union { int a; char b[4]; } x; x.a = 515; x.b[0]; }), "({ union { int a; char b[4]; } x; x.a = 515; x.b[0]; })");
+  assert(2, ({ union { int a; char b[4]; } x; x.a = 515; x.b[1]; }), "({ union { int a; char b[4]; } x; x.a = 515; x.b[1]; })");
+  assert(515, ({ union { int a; char b[4]; } x = { .b = {3, 2} }; x.a; }), "({ union { int a; char b[4]; } x = { .b = {3, 2} }; x.a; })");
+  assert(4, gunion1.c[0], "gunion1.c[0]");
+  assert(513, gunion2.i, "gunion2.i");
+  assert(4, ({ struct { int a : 3; int b : 5; } x; sizeof(x); }), "({ struct { int a : 3; int b : 5; } x; sizeof(x); })");
+  assert(8, ({ struct { int a : 20; int b : 20; } x; sizeof(x); }), "({ struct { int a : 20; int b : 20; } x; sizeof(x); })");
+  assert(-3, ({ struct { int a : 3; int b : 5; } x; x.a = 5; x.b = 3; x.a; }), "({ struct { int a : 3; int b : 5; } x; x.a = 5; x.b = 3; x.a; })");
+  assert(5, ({ struct { unsigned a : 3; int b : 5; } x; x.a = 13; x.b = -1; x.a; }), "({ struct { unsigned a : 3; int b : 5; } x; x.a = 13; x.b = -1; x.a; })");
+  assert(-1, ({ struct { unsigned a : 3; int b : 5; } x; x.a = 13; x.b = -1; x.b; }), "({ struct { unsigned a : 3; int b : 5; } x; x.a = 13; x.b = -1; x.b; })");
+  assert(-21, ({ struct { int a : 4; int b : 6; int c : 4; } x = { 1, 20, 2 }; x.b += 20; x.b - x.a * 2 + x.c * 2 + 1; }), "({ struct { int a : 4; int b : 6; int c : 4; } x = { 1, 20, 2 }; x.b += 20; x.b - x.a * 2 + x.c * 2 + 1; })");
+  assert(-1, ({ struct { int a : 2; } x; x.a = 7; }), "({ struct { int a : 2; } x; x.a = 7; })");
+  assert(5, gbits.a, "gbits.a");
+  assert(-3, gbits.b, "gbits.b");
+  assert(7, gbits.c, "gbits.c");
+  assert(1, gbits.d == 123456789012, "gbits.d == 123456789012");
+  assert(1, gbits.e, "gbits.e");
+
+  assert(7, ({ int (*f)(int, int) = fp_add; f(3, 4); }), "({ int (*f)(int, int) = fp_add; f(3, 4); })");
+  assert(7, ({ int (*f)(int, int) = &fp_add; (*f)(3, 4); }), "({ int (*f)(int, int) = &fp_add; (*f)(3, 4); })");
+  assert(-1, ({ int (*f)(int, int) = fp_sub; (**f)(3, 4); }), "({ int (*f)(int, int) = fp_sub; (**f)(3, 4); })");
+  assert(5, fp_apply(fp_add, 2, 3), "fp_apply(fp_add, 2, 3)");
+  assert(8, fp_pick(1)(10, 2), "fp_pick(1)(10, 2)");
+  assert(12, fp_pick(0)(10, 2), "fp_pick(0)(10, 2)");
+  assert(15, ({ int (*f[2])(int, int); f[0] = fp_add; f[1] = fp_sub; f[0](5, 3) * 10 + f[1](5, 3) - 67; }), "({ int (*f[2])(int, int); f[0] = fp_add; f[1] = fp_sub; f[0](5, 3) * 10 + f[1](5, 3) - 67; })");
+  assert(9, ({ struct { int (*op)(int, int); } s; s.op = fp_add; s.op(4, 5); }), "({ struct { int (*op)(int, int); } s; s.op = fp_add; s.op(4, 5); })");
+  assert(7, ({ int (*f)(void) = fp_void; f(); }), "({ int (*f)(void) = fp_void; f(); })");
+  assert(8, ({ int (*f)(int, int); sizeof(f); }), "({ int (*f)(int, int); sizeof(f); })");
+  assert(3, ((int (*)(int, int))fp_add)(1, 2), "((int (*)(int, int))fp_add)(1, 2)");
+  assert(7, g_fp(), "g_fp()");
+  assert(7, g_fp_addr(3, 4), "g_fp_addr(3, 4)");
+  assert(-1, g_fp_arr[1](3, 4), "g_fp_arr[1](3, 4)");
+  assert(5, g_fp_arr[0](2, 3), "g_fp_arr[0](2, 3)");
+  assert(6, g_fp_struct.op(9, 3), "g_fp_struct.op(9, 3)");
+
+  assert(2, 17 % 5, "17 % 5");
+  assert(-2, -17 % 5, "-17 % 5");
+  assert(2, ({ int a = 17; int b = -5; a % b; }), "({ int a = 17; int b = -5; a % b; })");
+  assert(3, ({ unsigned u = 4000000000; u % 7; }), "({ unsigned u = 4000000000; u % 7; })");
+  assert(16, 1 << 4, "1 << 4");
+  assert(-16, -256 >> 4, "-256 >> 4");
+  assert(1, ({ long l = 1; (l << 40) == 1099511627776; }), "({ long l = 1; (l << 40) == 1099511627776; })");
+  assert(268435455, ({ unsigned u = -1; u >> 4; }), "({ unsigned u = -1; u >> 4; })");
+  assert(-4, ({ char c = -8; c >> 1; }), "({ char c = -8; c >> 1; })");
+  assert(1, 1 << 2 < 5, "1 << 2 < 5");
+  assert(500, 1000 >> 3 % 2, "1000 >> 3 % 2");
+  assert(2, ({ int i = 100; i %= 7; i; }), "({ int i = 100; i %= 7; i; })");
+  assert(15, ({ int i = 0xff; i &= 0x0f; i; }), "({ int i = 0xff; i &= 0x0f; i; })");
+  assert(63, ({ int i = 15; i |= 0x30; i; }), "({ int i = 15; i |= 0x30; i; })");
+  assert(48, ({ int i = 15; i ^= 0x3f; i; }), "({ int i = 15; i ^= 0x3f; i; })");
+  assert(48, ({ int i = 3; i <<= 4; i; }), "({ int i = 3; i <<= 4; i; })");
+  assert(12, ({ int i = 48; i >>= 2; i; }), "({ int i = 48; i >>= 2; i; })");
+  assert(144, ({ unsigned char c = 200; c <<= 1; c; }), "({ unsigned char c = 200; c <<= 1; c; })");
+  assert(-125, ({ short s = -1000; s >>= 3; s; }), "({ short s = -1000; s >>= 3; s; })");
+  assert(1, ({ unsigned u = 0x80000000; u >>= 31; u; }), "({ unsigned u = 0x80000000; u >>= 31; u; })");
+  assert(8, ({ int x[1 << 3]; sizeof(x) / sizeof(int); }), "({ int x[1 << 3]; sizeof(x) / sizeof(int); })");
+
   printf("OK\n");
   return 0;
 }
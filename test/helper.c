@@ -0,0 +1,12 @@
+// the functions which test.c and the cases of the integration tests call,
+// compiled with gcc or cc. test.c defines ret3 and add6 by itself, and its
+// definitions take the place of the weak ones here
+__attribute__((weak)) int ret3() { return 3; }
+int ret5() { return 5; }
+int add(int x, int y) { return x+y; }
+int sub(int x, int y) { return x-y; }
+__attribute__((weak)) int add6(int a, int b, int c, int d, int e, int f) {
+  return a+b+c+d+e+f;
+}
+int char_fn() { return 257; }
+int static_fn() { return 5; }
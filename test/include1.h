@@ -0,0 +1,2 @@
+#include "test.h"
+#define INCLUDE1 5
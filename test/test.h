@@ -0,0 +1,9 @@
+#ifndef TEST_H
+#define TEST_H
+
+#define ASSERT(x, y) assert(x, y, #y)
+
+int printf();
+int exit();
+
+#endif
@@ -0,0 +1,333 @@
+// shared by the integration tests: the cases, and the helpers to compile C programs
+// with rust_chibicc or cc and to run them. each test crate uses a part of them
+#![allow(dead_code)]
+
+use std::fs;
+use std::path::{ Path, PathBuf };
+use std::process::Command;
+use std::sync::Mutex;
+use std::sync::atomic::{ AtomicUsize, Ordering };
+use std::thread;
+
+pub const SYSTEM_CC: &str = "cc";
+
+// the functions the programs call, compiled with cc. the Makefile compiles the same file
+pub const HELPER: &str = include_str!("../../test/helper.c");
+
+// these snippets reach a local variable by pointer arithmetic from the next one.
+// the layout of local variables is fixed only without optimization, since
+// mem2reg keeps the variables whose address is not taken in registers
+pub const LAYOUT_DEPENDENT: [&str; 5] = ["*(&x+1)", "*(1+&x)", "*(&y-1)", "*(z+1)", "*(z-1)"];
+
+pub fn is_layout_dependent(code: &str) -> bool {
+    LAYOUT_DEPENDENT.iter().any(|snippet| code.contains(snippet))
+}
+
+// a program and its exit status
+pub const CASES: &[(i32, &str)] = &[
+    (0, r#"int main() { return 0; }"#),
+    (42, r#"int main() { return 42; }"#),
+    (21, r#"int main() { return 5+20-4; }"#),
+    (41, r#"int main() { return  12 + 34 - 5 ; }"#),
+    (47, r#"int main() { return 5+6*7; }"#),
+    (15, r#"int main() { return 5*(9-6); }"#),
+    (4, r#"int main() { return (3+5)/2; }"#),
+    (10, r#"int main() { return -10+20; }"#),
+    (10, r#"int main() { return - -10; }"#),
+    (10, r#"int main() { return - - +10; }"#),
+
+    (0, r#"int main() { return 0==1; }"#),
+    (1, r#"int main() { return 42==42; }"#),
+    (1, r#"int main() { return 0!=1; }"#),
+    (0, r#"int main() { return 42!=42; }"#),
+
+    (1, r#"int main() { return 0<1; }"#),
+    (0, r#"int main() { return 1<1; }"#),
+    (0, r#"int main() { return 2<1; }"#),
+    (1, r#"int main() { return 0<=1; }"#),
+    (1, r#"int main() { return 1<=1; }"#),
+    (0, r#"int main() { return 2<=1; }"#),
+
+    (1, r#"int main() { return 1>0; }"#),
+    (0, r#"int main() { return 1>1; }"#),
+    (0, r#"int main() { return 1>2; }"#),
+    (1, r#"int main() { return 1>=0; }"#),
+    (1, r#"int main() { return 1>=1; }"#),
+    (0, r#"int main() { return 1>=2; }"#),
+    (1, r#"int main() { int x=2; return x>1; }"#),
+    (0, r#"int main() { int x=2; return x>2; }"#),
+    (0, r#"int main() { int x=2; return 1>x; }"#),
+    (1, r#"int main() { int x=2; return x>=2; }"#),
+    (0, r#"int main() { int x=2; return x>=3; }"#),
+    (1, r#"int main() { int x=-1; return x>=-2; }"#),
+
+    (3, r#"int main() { int a; a=3; return a; }"#),
+    (8, r#"int main() { int a; int z; a=3; z=5; return a+z; }"#),
+    (3, r#"int main() { int a=3; return a; }"#),
+    (8, r#"int main() { int a=3; int z=5; return a+z; }"#),
+
+    (1, r#"int main() { return 1; 2; 3; }"#),
+    (2, r#"int main() { 1; return 2; 3; }"#),
+    (3, r#"int main() { 1; 2; return 3; }"#),
+
+    (3, r#"int main() { int foo=3; return foo; }"#),
+    (8, r#"int main() { int foo123=3; int bar=5; return foo123+bar; }"#),
+
+    (3, r#"int main() { if (0) return 2; return 3; }"#),
+    (3, r#"int main() { if (1-1) return 2; return 3; }"#),
+    (2, r#"int main() { if (1) return 2; return 3; }"#),
+    (2, r#"int main() { if (2-1) return 2; return 3; }"#),
+
+    (3, r#"int main() { {1; {2;} return 3;} }"#),
+
+    (10, r#"int main() { int i=0; while(i<10) i=i+1; return i; }"#),
+    (55, r#"int main() { int i=0; int j=0; while(i<=10) {j=i+j; i=i+1;} return j; }"#),
+
+    (55, r#"int main() { int i=0; int j=0; for (i=0; i<=10; i=i+1) j=i+j; return j; }"#),
+    (3, r#"int main() { for (;;) return 3; return 5; }"#),
+
+    (3, r#"int main() { return ret3(); }"#),
+    (5, r#"int main() { return ret5(); }"#),
+    (2, r#"int main() { return sub(5, 3); }"#),
+    (21, r#"int main() { return add6(1,2,3,4,5,6); }"#),
+
+    (32, r#"int main() { return ret32(); } int ret32() { return 32; }"#),
+    (7, r#"int main() { return add2(3,4); } int add2(int x, int y) { return x+y; }"#),
+    (1, r#"int main() { return sub2(4,3); } int sub2(int x, int y) { return x-y; }"#),
+    (55, r#"int main() { return fib(9); } int fib(int x) { if (x<=1) return 1; return fib(x-1) + fib(x-2); }"#),
+
+    (3, r#"int main() { int x=3; return *&x; }"#),
+    (3, r#"int main() { int x=3; int *y=&x; int **z=&y; return **z; }"#),
+
+    (5, r#"int main() { int x=3; int y=5; return *(&x+1); }"#),
+    (5, r#"int main() { int x=3; int y=5; return *(1+&x); }"#),
+    (3, r#"int main() { int x=3; int y=5; return *(&y-1); }"#),
+
+    (2, r#"int main() { int x=3; return (&x+2)-&x; }"#),
+    (5, r#"int main() { int x=3; int y=5; int *z=&x; return *(z+1); }"#),
+    (3, r#"int main() { int x=3; int y=5; int *z=&y; return *(z-1); }"#),
+    (5, r#"int main() { int x=3; int *y=&x; *y=5; return x; }"#),
+    (7, r#"int main() { int x=3; int y=5; *(&x+1)=7; return y; }"#),
+    (7, r#"int main() { int x=3; int y=5; *(&y-1)=7; return x; }"#),
+    (8, r#"int main() { int x=3; int y=5; return foo(&x, y); } int foo(int *x, int y) { return *x + y; }"#),
+
+    (3, r#"int main() { int x[2]; int *y=&x; *y=3; return *x; }"#),
+
+    (3, r#"int main() { int x[3]; *x=3; *(x+1)=4; *(x+2)=5; return *x; }"#),
+    (4, r#"int main() { int x[3]; *x=3; *(x+1)=4; *(x+2)=5; return *(x+1); }"#),
+    (5, r#"int main() { int x[3]; *x=3; *(x+1)=4; *(x+2)=5; return *(x+2); }"#),
+
+    (0, r#"int main() { int x[2][3]; int *y=x; *y=0; return **x; }"#),
+    (1, r#"int main() { int x[2][3]; int *y=x; *(y+1)=1; return *(*x+1); }"#),
+    (2, r#"int main() { int x[2][3]; int *y=x; *(y+2)=2; return *(*x+2); }"#),
+    (3, r#"int main() { int x[2][3]; int *y=x; *(y+3)=3; return **(x+1); }"#),
+    (4, r#"int main() { int x[2][3]; int *y=x; *(y+4)=4; return *(*(x+1)+1); }"#),
+    (5, r#"int main() { int x[2][3]; int *y=x; *(y+5)=5; return *(*(x+1)+2); }"#),
+    (6, r#"int main() { int x[2][3]; int *y=x; *(y+6)=6; return **(x+2); }"#),
+
+    (3, r#"int main() { int x[3]; *x=3; x[1]=4; x[2]=5; return *x; }"#),
+    (4, r#"int main() { int x[3]; *x=3; x[1]=4; x[2]=5; return *(x+1); }"#),
+    (5, r#"int main() { int x[3]; *x=3; x[1]=4; x[2]=5; return *(x+2); }"#),
+    (5, r#"int main() { int x[3]; *x=3; x[1]=4; x[2]=5; return *(x+2); }"#),
+    (5, r#"int main() { int x[3]; *x=3; x[1]=4; 2[x]=5; return *(x+2); }"#),
+
+    (0, r#"int main() { int x[2][3]; int *y=x; y[0]=0; return x[0][0]; }"#),
+    (1, r#"int main() { int x[2][3]; int *y=x; y[1]=1; return x[0][1]; }"#),
+    (2, r#"int main() { int x[2][3]; int *y=x; y[2]=2; return x[0][2]; }"#),
+    (3, r#"int main() { int x[2][3]; int *y=x; y[3]=3; return x[1][0]; }"#),
+    (4, r#"int main() { int x[2][3]; int *y=x; y[4]=4; return x[1][1]; }"#),
+    (5, r#"int main() { int x[2][3]; int *y=x; y[5]=5; return x[1][2]; }"#),
+    (6, r#"int main() { int x[2][3]; int *y=x; y[6]=6; return x[2][0]; }"#),
+
+    (4, r#"int main() { int x; return sizeof(x); }"#),
+    (4, r#"int main() { int x; return sizeof x; }"#),
+    (8, r#"int main() { int *x; return sizeof(x); }"#),
+    (16, r#"int main() { int x[4]; return sizeof(x); }"#),
+    (48, r#"int main() { int x[3][4]; return sizeof(x); }"#),
+    (16, r#"int main() { int x[3][4]; return sizeof(*x); }"#),
+    (4, r#"int main() { int x[3][4]; return sizeof(**x); }"#),
+    (5, r#"int main() { int x[3][4]; return sizeof(**x) + 1; }"#),
+    (5, r#"int main() { int x[3][4]; return sizeof **x + 1; }"#),
+    (4, r#"int main() { int x[3][4]; return sizeof(**x + 1); }"#),
+
+    (0, r#"int x; int main() { return x; }"#),
+    (3, r#"int x; int main() { x=3; return x; }"#),
+    (0, r#"int x[4]; int main() { x[0]=0; x[1]=1; x[2]=2; x[3]=3; return x[0]; }"#),
+    (1, r#"int x[4]; int main() { x[0]=0; x[1]=1; x[2]=2; x[3]=3; return x[1]; }"#),
+    (2, r#"int x[4]; int main() { x[0]=0; x[1]=1; x[2]=2; x[3]=3; return x[2]; }"#),
+    (3, r#"int x[4]; int main() { x[0]=0; x[1]=1; x[2]=2; x[3]=3; return x[3]; }"#),
+
+    (4, r#"int x; int main() { return sizeof(x); }"#),
+    (16, r#"int x[4]; int main() { return sizeof(x); }"#),
+
+    (1, r#"int main() { char x=1; return x; }"#),
+    (1, r#"int main() { char x=1; char y=2; return x; }"#),
+    (2, r#"int main() { char x=1; char y=2; return y; }"#),
+
+    (1, r#"int main() { char x; return sizeof(x); }"#),
+    (10, r#"int main() { char x[10]; return sizeof(x); }"#),
+    (1, r#"int main() { return sub_char(7, 3, 3); } int sub_char(char a, char b, char c) { return a-b-c; }"#),
+
+    (97, r#"int main() { return "abc"[0]; }"#),
+    (98, r#"int main() { return "abc"[1]; }"#),
+    (99, r#"int main() { return "abc"[2]; }"#),
+    (0, r#"int main() { return "abc"[3]; }"#),
+    (4, r#"int main() { return sizeof("abc"); }"#),
+
+    (7, r#"int main() { return "\a"[0]; }"#),
+    (8, r#"int main() { return "\b"[0]; }"#),
+    (9, r#"int main() { return "\t"[0]; }"#),
+    (10, r#"int main() { return "\n"[0]; }"#),
+    (11, r#"int main() { return "\v"[0]; }"#),
+    (12, r#"int main() { return "\f"[0]; }"#),
+    (13, r#"int main() { return "\r"[0]; }"#),
+    (27, r#"int main() { return "\e"[0]; }"#),
+    (0, r#"int main() { return "\0"[0]; }"#),
+
+    (106, r#"int main() { return "\j"[0]; }"#),
+    (107, r#"int main() { return "\k"[0]; }"#),
+    (108, r#"int main() { return "\l"[0]; }"#),
+
+    (0, r#"int main() { return ({ 0; }); }"#),
+    (2, r#"int main() { return ({ 0; 1; 2; }); }"#),
+    (1, r#"int main() { ({ 0; return 1; 2; }); return 3; }"#),
+    (3, r#"int main() { return ({ int x=3; x; }); }"#),
+
+    (2, r#"int main() { /* return 1; */ return 2; }"#),
+    (2, r#"int main() { // return 1;
+return 2; }"#),
+
+    (2, r#"int main() { int x=2; { int x=3; } return x; }"#),
+    (2, r#"int main() { int x=2; { int x=3; } { int y=4; return x; }}"#),
+    (3, r#"int main() { int x=2; { x=3; } return x; }"#),
+
+    (1, r#"int main() { return ({ typedef int t; t x=1; x; }); }"#),
+    (1, r#"int main() { return ({ typedef struct {int a;} t; t x; x.a=1; x.a; }); }"#),
+    (1, r#"int main() { return ({ typedef int t; t t=1; t; }); }"#),
+    (2, r#"int main() { return ({ typedef struct {int a;} t; { typedef int t; } t x; x.a=2; x.a; }); }"#),
+];
+
+// the result of running an executable
+#[derive(PartialEq, Debug)]
+pub struct Run {
+    pub status: Option<i32>,
+    pub stdout: String
+}
+
+pub fn repo_path(path: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join(path)
+}
+
+// a clean directory for the files of a test
+pub fn work_dir(name: &str) -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join(name);
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).expect("cannot create the working directory");
+
+    dir
+}
+
+pub fn chibicc() -> Command {
+    Command::new(env!("CARGO_BIN_EXE_rust_chibicc"))
+}
+
+pub fn has_system_cc() -> bool {
+    Command::new(SYSTEM_CC).arg("--version").output().is_ok()
+}
+
+// compile C code with cc to an object, which is linked to the programs
+pub fn cc_object(dir: &Path, name: &str, source: &str) -> PathBuf {
+    let src = dir.join(format!("{}.c", name));
+    let obj = dir.join(format!("{}.o", name));
+    fs::write(&src, source).expect("cannot write a source file");
+    let mut cmd = Command::new(SYSTEM_CC);
+    cmd.arg("-w").arg("-c").arg("-o").arg(&obj).arg(&src);
+    if let Err(e) = exec(cmd) {
+        panic!("cc cannot compile {}:\n{}", name, e);
+    }
+
+    obj
+}
+
+pub fn write_source(dir: &Path, name: &str, code: &str) -> PathBuf {
+    let src = dir.join(name);
+    fs::write(&src, code).expect("cannot write a source file");
+
+    src
+}
+
+// compile and link a program with rust_chibicc. test.c includes test/test.h,
+// also from its copies in the working directories
+pub fn build(exe: &Path, src: &Path, args: &[&str], objects: &[&Path]) -> Result<PathBuf, String> {
+    let mut cmd = chibicc();
+    cmd.args(args).arg("-static").arg("-I").arg(repo_path("")).arg("-o").arg(exe).arg(src).args(objects);
+
+    exec(cmd).map(|_| exe.to_path_buf())
+}
+
+// compile and link a program with cc
+pub fn cc_build(exe: &Path, src: &Path, objects: &[&Path]) -> Result<PathBuf, String> {
+    let mut cmd = Command::new(SYSTEM_CC);
+    cmd.arg("-w").arg("-o").arg(exe).arg(src).args(objects);
+
+    exec(cmd).map(|_| exe.to_path_buf())
+}
+
+// fail with all the failures of the programs at once. an empty one is a success
+pub fn finish(failures: Vec<String>) {
+    let failures: Vec<String> = failures.into_iter().filter(|failure| !failure.is_empty()).collect();
+    assert!(failures.is_empty(), "{} failures:\n{}", failures.len(), failures.join("\n"));
+}
+
+// run a command and return its stderr if it fails
+pub fn exec(mut cmd: Command) -> Result<(), String> {
+    let output = cmd.output().map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).into_owned())
+    }
+
+    Ok(())
+}
+
+// run a command and return its stdout, or its stderr if it fails
+pub fn output(mut cmd: Command) -> Result<String, String> {
+    let output = cmd.output().map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).into_owned())
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+pub fn run(exe: &Path) -> Result<Run, String> {
+    let output = Command::new(exe).output().map_err(|e| e.to_string())?;
+
+    Ok(Run { status: output.status.code(), stdout: String::from_utf8_lossy(&output.stdout).into_owned() })
+}
+
+// apply `f` to the items on as many threads as the cpus. the results are in the order of the items
+pub fn par_map<T: Sync, R: Send>(items: &[T], f: impl Fn(&T) -> R + Sync) -> Vec<R> {
+    let workers = thread::available_parallelism().map_or(1, |n| n.get());
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(Vec::with_capacity(items.len()));
+
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| {
+                loop {
+                    let i = next.fetch_add(1, Ordering::SeqCst);
+                    let item = match items.get(i) {
+                        Some(item) => item,
+                        None => break
+                    };
+                    let result = f(item);
+                    results.lock().unwrap().push((i, result));
+                }
+            });
+        }
+    });
+
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|(i, _)| *i);
+    results.into_iter().map(|(_, result)| result).collect()
+}
@@ -0,0 +1,161 @@
+// the debug information written with -g. the DWARF sections of the object
+// are read back with readelf and objdump, which come with the assembler
+
+mod common;
+
+use common::{ chibicc, exec, output, run, work_dir, write_source };
+use std::path::Path;
+use std::process::Command;
+
+const SOURCE: &str = "\
+struct point { int x; int y; };
+int total = 0;
+int add(int a, int b) {
+  int sum = a + b;
+  return sum;
+}
+int main() {
+  struct point p;
+  p.x = 3;
+  p.y = 4;
+  total = add(p.x, p.y);
+  return total;
+}
+";
+
+fn tool(name: &str, args: &[&str], obj: &Path) -> String {
+    let mut cmd = Command::new(name);
+    cmd.args(args).arg(obj);
+
+    output(cmd).unwrap()
+}
+
+// the lines of readelf, with the runs of spaces squeezed, e.g. `DW_AT_name : add`
+fn squeeze(text: &str) -> Vec<String> {
+    text.lines().map(|line| line.split_whitespace().collect::<Vec<_>>().join(" ")).collect()
+}
+
+#[test]
+fn debug_info() {
+    let dir = work_dir("debug_info");
+    let src = write_source(&dir, "a.c", SOURCE);
+    let obj = dir.join("a.o");
+
+    let mut cmd = chibicc();
+    cmd.arg("-g").arg("-c").arg("-o").arg(&obj).arg(&src);
+    exec(cmd).unwrap();
+
+    let info = squeeze(&tool("readelf", &["--debug-dump=info"], &obj));
+    let has = |line: &str| info.iter().any(|l| l.ends_with(line));
+    for line in &[
+        "(DW_TAG_compile_unit)",
+        "DW_AT_producer : rust_chibicc",
+        "(DW_TAG_subprogram)",
+        "DW_AT_name : add",
+        "DW_AT_name : main",
+        "(DW_TAG_formal_parameter)",
+        "DW_AT_name : sum",
+        "DW_AT_decl_line : 4",
+        "(DW_TAG_structure_type)",
+        "DW_AT_name : point",
+        "DW_AT_name : y",
+        "DW_AT_data_member_location: 4",
+        "DW_AT_name : total"
+    ] {
+        assert!(has(line), "{} is not found in:\n{}", line, info.join("\n"));
+    }
+    // a local variable is at its offset from rbp
+    assert!(info.iter().any(|l| l.contains("DW_AT_location") && l.contains("DW_OP_fbreg: -")), "{}", info.join("\n"));
+
+    // each statement has a row in the line table
+    let lines = squeeze(&tool("objdump", &["--dwarf=decodedline"], &obj));
+    for row in &[3, 4, 5, 7, 9, 10, 11, 12] {
+        let row = format!("a.c {} ", row);
+        assert!(lines.iter().any(|l| l.starts_with(&row)), "{}is not found in:\n{}", row, lines.join("\n"));
+    }
+}
+
+#[test]
+fn debug_info_keeps_the_code() {
+    let dir = work_dir("debug_info_keeps_the_code");
+    let src = write_source(&dir, "a.c", SOURCE);
+
+    for opt in &["-O0", "-O2"] {
+        let exe = dir.join(format!("a{}", opt));
+        let mut cmd = chibicc();
+        cmd.arg("-g").arg(opt).arg("-static").arg("-o").arg(&exe).arg(&src);
+        exec(cmd).unwrap();
+        assert_eq!(run(&exe).unwrap().status, Some(7), "{}", opt);
+
+        // the instructions are the same as without -g
+        let asm = |debug: bool| {
+            let mut cmd = chibicc();
+            cmd.arg(opt).arg("-S").arg("-o").arg("-").arg(&src);
+            if debug {
+                cmd.arg("-g");
+            }
+            output(cmd).unwrap().lines()
+                .filter(|line| !line.trim_start().starts_with('.') && !line.starts_with(".L.end.") && !line.starts_with(".L.text."))
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(asm(false), asm(true), "{}", opt);
+    }
+}
+
+// the attributes of each entry of readelf, e.g. `DW_AT_name : r`
+fn entries(info: &[String]) -> Vec<Vec<String>> {
+    let mut entries: Vec<Vec<String>> = Vec::new();
+    for line in info {
+        if line.contains("Abbrev Number:") {
+            entries.push(Vec::new());
+        } else if let (Some(entry), Some(i)) = (entries.last_mut(), line.find("DW_AT_")) {
+            entry.push(line[i..].to_string());
+        }
+    }
+
+    entries
+}
+
+// a variable which mem2reg holds in a register has no location at -O2, so that
+// a debugger shows it as optimized out instead of its stack slot, which is never
+// written. a variable whose address is taken stays on the stack
+#[test]
+fn debug_info_of_promoted_variables() {
+    let dir = work_dir("debug_info_of_promoted_variables");
+    let src = write_source(&dir, "a.c", "\
+void g(int *p);
+int sq(int v) {
+  int r = v * v;
+  return r;
+}
+int keep() {
+  int k = 1;
+  g(&k);
+  return k;
+}
+");
+
+    for (opt, promoted) in &[("-O0", false), ("-O2", true)] {
+        let obj = dir.join(format!("a{}.o", opt));
+        let mut cmd = chibicc();
+        cmd.arg("-g").arg(opt).arg("-c").arg("-o").arg(&obj).arg(&src);
+        exec(cmd).unwrap();
+
+        let info = squeeze(&tool("readelf", &["--debug-dump=info"], &obj));
+        let entries = entries(&info);
+        let location = |name: &str| {
+            let entry = entries.iter().find(|entry| entry.contains(&format!("DW_AT_name : {}", name)))
+                .unwrap_or_else(|| panic!("{} is not found in:\n{}", name, info.join("\n")));
+            entry.iter().find(|attr| attr.starts_with("DW_AT_location")).cloned()
+        };
+
+        for name in &["v", "r"] {
+            match location(name) {
+                Some(loc) => assert!(!promoted && loc.contains("DW_OP_fbreg: -"), "{} ({}): {}", name, opt, loc),
+                None => assert!(promoted, "{} ({}) has no location", name, opt)
+            }
+        }
+        assert!(location("k").is_some_and(|loc| loc.contains("DW_OP_fbreg: -")), "k ({})", opt);
+    }
+}
@@ -0,0 +1,144 @@
+// the diagnostics printed by the driver, checked by their text and location
+
+mod common;
+
+use common::{ chibicc, work_dir, write_source };
+
+// compile `code` as a.c with `args`, and return whether it succeeds and the stderr
+fn compile(name: &str, code: &str, args: &[&str]) -> (bool, String) {
+    let dir = work_dir(name);
+    write_source(&dir, "a.c", code);
+
+    let out = chibicc().current_dir(&dir).args(args).arg("-S").arg("-o").arg("a.s").arg("a.c").output().unwrap();
+
+    (out.status.success(), String::from_utf8_lossy(&out.stderr).into_owned())
+}
+
+// the first line of each diagnostic, e.g. `a.c:1:14: error: stray break`
+fn headlines(stderr: &str) -> Vec<&str> {
+    stderr.lines().filter(|line| line.starts_with("a.c:")).collect()
+}
+
+// the errors found after parsing, by the lowering to the IR and by the code generator
+#[test]
+fn errors_after_parsing_are_located() {
+    for (code, expected) in &[
+        ("int main() {\n  break;\n}\n", "a.c:2:3: error: stray break"),
+        ("int main() {\n  continue;\n}\n", "a.c:2:3: error: stray continue"),
+        ("int main() {\n  int a[2]; int b[2];\n  a = b;\n  return 0;\n}\n", "a.c:3:3: error: not an lvalue"),
+        ("int main() {\n  int x;\n  (x + 1) = 2;\n}\n", "a.c:3:3: error: not an lvalue"),
+        ("int main() {\n  float x = 1;\n  return x << 2;\n}\n", "a.c:3:3: error: invalid operands to shl")
+    ] {
+        let (ok, stderr) = compile("errors_after_parsing_are_located", code, &[]);
+        assert!(!ok, "{:?} is compiled", code);
+        assert_eq!(headlines(&stderr), vec![*expected], "{}", stderr);
+    }
+}
+
+// each kind of warning, with the option which enables it
+#[test]
+fn warnings_are_located() {
+    for (code, args, expected) in &[
+        ("int main() {\n  int x;\n  return 0;\n}\n", &["-Wall"][..],
+            "a.c:2:7: warning: unused variable 'x' [-Wunused-variable]"),
+        ("int f(int a, int b) {\n  return a;\n}\n", &["-Wextra"][..],
+            "a.c:1:18: warning: unused parameter 'b' [-Wunused-parameter]"),
+        ("int main() {\n  return g(1);\n}\n", &[][..],
+            "a.c:2:10: warning: implicit declaration of function 'g' [-Wimplicit-function-declaration]"),
+        ("int f(int a) {\n  if (a)\n    return 1;\n}\n", &["-Wall"][..],
+            "a.c:1:5: warning: control reaches end of non-void function 'f' [-Wreturn-type]"),
+        ("int main() {\n  int *p = 0;\n  return p == 1;\n}\n", &[][..],
+            "a.c:3:12: warning: comparison between pointer and integer [-Wpointer-integer-compare]"),
+        ("int main() {\n  return 0;\n  main();\n}\n", &["-Wextra"][..],
+            "a.c:3:3: warning: unreachable code after 'return' in function 'main' [-Wunreachable-code]")
+    ] {
+        let (ok, stderr) = compile("warnings_are_located", code, args);
+        assert!(ok, "{:?} is not compiled: {}", code, stderr);
+        assert_eq!(headlines(&stderr), vec![*expected], "{}", stderr);
+
+        // -Wall does not enable the warnings of -Wextra
+        let (_, stderr) = compile("warnings_are_located", code, &["-Wall", "-Wno-implicit-function-declaration", "-Wno-pointer-integer-compare"]);
+        let expected = if args.contains(&"-Wall") { 1 } else { 0 };
+        assert_eq!(headlines(&stderr).len(), expected, "{}", stderr);
+    }
+}
+
+#[test]
+fn werror_turns_warnings_into_errors() {
+    let code = "int f(int a) {\n  int x;\n  if (a)\n    return 1;\n}\n";
+    let (ok, stderr) = compile("werror_turns_warnings_into_errors", code, &["-Wall", "-Werror"]);
+    assert!(!ok);
+    assert_eq!(stderr, "\
+a.c:1:5: error: control reaches end of non-void function 'f' [-Werror=return-type]
+ 1 | int f(int a) {
+   |     ^
+a.c:2:7: error: unused variable 'x' [-Werror=unused-variable]
+ 2 |   int x;
+   |       ^
+2 errors generated.
+");
+
+    // a disabled warning is not an error
+    let (ok, stderr) = compile("werror_turns_warnings_into_errors", code, &["-Werror"]);
+    assert!(ok && stderr.is_empty(), "{}", stderr);
+}
+
+// an unknown -Wno-<name> is ignored, and an unknown -W<name> is reported once without failing
+#[test]
+fn unknown_warning_options_are_accepted() {
+    let code = "int main() {\n  int x;\n  return 0;\n}\n";
+    let (ok, stderr) = compile("unknown_warning_options_are_accepted", code, &["-Wshadow", "-Wno-format", "-Wall"]);
+    assert!(ok, "{}", stderr);
+    assert_eq!(stderr.lines().filter(|line| !line.starts_with(' ')).collect::<Vec<_>>(), vec![
+        "rust_chibicc: warning: unknown warning option: -Wshadow",
+        "a.c:2:7: warning: unused variable 'x' [-Wunused-variable]"
+    ], "{}", stderr);
+
+    for args in &[&["-Wformat"][..], &["-Wpedantic"][..], &["-Wno-format", "-Wno-pedantic"][..]] {
+        let (ok, stderr) = compile("unknown_warning_options_are_accepted", "int main() {\n  return 0;\n}\n", args);
+        assert!(ok, "{:?}: {}", args, stderr);
+        let expected = args.iter()
+            .filter(|arg| !arg.starts_with("-Wno-"))
+            .map(|arg| format!("rust_chibicc: warning: unknown warning option: {}\n", arg))
+            .collect::<String>();
+        assert_eq!(stderr, expected, "{:?}", args);
+    }
+}
+
+// the warnings found by the parser, the errors, and the warnings found after
+// parsing are reported together in the order of the source
+#[test]
+fn diagnostics_are_in_source_order() {
+    let code = "\
+int f(int p) {
+  int unused;
+  return p == 1 ? g() : 0;
+}
+int main() {
+  int x = undefined;
+  return k();
+}
+";
+    let (ok, stderr) = compile("diagnostics_are_in_source_order", code, &["-Wall"]);
+    assert!(!ok);
+    assert_eq!(headlines(&stderr), vec![
+        "a.c:3:19: warning: implicit declaration of function 'g' [-Wimplicit-function-declaration]",
+        "a.c:6:11: error: undefined variable: undefined",
+        "a.c:7:10: warning: implicit declaration of function 'k' [-Wimplicit-function-declaration]"
+    ], "{}", stderr);
+
+    let code = "\
+int f(int p) {
+  int unused;
+  if (p)
+    return g();
+}
+";
+    let (ok, stderr) = compile("diagnostics_are_in_source_order", code, &["-Wall", "-Wextra"]);
+    assert!(ok);
+    assert_eq!(headlines(&stderr), vec![
+        "a.c:1:5: warning: control reaches end of non-void function 'f' [-Wreturn-type]",
+        "a.c:2:7: warning: unused variable 'unused' [-Wunused-variable]",
+        "a.c:4:12: warning: implicit declaration of function 'g' [-Wimplicit-function-declaration]"
+    ], "{}", stderr);
+}
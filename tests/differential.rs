@@ -0,0 +1,207 @@
+// differential testing against the system C compiler.
+// every program of the corpus is compiled with rust_chibicc and with cc,
+// and both executables must exit with the same status and print the same stdout.
+// a program which cc rejects, e.g. one using a chibicc extension, is skipped
+
+mod common;
+
+use common::{ SYSTEM_CC, chibicc, exec, has_system_cc, is_layout_dependent, repo_path, run, work_dir };
+use std::fs;
+use std::path::{ Path, PathBuf };
+use std::process::Command;
+
+// rust_chibicc is tested with each of them
+const OPT_LEVELS: [&str; 2] = ["-O0", "-O2"];
+
+// the results of these snippets depend on the compiler, e.g. on the layout of
+// local variables or the size of long double. the cases and the lines of test.c
+// containing them, or a layout dependent snippet, are left out
+const NOT_PORTABLE: [&str; 3] = [
+    "int a=&x; int b=&y; b-a;",
+    "sizeof(long double)",
+    "sizeof(1.5L)"
+];
+
+struct Program {
+    name: String,
+    source: String
+}
+
+struct Harness {
+    dir: PathBuf,
+    // objects linked to every program
+    objects: Vec<PathBuf>,
+    include_paths: Vec<PathBuf>,
+    failures: Vec<String>,
+    skipped: Vec<String>,
+    checked: usize
+}
+
+impl Harness {
+    fn new(name: &str) -> Self {
+        Self { dir: work_dir(name), objects: Vec::new(), include_paths: Vec::new(), failures: Vec::new(), skipped: Vec::new(), checked: 0 }
+    }
+
+    // compile C code with cc to link it to every program
+    fn add_object(&mut self, name: &str, source: &str) {
+        let obj = common::cc_object(&self.dir, name, source);
+        self.objects.push(obj);
+    }
+
+    fn check(&mut self, program: &Program) {
+        let src = self.dir.join(format!("{}.c", program.name));
+        fs::write(&src, &program.source).expect("cannot write a source file");
+
+        let expected = match self.compile_with_cc(&src, &program.name).and_then(|exe| run(&exe)) {
+            Ok(run) => run,
+            Err(_) => {
+                self.skipped.push(program.name.clone());
+                return
+            }
+        };
+        self.checked += 1;
+
+        for opt in OPT_LEVELS.iter() {
+            let actual = self.compile(&src, &program.name, opt).and_then(|exe| run(&exe));
+            match actual {
+                Ok(actual) if actual == expected => {},
+                Ok(actual) => self.failures.push(format!(
+                    "{} ({}): expected {:?}, but got {:?}\n{}", program.name, opt, expected, actual, program.source
+                )),
+                Err(e) => self.failures.push(format!("{} ({}): {}\n{}", program.name, opt, e, program.source))
+            }
+        }
+    }
+
+    fn compile_with_cc(&self, src: &Path, name: &str) -> Result<PathBuf, String> {
+        let exe = self.dir.join(format!("{}.cc", name));
+        let mut cmd = Command::new(SYSTEM_CC);
+        cmd.arg("-w").arg("-o").arg(&exe);
+        link(&mut cmd, src, &self.objects, &self.include_paths);
+
+        exec(cmd).map(|_| exe)
+    }
+
+    fn compile(&self, src: &Path, name: &str, opt: &str) -> Result<PathBuf, String> {
+        let exe = self.dir.join(format!("{}{}", name, opt));
+        let mut cmd = chibicc();
+        cmd.arg(opt).arg("-static").arg("-o").arg(&exe);
+        link(&mut cmd, src, &self.objects, &self.include_paths);
+
+        exec(cmd).map(|_| exe)
+    }
+
+    fn finish(self) {
+        assert!(self.failures.is_empty(), "{} mismatches:\n{}", self.failures.len(), self.failures.join("\n"));
+        if !self.skipped.is_empty() {
+            eprintln!("skipped, since cc rejects them: {}", self.skipped.join(", "));
+        }
+        assert!(self.checked > 0, "no program is checked");
+    }
+}
+
+fn link(cmd: &mut Command, src: &Path, objects: &[PathBuf], include_paths: &[PathBuf]) {
+    for dir in include_paths {
+        cmd.arg("-I").arg(dir);
+    }
+    cmd.arg(src).args(objects);
+}
+
+fn is_portable(code: &str) -> bool {
+    !NOT_PORTABLE.iter().any(|snippet| code.contains(snippet)) && !is_layout_dependent(code)
+}
+
+// test.c uses some chibicc extensions which cc rejects, so the lines of the errors
+// cc reports are removed until cc accepts the rest. the lines which are not portable
+// are removed beforehand
+fn strip_rejected_lines(dir: &Path, source: &str, include_paths: &[PathBuf]) -> String {
+    let mut lines: Vec<String> = source.lines()
+        .map(|line| if is_portable(line) { line.to_string() } else { String::new() })
+        .collect();
+    let src = dir.join("strip.c");
+
+    for _ in 0..10 {
+        fs::write(&src, lines.join("\n")).expect("cannot write a source file");
+        let mut cmd = Command::new(SYSTEM_CC);
+        cmd.arg("-w").arg("-fsyntax-only");
+        link(&mut cmd, &src, &[], include_paths);
+        let output = cmd.output().expect("cannot run cc");
+        if output.status.success() {
+            break
+        }
+
+        // file:line:col: error: msg
+        let prefix = format!("{}:", src.display());
+        for diag in String::from_utf8_lossy(&output.stderr).lines() {
+            let line_no = diag.strip_prefix(prefix.as_str())
+                .filter(|rest| rest.contains(": error:"))
+                .and_then(|rest| rest.split(':').next())
+                .and_then(|line_no| line_no.parse::<usize>().ok());
+            if let Some(line_no) = line_no {
+                lines[line_no - 1] = String::new();
+            }
+        }
+    }
+
+    lines.join("\n")
+}
+
+#[test]
+fn cases() {
+    if !has_system_cc() {
+        eprintln!("skipped, since {} is not found", SYSTEM_CC);
+        return
+    }
+
+    let mut harness = Harness::new("differential_cases");
+    harness.add_object("helper", common::HELPER);
+    for (i, (_, code)) in common::CASES.iter().enumerate() {
+        if is_portable(code) {
+            harness.check(&Program { name: format!("case_{}", i + 1), source: code.to_string() });
+        }
+    }
+
+    harness.finish();
+}
+
+#[test]
+fn test_c() {
+    if !has_system_cc() {
+        eprintln!("skipped, since {} is not found", SYSTEM_CC);
+        return
+    }
+
+    let mut harness = Harness::new("differential_test_c");
+    harness.add_object("helper", common::HELPER);
+    harness.include_paths.push(repo_path(""));
+
+    let source = fs::read_to_string(repo_path("test.c")).expect("cannot read test.c");
+    let source = strip_rejected_lines(&harness.dir, &source, &harness.include_paths);
+    harness.check(&Program { name: "test_c".to_string(), source });
+
+    harness.finish();
+}
+
+#[test]
+fn examples() {
+    if !has_system_cc() {
+        eprintln!("skipped, since {} is not found", SYSTEM_CC);
+        return
+    }
+
+    let mut harness = Harness::new("differential_examples");
+    let mut paths: Vec<PathBuf> = fs::read_dir(repo_path("examples"))
+        .expect("cannot read examples")
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "c"))
+        .collect();
+    paths.sort();
+
+    for path in &paths {
+        let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+        let source = fs::read_to_string(path).expect("cannot read an example");
+        harness.check(&Program { name, source });
+    }
+
+    harness.finish();
+}
@@ -0,0 +1,126 @@
+// the debugging views: --dump-tokens, --dump-ast and --emit-c.
+// the C source printed by --emit-c is compiled again, by rust_chibicc and by
+// cc, and it must behave as the original one. printing it once more must not
+// change it
+
+mod common;
+
+use common::{ CASES, HELPER, build, cc_build, chibicc, finish, is_layout_dependent, output, par_map, repo_path, run, work_dir, write_source };
+use std::path::{ Path, PathBuf };
+
+const SOURCE: &str = "\
+int g[2] = {1, 2};
+int main() {
+  int x = 3;
+  return x > g[1];
+}
+";
+
+fn dump(opt: &str, src: &Path) -> Result<String, String> {
+    let mut cmd = chibicc();
+    cmd.arg(opt).arg("-I").arg(repo_path("")).arg(src);
+
+    output(cmd)
+}
+
+// print the program as C to dir/name.1.c, and check that the printed one is
+// printed in the same way
+fn print_c(dir: &Path, name: &str, src: &Path) -> Result<PathBuf, String> {
+    let printed = write_source(dir, &format!("{}.1.c", name), &dump("--emit-c", src)?);
+
+    let again = dump("--emit-c", &printed)?;
+    let printed_again = write_source(dir, &format!("{}.2.c", name), &again);
+    if dump("--emit-c", &printed_again)? != again {
+        return Err(format!("{} changes when it is printed again", printed_again.display()))
+    }
+
+    Ok(printed)
+}
+
+#[test]
+fn dump_tokens() {
+    let src = write_source(&work_dir("dump_tokens"), "a.c", SOURCE);
+
+    let tokens = dump("--dump-tokens", &src).unwrap();
+    let lines: Vec<&str> = tokens.lines().collect();
+    let loc = src.display().to_string();
+    assert_eq!(lines[0], format!("{}:1:1 Reserved int", loc));
+    assert_eq!(lines[1], format!("{}:1:5 Ident g", loc));
+    assert_eq!(lines[2], format!("{}:1:6 Symbol [", loc));
+    assert_eq!(lines[3], format!("{}:1:7 Num 2 (int)", loc));
+    assert!(lines.contains(&format!("{}:4:12 Reserved >", loc).as_str()), "{}", tokens);
+    assert_eq!(lines.last().unwrap(), &format!("{}:6:1 Eof", loc));
+}
+
+#[test]
+fn dump_ast() {
+    let src = write_source(&work_dir("dump_ast"), "a.c", SOURCE);
+
+    let ast = dump("--dump-ast", &src).unwrap();
+    let expected = "\
+global g: int [2] (global)
+function main: int () (stack 8)
+  local x: int (local, offset 4)
+  ExprStmt at 3:3
+    Assign: int
+      Var x: int (local, offset 4)
+      Num 3: int
+  Return at 4:3
+    Gt: int
+      Var x: int (local, offset 4)
+      Deref: int
+        PtrAdd: int [2]
+          Var g: int [2] (global)
+          Num 1: int
+";
+    assert_eq!(ast, expected);
+}
+
+// the original is compiled by rust_chibicc, and the printed one by both. the
+// layout of local variables may differ with cc
+#[test]
+fn emit_c_cases() {
+    let dir = work_dir("emit_c_cases");
+    let helper = common::cc_object(&dir, "helper", HELPER);
+
+    let failures = par_map(&(0..CASES.len()).collect::<Vec<_>>(), |&i| {
+        let (expected, code) = CASES[i];
+        let name = format!("case_{}", i + 1);
+        let src = write_source(&dir, &format!("{}.c", name), code);
+
+        let check = || -> Result<(), String> {
+            let original = run(&build(&dir.join(&name), &src, &[], &[&helper])?)?;
+            if original.status != Some(expected) {
+                return Err(format!("{} expected, but got {:?}", expected, original.status))
+            }
+
+            let printed = print_c(&dir, &name, &src)?;
+            let actual = run(&build(&dir.join(format!("{}.1", name)), &printed, &[], &[&helper])?)?;
+            if actual != original {
+                return Err(format!("{:?} expected, but got {:?}", original, actual))
+            }
+            if !is_layout_dependent(code) {
+                let actual = run(&cc_build(&dir.join(format!("{}.1.cc", name)), &printed, &[&helper])?)?;
+                if actual != original {
+                    return Err(format!("{:?} expected with cc, but got {:?}", original, actual))
+                }
+            }
+
+            Ok(())
+        };
+        check().err().map_or(String::new(), |e| format!("{} => {}", code, e.trim_end()))
+    });
+
+    finish(failures);
+}
+
+// test.c is not compiled by cc, as it uses the builtins of rust_chibicc such as __va_area__
+#[test]
+fn emit_c_test_c() {
+    let dir = work_dir("emit_c_test_c");
+    let helper = common::cc_object(&dir, "helper", HELPER);
+
+    let printed = print_c(&dir, "test_c", &repo_path("test.c")).unwrap();
+    let actual = build(&dir.join("test_c"), &printed, &[], &[&helper]).and_then(|exe| run(&exe)).unwrap();
+    assert!(actual.status == Some(0) && actual.stdout.ends_with("OK\n"), "{:?}", actual);
+}
@@ -0,0 +1,181 @@
+// compile the programs with rust_chibicc, then assemble, link and run them.
+// the programs run in parallel and every failure is reported at once
+
+mod common;
+
+use common::{ CASES, HELPER, build, chibicc, finish, is_layout_dependent, output, par_map, repo_path, run, work_dir, write_source };
+use std::fs;
+use std::process::Command;
+
+// every case is run with each of them
+const OPT_LEVELS: [&str; 2] = ["-O0", "-O2"];
+
+// test.c is run with each optimization level, and with each pass alone
+const TEST_C_OPTIONS: [&str; 10] = [
+    "-O0",
+    "-O1",
+    "-O2",
+    "--peephole",
+    "--passes=simplify-cfg",
+    "--passes=const-prop",
+    "--passes=strength-reduce",
+    "--passes=copy-prop",
+    "--passes=dce",
+    "--passes=mem2reg"
+];
+
+#[test]
+fn cases() {
+    let dir = work_dir("exec_cases");
+    let helper = common::cc_object(&dir, "helper", HELPER);
+
+    let jobs: Vec<(usize, &str)> = (0..CASES.len())
+        .flat_map(|i| OPT_LEVELS.iter().map(move |opt| (i, *opt)))
+        .filter(|&(i, opt)| opt == "-O0" || !is_layout_dependent(CASES[i].1))
+        .collect();
+    let failures = par_map(&jobs, |&(i, opt)| {
+        let (expected, code) = CASES[i];
+        let name = format!("case_{}{}", i + 1, opt);
+        let src = write_source(&dir, &format!("{}.c", name), code);
+
+        match build(&dir.join(&name), &src, &[opt], &[&helper]).and_then(|exe| run(&exe)) {
+            Ok(actual) if actual.status == Some(expected) => String::new(),
+            Ok(actual) => format!("{} ({}) => {} expected, but got {:?}", code, opt, expected, actual.status),
+            Err(e) => format!("{} ({}) => {}", code, opt, e.trim_end())
+        }
+    });
+
+    finish(failures);
+}
+
+#[test]
+fn test_c() {
+    let dir = work_dir("exec_test_c");
+    let helper = common::cc_object(&dir, "helper", HELPER);
+    let source = fs::read_to_string(repo_path("test.c")).expect("cannot read test.c");
+    // the layout dependent lines are blanked out, so that the line numbers are kept
+    let optimized: Vec<&str> = source.lines().map(|line| if is_layout_dependent(line) { "" } else { line }).collect();
+    let src = repo_path("test.c");
+    let optimized_src = write_source(&dir, "test_c.c", &optimized.join("\n"));
+
+    let failures = par_map(&TEST_C_OPTIONS, |opt| {
+        let src = if *opt == "-O0" { &src } else { &optimized_src };
+        match build(&dir.join(format!("test_c{}", opt)), src, &[opt], &[&helper]).and_then(|exe| run(&exe)) {
+            Ok(actual) if actual.status == Some(0) && actual.stdout.ends_with("OK\n") => String::new(),
+            Ok(actual) => format!("test.c ({}) => {:?}", opt, actual),
+            Err(e) => format!("test.c ({}) => {}", opt, e.trim_end())
+        }
+    });
+
+    finish(failures);
+}
+
+// linked by cc as it is, which makes a position independent executable on
+// most systems. the options of cc which do not matter are accepted
+#[test]
+fn default_link() {
+    let dir = work_dir("exec_default_link");
+    let exe = dir.join("a");
+    let src = write_source(&dir, "a.c", "\
+int printf(char *fmt, ...);
+int g = 40;
+char *msg = \"%d\\n\";
+int *p = &g;
+int add(int x) { return x + *p; }
+int main() { int (*fp)(int) = add; printf(msg, fp(2)); return fp(2); }
+");
+
+    for opts in &[&[][..], &["-O2", "-fPIC", "-std=c11", "-Wl,--as-needed"][..]] {
+        let out = chibicc().args(*opts).arg("-o").arg(&exe).arg(&src).output().unwrap();
+        let stderr = String::from_utf8_lossy(&out.stderr);
+        // ld warns about an executable stack unless the object says otherwise
+        assert!(out.status.success() && stderr.is_empty(), "{:?}: {}", opts, stderr);
+        assert_eq!(run(&exe).unwrap(), common::Run { status: Some(42), stdout: "42\n".to_string() }, "{:?}", opts);
+    }
+}
+
+// the headers of the system. they have groups for other targets, which are
+// skipped by #if, e.g. `# include <gnu/stubs-32.h>`
+#[test]
+fn system_headers() {
+    let dir = work_dir("exec_system_headers");
+    let exe = dir.join("a");
+    let src = write_source(&dir, "a.c", "\
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <stdint.h>
+#include <stddef.h>
+struct pair { int a; long b; };
+int main() {
+  char *s = malloc(16);
+  strcpy(s, \"hello\");
+  printf(\"%s %d %d\\n\", s, (int)strlen(s), (int)offsetof(struct pair, b));
+  free(s);
+  return INT32_MAX == 2147483647 && NULL == 0 ? 3 : 1;
+}
+");
+
+    let out = chibicc().arg("-o").arg(&exe).arg(&src).output().unwrap();
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(out.status.success() && stderr.is_empty(), "{}", stderr);
+    assert_eq!(run(&exe).unwrap(), common::Run { status: Some(3), stdout: "hello 5 8\n".to_string() });
+}
+
+// -D defines an object-like or a function-like macro, and -U removes it
+#[test]
+fn command_line_macros() {
+    let dir = work_dir("exec_command_line_macros");
+    let exe = dir.join("a");
+    let src = write_source(&dir, "a.c", "\
+#ifdef U
+int main() { return 1; }
+#else
+int main() { return F(3) + ADD(G, 10) + N; }
+#endif
+");
+
+    build(&exe, &src, &["-DF(x)=x*2", "-DADD(a, b)=((a) + (b))", "-DG=20", "-DN", "-DU", "-UU"], &[]).unwrap();
+    assert_eq!(run(&exe).unwrap().status, Some(37));
+}
+
+// the headers of this compiler are found beside the executable when it is
+// installed elsewhere, instead of in the source tree it was built in
+#[test]
+fn own_headers_beside_executable() {
+    let dir = work_dir("exec_own_headers");
+    let bin = dir.join("bin");
+    fs::create_dir_all(bin.join("include")).unwrap();
+    fs::copy(env!("CARGO_BIN_EXE_rust_chibicc"), bin.join("rust_chibicc")).unwrap();
+    fs::write(bin.join("include/stdarg.h"), "#define INSTALLED 1\n").unwrap();
+    write_source(&dir, "a.c", "#include <stdarg.h>\nint x = INSTALLED;\n");
+
+    let mut cmd = Command::new(bin.join("rust_chibicc"));
+    cmd.current_dir(&dir).args(["-E", "a.c"]);
+    let out = output(cmd).unwrap();
+    assert!(out.contains("int x = 1;"), "{}", out);
+}
+
+// --peephole-stats prints the number of instructions before and after the
+// peephole optimization, which are those of the assembly without and with it
+#[test]
+fn peephole_stats() {
+    let dir = work_dir("exec_peephole_stats");
+    write_source(&dir, "a.c", "\
+int add(int a, int b) { return a + b; }
+int main() { int x = 3; return add(x, 4); }
+");
+
+    let out = chibicc().current_dir(&dir).args(["--peephole-stats", "-static", "-o", "a", "a.c"]).output().unwrap();
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    assert_eq!(run(&dir.join("a")).unwrap().status, Some(7));
+
+    let insns = |opts: &[&str]| {
+        let mut cmd = chibicc();
+        cmd.current_dir(&dir).args(opts).args(["-S", "-o", "-", "a.c"]);
+        output(cmd).unwrap().lines().filter(|line| line.starts_with("  ") && !line.trim_start().starts_with('.')).count()
+    };
+    let (before, after) = (insns(&[]), insns(&["--peephole"]));
+    assert!(after < before, "{} -> {}", before, after);
+    assert_eq!(String::from_utf8_lossy(&out.stderr), format!("a.c: peephole: {} -> {} instructions\n", before, after));
+}